@@ -0,0 +1,56 @@
+//! Drives a chat session from code with a mock client, printing the response events as they're
+//! streamed along with the final transcript.
+//!
+//! ```sh
+//! cargo run -p cli --example embed_chat
+//! ```
+
+use cli::api_client::StreamingClient;
+use cli::api_client::model::ChatResponseStream;
+use cli::cli::{
+    ChatSession,
+    ResponseEvent,
+};
+use cli::database::Database;
+use cli::platform::Env;
+use cli::telemetry::TelemetryThread;
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    // One inner list of events per response.
+    let client = StreamingClient::mock(vec![vec![
+        ChatResponseStream::AssistantResponseEvent {
+            content: "Use `tar -xzf archive.tar.gz` ".to_string(),
+        },
+        ChatResponseStream::AssistantResponseEvent {
+            content: "to extract a gzipped tarball.".to_string(),
+        },
+    ]]);
+
+    let mut database = Database::new().await?;
+    let telemetry = TelemetryThread::new(&Env::new(), &mut database).await?;
+
+    let mut session = ChatSession::builder(client)
+        .input(["How do I extract a tarball?"])
+        .build(&mut database)
+        .await?;
+    let mut events = session.subscribe();
+    let printer = tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if let ResponseEvent::AssistantText(text) = event {
+                println!("event: {text:?}");
+            }
+        }
+    });
+
+    session.run(&mut database, &telemetry).await?;
+    for entry in session.transcript() {
+        println!("transcript: {entry}");
+    }
+
+    // Dropping the session closes the event channel.
+    drop(session);
+    printer.await?;
+    telemetry.finish().await.ok();
+    Ok(())
+}
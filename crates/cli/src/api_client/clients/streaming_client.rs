@@ -5,6 +5,10 @@ use std::sync::{
 
 use amzn_codewhisperer_streaming_client::Client as CodewhispererStreamingClient;
 use amzn_qdeveloper_streaming_client::Client as QDeveloperStreamingClient;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::SdkError;
+use aws_smithy_types::error::metadata::ProvideErrorMetadata;
+use aws_types::region::Region;
 use aws_types::request_id::RequestId;
 use tracing::{
     debug,
@@ -16,6 +20,7 @@ use super::shared::{
     sigv4_sdk_config,
     stalled_stream_protection_config,
 };
+use crate::api_client::endpoints::uses_q_service;
 use crate::api_client::interceptor::opt_out::OptOutInterceptor;
 use crate::api_client::model::{
     ChatResponseStream,
@@ -25,6 +30,7 @@ use crate::api_client::{
     ApiClientError,
     Endpoint,
 };
+use crate::auth::AuthError;
 use crate::auth::builder_id::BearerResolver;
 use crate::aws_common::{
     UserAgentOverrideInterceptor,
@@ -58,25 +64,33 @@ mod inner {
 pub struct StreamingClient {
     inner: inner::Inner,
     profile: Option<AuthProfile>,
+    /// The region of the endpoint requests are sent to, `None` for the mock client.
+    region: Option<Region>,
 }
 
 impl StreamingClient {
-    pub async fn new(database: &mut Database) -> Result<Self, ApiClientError> {
-        Ok(
-            if crate::util::system_info::in_cloudshell()
-                || std::env::var("Q_USE_SENDMESSAGE").is_ok_and(|v| !v.is_empty())
-            {
-                Self::new_qdeveloper_client(database, &Endpoint::load_q(database)).await?
-            } else {
-                Self::new_codewhisperer_client(database, &Endpoint::load_codewhisperer(database)).await?
-            },
-        )
+    /// Creates a client for the configured endpoint, or the one for `region` instead if given and
+    /// there is one. Fails if the selected profile is in a different region than `region`.
+    pub async fn new(database: &mut Database, region: Option<&str>) -> Result<Self, ApiClientError> {
+        let endpoint = region.and_then(Endpoint::for_region);
+        Ok(if uses_q_service() {
+            let endpoint = endpoint.unwrap_or_else(|| Endpoint::load_q(database));
+            Self::new_qdeveloper_client(database, &endpoint).await?
+        } else {
+            let endpoint = endpoint.unwrap_or_else(|| Endpoint::load_codewhisperer(database));
+            let client = Self::new_codewhisperer_client(database, &endpoint).await?;
+            if let (Some(region), Some(profile)) = (region, &client.profile) {
+                check_profile_region(profile, region)?;
+            }
+            client
+        })
     }
 
     pub fn mock(events: Vec<Vec<ChatResponseStream>>) -> Self {
         Self {
             inner: inner::Inner::Mock(Arc::new(Mutex::new(events.into_iter()))),
             profile: None,
+            region: None,
         }
     }
 
@@ -105,7 +119,11 @@ impl StreamingClient {
             },
         };
 
-        Ok(Self { inner, profile })
+        Ok(Self {
+            inner,
+            profile,
+            region: Some(endpoint.region().clone()),
+        })
     }
 
     pub async fn new_qdeveloper_client(database: &Database, endpoint: &Endpoint) -> Result<Self, ApiClientError> {
@@ -123,9 +141,14 @@ impl StreamingClient {
         Ok(Self {
             inner: inner::Inner::QDeveloper(client),
             profile: None,
+            region: Some(endpoint.region().clone()),
         })
     }
 
+    pub fn region(&self) -> Option<&Region> {
+        self.region.as_ref()
+    }
+
     pub async fn send_message(
         &self,
         conversation_state: ConversationState,
@@ -171,7 +194,12 @@ impl StreamingClient {
                         });
 
                         if is_quota_breach {
-                            Err(ApiClientError::QuotaBreach("quota has reached its limit"))
+                            Err(ApiClientError::QuotaBreach {
+                                message: "quota has reached its limit",
+                                request_id: e.request_id().map(str::to_string),
+                            })
+                        } else if is_auth_expired(&e) {
+                            Err(ApiClientError::AuthExpired)
                         } else if is_context_window_overflow {
                             Err(ApiClientError::ContextWindowOverflow)
                         } else {
@@ -210,6 +238,24 @@ impl StreamingClient {
     }
 }
 
+/// Whether the request failed because the bearer token has expired, either as reported by the
+/// backend or because there was no valid token left to send.
+fn is_auth_expired<E>(err: &SdkError<E, HttpResponse>) -> bool
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+{
+    if err.raw_response().is_some_and(|resp| resp.status().as_u16() == 401)
+        || err
+            .as_service_error()
+            .is_some_and(|err| matches!(err.code(), Some("ExpiredTokenException" | "UnauthorizedException")))
+    {
+        return true;
+    }
+
+    std::iter::successors(Some(err as &(dyn std::error::Error + 'static)), |err| err.source())
+        .any(|err| matches!(err.downcast_ref::<AuthError>(), Some(AuthError::NoToken)))
+}
+
 #[derive(Debug)]
 pub enum SendMessageOutput {
     Codewhisperer(
@@ -251,6 +297,19 @@ impl RequestId for SendMessageOutput {
     }
 }
 
+/// Profiles only work with the endpoint in their own region, so the profile's requests can't be
+/// sent to `region` if it's a different one.
+fn check_profile_region(profile: &AuthProfile, region: &str) -> Result<(), ApiClientError> {
+    if profile.region() == region {
+        return Ok(());
+    }
+    Err(ApiClientError::ProfileRegionMismatch {
+        profile: profile.profile_name.clone(),
+        profile_region: profile.region().to_owned(),
+        region: region.to_owned(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,11 +324,47 @@ mod tests {
         let mut database = Database::new().await.unwrap();
         let endpoint = Endpoint::load_codewhisperer(&database);
 
-        let _ = StreamingClient::new(&mut database).await;
+        let _ = StreamingClient::new(&mut database, None).await;
         let _ = StreamingClient::new_codewhisperer_client(&mut database, &endpoint).await;
         let _ = StreamingClient::new_qdeveloper_client(&database, &endpoint).await;
     }
 
+    #[test]
+    fn test_check_profile_region() {
+        let profile = AuthProfile {
+            arn: "arn:aws:codewhisperer:us-east-1:123456789012:profile/ABCDEF".to_string(),
+            profile_name: "work".to_string(),
+        };
+        assert_eq!(profile.region(), "us-east-1");
+        assert!(check_profile_region(&profile, "us-east-1").is_ok());
+        assert_eq!(
+            check_profile_region(&profile, "eu-central-1").unwrap_err().to_string(),
+            "the selected profile work is in us-east-1, not eu-central-1. Run `q profile` to select a profile in \
+             eu-central-1, or leave out --region"
+        );
+    }
+
+    #[test]
+    fn test_is_auth_expired() {
+        use amzn_codewhisperer_streaming_client::operation::generate_assistant_response::GenerateAssistantResponseError;
+        use aws_smithy_runtime_api::http::Response;
+        use aws_smithy_types::body::SdkBody;
+
+        let service_error = |status: u16| {
+            SdkError::<_, HttpResponse>::service_error(
+                GenerateAssistantResponseError::unhandled("<unhandled>"),
+                Response::new(status.try_into().unwrap(), SdkBody::empty()),
+            )
+        };
+        assert!(is_auth_expired(&service_error(401)));
+        assert!(!is_auth_expired(&service_error(500)));
+        assert!(!is_auth_expired(&service_error(429)));
+
+        let no_token =
+            SdkError::<GenerateAssistantResponseError, HttpResponse>::construction_failure(AuthError::NoToken);
+        assert!(is_auth_expired(&no_token));
+    }
+
     #[tokio::test]
     async fn test_mock() {
         let client = StreamingClient::mock(vec![vec![
@@ -291,6 +386,7 @@ mod tests {
                     content: "Hello".into(),
                     user_input_message_context: None,
                     user_intent: None,
+                    model_id: None,
                 },
                 history: None,
             })
@@ -308,7 +404,7 @@ mod tests {
     #[tokio::test]
     async fn assistant_response() {
         let mut database = Database::new().await.unwrap();
-        let client = StreamingClient::new(&mut database).await.unwrap();
+        let client = StreamingClient::new(&mut database, None).await.unwrap();
         let mut response = client
             .send_message(ConversationState {
                 conversation_id: None,
@@ -317,6 +413,7 @@ mod tests {
                     content: "How about rustc?".into(),
                     user_input_message_context: None,
                     user_intent: None,
+                    model_id: None,
                 },
                 history: Some(vec![
                     ChatMessage::UserInputMessage(UserInputMessage {
@@ -324,6 +421,7 @@ mod tests {
                         content: "What language is the linux kernel written in, and who wrote it?".into(),
                         user_input_message_context: None,
                         user_intent: None,
+                        model_id: None,
                     }),
                     ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
                         content: "It is written in C by Linus Torvalds.".into(),
@@ -35,6 +35,19 @@ impl Endpoint {
         region: PROD_Q_ENDPOINT_REGION,
     };
 
+    /// The endpoints a session can be pointed at with `--region`, one per region.
+    pub fn available() -> Vec<Self> {
+        if uses_q_service() {
+            vec![Self::PROD_Q]
+        } else {
+            Self::CODEWHISPERER_ENDPOINTS.to_vec()
+        }
+    }
+
+    pub fn for_region(region: &str) -> Option<Self> {
+        Self::available().into_iter().find(|e| e.region().as_ref() == region)
+    }
+
     pub fn load_codewhisperer(database: &Database) -> Self {
         let (endpoint, region) = if let Some(Value::Object(o)) = database.settings.get(Setting::ApiCodeWhispererService)
         {
@@ -45,7 +58,7 @@ impl Endpoint {
             )
         } else if let Ok(Some(profile)) = database.get_auth_profile() {
             // The following branch is evaluated in the case of user profile being set.
-            let region = profile.arn.split(':').nth(3).unwrap_or_default().to_owned();
+            let region = profile.region().to_owned();
             match Self::CODEWHISPERER_ENDPOINTS
                 .iter()
                 .find(|e| e.region().as_ref() == region)
@@ -96,6 +109,11 @@ impl Endpoint {
     }
 }
 
+/// Whether chat goes through the Q service with SigV4 credentials rather than CodeWhisperer.
+pub(crate) fn uses_q_service() -> bool {
+    crate::util::system_info::in_cloudshell() || std::env::var("Q_USE_SENDMESSAGE").is_ok_and(|v| !v.is_empty())
+}
+
 #[cfg(test)]
 mod tests {
     use url::Url;
@@ -118,5 +136,13 @@ mod tests {
         };
         Url::parse(custom.url()).unwrap();
         assert_eq!(custom.region(), &Region::new("us-west-2"));
+
+        if !uses_q_service() {
+            assert_eq!(
+                Endpoint::for_region("eu-central-1").unwrap().url(),
+                PROD_CODEWHISPERER_FRA_ENDPOINT_URL
+            );
+            assert_eq!(Endpoint::for_region("us-west-2"), None);
+        }
     }
 }
@@ -11,6 +11,7 @@ use aws_credential_types::provider::error::CredentialsError;
 use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
 pub use aws_smithy_runtime_api::client::result::SdkError;
 use aws_smithy_types::event_stream::RawMessage;
+use aws_types::request_id::RequestId;
 use thiserror::Error;
 
 use crate::auth::AuthError;
@@ -46,8 +47,29 @@ pub enum ApiClientError {
     QDeveloperChatResponseStream(#[from] SdkError<QDeveloperChatResponseStreamError, RawMessage>),
 
     // quota breach
-    #[error("quota has reached its limit")]
-    QuotaBreach(&'static str),
+    #[error(
+        "quota has reached its limit{}",
+        .request_id.as_deref().map(|id| format!(" (Request ID: {id})")).unwrap_or_default()
+    )]
+    QuotaBreach {
+        message: &'static str,
+        request_id: Option<String>,
+    },
+
+    /// `--region` asked for a region other than the one of the selected Q Developer profile, which
+    /// can't be used with another region's endpoint.
+    #[error(
+        "the selected profile {profile} is in {profile_region}, not {region}. Run `q profile` to select a profile in {region}, or leave out --region"
+    )]
+    ProfileRegionMismatch {
+        profile: String,
+        profile_region: String,
+        region: String,
+    },
+
+    /// The bearer token has expired and couldn't be refreshed, so the user needs to log in again.
+    #[error("your session has expired")]
+    AuthExpired,
 
     /// Returned from the backend when the user input is too large to fit within the model context
     /// window.
@@ -67,6 +89,37 @@ pub enum ApiClientError {
     AuthError(#[from] AuthError),
 }
 
+impl ApiClientError {
+    /// Whether sending a message again might succeed, i.e. the request failed because of a network
+    /// problem or a server error. Quota, auth, and validation errors aren't retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::CodewhispererGenerateAssistantResponse(err) => is_transient(err),
+            Self::QDeveloperSendMessage(err) => is_transient(err),
+            _ => false,
+        }
+    }
+
+    /// The id the service gave the failed request, which support can use to look it up.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Self::CodewhispererGenerateAssistantResponse(err) => err.request_id(),
+            Self::QDeveloperSendMessage(err) => err.request_id(),
+            Self::QuotaBreach { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+fn is_transient<E>(err: &SdkError<E, HttpResponse>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::ResponseError(_) => true,
+        SdkError::DispatchFailure(failure) => failure.is_io() || failure.is_timeout(),
+        SdkError::ServiceError(err) => err.raw().status().is_server_error(),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::error::Error as _;
@@ -78,7 +131,11 @@ mod tests {
     use super::*;
 
     fn response() -> Response {
-        Response::new(500.try_into().unwrap(), SdkBody::empty())
+        response_with_status(500)
+    }
+
+    fn response_with_status(status: u16) -> Response {
+        Response::new(status.try_into().unwrap(), SdkBody::empty())
     }
 
     fn raw_message() -> RawMessage {
@@ -121,9 +178,65 @@ mod tests {
                 raw_message(),
             )),
             ApiClientError::SmithyBuild(aws_smithy_types::error::operation::BuildError::other("<other>")),
+            ApiClientError::AuthExpired,
         ]
     }
 
+    #[test]
+    fn test_is_retryable() {
+        let send_message_error = |status| {
+            ApiClientError::QDeveloperSendMessage(SdkError::service_error(
+                QDeveloperSendMessageError::unhandled("<unhandled>"),
+                response_with_status(status),
+            ))
+        };
+        assert!(send_message_error(500).is_retryable());
+        assert!(send_message_error(503).is_retryable());
+        assert!(!send_message_error(400).is_retryable());
+        assert!(!send_message_error(429).is_retryable());
+
+        assert!(
+            ApiClientError::CodewhispererGenerateAssistantResponse(SdkError::timeout_error("<timeout>")).is_retryable()
+        );
+        assert!(
+            ApiClientError::CodewhispererGenerateAssistantResponse(SdkError::dispatch_failure(
+                aws_smithy_runtime_api::client::result::ConnectorError::io("<io>".into())
+            ))
+            .is_retryable()
+        );
+        assert!(
+            !ApiClientError::QuotaBreach {
+                message: "quota has reached its limit",
+                request_id: None,
+            }
+            .is_retryable()
+        );
+        assert!(!ApiClientError::AuthExpired.is_retryable());
+        assert!(!ApiClientError::ContextWindowOverflow.is_retryable());
+    }
+
+    #[test]
+    fn test_request_id() {
+        let mut response = response_with_status(500);
+        response.headers_mut().insert("x-amzn-requestid", "abc-123");
+        let err = ApiClientError::CodewhispererGenerateAssistantResponse(SdkError::service_error(
+            GenerateAssistantResponseError::unhandled("<unhandled>"),
+            response,
+        ));
+        assert_eq!(err.request_id(), Some("abc-123"));
+
+        let quota_breach = ApiClientError::QuotaBreach {
+            message: "quota has reached its limit",
+            request_id: Some("def-456".to_string()),
+        };
+        assert_eq!(quota_breach.request_id(), Some("def-456"));
+        assert_eq!(
+            quota_breach.to_string(),
+            "quota has reached its limit (Request ID: def-456)"
+        );
+        assert_eq!(ApiClientError::AuthExpired.request_id(), None);
+    }
+
     #[test]
     fn test_errors() {
         for error in all_errors() {
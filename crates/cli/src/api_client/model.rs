@@ -92,14 +92,14 @@ pub struct Recommendation {
 // Streaming
 // =========
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ConversationState {
     pub conversation_id: Option<String>,
     pub user_input_message: UserInputMessage,
     pub history: Option<Vec<ChatMessage>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum ChatMessage {
     AssistantResponseMessage(AssistantResponseMessage),
     UserInputMessage(UserInputMessage),
@@ -193,6 +193,13 @@ impl Serialize for FigDocumentRef<'_> {
     }
 }
 
+fn serialize_document<S>(document: &AwsDocument, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    FigDocumentRef(document).serialize(serializer)
+}
+
 impl Serialize for FigDocument {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -424,7 +431,7 @@ impl From<ToolUse> for amzn_qdeveloper_streaming_client::types::ToolUse {
 }
 
 /// A tool result that contains the results for a tool request that was previously made.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ToolResult {
     /// The ID for the tool request.
     pub tool_use_id: String,
@@ -456,12 +463,18 @@ impl From<ToolResult> for amzn_qdeveloper_streaming_client::types::ToolResult {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Sent in place of an image in a tool result, since images can only be attached to the message.
+pub const IMAGE_RESULT_TEXT: &str = "See images data supplied";
+
+#[derive(Debug, Clone, Serialize)]
 pub enum ToolResultContentBlock {
     /// A tool result that is JSON format data.
+    #[serde(serialize_with = "serialize_document")]
     Json(AwsDocument),
     /// A tool result that is text.
     Text(String),
+    /// A tool result that is an image, e.g. a screenshot.
+    Image(ImageBlock),
 }
 
 impl From<ToolResultContentBlock> for amzn_codewhisperer_streaming_client::types::ToolResultContentBlock {
@@ -469,6 +482,7 @@ impl From<ToolResultContentBlock> for amzn_codewhisperer_streaming_client::types
         match value {
             ToolResultContentBlock::Json(document) => Self::Json(document),
             ToolResultContentBlock::Text(text) => Self::Text(text),
+            ToolResultContentBlock::Image(_) => Self::Text(IMAGE_RESULT_TEXT.to_string()),
         }
     }
 }
@@ -478,6 +492,7 @@ impl From<ToolResultContentBlock> for amzn_qdeveloper_streaming_client::types::T
         match value {
             ToolResultContentBlock::Json(document) => Self::Json(document),
             ToolResultContentBlock::Text(text) => Self::Text(text),
+            ToolResultContentBlock::Image(_) => Self::Text(IMAGE_RESULT_TEXT.to_string()),
         }
     }
 }
@@ -507,7 +522,7 @@ impl From<ToolResultStatus> for amzn_qdeveloper_streaming_client::types::ToolRes
 }
 
 /// Markdown text message.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AssistantResponseMessage {
     /// Unique identifier for the chat message
     pub message_id: Option<String>,
@@ -541,6 +556,13 @@ impl TryFrom<AssistantResponseMessage> for amzn_qdeveloper_streaming_client::typ
     }
 }
 
+/// Token counts for a single request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+}
+
 #[non_exhaustive]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ChatResponseStream {
@@ -562,6 +584,8 @@ pub enum ChatResponseStream {
     MessageMetadataEvent {
         conversation_id: Option<String>,
         utterance_id: Option<String>,
+        /// Tokens used by the request, when the service reports them.
+        usage: Option<TokenUsage>,
     },
     SupplementaryWebLinksEvent(()),
     ToolUseEvent {
@@ -608,6 +632,8 @@ impl From<amzn_codewhisperer_streaming_client::types::ChatResponseStream> for Ch
             ) => ChatResponseStream::MessageMetadataEvent {
                 conversation_id,
                 utterance_id,
+                // Not part of the service models yet.
+                usage: None,
             },
             amzn_codewhisperer_streaming_client::types::ChatResponseStream::ToolUseEvent(
                 amzn_codewhisperer_streaming_client::types::ToolUseEvent {
@@ -664,6 +690,8 @@ impl From<amzn_qdeveloper_streaming_client::types::ChatResponseStream> for ChatR
             ) => ChatResponseStream::MessageMetadataEvent {
                 conversation_id,
                 utterance_id,
+                // Not part of the service models yet.
+                usage: None,
             },
             amzn_qdeveloper_streaming_client::types::ChatResponseStream::ToolUseEvent(
                 amzn_qdeveloper_streaming_client::types::ToolUseEvent {
@@ -742,7 +770,7 @@ impl From<EnvironmentVariable> for amzn_qdeveloper_streaming_client::types::Envi
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct GitState {
     pub status: String,
 }
@@ -853,12 +881,13 @@ impl From<ImageBlock> for amzn_qdeveloper_streaming_client::types::ImageBlock {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UserInputMessage {
     pub content: String,
     pub user_input_message_context: Option<UserInputMessageContext>,
     pub user_intent: Option<UserIntent>,
     pub images: Option<Vec<ImageBlock>>,
+    pub model_id: Option<String>,
 }
 
 impl From<UserInputMessage> for amzn_codewhisperer_streaming_client::types::UserInputMessage {
@@ -868,6 +897,7 @@ impl From<UserInputMessage> for amzn_codewhisperer_streaming_client::types::User
             .set_images(value.images.map(|images| images.into_iter().map(Into::into).collect()))
             .set_user_input_message_context(value.user_input_message_context.map(Into::into))
             .set_user_intent(value.user_intent.map(Into::into))
+            .set_model_id(value.model_id)
             .origin(amzn_codewhisperer_streaming_client::types::Origin::Cli)
             .build()
             .expect("Failed to build UserInputMessage")
@@ -881,13 +911,14 @@ impl From<UserInputMessage> for amzn_qdeveloper_streaming_client::types::UserInp
             .set_images(value.images.map(|images| images.into_iter().map(Into::into).collect()))
             .set_user_input_message_context(value.user_input_message_context.map(Into::into))
             .set_user_intent(value.user_intent.map(Into::into))
+            .set_model_id(value.model_id)
             .origin(amzn_qdeveloper_streaming_client::types::Origin::Cli)
             .build()
             .expect("Failed to build UserInputMessage")
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct UserInputMessageContext {
     pub env_state: Option<EnvState>,
     pub git_state: Option<GitState>,
@@ -917,7 +948,7 @@ impl From<UserInputMessageContext> for amzn_qdeveloper_streaming_client::types::
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum UserIntent {
     ApplyCommonBestPractices,
 }
@@ -976,6 +1007,7 @@ mod tests {
                 })]),
             }),
             user_intent: Some(UserIntent::ApplyCommonBestPractices),
+            model_id: Some("test model".to_string()),
         };
 
         let codewhisper_input =
@@ -989,6 +1021,7 @@ mod tests {
             content: "test content".to_string(),
             user_input_message_context: None,
             user_intent: None,
+            model_id: None,
         };
 
         let codewhisper_minimal =
@@ -1153,7 +1186,8 @@ mod tests {
             ChatResponseStream::from(user_input_event),
             ChatResponseStream::MessageMetadataEvent {
                 conversation_id: None,
-                utterance_id: None
+                utterance_id: None,
+                usage: None
             }
         );
 
@@ -1164,7 +1198,8 @@ mod tests {
             ChatResponseStream::from(user_input_event),
             ChatResponseStream::MessageMetadataEvent {
                 conversation_id: None,
-                utterance_id: None
+                utterance_id: None,
+                usage: None
             }
         );
 
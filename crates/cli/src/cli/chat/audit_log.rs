@@ -0,0 +1,147 @@
+//! An append-only JSONL log of a chat session, written to the path in the `chat.auditLog` setting.
+//!
+//! Every line is a single JSON object with the `timestamp` (RFC 3339, UTC) and `conversation_id`
+//! of the event, and a `type` field:
+//!
+//! - `prompt`: `{"type":"prompt","prompt":"..."}` is a prompt sent by the user.
+//! - `response`: `{"type":"response","message_id":"...","text":"..."}` is the full text of a
+//!   response from the model. `message_id` may be `null`.
+//! - `tool_use` and `tool_result`: a tool use requested by the model and the result sent back for
+//!   it, in the same shape as the events of [super::json_events].
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing::warn;
+
+use super::json_events::JsonEvent;
+use super::message::{
+    AssistantMessage,
+    UserMessage,
+};
+use crate::platform::Context;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AuditEvent<'a> {
+    Prompt { prompt: &'a str },
+    Response { message_id: Option<&'a str>, text: &'a str },
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    ctx: Arc<Context>,
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(ctx: Arc<Context>, path: PathBuf) -> Self {
+        Self { ctx, path }
+    }
+
+    /// Records the prompt and tool results of a message about to be sent.
+    pub async fn record_user_message(&self, conversation_id: &str, message: &UserMessage) {
+        for result in message.tool_use_results().unwrap_or_default() {
+            self.record(conversation_id, JsonEvent::from_tool_result(result)).await;
+        }
+        if let Some(prompt) = message.prompt().filter(|prompt| !prompt.is_empty()) {
+            self.record(conversation_id, AuditEvent::Prompt { prompt }).await;
+        }
+    }
+
+    /// Records a response and the tools it asked to use.
+    pub async fn record_assistant_message(&self, conversation_id: &str, message: &AssistantMessage) {
+        self.record(conversation_id, AuditEvent::Response {
+            message_id: message.message_id(),
+            text: message.content(),
+        })
+        .await;
+        for tool_use in message.tool_uses().unwrap_or_default() {
+            self.record(conversation_id, JsonEvent::ToolUse {
+                id: tool_use.id.clone(),
+                name: tool_use.name.clone(),
+                args: tool_use.args.clone(),
+            })
+            .await;
+        }
+    }
+
+    /// Appends `event` as a line. Failures are only logged, the log must never interrupt the
+    /// session.
+    async fn record(&self, conversation_id: &str, event: impl Serialize) {
+        if let Err(err) = self.append(conversation_id, event).await {
+            warn!(?err, path = ?self.path, "Failed to write to the audit log");
+        }
+    }
+
+    async fn append(&self, conversation_id: &str, event: impl Serialize) -> Result<(), Box<dyn std::error::Error>> {
+        let mut line = serde_json::json!({
+            "timestamp": OffsetDateTime::now_utc().format(&Rfc3339)?,
+            "conversation_id": conversation_id,
+        });
+        if let (Some(line), serde_json::Value::Object(fields)) = (line.as_object_mut(), serde_json::to_value(event)?) {
+            line.extend(fields);
+        }
+        let mut line = serde_json::to_vec(&line)?;
+        line.push(b'\n');
+
+        if let Some(parent) = self.path.parent() {
+            self.ctx.fs().create_dir_all(parent).await?;
+        }
+        self.ctx.fs().append_file(&self.path, line).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::chat::message::AssistantToolUse;
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let path = PathBuf::from("/logs/audit.jsonl");
+        let log = AuditLog::new(Arc::clone(&ctx), path.clone());
+
+        log.record_user_message("conv", &UserMessage::new_prompt("list files".to_string()))
+            .await;
+        log.record_assistant_message(
+            "conv",
+            &AssistantMessage::new_tool_use(Some("m1".to_string()), "Sure".to_string(), vec![AssistantToolUse {
+                id: "t1".to_string(),
+                name: "execute_bash".to_string(),
+                args: serde_json::json!({ "command": "ls" }),
+                ..Default::default()
+            }]),
+        )
+        .await;
+
+        let lines = ctx
+            .fs()
+            .read_to_string(&path)
+            .await
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line["conversation_id"] == "conv"));
+        assert!(lines.iter().all(|line| line["timestamp"].is_string()));
+        assert_eq!(lines[0]["type"], "prompt");
+        assert_eq!(lines[0]["prompt"], "list files");
+        assert_eq!(lines[1]["type"], "response");
+        assert_eq!(lines[1]["message_id"], "m1");
+        assert_eq!(lines[1]["text"], "Sure");
+        assert_eq!(lines[2]["type"], "tool_use");
+        assert_eq!(lines[2]["args"]["command"], "ls");
+
+        // A path that can't be written to doesn't fail the session.
+        AuditLog::new(Arc::clone(&ctx), PathBuf::from("/logs"))
+            .record_user_message("conv", &UserMessage::new_prompt("hello".to_string()))
+            .await;
+    }
+}
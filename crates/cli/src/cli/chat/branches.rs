@@ -0,0 +1,110 @@
+//! Named branches of the conversation for `/fork` and `/branch`.
+//!
+//! Only the active branch lives in [super::ChatContext]; the others are kept here, in memory, until
+//! they're switched to.
+
+use std::collections::BTreeMap;
+
+use eyre::{
+    Result,
+    bail,
+};
+
+/// Name of the branch every session starts on.
+pub const DEFAULT_BRANCH: &str = "main";
+
+#[derive(Debug, Clone)]
+pub struct Branches<T> {
+    current: String,
+    inactive: BTreeMap<String, T>,
+}
+
+impl<T: Clone> Branches<T> {
+    pub fn new() -> Self {
+        Self {
+            current: DEFAULT_BRANCH.to_string(),
+            inactive: BTreeMap::new(),
+        }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    /// Returns the names of all branches, including the active one, sorted by name.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.inactive.keys().map(String::as_str).collect::<Vec<_>>();
+        names.push(&self.current);
+        names.sort_unstable();
+        names
+    }
+
+    /// Keeps a snapshot of `state` under the active branch and makes `name` the active branch, so
+    /// that `state` continues as the new branch.
+    pub fn fork(&mut self, name: &str, state: &T) -> Result<()> {
+        self.validate_new_name(name)?;
+        self.inactive.insert(self.current.clone(), state.clone());
+        self.current = name.to_string();
+        Ok(())
+    }
+
+    /// Makes `name` the active branch, swapping its state into `state` and keeping the previously
+    /// active one.
+    pub fn switch(&mut self, name: &str, state: &mut T) -> Result<()> {
+        if name == self.current {
+            bail!("Already on branch '{name}'");
+        }
+        let Some(target) = self.inactive.remove(name) else {
+            bail!("Branch '{name}' does not exist. Create it with /fork {name}");
+        };
+        let previous = std::mem::replace(state, target);
+        self.inactive
+            .insert(std::mem::replace(&mut self.current, name.to_string()), previous);
+        Ok(())
+    }
+
+    fn validate_new_name(&self, name: &str) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+        {
+            bail!("Branch names may only contain letters, numbers, '-', '_', '.' and '/'");
+        }
+        if name == self.current || self.inactive.contains_key(name) {
+            bail!("Branch '{name}' already exists");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fork_and_switch() {
+        let mut branches = Branches::new();
+        let mut state = vec!["hello"];
+
+        branches.fork("idea", &state).unwrap();
+        assert_eq!(branches.current(), "idea");
+        state.push("try this instead");
+        assert_eq!(branches.names(), vec!["idea", "main"]);
+
+        assert!(branches.fork("main", &state).is_err());
+        assert!(branches.fork("has space", &state).is_err());
+        assert!(branches.switch("idea", &mut state).is_err());
+        assert!(branches.switch("missing", &mut state).is_err());
+
+        branches.switch("main", &mut state).unwrap();
+        assert_eq!(branches.current(), "main");
+        assert_eq!(state, vec!["hello"]);
+        state.push("original path");
+
+        branches.switch("idea", &mut state).unwrap();
+        assert_eq!(state, vec!["hello", "try this instead"]);
+        branches.switch("main", &mut state).unwrap();
+        assert_eq!(state, vec!["hello", "original path"]);
+    }
+}
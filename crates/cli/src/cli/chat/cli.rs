@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use clap::{
     Args,
@@ -17,14 +18,25 @@ pub struct Chat {
     /// prompt requests permissions to use a tool, unless --trust-all-tools is also used.
     #[arg(long)]
     pub no_interactive: bool,
-    /// Resumes the previous conversation from this directory.
+    /// Stay interactive even when STDIN is piped. The piped content becomes the first question
+    /// and later turns are read from the terminal.
+    #[arg(long, conflicts_with = "no_interactive")]
+    pub interactive: bool,
+    /// Resumes the previous conversation from this directory, or the most recent one otherwise.
     #[arg(short, long)]
     pub resume: bool,
     /// The first question to ask
     pub input: Option<String>,
+    /// Read the first question from a file instead. Cannot be combined with the positional input
+    /// or piped STDIN.
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
     /// Context profile to use
     #[arg(long = "profile")]
     pub profile: Option<String>,
+    /// AWS region to chat in for this session instead of the configured one, e.g. eu-central-1
+    #[arg(long)]
+    pub region: Option<String>,
     /// Allows the model to use any tool to run commands without asking for confirmation.
     #[arg(long)]
     pub trust_all_tools: bool,
@@ -32,6 +44,55 @@ pub struct Chat {
     /// '--trust-tools=fs_read,fs_write', trust no tools: '--trust-tools='
     #[arg(long, value_delimiter = ',', value_name = "TOOL_NAMES")]
     pub trust_tools: Option<Vec<String>>,
+    /// Path to a file of pre-approved tool names or tool use ids, one per line (lines starting
+    /// with '#' are ignored). Only consulted with --no-interactive; unlisted tools still fail.
+    #[arg(long, value_name = "PATH")]
+    pub approval_file: Option<PathBuf>,
+    /// Keep the per-session scratch directory used for tool artifacts after the chat exits.
+    #[arg(long)]
+    pub keep_scratch: bool,
+    /// Output format for --no-interactive. 'json' writes newline-delimited JSON events to STDOUT
+    /// instead of the rendered response.
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+    /// Stop after this many responses from the model, exiting with code 6. Guards scripted runs
+    /// against tool use loops that never finish.
+    #[arg(long, value_name = "N")]
+    pub max_turns: Option<usize>,
+    /// Print the request that would be sent for the question as JSON, including the context
+    /// files, history, and tool specs, and exit without sending it.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Hide the spinner, welcome banner, and tool status lines, printing only responses and
+    /// errors. Useful for demos and recordings.
+    #[arg(long)]
+    pub quiet: bool,
+    /// Print each response at once after it has been received instead of as it streams in. Can
+    /// also be turned off for all sessions with the chat.streaming setting.
+    #[arg(long)]
+    pub no_stream: bool,
+    /// List the context profiles, marking the default one, and exit without starting a session.
+    #[arg(long, conflicts_with = "profile")]
+    pub list_profiles: bool,
+    /// Save the model's responses to this file as they come in, in the format --replay plays
+    /// back. Attach it to bug reports to make them reproducible.
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Answer with the responses saved by --record, in order, instead of sending the questions to
+    /// Amazon Q. Works offline and without logging in.
+    #[arg(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+    /// Use the contents of this file as the system prompt for the whole session, in place of the
+    /// profile's.
+    #[arg(long, value_name = "PATH")]
+    pub system_prompt_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
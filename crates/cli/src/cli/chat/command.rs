@@ -16,6 +16,8 @@ use serde::{
     Serialize,
 };
 
+use crate::telemetry::FeedbackSentiment;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Command {
     Ask {
@@ -28,7 +30,15 @@ pub enum Command {
     Help,
     Issue {
         prompt: Option<String>,
+        /// Whether the issue includes recent turns of the transcript, unless `--no-transcript` was
+        /// given.
+        transcript: bool,
+    },
+    Feedback {
+        sentiment: FeedbackSentiment,
+        note: Option<String>,
     },
+    Replay,
     Quit,
     Profile {
         subcommand: ProfileSubcommand,
@@ -51,6 +61,17 @@ pub enum Command {
         subcommand: Option<PromptsSubcommand>,
     },
     Usage,
+    Tokens,
+    Stats,
+    Expand,
+    Retry,
+    Redo,
+    Fork {
+        name: String,
+    },
+    Branch {
+        name: Option<String>,
+    },
     Load {
         path: String,
     },
@@ -59,28 +80,102 @@ pub enum Command {
         force: bool,
     },
     Mcp,
+    Debug,
+    Rewind {
+        turn: usize,
+    },
+    Model {
+        name: Option<String>,
+    },
+    Export {
+        path: String,
+        format: ExportFormat,
+    },
+    Copy {
+        target: CopyTarget,
+    },
+    /// Writes the `index`th (1-based) code block of the last response to `path`.
+    Yank {
+        path: String,
+        index: usize,
+        force: bool,
+    },
+    /// Lists the turns of the transcript, or only those containing `query`.
+    History {
+        query: Option<String>,
+    },
+    /// Toggles printing raw response events, or turns it on or off if `enabled` is given.
+    Verbose {
+        enabled: Option<bool>,
+    },
+    /// Lists the aliases of the current profile without a `name`, otherwise defines `name` as
+    /// `expansion` or removes it if there's no `expansion`.
+    Alias {
+        name: Option<String>,
+        expansion: Option<String>,
+    },
+    /// Lists the starter prompts without a `name`, otherwise seeds the next prompt with the
+    /// template called `name`.
+    Templates {
+        name: Option<String>,
+    },
+    /// Shows the identity the session is signed in with.
+    Whoami,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyTarget {
+    /// The full text of the last assistant response.
+    Last,
+    /// The first code block of the last assistant response.
+    LastCode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProfileSubcommand {
     List,
+    Show,
     Create { name: String },
     Delete { name: String },
     Set { name: String },
     Rename { old_name: String, new_name: String },
+    Copy { from: String, to: String, force: bool },
+    Overlay { action: OverlayAction },
     Help,
 }
 
+/// What `/profile overlay` does with the profiles layered on the current one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverlayAction {
+    List,
+    Add { name: String },
+    Remove { name: String },
+}
+
 impl ProfileSubcommand {
     const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available commands</cyan!>
   <em>help</em>                <black!>Show an explanation for the profile command</black!>
   <em>list</em>                <black!>List all available profiles</black!>
+  <em>show</em>                <black!>Show the current profile's context, trusted tools, and model</black!>
   <em>create <<name>></em>       <black!>Create a new profile with the specified name</black!>
   <em>delete <<name>></em>       <black!>Delete the specified profile</black!>
   <em>set <<name>></em>          <black!>Switch to the specified profile</black!>
-  <em>rename <<old>> <<new>></em>  <black!>Rename a profile</black!>"};
+  <em>rename <<old>> <<new>></em>  <black!>Rename a profile</black!>
+  <em>copy <<from>> <<to>></em>    <black!>Copy a profile's context to a new profile</black!>
+    <black!>--force</black!>           <black!>Overwrite the destination profile if it exists</black!>
+  <em>overlay</em>             <black!>List the profiles layered on the current one</black!>
+  <em>overlay add <<name>></em>  <black!>Also use the context rules of another profile</black!>
+  <em>overlay rm <<name>></em>   <black!>Stop using an overlaid profile's context rules</black!>"};
+    const COPY_USAGE: &str = "/profile copy <from_profile_name> <to_profile_name> [--force]";
     const CREATE_USAGE: &str = "/profile create <profile_name>";
     const DELETE_USAGE: &str = "/profile delete <profile_name>";
+    const OVERLAY_USAGE: &str = "/profile overlay [add|rm <profile_name>]";
     const RENAME_USAGE: &str = "/profile rename <old_profile_name> <new_profile_name>";
     const SET_USAGE: &str = "/profile set <profile_name>";
 
@@ -102,6 +197,7 @@ Profiles allow you to organize and manage different sets of context files for di
 • The "default" profile is used when no profile is specified
 • You can switch between profiles to work on different projects
 • Each profile maintains its own set of context files
+• Overlays add another profile's context files to the current profile's, each file only once
 "#,
             Self::AVAILABLE_COMMANDS
         )
@@ -163,47 +259,83 @@ pub enum HooksSubcommand {
 pub enum ContextSubcommand {
     Show {
         expand: bool,
+        /// List the matched files largest first.
+        by_size: bool,
     },
     Add {
         global: bool,
         force: bool,
+        refresh: bool,
         paths: Vec<String>,
+        /// A profile other than the active one to add the rules to, from `--profile`.
+        profile: Option<String>,
     },
     Remove {
         global: bool,
         paths: Vec<String>,
+        profile: Option<String>,
     },
     Clear {
         global: bool,
+        profile: Option<String>,
+    },
+    /// Reports which rules no longer match anything and offers to remove them.
+    Validate,
+    /// Lists the rules that differ between the current profile and `profile`.
+    Diff {
+        profile: String,
     },
     Hooks {
         subcommand: Option<HooksSubcommand>,
     },
+    Prompt {
+        action: SystemPromptAction,
+    },
     Help,
 }
 
+/// What `/context prompt` does with the current profile's system prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemPromptAction {
+    Show,
+    Set { prompt: String },
+    Clear,
+}
+
 impl ContextSubcommand {
-    const ADD_USAGE: &str = "/context add [--global] [--force] <path1> [path2...]";
+    const ADD_USAGE: &str = "/context add [--global | --profile <name>] [--force] [--refresh] <path1> [path2...]";
     const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available commands</cyan!>
   <em>help</em>                           <black!>Show an explanation for the context command</black!>
 
-  <em>show [--expand]</em>                <black!>Display the context rule configuration and matched files</black!>
+  <em>show [--expand] [--by-size]</em>    <black!>Display the context rule configuration and matched files</black!>
                                           <black!>--expand: Print out each matched file's content, hook</black!> 
                                           <black!>          configurations and last conversation summary </black!>
+                                          <black!>--by-size: List the matched files largest first</black!>
 
-  <em>add [--global] [--force] <<paths...>></em>
-                                 <black!>Add context rules (filenames or glob patterns)</black!>
+  <em>add [--global] [--force] [--refresh] <<paths...>></em>
+                                 <black!>Add context rules (filenames, glob patterns or http(s) URLs)</black!>
                                  <black!>--global: Add to global rules (available in all profiles)</black!>
-                                 <black!>--force: Include even if matched files exceed size limits</black!>
+                                 <black!>--profile <<name>>: Add to another profile without switching to it</black!>
+                                 <black!>--force: Add even if nothing matches yet</black!>
+                                 <black!>--refresh: Fetch URLs that were already added again</black!>
 
-  <em>rm [--global] <<paths...>></em>       <black!>Remove specified rules from current profile</black!>
+  <em>rm [--global] <<paths...>></em>       <black!>Remove specified rules, or the rules numbered by show, from current profile</black!>
                                  <black!>--global: Remove specified rules globally</black!>
+                                 <black!>--profile <<name>>: Remove from another profile</black!>
 
   <em>clear [--global]</em>               <black!>Remove all rules from current profile</black!>
                                  <black!>--global: Remove global rules</black!>
+                                 <black!>--profile <<name>>: Remove all rules from another profile</black!>
+
+  <em>validate</em>                       <black!>Find rules whose files are missing or match nothing, and offer to remove them</black!>
+
+  <em>diff <<profile>></em>                 <black!>List the rules the current profile has (+) or lacks (-) compared to another profile</black!>
 
-  <em>hooks</em>                          <black!>View and manage context hooks</black!>"};
-    const CLEAR_USAGE: &str = "/context clear [--global]";
+  <em>hooks</em>                          <black!>View and manage context hooks</black!>
+
+  <em>prompt [set <<text>>|clear]</em>      <black!>Show, set or clear the current profile's system prompt</black!>"};
+    const CLEAR_USAGE: &str = "/context clear [--global | --profile <name>]";
+    const DIFF_USAGE: &str = "/context diff <profile>";
     const HOOKS_AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
   <em>hooks help</em>                         <black!>Show an explanation for context hooks commands</black!>
 
@@ -226,8 +358,20 @@ impl ContextSubcommand {
 
   <em>hooks disable-all [--global]</em>       <black!>Disable all existing context hooks</black!>
                                          <black!>--global: Disable all in global hooks</black!>"};
-    const REMOVE_USAGE: &str = "/context rm [--global] <path1> [path2...]";
-    const SHOW_USAGE: &str = "/context show [--expand]";
+    const PROMPT_USAGE: &str = "/context prompt [set <text>|clear]";
+    const REMOVE_USAGE: &str = "/context rm [--global | --profile <name>] <path1> [path2...]";
+    const SHOW_USAGE: &str = "/context show [--expand] [--by-size]";
+    const VALIDATE_USAGE: &str = "/context validate";
+
+    /// The profile given with `--profile`, for subcommands that can edit another profile.
+    pub fn target_profile(&self) -> Option<&str> {
+        match self {
+            Self::Add { profile, .. } | Self::Remove { profile, .. } | Self::Clear { profile, .. } => {
+                profile.as_deref()
+            },
+            _ => None,
+        }
+    }
 
     fn usage_msg(header: impl AsRef<str>) -> String {
         format!("{}\n\n{}", header.as_ref(), Self::AVAILABLE_COMMANDS)
@@ -254,6 +398,8 @@ the output as context to Amazon Q.
 
 <cyan!>Notes</cyan!>
 • You can add specific files or use glob patterns (e.g., "*.py", "src/**/*.js")
+• Brace patterns are expanded too (e.g., "docs/{{api,guide}}.md")
+• Patterns are matched again each time context is loaded, so new files are picked up
 • Profile rules apply only to the current profile
 • Global rules apply across all profiles
 • Context is preserved between chat sessions
@@ -402,7 +548,269 @@ pub struct PromptsGetParam {
     pub arguments: Option<Vec<String>>,
 }
 
+/// A command as listed by `/help`, see [Command::help_entries].
+#[derive(Debug, Clone, Copy)]
+pub struct HelpEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Subcommands or arguments, listed indented below the command.
+    pub subcommands: &'static [(&'static str, &'static str)],
+    /// Short description for the shortcuts in the welcome banner, if the command is listed there.
+    pub shortcut: Option<&'static str>,
+}
+
+const HELP_ENTRIES: &[HelpEntry] = &[
+    HelpEntry {
+        name: "/clear",
+        description: "Clear the conversation history",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/issue",
+        description: "Report an issue or make a feature request, --no-transcript leaves out recent turns",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/feedback",
+        description: "Rate the last response, e.g. /feedback down <what went wrong>",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/replay",
+        description: "Print the last response again, e.g. after the terminal got garbled",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/editor",
+        description: "Open $EDITOR (defaults to vi) to compose a prompt",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/help",
+        description: "Show this help dialogue",
+        subcommands: &[],
+        shortcut: Some("all commands"),
+    },
+    HelpEntry {
+        name: "/quit",
+        description: "Quit the application",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/compact",
+        description: "Summarize the conversation to free up context space",
+        subcommands: &[
+            ("help", "Show help for the compact command"),
+            ("[prompt]", "Optional custom prompt to guide summarization"),
+        ],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/tools",
+        description: "View and manage tools and permissions",
+        subcommands: &[
+            ("help", "Show an explanation for the trust command"),
+            ("trust", "Trust a specific tool or tools for the session"),
+            ("untrust", "Revert a tool or tools to per-request confirmation"),
+            ("trustall", "Trust all tools (equivalent to deprecated /acceptall)"),
+            ("reset", "Reset all tools to default permission levels"),
+        ],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/mcp",
+        description: "See mcp server loaded",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/profile",
+        description: "Manage profiles",
+        subcommands: &[
+            ("help", "Show profile help"),
+            ("list", "List profiles"),
+            ("show", "Show the current profile's context, trusted tools, and model"),
+            ("set", "Set the current profile"),
+            ("create", "Create a new profile"),
+            ("delete", "Delete a profile"),
+            ("rename", "Rename a profile"),
+            ("copy", "Copy a profile to a new profile"),
+            ("overlay", "Layer other profiles' context on the current profile"),
+        ],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/prompts",
+        description: "View and retrieve prompts",
+        subcommands: &[
+            ("help", "Show prompts help"),
+            ("list", "List or search available prompts"),
+            ("get", "Retrieve and send a prompt"),
+        ],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/context",
+        description: "Manage context files and hooks for the chat session",
+        subcommands: &[
+            ("help", "Show context help"),
+            (
+                "show",
+                "Display current context rules configuration [--expand] [--by-size]",
+            ),
+            ("add", "Add file(s) to context [--global] [--force]"),
+            ("rm", "Remove file(s) from context [--global]"),
+            ("clear", "Clear all files from current context [--global]"),
+            (
+                "validate",
+                "Find and remove context rules that no longer match any files",
+            ),
+            ("diff", "Compare the current profile's rules with another profile's"),
+            ("hooks", "View and manage context hooks"),
+            ("prompt", "Show, set or clear the current profile's system prompt"),
+        ],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/usage",
+        description: "Show current session's context window usage",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/whoami",
+        description: "Show the account, start URL and region this session is signed in with",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/tokens",
+        description: "Show the approximate token count of the next request",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/stats",
+        description: "Show message and tool counts and the elapsed time for this session",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/expand",
+        description: "Show the full output of the last tool that ran",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/load",
+        description: "Load conversation state from a JSON file",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/save",
+        description: "Save conversation state to a JSON file",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/debug",
+        description: "Show session details such as the conversation id and scratch directory",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/rewind",
+        description: "Discard every turn after the given turn number",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/copy",
+        description: "Copy the last response to the clipboard, or only its first code block with /copy code",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/yank",
+        description: "Write a code block of the last response to a file [n] [--force]",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/export",
+        description: "Export the transcript to a Markdown or JSON file [--format markdown|json]",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/retry",
+        description: "Send the last message again, discarding the previous response",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/redo",
+        description: "Run the tools that failed in the last turn again",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/fork",
+        description: "Continue on a new branch of the conversation, keeping the current one to return to",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/branch",
+        description: "List the conversation branches, or switch to one with /branch <name>",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/history",
+        description: "List the turns of this session, or search them with /history <query>",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/verbose",
+        description: "Toggle showing tool inputs and raw response events as they stream [on|off]",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/alias",
+        description: "List prompt macros, or define one that @name expands to [<name> <text>|--remove <name>]",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/templates",
+        description: "List starter prompts, or start your next prompt from one [use <name>]",
+        subcommands: &[],
+        shortcut: None,
+    },
+    HelpEntry {
+        name: "/model",
+        description: "List available models, or switch the model used for the rest of the session",
+        subcommands: &[],
+        shortcut: None,
+    },
+];
+
 impl Command {
+    /// The commands listed by `/help` and the welcome banner, in the order they're listed.
+    pub fn help_entries() -> &'static [HelpEntry] {
+        HELP_ENTRIES
+    }
+
     // Check if input is a common single-word command that should use slash prefix
     fn check_common_command(input: &str) -> Option<String> {
         let input_lower = input.trim().to_lowercase();
@@ -494,14 +902,27 @@ impl Command {
                     }
                 },
                 "issue" => {
-                    if parts.len() > 1 {
-                        Self::Issue {
-                            prompt: Some(parts[1..].join(" ")),
-                        }
-                    } else {
-                        Self::Issue { prompt: None }
+                    let transcript = parts.get(1) != Some(&"--no-transcript");
+                    let prompt_start = if transcript { 1 } else { 2 };
+                    Self::Issue {
+                        prompt: (parts.len() > prompt_start).then(|| parts[prompt_start..].join(" ")),
+                        transcript,
+                    }
+                },
+                "feedback" => {
+                    const USAGE: &str = "Usage: /feedback <up|down> [note]";
+                    let sentiment = match parts.get(1).copied() {
+                        Some("up" | "good" | "+") => FeedbackSentiment::Positive,
+                        Some("down" | "bad" | "-") => FeedbackSentiment::Negative,
+                        Some(other) => return Err(format!("Unknown rating '{}'. {}", other, USAGE)),
+                        None => return Err(USAGE.to_string()),
+                    };
+                    Self::Feedback {
+                        sentiment,
+                        note: (parts.len() > 2).then(|| parts[2..].join(" ")),
                     }
                 },
+                "replay" => Self::Replay,
                 "q" | "exit" | "quit" => Self::Quit,
                 "profile" => {
                     if parts.len() < 2 {
@@ -523,6 +944,9 @@ impl Command {
                         "list" => Self::Profile {
                             subcommand: ProfileSubcommand::List,
                         },
+                        "show" => Self::Profile {
+                            subcommand: ProfileSubcommand::Show,
+                        },
                         "create" => {
                             let name = parts.get(2);
                             match name {
@@ -558,6 +982,40 @@ impl Command {
                                 _ => usage_err!(ProfileSubcommand::RENAME_USAGE),
                             }
                         },
+                        "copy" => {
+                            let force = parts.contains(&"-f") || parts.contains(&"--force");
+                            let names = parts[2..]
+                                .iter()
+                                .filter(|part| !matches!(**part, "-f" | "--force"))
+                                .collect::<Vec<_>>();
+                            match names.as_slice() {
+                                [from, to] => Self::Profile {
+                                    subcommand: ProfileSubcommand::Copy {
+                                        from: (*from).to_string(),
+                                        to: (*to).to_string(),
+                                        force,
+                                    },
+                                },
+                                _ => usage_err!(ProfileSubcommand::COPY_USAGE),
+                            }
+                        },
+                        "overlay" => {
+                            let action = match (parts.get(2).map(|part| part.to_lowercase()), parts.get(3)) {
+                                (None, _) => OverlayAction::List,
+                                (Some(action), Some(name)) if action == "add" => OverlayAction::Add {
+                                    name: (*name).to_string(),
+                                },
+                                (Some(action), Some(name)) if action == "rm" || action == "remove" => {
+                                    OverlayAction::Remove {
+                                        name: (*name).to_string(),
+                                    }
+                                },
+                                _ => usage_err!(ProfileSubcommand::OVERLAY_USAGE),
+                            };
+                            Self::Profile {
+                                subcommand: ProfileSubcommand::Overlay { action },
+                            }
+                        },
                         "set" => {
                             let name = parts.get(2);
                             match name {
@@ -596,54 +1054,70 @@ impl Command {
                     match parts[1].to_lowercase().as_str() {
                         "show" => {
                             let mut expand = false;
+                            let mut by_size = false;
                             for part in &parts[2..] {
-                                if *part == "--expand" {
-                                    expand = true;
-                                } else {
-                                    usage_err!(ContextSubcommand::SHOW_USAGE);
+                                match *part {
+                                    "--expand" => expand = true,
+                                    "--by-size" => by_size = true,
+                                    _ => usage_err!(ContextSubcommand::SHOW_USAGE),
                                 }
                             }
                             Self::Context {
-                                subcommand: ContextSubcommand::Show { expand },
+                                subcommand: ContextSubcommand::Show { expand, by_size },
                             }
                         },
                         "add" => {
                             // Parse add command with paths and flags
                             let mut global = false;
                             let mut force = false;
+                            let mut refresh = false;
                             let mut paths = Vec::new();
 
-                            let args = match shlex::split(&parts[2..].join(" ")) {
+                            let mut args = match shlex::split(&parts[2..].join(" ")) {
                                 Some(args) => args,
                                 None => return Err("Failed to parse quoted arguments".to_string()),
                             };
+                            let Ok(profile) = take_profile_arg(&mut args) else {
+                                usage_err!(ContextSubcommand::ADD_USAGE);
+                            };
 
                             for arg in &args {
                                 if arg == "--global" {
                                     global = true;
                                 } else if arg == "--force" || arg == "-f" {
                                     force = true;
+                                } else if arg == "--refresh" {
+                                    refresh = true;
                                 } else {
                                     paths.push(arg.to_string());
                                 }
                             }
 
-                            if paths.is_empty() {
+                            if paths.is_empty() || (global && profile.is_some()) {
                                 usage_err!(ContextSubcommand::ADD_USAGE);
                             }
 
                             Self::Context {
-                                subcommand: ContextSubcommand::Add { global, force, paths },
+                                subcommand: ContextSubcommand::Add {
+                                    global,
+                                    force,
+                                    refresh,
+                                    paths,
+                                    profile,
+                                },
                             }
                         },
                         "rm" => {
                             // Parse rm command with paths and --global flag
                             let mut global = false;
                             let mut paths = Vec::new();
-                            let args = match shlex::split(&parts[2..].join(" ")) {
+                            let mut args = match shlex::split(&parts[2..].join(" ")) {
                                 Some(args) => args,
                                 None => return Err("Failed to parse quoted arguments".to_string()),
                             };
+                            let Ok(profile) = take_profile_arg(&mut args) else {
+                                usage_err!(ContextSubcommand::REMOVE_USAGE);
+                            };
 
                             for arg in &args {
                                 if arg == "--global" {
@@ -653,20 +1127,24 @@ impl Command {
                                 }
                             }
 
-                            if paths.is_empty() {
+                            if paths.is_empty() || (global && profile.is_some()) {
                                 usage_err!(ContextSubcommand::REMOVE_USAGE);
                             }
 
                             Self::Context {
-                                subcommand: ContextSubcommand::Remove { global, paths },
+                                subcommand: ContextSubcommand::Remove { global, paths, profile },
                             }
                         },
                         "clear" => {
-                            // Parse clear command with optional --global flag
+                            // Parse clear command with optional --global or --profile flag
                             let mut global = false;
+                            let mut args = parts[2..].iter().map(|part| part.to_string()).collect::<Vec<_>>();
+                            let Ok(profile) = take_profile_arg(&mut args) else {
+                                usage_err!(ContextSubcommand::CLEAR_USAGE);
+                            };
 
-                            for part in &parts[2..] {
-                                if *part == "--global" {
+                            for arg in &args {
+                                if arg == "--global" && profile.is_none() {
                                     global = true;
                                 } else {
                                     usage_err!(ContextSubcommand::CLEAR_USAGE);
@@ -674,12 +1152,47 @@ impl Command {
                             }
 
                             Self::Context {
-                                subcommand: ContextSubcommand::Clear { global },
+                                subcommand: ContextSubcommand::Clear { global, profile },
+                            }
+                        },
+                        "diff" => {
+                            let [_, _, profile] = parts.as_slice() else {
+                                usage_err!(ContextSubcommand::DIFF_USAGE);
+                            };
+                            Self::Context {
+                                subcommand: ContextSubcommand::Diff {
+                                    profile: (*profile).to_string(),
+                                },
+                            }
+                        },
+                        "validate" => {
+                            if parts.len() > 2 {
+                                usage_err!(ContextSubcommand::VALIDATE_USAGE);
+                            }
+                            Self::Context {
+                                subcommand: ContextSubcommand::Validate,
                             }
                         },
                         "help" => Self::Context {
                             subcommand: ContextSubcommand::Help,
                         },
+                        "prompt" => {
+                            let action = match parts.get(2).map(|part| part.to_lowercase()).as_deref() {
+                                None => SystemPromptAction::Show,
+                                Some("clear") if parts.len() == 3 => SystemPromptAction::Clear,
+                                Some("set") if parts.len() > 3 => {
+                                    // Keep the prompt as it was written, including any newlines.
+                                    let prompt = command
+                                        .split_once(parts[2])
+                                        .map_or_else(|| parts[3..].join(" "), |(_, prompt)| prompt.trim().to_string());
+                                    SystemPromptAction::Set { prompt }
+                                },
+                                _ => usage_err!(ContextSubcommand::PROMPT_USAGE),
+                            };
+                            Self::Context {
+                                subcommand: ContextSubcommand::Prompt { action },
+                            }
+                        },
                         "hooks" => {
                             if parts.get(2).is_none() {
                                 return Ok(Self::Context {
@@ -819,6 +1332,23 @@ impl Command {
                     }
                 },
                 "usage" => Self::Usage,
+                "whoami" => Self::Whoami,
+                "tokens" => Self::Tokens,
+                "retry" => Self::Retry,
+                "redo" => Self::Redo,
+                "stats" => Self::Stats,
+                "expand" => Self::Expand,
+                "fork" => {
+                    let Some(name) = parts.get(1) else {
+                        return Err("branch name is required".to_string());
+                    };
+                    Self::Fork {
+                        name: (*name).to_string(),
+                    }
+                },
+                "branch" => Self::Branch {
+                    name: parts.get(1).map(|name| (*name).to_string()),
+                },
                 "load" => {
                     let Some(path) = parts.get(1) else {
                         return Err("path is required".to_string());
@@ -839,6 +1369,145 @@ impl Command {
                     Self::Save { path, force }
                 },
                 "mcp" => Self::Mcp,
+                "debug" => Self::Debug,
+                "history" => Self::History {
+                    query: Some(command.trim_start()[parts[0].len()..].trim())
+                        .filter(|query| !query.is_empty())
+                        .map(str::to_string),
+                },
+                "verbose" => Self::Verbose {
+                    enabled: match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                        None => None,
+                        Some("on") => Some(true),
+                        Some("off") => Some(false),
+                        Some(other) => {
+                            return Err(format!("Unknown argument '{}'. Usage: /verbose [on|off]", other));
+                        },
+                    },
+                },
+                "alias" => {
+                    const USAGE: &str = "Usage: /alias [<name> <text> | --remove <name>]";
+                    match parts.get(1).copied() {
+                        None => Self::Alias {
+                            name: None,
+                            expansion: None,
+                        },
+                        Some("--remove") => match parts.get(2..) {
+                            Some([name]) => Self::Alias {
+                                name: Some((*name).to_string()),
+                                expansion: None,
+                            },
+                            _ => return Err(USAGE.to_string()),
+                        },
+                        Some(name) if !super::context::is_valid_alias_name(name) => {
+                            return Err(format!(
+                                "Invalid alias name '{}', only letters, digits, '_' and '-' are allowed. {}",
+                                name, USAGE
+                            ));
+                        },
+                        Some(name) => {
+                            // Keep the text as it was written, including any newlines.
+                            let expansion = command.trim_start()[parts[0].len()..].trim_start()[name.len()..].trim();
+                            if expansion.is_empty() {
+                                return Err(format!("text to expand to is required. {}", USAGE));
+                            }
+                            Self::Alias {
+                                name: Some(name.to_string()),
+                                expansion: Some(expansion.to_string()),
+                            }
+                        },
+                    }
+                },
+                "templates" => {
+                    const USAGE: &str = "Usage: /templates [use <name>]";
+                    match (parts.get(1).copied(), parts.get(2..)) {
+                        (None, _) => Self::Templates { name: None },
+                        (Some("use"), Some([name])) => Self::Templates {
+                            name: Some((*name).to_string()),
+                        },
+                        (Some("use"), _) => return Err(format!("template name is required. {}", USAGE)),
+                        (Some(other), _) => return Err(format!("Unknown subcommand '{}'. {}", other, USAGE)),
+                    }
+                },
+                "rewind" => {
+                    let Some(turn) = parts.get(1) else {
+                        return Err("turn number is required. Usage: /rewind <n>".to_string());
+                    };
+                    match turn.parse::<usize>() {
+                        Ok(turn) => Self::Rewind { turn },
+                        Err(_) => return Err(format!("Invalid turn number '{}'. Usage: /rewind <n>", turn)),
+                    }
+                },
+                "model" => Self::Model {
+                    name: parts.get(1).map(|name| (*name).to_string()),
+                },
+                "copy" => match parts.get(1).copied() {
+                    None => Self::Copy {
+                        target: CopyTarget::Last,
+                    },
+                    Some("code") => Self::Copy {
+                        target: CopyTarget::LastCode,
+                    },
+                    Some(other) => {
+                        return Err(format!("Unknown copy target '{}'. Usage: /copy [code]", other));
+                    },
+                },
+                "yank" => {
+                    const USAGE: &str = "Usage: /yank <path> [n] [--force]";
+                    let mut path = None;
+                    let mut index = None;
+                    let mut force = false;
+                    for arg in &parts[1..] {
+                        match *arg {
+                            "-f" | "--force" => force = true,
+                            arg if path.is_none() => path = Some(arg.to_string()),
+                            arg if index.is_none() => match arg.parse::<usize>() {
+                                Ok(n) if n > 0 => index = Some(n),
+                                _ => return Err(format!("Invalid code block number '{}'. {}", arg, USAGE)),
+                            },
+                            _ => return Err(USAGE.to_string()),
+                        }
+                    }
+                    let Some(path) = path else {
+                        return Err(format!("path is required. {}", USAGE));
+                    };
+                    Self::Yank {
+                        path,
+                        index: index.unwrap_or(1),
+                        force,
+                    }
+                },
+                "export" => {
+                    const USAGE: &str = "Usage: /export <path> [--format markdown|json]";
+                    let mut path = None;
+                    let mut format = None;
+                    let mut args = parts[1..].iter();
+                    while let Some(arg) = args.next() {
+                        match *arg {
+                            "--format" => {
+                                format = match args.next().map(|f| f.to_lowercase()).as_deref() {
+                                    Some("markdown" | "md") => Some(ExportFormat::Markdown),
+                                    Some("json") => Some(ExportFormat::Json),
+                                    Some(other) => {
+                                        return Err(format!("Unknown export format '{}'. {}", other, USAGE));
+                                    },
+                                    None => return Err(format!("--format requires a value. {}", USAGE)),
+                                };
+                            },
+                            arg if path.is_none() => path = Some(arg.to_string()),
+                            _ => return Err(USAGE.to_string()),
+                        }
+                    }
+                    let Some(path) = path else {
+                        return Err(format!("path is required. {}", USAGE));
+                    };
+                    let format = format.unwrap_or(if path.ends_with(".json") {
+                        ExportFormat::Json
+                    } else {
+                        ExportFormat::Markdown
+                    });
+                    Self::Export { path, format }
+                },
                 unknown_command => {
                     let looks_like_path = {
                         let after_slash_command_str = parts[1..].join(" ");
@@ -902,6 +1571,19 @@ impl Command {
     }
 }
 
+/// Removes `--profile <name>` from `args`, returning the name. Errors if the name is missing.
+fn take_profile_arg(args: &mut Vec<String>) -> Result<Option<String>, ()> {
+    let Some(index) = args.iter().position(|arg| arg == "--profile") else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        return Err(());
+    }
+    let name = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(name))
+}
+
 fn parse_input_to_prompts_get_command(command: &str) -> Result<PromptsGetCommand, String> {
     let input = shell_words::split(command).map_err(|e| format!("Error splitting command for prompts: {:?}", e))?;
     let mut iter = input.into_iter();
@@ -919,6 +1601,41 @@ fn parse_input_to_prompts_get_command(command: &str) -> Result<PromptsGetCommand
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_help_entries_are_completed() {
+        for entry in Command::help_entries() {
+            assert!(
+                super::super::prompt::COMMANDS.contains(&entry.name),
+                "{} is missing from the completions",
+                entry.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_completed_subcommands_are_listed() {
+        for command in super::super::prompt::COMMANDS {
+            let mut words = command.split_whitespace();
+            let (Some(name), Some(subcommand)) = (words.next(), words.next()) else {
+                continue;
+            };
+            if subcommand.starts_with("--") {
+                continue;
+            }
+            let entry = Command::help_entries()
+                .iter()
+                .find(|entry| entry.name == name)
+                .unwrap_or_else(|| panic!("{name} is missing from /help"));
+            // Commands without a list of subcommands explain them in their description instead.
+            let listed = entry
+                .subcommands
+                .iter()
+                .any(|(listed, _)| listed.split_whitespace().next() == Some(subcommand))
+                || (entry.subcommands.is_empty() && entry.description.contains(subcommand));
+            assert!(listed, "{name} {subcommand} is missing from /help");
+        }
+    }
+
     #[test]
     fn test_command_parse() {
         let mut stdout = std::io::stdout();
@@ -953,6 +1670,7 @@ mod tests {
                 compact!(Some("custom prompt".to_string()), true),
             ),
             ("/profile list", profile!(ProfileSubcommand::List)),
+            ("/profile show", profile!(ProfileSubcommand::Show)),
             (
                 "/profile create new_profile",
                 profile!(ProfileSubcommand::Create {
@@ -970,6 +1688,44 @@ mod tests {
                     new_name: "new".to_string(),
                 }),
             ),
+            (
+                "/profile copy a b",
+                profile!(ProfileSubcommand::Copy {
+                    from: "a".to_string(),
+                    to: "b".to_string(),
+                    force: false,
+                }),
+            ),
+            (
+                "/profile overlay",
+                profile!(ProfileSubcommand::Overlay {
+                    action: OverlayAction::List,
+                }),
+            ),
+            (
+                "/profile overlay add team",
+                profile!(ProfileSubcommand::Overlay {
+                    action: OverlayAction::Add {
+                        name: "team".to_string(),
+                    },
+                }),
+            ),
+            (
+                "/profile overlay rm team",
+                profile!(ProfileSubcommand::Overlay {
+                    action: OverlayAction::Remove {
+                        name: "team".to_string(),
+                    },
+                }),
+            ),
+            (
+                "/profile copy --force a b",
+                profile!(ProfileSubcommand::Copy {
+                    from: "a".to_string(),
+                    to: "b".to_string(),
+                    force: true,
+                }),
+            ),
             (
                 "/profile set p",
                 profile!(ProfileSubcommand::Set { name: "p".to_string() }),
@@ -978,17 +1734,35 @@ mod tests {
                 "/profile set p",
                 profile!(ProfileSubcommand::Set { name: "p".to_string() }),
             ),
-            ("/context show", context!(ContextSubcommand::Show { expand: false })),
+            (
+                "/context show",
+                context!(ContextSubcommand::Show {
+                    expand: false,
+                    by_size: false
+                }),
+            ),
             (
                 "/context show --expand",
-                context!(ContextSubcommand::Show { expand: true }),
+                context!(ContextSubcommand::Show {
+                    expand: true,
+                    by_size: false
+                }),
+            ),
+            (
+                "/context show --by-size --expand",
+                context!(ContextSubcommand::Show {
+                    expand: true,
+                    by_size: true
+                }),
             ),
             (
                 "/context add p1 p2",
                 context!(ContextSubcommand::Add {
                     global: false,
                     force: false,
-                    paths: vec!["p1".into(), "p2".into()]
+                    refresh: false,
+                    paths: vec!["p1".into(), "p2".into()],
+                    profile: None,
                 }),
             ),
             (
@@ -996,35 +1770,208 @@ mod tests {
                 context!(ContextSubcommand::Add {
                     global: true,
                     force: true,
-                    paths: vec!["p1".into(), "p2".into()]
+                    refresh: false,
+                    paths: vec!["p1".into(), "p2".into()],
+                    profile: None,
+                }),
+            ),
+            (
+                "/context add --refresh https://example.com/guide.md",
+                context!(ContextSubcommand::Add {
+                    global: false,
+                    force: false,
+                    refresh: true,
+                    paths: vec!["https://example.com/guide.md".into()],
+                    profile: None,
                 }),
             ),
             (
                 "/context rm p1 p2",
                 context!(ContextSubcommand::Remove {
                     global: false,
-                    paths: vec!["p1".into(), "p2".into()]
+                    paths: vec!["p1".into(), "p2".into()],
+                    profile: None,
                 }),
             ),
             (
                 "/context rm --global p1 p2",
                 context!(ContextSubcommand::Remove {
                     global: true,
-                    paths: vec!["p1".into(), "p2".into()]
+                    paths: vec!["p1".into(), "p2".into()],
+                    profile: None,
+                }),
+            ),
+            (
+                "/context add --profile work p1",
+                context!(ContextSubcommand::Add {
+                    global: false,
+                    force: false,
+                    refresh: false,
+                    paths: vec!["p1".into()],
+                    profile: Some("work".into()),
+                }),
+            ),
+            (
+                "/context rm p1 --profile work",
+                context!(ContextSubcommand::Remove {
+                    global: false,
+                    paths: vec!["p1".into()],
+                    profile: Some("work".into()),
+                }),
+            ),
+            (
+                "/context clear --profile work",
+                context!(ContextSubcommand::Clear {
+                    global: false,
+                    profile: Some("work".into()),
+                }),
+            ),
+            (
+                "/context clear",
+                context!(ContextSubcommand::Clear {
+                    global: false,
+                    profile: None,
                 }),
             ),
-            ("/context clear", context!(ContextSubcommand::Clear { global: false })),
             (
                 "/context clear --global",
-                context!(ContextSubcommand::Clear { global: true }),
+                context!(ContextSubcommand::Clear {
+                    global: true,
+                    profile: None,
+                }),
+            ),
+            ("/context validate", context!(ContextSubcommand::Validate)),
+            (
+                "/context diff shared",
+                context!(ContextSubcommand::Diff {
+                    profile: "shared".to_string(),
+                }),
             ),
-            ("/issue", Command::Issue { prompt: None }),
+            (
+                "/context prompt",
+                context!(ContextSubcommand::Prompt {
+                    action: SystemPromptAction::Show
+                }),
+            ),
+            (
+                "/context prompt set Answer  tersely.\nUse British spelling.",
+                context!(ContextSubcommand::Prompt {
+                    action: SystemPromptAction::Set {
+                        prompt: "Answer  tersely.\nUse British spelling.".to_string()
+                    }
+                }),
+            ),
+            (
+                "/context prompt clear",
+                context!(ContextSubcommand::Prompt {
+                    action: SystemPromptAction::Clear
+                }),
+            ),
+            ("/debug", Command::Debug),
+            ("/history", Command::History { query: None }),
+            ("/history read  file", Command::History {
+                query: Some("read  file".to_string()),
+            }),
+            ("/ history foo", Command::History {
+                query: Some("foo".to_string()),
+            }),
+            ("/\u{3000}\u{3000}\u{3000}history foo", Command::History {
+                query: Some("foo".to_string()),
+            }),
+            ("/verbose", Command::Verbose { enabled: None }),
+            ("/verbose on", Command::Verbose { enabled: Some(true) }),
+            ("/verbose OFF", Command::Verbose { enabled: Some(false) }),
+            ("/alias", Command::Alias {
+                name: None,
+                expansion: None,
+            }),
+            ("/alias rust Use Rust 2021 idioms,\n  no unwrap", Command::Alias {
+                name: Some("rust".to_string()),
+                expansion: Some("Use Rust 2021 idioms,\n  no unwrap".to_string()),
+            }),
+            ("/alias --remove rust", Command::Alias {
+                name: Some("rust".to_string()),
+                expansion: None,
+            }),
+            ("/templates", Command::Templates { name: None }),
+            ("/templates use explain", Command::Templates {
+                name: Some("explain".to_string()),
+            }),
+            ("/rewind 2", Command::Rewind { turn: 2 }),
+            ("/tokens", Command::Tokens),
+            ("/copy", Command::Copy {
+                target: CopyTarget::Last,
+            }),
+            ("/copy code", Command::Copy {
+                target: CopyTarget::LastCode,
+            }),
+            ("/yank main.rs", Command::Yank {
+                path: "main.rs".to_string(),
+                index: 1,
+                force: false,
+            }),
+            ("/yank src/main.rs 2 --force", Command::Yank {
+                path: "src/main.rs".to_string(),
+                index: 2,
+                force: true,
+            }),
+            ("/retry", Command::Retry),
+            ("/redo", Command::Redo),
+            ("/stats", Command::Stats),
+            ("/expand", Command::Expand),
+            ("/whoami", Command::Whoami),
+            ("/fork idea", Command::Fork {
+                name: "idea".to_string(),
+            }),
+            ("/branch", Command::Branch { name: None }),
+            ("/branch main", Command::Branch {
+                name: Some("main".to_string()),
+            }),
+            ("/model", Command::Model { name: None }),
+            ("/export chat.md", Command::Export {
+                path: "chat.md".to_string(),
+                format: ExportFormat::Markdown,
+            }),
+            ("/export chat.json", Command::Export {
+                path: "chat.json".to_string(),
+                format: ExportFormat::Json,
+            }),
+            ("/export chat.txt --format json", Command::Export {
+                path: "chat.txt".to_string(),
+                format: ExportFormat::Json,
+            }),
+            ("/model claude-3.7-sonnet", Command::Model {
+                name: Some("claude-3.7-sonnet".to_string()),
+            }),
+            ("/issue", Command::Issue {
+                prompt: None,
+                transcript: true,
+            }),
             ("/issue there was an error in the chat", Command::Issue {
                 prompt: Some("there was an error in the chat".to_string()),
+                transcript: true,
             }),
             ("/issue \"there was an error in the chat\"", Command::Issue {
                 prompt: Some("\"there was an error in the chat\"".to_string()),
+                transcript: true,
+            }),
+            ("/issue --no-transcript", Command::Issue {
+                prompt: None,
+                transcript: false,
+            }),
+            ("/issue --no-transcript chat crashed", Command::Issue {
+                prompt: Some("chat crashed".to_string()),
+                transcript: false,
+            }),
+            ("/feedback up", Command::Feedback {
+                sentiment: FeedbackSentiment::Positive,
+                note: None,
+            }),
+            ("/feedback - wrong flag for tar", Command::Feedback {
+                sentiment: FeedbackSentiment::Negative,
+                note: Some("wrong flag for tar".to_string()),
             }),
+            ("/replay", Command::Replay),
             (
                 "/context hooks",
                 context!(ContextSubcommand::Hooks { subcommand: None }),
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use super::token_counter::TokenCounter;
 
 // These limits are the internal undocumented values from the service for each item
@@ -26,3 +28,38 @@ pub const MAX_NUMBER_OF_IMAGES_PER_REQUEST: usize = 10;
 
 /// In bytes - 10 MB
 pub const MAX_IMAGE_SIZE: usize = 10 * 1024 * 1024;
+
+/// In bytes - individual context files larger than this are skipped, unless overridden by the
+/// `chat.maxContextFileBytes` setting.
+pub const DEFAULT_MAX_CONTEXT_FILE_BYTES: usize = 256 * 1024;
+
+/// Seconds a tool may run before it is cancelled, unless overridden by the `chat.toolTimeoutSecs`
+/// setting or a per-tool `timeoutSecs` in `chat.toolPolicy`. Zero means no limit.
+pub const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 600;
+
+/// Times a message is sent again after a transient failure, unless overridden by the
+/// `chat.maxRetries` setting.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Delay before the first retry of a message, doubled for each retry after it up to
+/// [RETRY_MAX_DELAY].
+pub const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between retries of a message, however many retries `chat.maxRetries` allows.
+pub const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Number of diff lines shown when previewing an `fs_write` edit, unless overridden by the
+/// `chat.diffPreviewMaxLines` setting.
+pub const DEFAULT_DIFF_PREVIEW_MAX_LINES: usize = 50;
+
+/// Number of recent user and assistant message pairs kept by `/compact`, unless overridden by the
+/// `chat.compactKeepTurns` setting.
+pub const DEFAULT_COMPACT_KEEP_TURNS: usize = 1;
+
+/// Models selectable with `/model`, as pairs of display name and the model identifier sent to the
+/// backend.
+pub const MODEL_OPTIONS: &[(&str, &str)] = &[
+    ("claude-4-sonnet", "CLAUDE_SONNET_4_20250514_V1_0"),
+    ("claude-3.7-sonnet", "CLAUDE_3_7_SONNET_20250219_V1_0"),
+    ("claude-3.5-sonnet", "CLAUDE_3_5_SONNET_20241022_V2_0"),
+];
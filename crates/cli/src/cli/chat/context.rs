@@ -1,4 +1,9 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{
+    BTreeMap,
+    HashMap,
+    HashSet,
+};
 use std::io::Write;
 use std::path::{
     Path,
@@ -11,24 +16,50 @@ use eyre::{
     eyre,
 };
 use glob::glob;
+use ignore::gitignore::{
+    Gitignore,
+    GitignoreBuilder,
+};
 use regex::Regex;
 use serde::{
     Deserialize,
     Serialize,
 };
-use tracing::debug;
+use sha2::{
+    Digest,
+    Sha256,
+};
+use tracing::{
+    debug,
+    warn,
+};
 
-use super::consts::CONTEXT_FILES_MAX_SIZE;
+use super::consts::{
+    CONTEXT_FILES_MAX_SIZE,
+    DEFAULT_MAX_CONTEXT_FILE_BYTES,
+};
 use super::hooks::{
     Hook,
     HookExecutor,
 };
 use super::util::drop_matched_context_files;
+use super::util::redact::Redactor;
 use crate::platform::Context;
 use crate::util::directories;
 
 pub const AMAZONQ_FILENAME: &str = "AmazonQ.md";
 
+/// File in the working directory listing, in gitignore syntax, the files that globs and
+/// directories in context rules should not expand to.
+pub const QIGNORE_FILENAME: &str = ".qignore";
+
+/// Number of leading bytes checked for null bytes when deciding whether a file is binary.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Name of the directory within a profile's directory where the content of URL context rules is
+/// cached.
+const URL_CACHE_DIR: &str = "url_cache";
+
 /// Configuration for context files, containing paths to include in the context.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(default)]
@@ -38,6 +69,16 @@ pub struct ContextConfig {
 
     /// Map of Hook Name to [`Hook`]. The hook name serves as the hook's ID.
     pub hooks: HashMap<String, Hook>,
+
+    /// Instructions sent ahead of the rest of the context, set with `/context prompt set`. Only
+    /// used from profile configurations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+
+    /// Prompt macros defined with `/alias`, expanded wherever `@name` appears in the user's input.
+    /// Only used from profile configurations.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
 }
 
 #[allow(dead_code)]
@@ -50,6 +91,27 @@ pub struct ContextManager {
 
     max_context_files_size: usize,
 
+    /// Files larger than this many bytes are skipped instead of being added to the context.
+    #[serde(skip, default = "default_max_context_file_bytes")]
+    pub max_context_file_bytes: usize,
+
+    /// Whether the working directory's `.gitignore` is honored alongside [QIGNORE_FILENAME].
+    #[serde(skip)]
+    pub respect_gitignore: bool,
+
+    /// Most files sent as context, from `chat.maxContextFiles`. Files past it are left out in
+    /// [Self::context_file_order].
+    #[serde(skip)]
+    pub max_context_files: Option<usize>,
+
+    /// Which files are kept when more than [Self::max_context_files] match.
+    #[serde(skip)]
+    pub context_file_order: ContextFileOrder,
+
+    /// Masks secrets in context files before they're sent, unless `chat.redactSecrets` is off.
+    #[serde(skip)]
+    pub redactor: Option<Redactor>,
+
     /// Global context configuration that applies to all profiles.
     pub global_config: ContextConfig,
 
@@ -59,8 +121,31 @@ pub struct ContextManager {
     /// Context configuration for the current profile.
     pub profile_config: ContextConfig,
 
+    /// Profiles layered on top of the current one with `/profile overlay add`, in the order they
+    /// were added. See [Self::get_context_files] for how they're merged.
+    #[serde(default)]
+    pub overlays: Vec<Overlay>,
+
     #[serde(skip)]
     pub hook_executor: HookExecutor,
+
+    /// The rules as numbered by the last `/context show`, used to resolve `/context rm <number>`.
+    #[serde(skip)]
+    shown_paths: Option<ShownPaths>,
+}
+
+/// A profile whose context rules are used alongside the current profile's.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Overlay {
+    pub name: String,
+    pub config: ContextConfig,
+}
+
+#[derive(Debug, Clone)]
+struct ShownPaths {
+    profile: String,
+    global: Vec<String>,
+    profile_paths: Vec<String>,
 }
 
 #[allow(dead_code)]
@@ -93,10 +178,17 @@ impl ContextManager {
         Ok(Self {
             ctx,
             max_context_files_size,
+            max_context_file_bytes: DEFAULT_MAX_CONTEXT_FILE_BYTES,
+            respect_gitignore: false,
+            max_context_files: None,
+            context_file_order: ContextFileOrder::default(),
+            redactor: None,
             global_config,
             current_profile,
             profile_config,
+            overlays: Vec::new(),
             hook_executor: HookExecutor::new(),
+            shown_paths: None,
         })
     }
 
@@ -133,40 +225,63 @@ impl ContextManager {
     pub async fn reload_config(&mut self) -> Result<()> {
         self.global_config = load_global_config(&self.ctx).await?;
         self.profile_config = load_profile_config(&self.ctx, &self.current_profile).await?;
+        for overlay in &mut self.overlays {
+            overlay.config = load_profile_config(&self.ctx, &overlay.name).await?;
+        }
         Ok(())
     }
 
     /// Add paths to the context configuration.
     ///
+    /// Paths may contain glob (`src/**/*.rs`) and brace (`docs/{api,guide}.md`) patterns. These
+    /// are stored as written and only expanded when the context files are collected, so files
+    /// created after the pattern was added are still picked up.
+    ///
     /// # Arguments
     /// * `paths` - List of paths to add
     /// * `global` - If true, add to global configuration; otherwise, add to current profile
     ///   configuration
-    /// * `force` - If true, skip validation that the path exists
+    /// * `force` - If true, add paths even if they don't match any files yet
+    /// * `refresh` - If true, URLs that were already added are fetched again instead of being
+    ///   rejected as duplicates
+    ///
+    /// URLs are always fetched when added, and the content is cached for later prompts.
     ///
     /// # Returns
-    /// A Result indicating success or an error
-    pub async fn add_paths(&mut self, paths: Vec<String>, global: bool, force: bool) -> Result<()> {
+    /// The paths that were added with `force` but don't currently match any files
+    pub async fn add_paths(
+        &mut self,
+        paths: Vec<String>,
+        global: bool,
+        force: bool,
+        refresh: bool,
+    ) -> Result<Vec<String>> {
         let mut all_paths = self.global_config.paths.clone();
         all_paths.append(&mut self.profile_config.paths.clone());
-
-        // Validate paths exist before adding them
-        if !force {
+        let options = PathOptions {
+            refresh,
+            ..self.path_options(true)?
+        };
+
+        // Check each path to make sure it exists or matches at least one file
+        let mut unmatched = Vec::new();
+        for path in &paths {
+            // We're using a temporary context_files vector just for validation
+            // Validating ensures we error if glob patterns don't match any files
             let mut context_files = Vec::new();
-
-            // Check each path to make sure it exists or matches at least one file
-            for path in &paths {
-                // We're using a temporary context_files vector just for validation
-                // Pass is_validation=true to ensure we error if glob patterns don't match any files
-                match process_path(&self.ctx, path, &mut context_files, true).await {
-                    Ok(_) => {}, // Path is valid
-                    Err(e) => return Err(eyre!("Invalid path '{}': {}. Use --force to add anyway.", path, e)),
+            if let Err(e) = process_path(&self.ctx, path, &options, &mut context_files, &mut Vec::new()).await {
+                if !force {
+                    return Err(eyre!("Invalid path '{}': {}. Use --force to add anyway.", path, e));
                 }
+                unmatched.push(path.clone());
             }
         }
 
         // Add each path, checking for duplicates
         for path in paths {
+            if refresh && is_url(&path) && all_paths.contains(&path) {
+                continue;
+            }
             if all_paths.contains(&path) {
                 return Err(eyre!("Rule '{}' already exists.", path));
             }
@@ -180,7 +295,7 @@ impl ContextManager {
         // Save the updated configuration
         self.save_config(global).await?;
 
-        Ok(())
+        Ok(unmatched)
     }
 
     /// Remove paths from the context configuration.
@@ -219,6 +334,66 @@ impl ContextManager {
         Ok(())
     }
 
+    /// Remembers the rules as they are currently numbered, so that [Self::remove_paths_by_index]
+    /// removes the rules the user saw.
+    pub fn mark_paths_shown(&mut self) {
+        self.shown_paths = Some(ShownPaths {
+            profile: self.current_profile.clone(),
+            global: self.global_config.paths.clone(),
+            profile_paths: self.profile_config.paths.clone(),
+        });
+    }
+
+    /// Returns the 1-based rule numbers in `args` if they should be removed by number rather than
+    /// by path, i.e. every argument is a number and none of them is itself a rule.
+    pub fn rule_numbers(&self, args: &[String], global: bool) -> Option<Vec<usize>> {
+        let paths = if global {
+            &self.global_config.paths
+        } else {
+            &self.profile_config.paths
+        };
+        if args.iter().any(|arg| paths.contains(arg)) {
+            return None;
+        }
+        args.iter().map(|arg| arg.parse().ok()).collect()
+    }
+
+    /// Remove rules by the 1-based numbers they were shown with by the last `/context show`.
+    ///
+    /// Nothing is removed if a number is out of range, or if the rules changed since they were
+    /// shown, since the numbers may then refer to different rules.
+    ///
+    /// # Returns
+    /// The rules that were removed
+    pub async fn remove_paths_by_index(&mut self, numbers: &[usize], global: bool) -> Result<Vec<String>> {
+        let Some(shown) = &self.shown_paths else {
+            return Err(eyre!("Run /context show to see the rule numbers first"));
+        };
+        let (shown_paths, current_paths) = if global {
+            (&shown.global, &self.global_config.paths)
+        } else {
+            (&shown.profile_paths, &self.profile_config.paths)
+        };
+        if shown.profile != self.current_profile || shown_paths != current_paths {
+            return Err(eyre!(
+                "The context rules changed since they were shown. Run /context show to see the current numbers"
+            ));
+        }
+
+        let paths = numbers
+            .iter()
+            .map(|&n| {
+                n.checked_sub(1)
+                    .and_then(|i| shown_paths.get(i))
+                    .cloned()
+                    .ok_or_else(|| eyre!("There is no rule number {n}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.remove_paths(paths.clone(), global).await?;
+        self.mark_paths_shown();
+        Ok(paths)
+    }
+
     /// List all available profiles.
     ///
     /// # Returns
@@ -351,6 +526,11 @@ impl ContextManager {
             return Err(eyre!(
                 "Cannot delete the active profile. Switch to another profile first"
             ));
+        } else if self.overlays.iter().any(|overlay| overlay.name == name) {
+            return Err(eyre!(
+                "Cannot delete an overlaid profile. Remove it with /profile overlay rm {} first",
+                name
+            ));
         }
 
         let profile_path = profile_dir_path(&self.ctx, name)?;
@@ -394,6 +574,10 @@ impl ContextManager {
 
         self.ctx.fs().rename(&old_profile_path, &new_profile_path).await?;
 
+        if let Some(overlay) = self.overlays.iter_mut().find(|overlay| overlay.name == old_name) {
+            overlay.name = new_name.to_string();
+        }
+
         // If the current profile is being renamed, update the current_profile field
         if self.current_profile == old_name {
             self.current_profile = new_name.to_string();
@@ -403,6 +587,79 @@ impl ContextManager {
         Ok(())
     }
 
+    /// Copy a profile's configuration to another profile.
+    ///
+    /// # Arguments
+    /// * `from` - Name of the profile to copy
+    /// * `to` - Name of the profile to create
+    /// * `force` - Whether to overwrite `to` if it already exists
+    ///
+    /// # Returns
+    /// A Result indicating success or an error
+    pub async fn copy_profile(&mut self, from: &str, to: &str, force: bool) -> Result<()> {
+        validate_profile_name(to)?;
+        if from == to {
+            return Err(eyre!("Cannot copy a profile to itself"));
+        }
+        if from != "default" && !profile_dir_path(&self.ctx, from)?.exists() {
+            return Err(eyre!("Profile '{}' not found", from));
+        }
+        if !force && (to == "default" || profile_dir_path(&self.ctx, to)?.exists()) {
+            return Err(eyre!("Profile '{}' already exists. Use --force to overwrite it", to));
+        }
+
+        let config = match from == self.current_profile {
+            true => self.profile_config.clone(),
+            false => load_profile_config(&self.ctx, from).await?,
+        };
+        let contents = serde_json::to_string_pretty(&config)
+            .map_err(|e| eyre!("Failed to serialize profile configuration: {}", e))?;
+        let profile_path = profile_context_path(&self.ctx, to)?;
+        if let Some(parent) = profile_path.parent() {
+            self.ctx.fs().create_dir_all(parent).await?;
+        }
+        self.ctx.fs().write(&profile_path, contents).await?;
+
+        if self.current_profile == to {
+            self.profile_config = config;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a manager with the profile `name` loaded in place of the current one, so its rules
+    /// can be edited without switching to it. Changes made through it are saved to that profile.
+    pub async fn for_profile(&self, name: &str) -> Result<Self> {
+        validate_profile_name(name)?;
+        if name != "default" && !profile_context_path(&self.ctx, name)?.exists() {
+            return Err(eyre!("Profile '{}' does not exist", name));
+        }
+
+        let mut manager = self.clone();
+        manager.current_profile = name.to_string();
+        manager.profile_config = load_profile_config(&self.ctx, name).await?;
+        manager.overlays.clear();
+        Ok(manager)
+    }
+
+    /// Compares the rules of the current profile with those of the profile `name`, as
+    /// `/context diff` shows them. Rules only the current profile has are
+    /// [PathDiff::Added], rules only `name` has are [PathDiff::Removed].
+    pub async fn diff_profile(&self, name: &str) -> Result<Vec<PathDiff>> {
+        let other = self.for_profile(name).await?;
+        let (theirs, ours) = (&other.profile_config.paths, &self.profile_config.paths);
+        Ok(theirs
+            .iter()
+            .filter(|path| !ours.contains(path))
+            .map(|path| PathDiff::Removed(path.clone()))
+            .chain(
+                ours.iter()
+                    .filter(|path| !theirs.contains(path))
+                    .map(|path| PathDiff::Added(path.clone())),
+            )
+            .collect())
+    }
+
     /// Switch to a different profile.
     ///
     /// # Arguments
@@ -422,6 +679,7 @@ impl ContextManager {
             // Update the current profile
             self.current_profile = name.to_string();
             self.profile_config = profile_config;
+            self.overlays.retain(|overlay| overlay.name != name);
 
             return Ok(());
         }
@@ -435,41 +693,180 @@ impl ContextManager {
         // Update the current profile
         self.current_profile = name.to_string();
         self.profile_config = load_profile_config(&self.ctx, name).await?;
+        self.overlays.retain(|overlay| overlay.name != name);
+
+        Ok(())
+    }
+
+    /// Layer the context rules of another profile on top of the current profile's.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the profile to overlay
+    ///
+    /// # Returns
+    /// A Result indicating success or an error
+    pub async fn add_overlay(&mut self, name: &str) -> Result<()> {
+        validate_profile_name(name)?;
+        if name == self.current_profile {
+            return Err(eyre!("'{}' is the current profile", name));
+        }
+        if self.overlays.iter().any(|overlay| overlay.name == name) {
+            return Err(eyre!("Profile '{}' is already overlaid", name));
+        }
+        if name != "default" && !profile_context_path(&self.ctx, name)?.exists() {
+            return Err(eyre!("Profile '{}' does not exist", name));
+        }
 
+        let config = load_profile_config(&self.ctx, name).await?;
+        self.overlays.push(Overlay {
+            name: name.to_string(),
+            config,
+        });
         Ok(())
     }
 
-    /// Get all context files (global + profile-specific).
+    /// Stop using the context rules of an overlaid profile.
+    pub fn remove_overlay(&mut self, name: &str) -> Result<()> {
+        let Some(index) = self.overlays.iter().position(|overlay| overlay.name == name) else {
+            return Err(eyre!("Profile '{}' is not overlaid", name));
+        };
+        self.overlays.remove(index);
+        Ok(())
+    }
+
+    /// Get all context files (global + profile-specific + overlays).
     ///
     /// This method:
-    /// 1. Processes all paths in the global and profile configurations
+    /// 1. Processes all paths in the global and profile configurations, then those of each overlay
+    ///    in the order the overlays were added
     /// 2. Expands glob patterns to include matching files
     /// 3. Reads the content of each file
-    /// 4. Returns a vector of (filename, content) pairs
+    /// 4. Masks secrets in the content if [Self::redactor] is set
+    /// 5. Returns a vector of (filename, content) pairs, sorted by filename, with a file matched by
+    ///    more than one layer included only once
+    ///
+    /// Only the paths of overlays are used; hooks and the system prompt come from the current
+    /// profile alone.
     ///
     ///
     /// # Returns
     /// A Result containing a vector of (filename, content) pairs or an error
     pub async fn get_context_files(&self) -> Result<Vec<(String, String)>> {
+        Ok(self.get_capped_context_files().await?.0)
+    }
+
+    /// Like [Self::get_context_files], but also returns the names of the files left out by
+    /// [Self::max_context_files].
+    async fn get_capped_context_files(&self) -> Result<(Vec<(String, String)>, Vec<String>)> {
         let mut context_files = Vec::new();
 
         self.collect_context_files(&self.global_config.paths, &mut context_files)
             .await?;
         self.collect_context_files(&self.profile_config.paths, &mut context_files)
             .await?;
+        for overlay in &self.overlays {
+            self.collect_context_files(&overlay.config.paths, &mut context_files)
+                .await?;
+        }
 
         context_files.sort_by(|a, b| a.0.cmp(&b.0));
         context_files.dedup_by(|a, b| a.0 == b.0);
+        let mut omitted = Vec::new();
+        if let Some(max_files) = self.max_context_files {
+            omitted = cap_context_files(&self.ctx, &mut context_files, max_files, self.context_file_order).await;
+        }
+        for (_, content) in &mut context_files {
+            *content = self.redact(content).0;
+        }
 
-        Ok(context_files)
+        Ok((context_files, omitted))
+    }
+
+    /// Masks secrets in the content of a context file with [Self::redactor], returning the
+    /// content as it's sent and the number of secrets that were masked.
+    pub fn redact(&self, content: &str) -> (String, usize) {
+        match &self.redactor {
+            Some(redactor) => redactor.redact(content),
+            None => (content.to_string(), 0),
+        }
     }
 
     pub async fn get_context_files_by_path(&self, path: &str) -> Result<Vec<(String, String)>> {
         let mut context_files = Vec::new();
-        process_path(&self.ctx, path, &mut context_files, true).await?;
+        process_path(
+            &self.ctx,
+            path,
+            &self.path_options(true)?,
+            &mut context_files,
+            &mut Vec::new(),
+        )
+        .await?;
         Ok(context_files)
     }
 
+    /// Get the files matched by the global and profile configuration that were left out of the
+    /// context because they are binary, too large, or over [Self::max_context_files].
+    pub async fn get_skipped_context_files(&self) -> Result<Vec<SkippedContextFile>> {
+        let mut files = Vec::new();
+        let mut skipped = Vec::new();
+        let options = self.path_options(false)?;
+        let overlay_paths = self.overlays.iter().flat_map(|overlay| &overlay.config.paths);
+        for path in self
+            .global_config
+            .paths
+            .iter()
+            .chain(&self.profile_config.paths)
+            .chain(overlay_paths)
+        {
+            process_path(&self.ctx, path, &options, &mut files, &mut skipped).await?;
+        }
+
+        if let Some(max_files) = self.max_context_files {
+            files.sort_by(|a, b| a.0.cmp(&b.0));
+            files.dedup_by(|a, b| a.0 == b.0);
+            for path in cap_context_files(&self.ctx, &mut files, max_files, self.context_file_order).await {
+                skipped.push(SkippedContextFile {
+                    path,
+                    reason: SkipReason::OverFileLimit { limit: max_files },
+                });
+            }
+        }
+
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        skipped.dedup_by(|a, b| a.path == b.path);
+        Ok(skipped)
+    }
+
+    /// Checks whether each rule of the global and profile configuration still matches anything,
+    /// resolving them the same way as [Self::get_context_files].
+    pub async fn validate_paths(&self) -> Result<Vec<ContextPathStatus>> {
+        let options = self.path_options(true)?;
+        let global_paths = self.global_config.paths.iter().map(|path| (path, true));
+        let profile_paths = self.profile_config.paths.iter().map(|path| (path, false));
+
+        let mut statuses = Vec::new();
+        for (path, global) in global_paths.chain(profile_paths) {
+            let mut files = Vec::new();
+            let mut skipped = Vec::new();
+            let result = process_path(&self.ctx, path, &options, &mut files, &mut skipped).await;
+
+            let validity = match result {
+                Ok(()) => PathValidity::Valid {
+                    files: files.len() + skipped.len(),
+                },
+                Err(_) if is_glob(path) => PathValidity::EmptyGlob,
+                Err(_) => PathValidity::Missing,
+            };
+            statuses.push(ContextPathStatus {
+                path: path.clone(),
+                global,
+                validity,
+            });
+        }
+
+        Ok(statuses)
+    }
+
     /// Get all context files from the global configuration.
     pub async fn get_global_context_files(&self) -> Result<Vec<(String, String)>> {
         let mut context_files = Vec::new();
@@ -491,26 +888,115 @@ impl ContextManager {
     }
 
     /// Collects context files and optionally drops files if the total size exceeds the limit.
-    /// Returns (files_to_use, dropped_files)
-    pub async fn collect_context_files_with_limit(&self) -> Result<(Vec<(String, String)>, Vec<(String, String)>)> {
-        let mut files = self.get_context_files().await?;
+    /// Returns (files_to_use, dropped_files, omitted_files), where the omitted files are the
+    /// names of the files left out by [Self::max_context_files].
+    pub async fn collect_context_files_with_limit(
+        &self,
+    ) -> Result<(Vec<(String, String)>, Vec<(String, String)>, Vec<String>)> {
+        let (mut files, omitted_files) = self.get_capped_context_files().await?;
 
         let dropped_files = drop_matched_context_files(&mut files, self.max_context_files_size).unwrap_or_default();
 
         // remove dropped files from files
         files.retain(|file| !dropped_files.iter().any(|dropped| dropped.0 == file.0));
 
-        Ok((files, dropped_files))
+        Ok((files, dropped_files, omitted_files))
     }
 
     async fn collect_context_files(&self, paths: &[String], context_files: &mut Vec<(String, String)>) -> Result<()> {
+        let mut skipped = Vec::new();
+        // Without validation, non-matching globs are handled gracefully
+        let options = self.path_options(false)?;
         for path in paths {
-            // Use is_validation=false to handle non-matching globs gracefully
-            process_path(&self.ctx, path, context_files, false).await?;
+            process_path(&self.ctx, path, &options, context_files, &mut skipped).await?;
+        }
+        for file in skipped {
+            warn!(path = file.path, reason = %file.reason, "skipped context file");
         }
         Ok(())
     }
 
+    /// The custom system prompt of the current profile, if one is set.
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.profile_config
+            .system_prompt
+            .as_deref()
+            .filter(|prompt| !prompt.trim().is_empty())
+    }
+
+    /// Sets or, with `None` or an empty prompt, removes the system prompt of the current profile.
+    pub async fn set_system_prompt(&mut self, prompt: Option<String>) -> Result<()> {
+        self.profile_config.system_prompt = prompt.filter(|prompt| !prompt.trim().is_empty());
+        self.save_config(false).await
+    }
+
+    /// The prompt macros of the current profile, by name.
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.profile_config.aliases
+    }
+
+    /// Defines the alias `name` for the current profile, replacing any previous expansion, or
+    /// removes it with `None`. Returns whether the alias existed before.
+    pub async fn set_alias(&mut self, name: &str, expansion: Option<String>) -> Result<bool> {
+        let existed = match expansion {
+            Some(expansion) => self
+                .profile_config
+                .aliases
+                .insert(name.to_string(), expansion)
+                .is_some(),
+            None => self.profile_config.aliases.remove(name).is_some(),
+        };
+        self.save_config(false).await?;
+        Ok(existed)
+    }
+
+    /// Replaces every `@name` word in `input` that names an alias of the current profile with its
+    /// expansion. Other `@` words, such as prompt names, are left as they are.
+    pub fn expand_aliases(&self, input: &str) -> String {
+        expand_aliases(input, &self.profile_config.aliases)
+    }
+
+    /// Directory where the content of URL rules is cached for the current profile.
+    fn url_cache_dir(&self) -> Result<PathBuf> {
+        Ok(profile_dir_path(&self.ctx, &self.current_profile)?.join(URL_CACHE_DIR))
+    }
+
+    /// Builds the matcher for the files that globs and directories shouldn't expand to, from
+    /// [QIGNORE_FILENAME] and, if [Self::respect_gitignore] is set, `.gitignore` in the working
+    /// directory. Missing files are treated as empty.
+    fn ignore_matcher(&self) -> Result<Gitignore> {
+        let root = self.ctx.fs().chroot_path(self.ctx.env().current_dir()?);
+        let mut builder = GitignoreBuilder::new(&root);
+        let mut files = vec![QIGNORE_FILENAME];
+        if self.respect_gitignore {
+            files.push(".gitignore");
+        }
+        for file in files {
+            let path = root.join(file);
+            if !path.is_file() {
+                continue;
+            }
+            // Invalid lines are reported here but don't prevent the valid ones from applying.
+            if let Some(err) = builder.add(&path) {
+                warn!(path = %path.display(), %err, "invalid ignore file");
+            }
+        }
+        builder
+            .build()
+            .map_err(|e| eyre!("Failed to build ignore rules: {}", e))
+    }
+
+    /// Options for resolving this manager's rules with [process_path], without refreshing URLs.
+    fn path_options(&self, is_validation: bool) -> Result<PathOptions> {
+        Ok(PathOptions {
+            url_cache_dir: self.url_cache_dir()?,
+            ignore: self.ignore_matcher()?,
+            max_file_bytes: self.max_context_file_bytes,
+            is_validation,
+            refresh: false,
+        })
+    }
+
     fn get_config_mut(&mut self, global: bool) -> &mut ContextConfig {
         if global {
             &mut self.global_config
@@ -642,10 +1128,39 @@ async fn load_global_config(ctx: &Context) -> Result<ContextConfig> {
                 AMAZONQ_FILENAME.to_string(),
             ],
             hooks: HashMap::new(),
+            system_prompt: None,
+            aliases: BTreeMap::new(),
         })
     }
 }
 
+/// Whether `name` can be used for an alias, i.e. it only consists of letters, digits, `_` and `-`.
+pub fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// See [ContextManager::expand_aliases]. Punctuation right after the name, as in `@name,`, is
+/// kept after the expansion.
+fn expand_aliases(input: &str, aliases: &BTreeMap<String, String>) -> String {
+    if aliases.is_empty() {
+        return input.to_string();
+    }
+
+    input
+        .split_inclusive(char::is_whitespace)
+        .map(|piece| {
+            let Some(word) = piece.trim_end().strip_prefix('@') else {
+                return Cow::Borrowed(piece);
+            };
+            let name = word.trim_end_matches(|c: char| matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')'));
+            match aliases.get(name) {
+                Some(expansion) => Cow::Owned(format!("{expansion}{}", &piece[1 + name.len()..])),
+                None => Cow::Borrowed(piece),
+            }
+        })
+        .collect()
+}
+
 /// Load a profile's context configuration.
 ///
 /// If the profile configuration file doesn't exist, creates a default configuration.
@@ -663,6 +1178,21 @@ async fn load_profile_config(ctx: &Context, profile_name: &str) -> Result<Contex
     }
 }
 
+/// How [process_path] resolves the rules of a context configuration.
+struct PathOptions {
+    /// Where the content of URLs is cached.
+    url_cache_dir: PathBuf,
+    /// Files that globs and directories shouldn't expand to.
+    ignore: Gitignore,
+    /// Files larger than this are skipped rather than added.
+    max_file_bytes: usize,
+    /// If true, error when glob patterns don't match or URLs can't be fetched; if false, silently
+    /// skip them.
+    is_validation: bool,
+    /// Whether URLs are fetched again even if they're cached.
+    refresh: bool,
+}
+
 /// Process a path, handling glob patterns and file types.
 ///
 /// This method:
@@ -671,20 +1201,114 @@ async fn load_profile_config(ctx: &Context, profile_name: &str) -> Result<Contex
 /// 3. For each resulting path, adds the file to the context collection
 /// 4. Handles directories by including all files in the directory (non-recursive)
 /// 5. With force=true, includes paths that don't exist yet
+/// 6. Fetches `http(s)://` URLs, see [add_url_to_context]
 ///
 /// # Arguments
 /// * `path` - The path to process
+/// * `options` - How paths are resolved, see [PathOptions]
 /// * `context_files` - The collection to add files to
-/// * `is_validation` - If true, error when glob patterns don't match; if false, silently skip
+/// * `skipped` - The collection to add files that were left out to
 ///
 /// # Returns
 /// A Result indicating success or an error
 async fn process_path(
     ctx: &Context,
     path: &str,
+    options: &PathOptions,
     context_files: &mut Vec<(String, String)>,
-    is_validation: bool,
+    skipped: &mut Vec<SkippedContextFile>,
 ) -> Result<()> {
+    if is_url(path) {
+        return match add_url_to_context(
+            ctx,
+            path,
+            &options.url_cache_dir,
+            context_files,
+            skipped,
+            options.max_file_bytes,
+            options.refresh,
+        )
+        .await
+        {
+            Err(e) if !options.is_validation => {
+                warn!(url = path, ?e, "failed to fetch context URL");
+                Ok(())
+            },
+            res => res,
+        };
+    }
+
+    // Each alternative of a brace pattern is processed on its own. When validating, the path is
+    // valid as long as one of the alternatives is.
+    let mut first_err = None;
+    let mut any_valid = false;
+    for pattern in expand_braces(path) {
+        match process_single_path(ctx, &pattern, options, context_files, skipped).await {
+            Ok(()) => any_valid = true,
+            Err(e) => {
+                first_err.get_or_insert(e);
+            },
+        }
+    }
+
+    match first_err {
+        Some(e) if !any_valid => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Expands brace patterns like `docs/{api,guide}.md` into `docs/api.md` and `docs/guide.md`.
+/// Braces may be nested; unbalanced braces are kept as-is.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+
+    // Find the matching closing brace and the top-level commas within it.
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut close = None;
+    for (i, c) in pattern[open..].char_indices().map(|(i, c)| (i + open, c)) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            },
+            ',' if depth == 1 => commas.push(i),
+            _ => {},
+        }
+    }
+    let Some(close) = close else {
+        return vec![pattern.to_string()];
+    };
+
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+    bounds
+        .windows(2)
+        .flat_map(|w| expand_braces(&format!("{prefix}{}{suffix}", &pattern[w[0] + 1..w[1]])))
+        .collect()
+}
+
+async fn process_single_path(
+    ctx: &Context,
+    path: &str,
+    options: &PathOptions,
+    context_files: &mut Vec<(String, String)>,
+    skipped: &mut Vec<SkippedContextFile>,
+) -> Result<()> {
+    let &PathOptions {
+        ref ignore,
+        max_file_bytes,
+        is_validation,
+        ..
+    } = options;
     // Expand ~ to home directory
     let expanded_path = if path.starts_with('~') {
         if let Some(home_dir) = ctx.env().home() {
@@ -711,7 +1335,7 @@ async fn process_path(
     let full_path = ctx.fs().chroot_path_str(full_path);
 
     // Check if the path contains glob patterns
-    if full_path.contains('*') || full_path.contains('?') || full_path.contains('[') {
+    if is_glob(&full_path) {
         // Expand glob pattern
         match glob(&full_path) {
             Ok(entries) => {
@@ -721,7 +1345,11 @@ async fn process_path(
                     match entry {
                         Ok(path) => {
                             if path.is_file() {
-                                add_file_to_context(ctx, &path, context_files).await?;
+                                if is_ignored(ignore, &path) {
+                                    skip_ignored(&path, skipped);
+                                    continue;
+                                }
+                                add_file_to_context(ctx, &path, context_files, skipped, max_file_bytes).await?;
                                 found_any = true;
                             }
                         },
@@ -743,14 +1371,18 @@ async fn process_path(
         let path = Path::new(&full_path);
         if path.exists() {
             if path.is_file() {
-                add_file_to_context(ctx, path, context_files).await?;
+                add_file_to_context(ctx, path, context_files, skipped, max_file_bytes).await?;
             } else if path.is_dir() {
                 // For directories, add all files in the directory (non-recursive)
                 let mut read_dir = ctx.fs().read_dir(path).await?;
                 while let Some(entry) = read_dir.next_entry().await? {
                     let path = entry.path();
                     if path.is_file() {
-                        add_file_to_context(ctx, &path, context_files).await?;
+                        if is_ignored(ignore, &path) {
+                            skip_ignored(&path, skipped);
+                            continue;
+                        }
+                        add_file_to_context(ctx, &path, context_files, skipped, max_file_bytes).await?;
                     }
                 }
             }
@@ -763,25 +1395,360 @@ async fn process_path(
     Ok(())
 }
 
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Whether `path`, or any directory it's in, is matched by `ignore`. Only paths within the working
+/// directory can be ignored.
+fn is_ignored(ignore: &Gitignore, path: &Path) -> bool {
+    path.starts_with(ignore.path()) && ignore.matched_path_or_any_parents(path, false).is_ignore()
+}
+
+fn skip_ignored(path: &Path, skipped: &mut Vec<SkippedContextFile>) {
+    skipped.push(SkippedContextFile {
+        path: path.to_string_lossy().to_string(),
+        reason: SkipReason::Ignored,
+    });
+}
+
 /// Add a file to the context collection.
 ///
 /// This method:
-/// 1. Reads the content of the file
-/// 2. Adds the (filename, content) pair to the context collection
+/// 1. Checks that the file is at most `max_file_bytes` large
+/// 2. Reads the content of the file, skipping it if it appears to be binary
+/// 3. Adds the (filename, content) pair to the context collection
 ///
 /// # Arguments
 /// * `path` - The path to the file
 /// * `context_files` - The collection to add the file to
+/// * `skipped` - The collection to add the file to if it is skipped
+/// * `max_file_bytes` - The maximum size of the file
 ///
 /// # Returns
 /// A Result indicating success or an error
-async fn add_file_to_context(ctx: &Context, path: &Path, context_files: &mut Vec<(String, String)>) -> Result<()> {
+async fn add_file_to_context(
+    ctx: &Context,
+    path: &Path,
+    context_files: &mut Vec<(String, String)>,
+    skipped: &mut Vec<SkippedContextFile>,
+    max_file_bytes: usize,
+) -> Result<()> {
     let filename = path.to_string_lossy().to_string();
-    let content = ctx.fs().read_to_string(path).await?;
-    context_files.push((filename, content));
+
+    // Like the `is_file` checks in `process_single_path`, `path` has already been chrooted.
+    let size = path.metadata()?.len();
+    if size > max_file_bytes as u64 {
+        skipped.push(SkippedContextFile {
+            path: filename,
+            reason: SkipReason::TooLarge {
+                size,
+                limit: max_file_bytes,
+            },
+        });
+        return Ok(());
+    }
+
+    let content = ctx.fs().read(path).await?;
+    push_context_content(filename, &content, context_files, skipped);
+    Ok(())
+}
+
+/// Whether a context rule is a URL rather than a path or glob pattern.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// Add the content of a URL to the context collection.
+///
+/// The content is cached in `cache_dir` and only fetched when there is no cached copy yet, or
+/// when `refresh` is set. Responses larger than `max_file_bytes` are skipped like large files.
+async fn add_url_to_context(
+    ctx: &Context,
+    url: &str,
+    cache_dir: &Path,
+    context_files: &mut Vec<(String, String)>,
+    skipped: &mut Vec<SkippedContextFile>,
+    max_file_bytes: usize,
+    refresh: bool,
+) -> Result<()> {
+    let cache_path = cache_dir.join(format!("{:x}", Sha256::digest(url.as_bytes())));
+    if !refresh && ctx.fs().exists(&cache_path) {
+        let content = ctx.fs().read(&cache_path).await?;
+        push_context_content(url.to_string(), &content, context_files, skipped);
+        return Ok(());
+    }
+
+    let content = match fetch_url(url, max_file_bytes).await? {
+        Ok(content) => content,
+        Err(size) => {
+            skipped.push(SkippedContextFile {
+                path: url.to_string(),
+                reason: SkipReason::TooLarge {
+                    size,
+                    limit: max_file_bytes,
+                },
+            });
+            return Ok(());
+        },
+    };
+    ctx.fs().create_dir_all(cache_dir).await?;
+    ctx.fs().write(&cache_path, &content).await?;
+    push_context_content(url.to_string(), &content, context_files, skipped);
     Ok(())
 }
 
+/// Fetches the body of `url`, or returns the number of bytes received so far as the error if it
+/// exceeds `max_bytes`.
+async fn fetch_url(url: &str, max_bytes: usize) -> Result<Result<Vec<u8>, u64>> {
+    let mut response = crate::request::new_client()?
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?;
+    if let Some(size) = response.content_length().filter(|size| *size > max_bytes as u64) {
+        return Ok(Err(size));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > max_bytes {
+            return Ok(Err(body.len() as u64));
+        }
+    }
+    Ok(Ok(body))
+}
+
+/// Adds `content` to the context collection as `filename`, unless it appears to be binary.
+fn push_context_content(
+    filename: String,
+    content: &[u8],
+    context_files: &mut Vec<(String, String)>,
+    skipped: &mut Vec<SkippedContextFile>,
+) {
+    if content.iter().take(BINARY_SNIFF_LEN).any(|b| *b == 0) {
+        skipped.push(SkippedContextFile {
+            path: filename,
+            reason: SkipReason::Binary,
+        });
+        return;
+    }
+
+    context_files.push((filename, String::from_utf8_lossy(content).into_owned()));
+}
+
+/// Inlines the files referenced as `@path` in `input`, as in "explain @src/main.rs". Each reference
+/// is replaced by its path and the file's contents are appended to the prompt, in the same
+/// `[path]` format as context files, with secrets masked by `redactor` like in context files.
+/// References to files that don't exist, are binary or are larger than `max_file_bytes` are left as
+/// written and returned alongside the prompt.
+pub async fn inline_file_references(
+    ctx: &Context,
+    input: &str,
+    max_file_bytes: usize,
+    redactor: Option<&Redactor>,
+) -> (String, Vec<SkippedContextFile>) {
+    let mut prompt = String::new();
+    let mut files: Vec<(String, String)> = Vec::new();
+    let mut skipped = Vec::new();
+
+    for piece in input.split_inclusive(char::is_whitespace) {
+        let reference = piece
+            .trim_end()
+            .strip_prefix('@')
+            .map(|word| word.trim_end_matches(|c: char| matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')')))
+            .filter(|reference| !reference.is_empty());
+        let Some(reference) = reference else {
+            prompt.push_str(piece);
+            continue;
+        };
+
+        let inlined = files.iter().any(|(path, _)| path == reference)
+            || read_file_reference(ctx, reference, &mut files, &mut skipped, max_file_bytes).await;
+        if inlined {
+            prompt.push_str(&piece[1..]);
+        } else {
+            prompt.push_str(piece);
+        }
+    }
+
+    for (path, content) in files {
+        let content = match redactor {
+            Some(redactor) => redactor.redact(&content).0,
+            None => content,
+        };
+        prompt.push_str(&format!("\n\n[{path}]\n{content}"));
+    }
+    (prompt, skipped)
+}
+
+/// Reads the file `reference` names into `files` under that name, returning whether it could be.
+async fn read_file_reference(
+    ctx: &Context,
+    reference: &str,
+    files: &mut Vec<(String, String)>,
+    skipped: &mut Vec<SkippedContextFile>,
+    max_file_bytes: usize,
+) -> bool {
+    let path = match reference.strip_prefix("~/") {
+        Some(rest) => match ctx.env().home() {
+            Some(home) => home.join(rest),
+            None => PathBuf::from(reference),
+        },
+        None => match ctx.env().current_dir() {
+            Ok(cwd) => cwd.join(reference),
+            Err(_) => PathBuf::from(reference),
+        },
+    };
+    // Required in chroot testing scenarios so that we can use `Path::is_file`.
+    let path = ctx.fs().chroot_path(path);
+
+    let skipped_before = skipped.len();
+    let mut read = Vec::new();
+    if !path.is_file()
+        || add_file_to_context(ctx, &path, &mut read, skipped, max_file_bytes)
+            .await
+            .is_err()
+    {
+        skipped.truncate(skipped_before);
+        skipped.push(SkippedContextFile {
+            path: reference.to_string(),
+            reason: SkipReason::NotFound,
+        });
+        return false;
+    }
+    if let Some(skipped) = skipped.get_mut(skipped_before) {
+        skipped.path = reference.to_string();
+        return false;
+    }
+    files.extend(read.into_iter().map(|(_, content)| (reference.to_string(), content)));
+    true
+}
+
+/// Whether a context rule still matches anything, as reported by `/context validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextPathStatus {
+    pub path: String,
+    pub global: bool,
+    pub validity: PathValidity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathValidity {
+    /// The rule matches this many files, or is a URL that could be fetched.
+    Valid { files: usize },
+    /// The rule is a glob pattern that no longer matches any files.
+    EmptyGlob,
+    /// The file or directory doesn't exist anymore, or the URL can't be fetched.
+    Missing,
+}
+
+/// A rule that differs between two profiles, see [ContextManager::diff_profile].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathDiff {
+    Added(String),
+    Removed(String),
+}
+
+/// A file matched by a context path that was left out of the context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedContextFile {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file is larger than `chat.maxContextFileBytes`.
+    TooLarge { size: u64, limit: usize },
+    /// The file contains null bytes near its start.
+    Binary,
+    /// The file is matched by `.qignore`, or by `.gitignore` with `chat.respectGitignore`.
+    Ignored,
+    /// An `@path` reference in a prompt doesn't name an existing file.
+    NotFound,
+    /// More files matched than `chat.maxContextFiles` allows, and this one wasn't among those kept.
+    OverFileLimit { limit: usize },
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { size, limit } => write!(f, "{size} bytes exceeds the limit of {limit} bytes"),
+            Self::Binary => write!(f, "appears to be binary"),
+            Self::Ignored => write!(f, "excluded by ignore rules"),
+            Self::NotFound => write!(f, "no such file"),
+            Self::OverFileLimit { limit } => write!(f, "more than {limit} files matched (chat.maxContextFiles)"),
+        }
+    }
+}
+
+/// Which context files are kept when more match than `chat.maxContextFiles` allows, from
+/// `chat.contextFilesOrder`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContextFileOrder {
+    /// `newest`: the most recently modified files. URLs count as older than any file.
+    #[default]
+    Newest,
+    /// `largest`: the files with the most content.
+    Largest,
+}
+
+impl ContextFileOrder {
+    pub fn from_setting(value: Option<&str>) -> Self {
+        match value.map(str::to_lowercase).as_deref() {
+            Some("newest") | None => Self::Newest,
+            Some("largest") => Self::Largest,
+            Some(other) => {
+                warn!("unknown chat.contextFilesOrder '{other}', using newest");
+                Self::Newest
+            },
+        }
+    }
+}
+
+/// Keeps the first `max_files` of `files` in `order`, returning the names of the files left out.
+/// Ties are broken by name so the selection is the same every time, and the kept files stay in
+/// the order they were given.
+async fn cap_context_files(
+    ctx: &Context,
+    files: &mut Vec<(String, String)>,
+    max_files: usize,
+    order: ContextFileOrder,
+) -> Vec<String> {
+    if files.len() <= max_files {
+        return Vec::new();
+    }
+
+    let mut ranked = files.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+    match order {
+        ContextFileOrder::Newest => {
+            let mut modified = HashMap::new();
+            for (name, _) in files.iter() {
+                let metadata = ctx.fs().symlink_metadata(name).await;
+                modified.insert(name.as_str(), metadata.and_then(|metadata| metadata.modified()).ok());
+            }
+            ranked.sort_by(|a, b| modified[b].cmp(&modified[a]).then(a.cmp(b)));
+        },
+        ContextFileOrder::Largest => {
+            let sizes = files
+                .iter()
+                .map(|(name, content)| (name.as_str(), content.len()))
+                .collect::<HashMap<_, _>>();
+            ranked.sort_by(|a, b| sizes[b].cmp(&sizes[a]).then(a.cmp(b)));
+        },
+    }
+    let omitted = ranked[max_files..]
+        .iter()
+        .map(|name| (*name).to_string())
+        .collect::<Vec<_>>();
+
+    let omitted_set = omitted.iter().collect::<HashSet<_>>();
+    files.retain(|(name, _)| !omitted_set.contains(name));
+    omitted
+}
+
 /// Validate a profile name.
 ///
 /// Profile names can only contain alphanumeric characters, hyphens, and underscores.
@@ -812,6 +1779,10 @@ fn default_context() -> Arc<Context> {
     Context::new()
 }
 
+fn default_max_context_file_bytes() -> usize {
+    DEFAULT_MAX_CONTEXT_FILE_BYTES
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Stdout;
@@ -869,6 +1840,17 @@ mod tests {
         manager.switch_profile("test_profile").await?;
         assert!(manager.switch_profile("notexists").await.is_err());
 
+        // Copying
+        manager
+            .add_paths(vec!["docs/*.md".to_string()], false, true, false)
+            .await?;
+        manager.copy_profile("test_profile", "copied", false).await?;
+        assert_eq!(load_profile_config(&ctx, "copied").await?.paths, vec!["docs/*.md"]);
+        assert!(manager.copy_profile("test_profile", "alt", false).await.is_err());
+        assert!(manager.copy_profile("notexists", "other", false).await.is_err());
+        manager.copy_profile("test_profile", "alt", true).await?;
+        assert_eq!(load_profile_config(&ctx, "alt").await?.paths, vec!["docs/*.md"]);
+
         // Renaming
         manager.rename_profile("alt", "renamed").await?;
         assert!(!profile_context_path(&ctx, "alt")?.exists());
@@ -885,6 +1867,113 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_expand_aliases() {
+        let aliases = BTreeMap::from([
+            ("rust".to_string(), "Use Rust 2021 idioms, no unwrap".to_string()),
+            ("short".to_string(), "Be brief".to_string()),
+        ]);
+        assert_eq!(
+            expand_aliases("Write a parser. @rust", &aliases),
+            "Write a parser. Use Rust 2021 idioms, no unwrap"
+        );
+        assert_eq!(
+            expand_aliases("@short, then\n@rust.", &aliases),
+            "Be brief, then\nUse Rust 2021 idioms, no unwrap."
+        );
+        assert_eq!(
+            expand_aliases("mail me@rust @unknown @rusty", &aliases),
+            "mail me@rust @unknown @rusty"
+        );
+        assert_eq!(expand_aliases("@rust", &BTreeMap::new()), "@rust");
+
+        assert!(is_valid_alias_name("no-unwrap_2"));
+        assert!(!is_valid_alias_name(""));
+        assert!(!is_valid_alias_name("a.b"));
+    }
+
+    #[tokio::test]
+    async fn test_aliases() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let ctx = Arc::clone(&manager.ctx);
+
+        assert!(!manager.set_alias("rust", Some("No unwrap".to_string())).await?);
+        assert!(
+            manager
+                .set_alias("rust", Some("Use Rust 2021 idioms".to_string()))
+                .await?
+        );
+        assert_eq!(manager.expand_aliases("@rust please"), "Use Rust 2021 idioms please");
+        assert_eq!(
+            load_profile_config(&ctx, "default").await?.aliases.get("rust"),
+            Some(&"Use Rust 2021 idioms".to_string())
+        );
+
+        manager.create_profile("other").await?;
+        manager.switch_profile("other").await?;
+        assert!(manager.aliases().is_empty());
+
+        manager.switch_profile("default").await?;
+        assert!(manager.set_alias("rust", None).await?);
+        assert!(!manager.set_alias("rust", None).await?);
+        assert!(load_profile_config(&ctx, "default").await?.aliases.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_edit_other_profile() -> Result<()> {
+        let manager = create_test_context_manager(None).await?;
+        let ctx: Arc<Context> = Arc::clone(&manager.ctx);
+        manager.create_profile("work").await?;
+        assert!(manager.for_profile("notexists").await.is_err());
+
+        let mut work = manager.for_profile("work").await?;
+        work.add_paths(vec!["docs/*.md".to_string()], false, true, false)
+            .await?;
+        assert_eq!(load_profile_config(&ctx, "work").await?.paths, vec!["docs/*.md"]);
+        assert_eq!(manager.current_profile, "default");
+        assert!(manager.profile_config.paths.is_empty());
+
+        work.clear(false).await?;
+        assert!(load_profile_config(&ctx, "work").await?.paths.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_profile() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        manager.create_profile("shared").await?;
+        manager
+            .for_profile("shared")
+            .await?
+            .add_paths(
+                vec!["README.md".to_string(), "docs/*.md".to_string()],
+                false,
+                true,
+                false,
+            )
+            .await?;
+        manager
+            .add_paths(
+                vec!["docs/*.md".to_string(), "notes.md".to_string()],
+                false,
+                true,
+                false,
+            )
+            .await?;
+
+        assert_eq!(manager.diff_profile("shared").await?, vec![
+            PathDiff::Removed("README.md".to_string()),
+            PathDiff::Added("notes.md".to_string()),
+        ]);
+        assert!(manager.diff_profile("default").await?.is_empty());
+        assert!(manager.diff_profile("notexists").await.is_err());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_collect_exceeds_limit() -> Result<()> {
         let mut manager = create_test_context_manager(Some(2)).await?;
@@ -895,9 +1984,11 @@ mod tests {
         ctx.fs()
             .write("test/to-drop.md", "long content that exceed limit")
             .await?;
-        manager.add_paths(vec!["test/*.md".to_string()], false, false).await?;
+        manager
+            .add_paths(vec!["test/*.md".to_string()], false, false, false)
+            .await?;
 
-        let (used, dropped) = manager.collect_context_files_with_limit().await.unwrap();
+        let (used, dropped, _) = manager.collect_context_files_with_limit().await.unwrap();
 
         assert!(used.len() + dropped.len() == 2);
         assert!(used.len() == 1);
@@ -920,7 +2011,9 @@ mod tests {
             "no files should be returned for an empty profile when force is false"
         );
 
-        manager.add_paths(vec!["test/*.md".to_string()], false, false).await?;
+        manager
+            .add_paths(vec!["test/*.md".to_string()], false, false, false)
+            .await?;
         let files = manager.get_context_files().await?;
         assert!(files[0].0.ends_with("p1.md"));
         assert_eq!(files[0].1, "p1");
@@ -929,15 +2022,385 @@ mod tests {
 
         assert!(
             manager
-                .add_paths(vec!["test/*.txt".to_string()], false, false)
+                .add_paths(vec!["test/*.txt".to_string()], false, false, false)
                 .await
                 .is_err(),
             "adding a glob with no matching and without force should fail"
         );
+        assert_eq!(
+            manager
+                .add_paths(vec!["test/*.txt".to_string()], false, true, false)
+                .await?,
+            vec!["test/*.txt".to_string()],
+            "adding a glob with no matching and with force should report it as unmatched"
+        );
+
+        ctx.fs().create_dir_all("docs/nested").await?;
+        ctx.fs().write("docs/api.md", "api").await?;
+        ctx.fs().write("docs/nested/guide.md", "guide").await?;
+        manager.clear(false).await?;
+        assert!(
+            manager
+                .add_paths(
+                    vec!["docs/{api,missing}.md".to_string(), "docs/**/*.md".to_string()],
+                    false,
+                    false,
+                    false
+                )
+                .await?
+                .is_empty()
+        );
+        let files = manager.get_context_files().await?;
+        assert_eq!(
+            files.len(),
+            2,
+            "files matched by several patterns should only be included once"
+        );
+        assert!(files[0].0.ends_with("docs/api.md"));
+        assert!(files[1].0.ends_with("docs/nested/guide.md"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_paths() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let ctx: Arc<Context> = Arc::clone(&manager.ctx);
+
+        ctx.fs().create_dir_all("test").await?;
+        ctx.fs().write("test/p1.md", "p1").await?;
+        ctx.fs().write("test/p2.md", "p2").await?;
+        ctx.fs().write("test/gone.md", "gone").await?;
+        manager
+            .add_paths(
+                vec![
+                    "test/*.md".to_string(),
+                    "test/gone.md".to_string(),
+                    "test/*.txt".to_string(),
+                ],
+                false,
+                true,
+                false,
+            )
+            .await?;
+        ctx.fs().remove_file("test/gone.md").await?;
+
+        let statuses = manager
+            .validate_paths()
+            .await?
+            .into_iter()
+            .filter(|status| !status.global)
+            .map(|status| (status.path, status.validity))
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, vec![
+            ("test/*.md".to_string(), PathValidity::Valid { files: 2 }),
+            ("test/gone.md".to_string(), PathValidity::Missing),
+            ("test/*.txt".to_string(), PathValidity::EmptyGlob),
+        ]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_paths_by_index() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        manager.global_config.paths.clear();
+        manager
+            .add_paths(vec!["a.md".into(), "b.md".into(), "3".into()], false, true, false)
+            .await?;
+
+        // Numbers can only be used once the rules have been shown.
+        let args = vec!["2".to_string()];
+        assert_eq!(manager.rule_numbers(&args, false), Some(vec![2]));
+        assert!(manager.remove_paths_by_index(&[2], false).await.is_err());
+
+        manager.mark_paths_shown();
+        assert!(manager.remove_paths_by_index(&[4], false).await.is_err());
+        assert!(manager.remove_paths_by_index(&[0], false).await.is_err());
+        assert_eq!(manager.remove_paths_by_index(&[2], false).await?, vec!["b.md"]);
+        assert_eq!(manager.profile_config.paths, vec!["a.md", "3"]);
+
+        // A rule that looks like a number is removed by path.
+        assert_eq!(manager.rule_numbers(&["3".to_string()], false), None);
+
+        // Stale numbers are rejected.
+        manager.add_paths(vec!["c.md".into()], false, true, false).await?;
+        assert!(manager.remove_paths_by_index(&[1], false).await.is_err());
+        assert_eq!(manager.profile_config.paths, vec!["a.md", "3", "c.md"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_skip_binary_and_oversized_files() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        manager.max_context_file_bytes = 8;
+        let ctx: Arc<Context> = Arc::clone(&manager.ctx);
+
+        ctx.fs().create_dir_all("test").await?;
+        ctx.fs().write("test/a.md", "small").await?;
+        ctx.fs().write("test/b.bin", b"ab\0cd").await?;
+        ctx.fs().write("test/c.log", "way too large").await?;
+        manager
+            .add_paths(vec!["test/*".to_string()], false, false, false)
+            .await?;
+
+        let files = manager.get_context_files().await?;
+        assert_eq!(files.len(), 1);
+        assert!(files[0].0.ends_with("a.md"));
+
+        let skipped = manager.get_skipped_context_files().await?;
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped[0].path.ends_with("b.bin"));
+        assert_eq!(skipped[0].reason, SkipReason::Binary);
+        assert!(skipped[1].path.ends_with("c.log"));
+        assert_eq!(skipped[1].reason, SkipReason::TooLarge { size: 13, limit: 8 });
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_context_files() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let ctx: Arc<Context> = Arc::clone(&manager.ctx);
+
+        ctx.fs().create_dir_all("test").await?;
+        ctx.fs().write("test/a.md", "a").await?;
+        ctx.fs().write("test/b.md", "bbb").await?;
+        ctx.fs().write("test/c.md", "cc").await?;
+        ctx.fs().write("test/d.md", "cc").await?;
+        manager
+            .add_paths(vec!["test/*".to_string()], false, false, false)
+            .await?;
+        let files = manager.get_context_files().await?;
+        assert_eq!(files.len(), 4);
+
+        // Modified oldest to newest: c, a, d, b.
+        let base = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        for (i, name) in ["c.md", "a.md", "d.md", "b.md"].iter().enumerate() {
+            let (path, _) = files.iter().find(|f| f.0.ends_with(name)).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(path)?
+                .set_modified(base + std::time::Duration::from_secs(i as u64))?;
+        }
+
+        let names = |files: Vec<(String, String)>| {
+            files
+                .into_iter()
+                .map(|f| f.0.rsplit('/').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // The most recently modified files are kept, still sorted by name.
+        manager.max_context_files = Some(2);
+        assert_eq!(names(manager.get_context_files().await?), vec!["b.md", "d.md"]);
+        let skipped = manager.get_skipped_context_files().await?;
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped[0].path.ends_with("a.md"));
+        assert!(skipped[1].path.ends_with("c.md"));
+        assert_eq!(skipped[0].reason, SkipReason::OverFileLimit { limit: 2 });
+        let (used, dropped, omitted) = manager.collect_context_files_with_limit().await?;
+        assert_eq!(names(used), vec!["b.md", "d.md"]);
+        assert!(dropped.is_empty());
+        assert_eq!(omitted.len(), 2);
+
+        // The largest files are kept, with ties broken by name.
+        manager.context_file_order = ContextFileOrder::Largest;
+        assert_eq!(names(manager.get_context_files().await?), vec!["b.md", "c.md"]);
+        manager.max_context_files = Some(0);
+        assert!(manager.get_context_files().await?.is_empty());
+
+        assert_eq!(
+            ContextFileOrder::from_setting(Some("Largest")),
+            ContextFileOrder::Largest
+        );
+        assert_eq!(ContextFileOrder::from_setting(Some("oldest")), ContextFileOrder::Newest);
+        assert_eq!(ContextFileOrder::from_setting(None), ContextFileOrder::Newest);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inline_file_references() -> Result<()> {
+        let manager = create_test_context_manager(None).await?;
+        let ctx: Arc<Context> = Arc::clone(&manager.ctx);
+        ctx.fs().create_dir_all("src").await?;
+        ctx.fs().write("src/main.rs", "fn main() {}").await?;
+        ctx.fs().write("src/big.rs", "this file is way too large").await?;
+
+        let (prompt, skipped) =
+            inline_file_references(&ctx, "explain @src/main.rs, then @src/main.rs again", 16, None).await;
+        assert_eq!(
+            prompt,
+            "explain src/main.rs, then src/main.rs again\n\n[src/main.rs]\nfn main() {}"
+        );
+        assert!(skipped.is_empty());
+
+        let (prompt, skipped) =
+            inline_file_references(&ctx, "compare @src/big.rs with @nope.rs or @ me", 16, None).await;
+        assert_eq!(prompt, "compare @src/big.rs with @nope.rs or @ me");
+        assert_eq!(skipped, vec![
+            SkippedContextFile {
+                path: "src/big.rs".to_string(),
+                reason: SkipReason::TooLarge { size: 26, limit: 16 },
+            },
+            SkippedContextFile {
+                path: "nope.rs".to_string(),
+                reason: SkipReason::NotFound,
+            },
+        ]);
+
+        // Secrets are masked like in context files.
+        ctx.fs().write(".env", "DB_PASSWORD=hunter2\n").await?;
+        let redactor = Redactor::new(&[]);
+        let (prompt, _) = inline_file_references(&ctx, "check @.env", 64, Some(&redactor)).await;
+        assert_eq!(prompt, "check .env\n\n[.env]\nDB_PASSWORD=[REDACTED]\n");
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_redact_context_files() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let ctx = Arc::clone(&manager.ctx);
+
+        ctx.fs().write(".env", "PORT=8080\nDB_PASSWORD=hunter2\n").await?;
+        manager.add_paths(vec![".env".to_string()], false, false, false).await?;
+
+        let content = |files: Vec<(String, String)>| files.into_iter().map(|f| f.1).collect::<Vec<_>>();
+        assert_eq!(content(manager.get_context_files().await?), vec![
+            "PORT=8080\nDB_PASSWORD=hunter2\n"
+        ]);
+
+        manager.redactor = Some(Redactor::new(&["PORT=[0-9]+".to_string()]));
+        assert_eq!(content(manager.get_context_files().await?), vec![
+            "[REDACTED]\nDB_PASSWORD=[REDACTED]\n"
+        ]);
+        assert_eq!(manager.redact("DB_PASSWORD=hunter2").1, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ignore_files() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let ctx = Arc::clone(&manager.ctx);
+
+        ctx.fs().create_dir_all("src/generated").await?;
+        ctx.fs().write("src/main.rs", "fn main() {}").await?;
+        ctx.fs().write("src/generated/api.rs", "// generated").await?;
+        ctx.fs().write("src/notes.log", "log").await?;
+        ctx.fs().write(QIGNORE_FILENAME, "generated/\n").await?;
+        ctx.fs().write(".gitignore", "*.log\n").await?;
+        manager
+            .add_paths(vec!["src/**/*".to_string()], false, false, false)
+            .await?;
+
+        let names = |files: Vec<(String, String)>| files.into_iter().map(|f| f.0).collect::<Vec<_>>();
+        let files = names(manager.get_context_files().await?);
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("src/main.rs"));
+        assert!(files[1].ends_with("src/notes.log"));
+        let skipped = manager.get_skipped_context_files().await?;
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].path.ends_with("src/generated/api.rs"));
+        assert_eq!(skipped[0].reason, SkipReason::Ignored);
+
+        manager.respect_gitignore = true;
+        let files = names(manager.get_context_files().await?);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("src/main.rs"));
+
+        // Files named explicitly are added even if they're ignored.
+        let files = names(manager.get_context_files_by_path("src/notes.log").await?);
+        assert_eq!(files.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_url_paths() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let mut server = mockito::Server::new_async().await;
+        let guide = server
+            .mock("GET", "/guide.md")
+            .with_body("# Guide")
+            .expect(1)
+            .create_async()
+            .await;
+
+        // Unmocked paths are answered with an error status, so fetching them fails.
+        let missing = format!("{}/missing.md", server.url());
+        assert!(
+            manager
+                .add_paths(vec![missing.clone()], false, false, false)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            manager.add_paths(vec![missing.clone()], false, true, false).await?,
+            vec![missing.clone()]
+        );
+        // Failed fetches are skipped when collecting the context.
+        assert!(manager.get_context_files().await?.is_empty());
+        manager.remove_paths(vec![missing], false).await?;
+
+        // The content is fetched once when the URL is added, then read from the cache.
+        let url = format!("{}/guide.md", server.url());
+        assert!(
+            manager
+                .add_paths(vec![url.clone()], false, false, false)
+                .await?
+                .is_empty()
+        );
+        assert_eq!(manager.get_context_files().await?, vec![(
+            url.clone(),
+            "# Guide".to_string()
+        )]);
+        assert_eq!(manager.get_context_files().await?, vec![(
+            url.clone(),
+            "# Guide".to_string()
+        )]);
+        guide.assert_async().await;
+        guide.remove_async().await;
+
+        // Refreshing fetches again, and URLs already added aren't rejected as duplicates.
+        let updated = server
+            .mock("GET", "/guide.md")
+            .with_body("# Guide v2")
+            .expect(1)
+            .create_async()
+            .await;
+        assert!(manager.add_paths(vec![url.clone()], false, false, false).await.is_err());
+        assert!(
+            manager
+                .add_paths(vec![url.clone()], false, false, true)
+                .await?
+                .is_empty()
+        );
+        updated.assert_async().await;
+        assert_eq!(manager.get_context_files().await?, vec![(
+            url.clone(),
+            "# Guide v2".to_string()
+        )]);
+        assert_eq!(manager.profile_config.paths, vec![url]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("docs/api.md"), vec!["docs/api.md"]);
+        assert_eq!(expand_braces("docs/{api,guide}.md"), vec![
+            "docs/api.md",
+            "docs/guide.md"
+        ]);
+        assert_eq!(expand_braces("{a,b}/{c,d}"), vec!["a/c", "a/d", "b/c", "b/d"]);
+        assert_eq!(expand_braces("x{a,{b,c}}"), vec!["xa", "xb", "xc"]);
+        assert_eq!(expand_braces("x{a,b"), vec!["x{a,b"]);
+    }
+
     #[tokio::test]
     async fn test_add_hook() -> Result<()> {
         let mut manager = create_test_context_manager(None).await?;
@@ -1031,6 +2494,60 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_overlays() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let ctx = Arc::clone(&manager.ctx);
+        ctx.fs().write("shared.md", "shared").await?;
+        ctx.fs().write("team.md", "team").await?;
+        ctx.fs().write("project.md", "project").await?;
+
+        manager.create_profile("team").await?;
+        manager.switch_profile("team").await?;
+        manager
+            .add_paths(
+                vec!["team.md".to_string(), "shared.md".to_string()],
+                false,
+                false,
+                false,
+            )
+            .await?;
+        manager.create_profile("project").await?;
+        manager.switch_profile("project").await?;
+        manager
+            .add_paths(
+                vec!["project.md".to_string(), "shared.md".to_string()],
+                false,
+                false,
+                false,
+            )
+            .await?;
+
+        manager.add_overlay("team").await?;
+        assert!(manager.add_overlay("team").await.is_err());
+        assert!(manager.add_overlay("project").await.is_err());
+        assert!(manager.add_overlay("notexists").await.is_err());
+        assert!(manager.delete_profile("team").await.is_err());
+
+        let files = manager.get_context_files().await?;
+        let names = files.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names.iter().filter(|name| name.ends_with("shared.md")).count(), 1);
+        assert!(names.iter().any(|name| name.ends_with("team.md")));
+        assert!(names.iter().any(|name| name.ends_with("project.md")));
+
+        // Switching to an overlaid profile makes it the base instead.
+        manager.switch_profile("team").await?;
+        assert!(manager.overlays.is_empty());
+
+        manager.add_overlay("project").await?;
+        manager.remove_overlay("project")?;
+        assert!(manager.remove_overlay("project").is_err());
+        let files = manager.get_context_files().await?;
+        assert!(!files.iter().any(|(name, _)| name.ends_with("project.md")));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_hooks_across_profiles() -> Result<()> {
         let mut manager = create_test_context_manager(None).await?;
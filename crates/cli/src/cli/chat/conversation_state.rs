@@ -34,6 +34,7 @@ use super::hooks::{
 };
 use super::message::{
     AssistantMessage,
+    AssistantToolUse,
     ToolUseResult,
     ToolUseResultBlock,
     UserMessage,
@@ -59,7 +60,7 @@ use crate::api_client::model::{
     AssistantResponseMessage,
     ChatMessage,
     ConversationState as FigConversationState,
-    ImageBlock,
+    IMAGE_RESULT_TEXT,
     Tool,
     ToolInputSchema,
     ToolResult,
@@ -77,14 +78,63 @@ use crate::platform::Context;
 const CONTEXT_ENTRY_START_HEADER: &str = "--- CONTEXT ENTRY BEGIN ---\n";
 const CONTEXT_ENTRY_END_HEADER: &str = "--- CONTEXT ENTRY END ---\n\n";
 
+/// Version of the serialized [ConversationState] format, e.g. as written by `/save`. This should
+/// be bumped whenever a change would prevent older versions from reading the new format.
+pub const CONVERSATION_STATE_VERSION: u32 = 1;
+
+/// The most notes on trimmed turns kept with [HistoryStrategy::Summarize]. Older notes are
+/// dropped first.
+const MAX_TRIMMED_TURN_NOTES: usize = 50;
+
+/// The most bytes of a prompt or response kept in a note on a trimmed turn.
+const MAX_TRIMMED_TURN_NOTE_BYTES: usize = 200;
+
+/// How the history is trimmed once it grows past [MAX_CONVERSATION_STATE_HISTORY_LEN], set with
+/// `chat.historyStrategy`. Whole turns are always dropped, so that tool results are never
+/// separated from the tool uses they answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HistoryStrategy {
+    /// `recent`: drops the oldest turns, keeping the most recent ones.
+    #[default]
+    Recent,
+    /// `firstAndLast`: keeps the first turn, which usually sets up the task, and drops the oldest
+    /// turns after it. Falls back to [Self::Recent] when the first turn alone fills the history.
+    FirstAndLast,
+    /// `summarize`: drops the oldest turns like [Self::Recent], but keeps a shortened note of
+    /// each dropped prompt and response that is sent as context with every request.
+    Summarize,
+}
+
+impl HistoryStrategy {
+    pub fn from_setting(value: Option<&str>) -> Self {
+        match value.map(str::to_lowercase).as_deref() {
+            Some("recent") | None => Self::Recent,
+            Some("firstandlast") => Self::FirstAndLast,
+            Some("summarize") => Self::Summarize,
+            Some(other) => {
+                warn!("unknown chat.historyStrategy '{other}', using recent");
+                Self::Recent
+            },
+        }
+    }
+}
+
 /// Tracks state related to an ongoing conversation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConversationState {
+    /// Version of the serialized format. States serialized before versioning was introduced are
+    /// read as version `0`.
+    #[serde(default)]
+    version: u32,
     /// Randomly generated on creation.
     conversation_id: String,
     /// The next user message to be sent as part of the conversation. Required to be [Some] before
     /// calling [Self::as_sendable_conversation_state].
     next_message: Option<UserMessage>,
+    /// The last user message whose request failed, kept so that it can be sent again with
+    /// `/retry`.
+    #[serde(skip)]
+    failed_message: Option<UserMessage>,
     history: VecDeque<(UserMessage, AssistantMessage)>,
     /// The range in the history sendable to the backend (start inclusive, end exclusive).
     valid_history_range: (usize, usize),
@@ -103,6 +153,15 @@ pub struct ConversationState {
     context_message_length: Option<usize>,
     /// Stores the latest conversation summary created by /compact
     latest_summary: Option<String>,
+    /// Shortened notes on the turns dropped from the history with [HistoryStrategy::Summarize].
+    #[serde(default)]
+    trimmed_turns: VecDeque<String>,
+    /// How the history is trimmed once it grows too long.
+    #[serde(skip)]
+    pub history_strategy: HistoryStrategy,
+    /// The system prompt from `--system-prompt-file`, used instead of the profile's.
+    #[serde(skip)]
+    pub system_prompt: Option<String>,
     #[serde(skip)]
     pub updates: Option<SharedWriter>,
 }
@@ -134,8 +193,10 @@ impl ConversationState {
         };
 
         Self {
+            version: CONVERSATION_STATE_VERSION,
             conversation_id: conversation_id.to_string(),
             next_message: None,
+            failed_message: None,
             history: VecDeque::new(),
             valid_history_range: Default::default(),
             transcript: VecDeque::with_capacity(MAX_CONVERSATION_STATE_HISTORY_LEN),
@@ -156,6 +217,9 @@ impl ConversationState {
             tool_manager,
             context_message_length: None,
             latest_summary: None,
+            trimmed_turns: VecDeque::new(),
+            history_strategy: HistoryStrategy::default(),
+            system_prompt: None,
             updates,
         }
     }
@@ -163,6 +227,7 @@ impl ConversationState {
     /// Reloads necessary fields after being deserialized. This should be called after
     /// deserialization.
     pub async fn reload_serialized_state(&mut self, ctx: Arc<Context>, updates: Option<SharedWriter>) {
+        self.version = CONVERSATION_STATE_VERSION;
         self.updates = updates;
 
         // Try to reload ContextManager, but do not return an error if we fail.
@@ -191,6 +256,11 @@ impl ConversationState {
         }
     }
 
+    /// Returns the version of the format this state was serialized with.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
     pub fn latest_summary(&self) -> Option<&str> {
         self.latest_summary.as_deref()
     }
@@ -203,11 +273,63 @@ impl ConversationState {
     pub fn clear(&mut self, preserve_summary: bool) {
         self.next_message = None;
         self.history.clear();
+        self.trimmed_turns.clear();
         if !preserve_summary {
             self.latest_summary = None;
         }
     }
 
+    /// Returns the number of turns in the history. A turn starts with a user prompt and includes
+    /// every tool use round trip that followed it.
+    pub fn turn_count(&self) -> usize {
+        self.history
+            .iter()
+            .filter(|(user, _)| !user.has_tool_use_results())
+            .count()
+    }
+
+    /// Truncates the conversation to just after turn `turn` (1-indexed, `0` discards every turn),
+    /// removing later history along with their transcript entries. Returns the number of turns
+    /// that were removed.
+    pub fn rewind(&mut self, turn: usize) -> usize {
+        let turn_count = self.turn_count();
+        if turn >= turn_count {
+            return 0;
+        }
+
+        // The index of the history entry that starts the turn following `turn`.
+        let cutoff = self
+            .history
+            .iter()
+            .enumerate()
+            .filter(|(_, (user, _))| !user.has_tool_use_results())
+            .nth(turn)
+            .map_or(self.history.len(), |(i, _)| i);
+        let removed_entries = self.history.len() - cutoff;
+        self.history.truncate(cutoff);
+        self.next_message = None;
+
+        // Every history entry has exactly one assistant transcript entry, so drop everything after
+        // the last assistant entry that is still part of the history.
+        let mut assistant_entries_seen = 0;
+        let transcript_len = self
+            .transcript
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, entry)| {
+                if is_assistant_transcript_entry(entry) {
+                    assistant_entries_seen += 1;
+                }
+                assistant_entries_seen > removed_entries
+            })
+            .map_or(0, |(i, _)| i + 1);
+        self.transcript.truncate(transcript_len);
+
+        self.enforce_conversation_invariants();
+        turn_count - turn
+    }
+
     /// Appends a collection prompts into history and returns the last message in the collection.
     /// It asserts that the collection ends with a prompt that assumes the role of user.
     pub fn append_prompts(&mut self, mut prompts: VecDeque<Prompt>) -> Option<String> {
@@ -245,6 +367,37 @@ impl ConversationState {
         self.next_message = None;
     }
 
+    /// Clears [Self::next_message] after its request failed, keeping it around for
+    /// [Self::prepare_retry].
+    pub fn stash_failed_user_message(&mut self) {
+        self.failed_message = self.next_message.take();
+    }
+
+    /// Sets up the conversation so that the most recent user message can be sent again.
+    ///
+    /// If the last request failed, its message is restored. Otherwise, the last assistant response
+    /// is removed from the history (along with its transcript entry) and the user message that
+    /// prompted it becomes the next message. Returns `false` if there is nothing to retry.
+    pub fn prepare_retry(&mut self) -> bool {
+        if let Some(failed_message) = self.failed_message.take() {
+            self.next_message = Some(failed_message);
+        } else if let Some((user, _)) = self.history.pop_back() {
+            self.next_message = Some(user);
+            if self
+                .transcript
+                .back()
+                .is_some_and(|entry| is_assistant_transcript_entry(entry))
+            {
+                self.transcript.pop_back();
+            }
+        } else {
+            return false;
+        }
+
+        self.enforce_conversation_invariants();
+        true
+    }
+
     pub async fn set_next_user_message(&mut self, input: String) {
         debug_assert!(self.next_message.is_none(), "next_message should not exist");
         if let Some(next_message) = self.next_message.as_ref() {
@@ -260,6 +413,7 @@ impl ConversationState {
 
         let msg = UserMessage::new_prompt(input);
         self.next_message = Some(msg);
+        self.failed_message = None;
     }
 
     /// Sets the response message according to the currently set [Self::next_message].
@@ -269,12 +423,88 @@ impl ConversationState {
 
         self.append_assistant_transcript(&message);
         self.history.push_back((next_user_message, message));
+        self.save(database);
+    }
 
+    /// Saves the conversation for the current directory, so that it can be picked up again with
+    /// `q chat --resume`.
+    pub fn save(&self, database: &mut Database) {
         if let Ok(cwd) = std::env::current_dir() {
             database.set_conversation_by_path(cwd, self).ok();
         }
     }
 
+    /// Commits the text received so far from a response stream that was interrupted by the user,
+    /// so that follow-up prompts still have the context of what was said.
+    ///
+    /// The partial text is stored as a plain response without tool uses, so the next user message
+    /// doesn't need any tool results. Does nothing if there was no text or no pending user message.
+    pub fn push_interrupted_response(&mut self, partial_text: String, database: &mut Database) {
+        if partial_text.trim().is_empty() {
+            return;
+        }
+        self.push_partial_response(partial_text, "[Response interrupted by the user]", database);
+    }
+
+    /// Commits the text of a response that was cut off after `max_bytes` because of the
+    /// `chat.maxResponseBytes` setting, telling the model so on the next turn. Like
+    /// [Self::push_interrupted_response], tool uses requested before the cutoff are dropped.
+    pub fn push_truncated_response(&mut self, partial_text: String, max_bytes: usize, database: &mut Database) {
+        let note = format!("[Response truncated after {max_bytes} bytes by the user's chat.maxResponseBytes setting]");
+        self.push_partial_response(partial_text, &note, database);
+    }
+
+    fn push_partial_response(&mut self, partial_text: String, note: &str, database: &mut Database) {
+        if self.next_message.is_none() {
+            return;
+        }
+
+        let content = format!("{}\n\n{note}", partial_text.trim_end())
+            .trim_start()
+            .to_string();
+        self.push_assistant_message(AssistantMessage::new_response(None, content), database);
+    }
+
+    /// Returns the tool uses whose results in the most recent turn were errors.
+    pub fn failed_tool_uses(&self) -> Vec<AssistantToolUse> {
+        let mut turns = self.history.iter().rev();
+        let Some(results) = turns.next().and_then(|(user, _)| user.tool_use_results()) else {
+            return Vec::new();
+        };
+        let failed_ids = results
+            .iter()
+            .filter(|result| matches!(result.status, crate::api_client::model::ToolResultStatus::Error))
+            .map(|result| result.tool_use_id.as_str())
+            .collect::<HashSet<_>>();
+
+        turns
+            .next()
+            .and_then(|(_, assistant)| assistant.tool_uses())
+            .unwrap_or_default()
+            .iter()
+            .filter(|tool_use| failed_ids.contains(tool_use.id.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Adds a turn in which the user asks for `tool_uses` to be run again and the assistant
+    /// requests them, so that their results can be sent back like any other tool results.
+    pub fn push_redo_tool_uses(&mut self, tool_uses: Vec<AssistantToolUse>, database: &mut Database) {
+        let names = tool_uses
+            .iter()
+            .map(|tool_use| tool_use.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.next_message = Some(UserMessage::new_prompt(format!(
+            "Run the tools that failed in the last turn again: {names}"
+        )));
+        self.failed_message = None;
+        self.push_assistant_message(
+            AssistantMessage::new_tool_use(None, "Running the failed tools again.".to_string(), tool_uses),
+            database,
+        );
+    }
+
     /// Returns the conversation id.
     pub fn conversation_id(&self) -> &str {
         self.conversation_id.as_ref()
@@ -420,17 +650,38 @@ impl ConversationState {
         self.next_message = Some(UserMessage::new_tool_use_results(tool_results));
     }
 
-    pub fn add_tool_results_with_images(&mut self, tool_results: Vec<ToolUseResult>, images: Vec<ImageBlock>) {
-        debug_assert!(self.next_message.is_none());
-        self.next_message = Some(UserMessage::new_tool_use_results_with_images(tool_results, images));
+    /// Sets the next user message with "cancelled" tool results, preceded by the results of any
+    /// tools from the same batch that already completed.
+    pub fn abandon_tool_use(
+        &mut self,
+        completed: Vec<ToolUseResult>,
+        tools_to_be_abandoned: Vec<QueuedTool>,
+        deny_input: String,
+    ) {
+        let message =
+            UserMessage::new_cancelled_tool_uses(Some(deny_input), tools_to_be_abandoned.iter().map(|t| t.id.as_str()));
+        self.set_cancelled_tool_uses(completed, message);
     }
 
-    /// Sets the next user message with "cancelled" tool results.
-    pub fn abandon_tool_use(&mut self, tools_to_be_abandoned: Vec<QueuedTool>, deny_input: String) {
-        self.next_message = Some(UserMessage::new_cancelled_tool_uses(
-            Some(deny_input),
-            tools_to_be_abandoned.iter().map(|t| t.id.as_str()),
-        ));
+    /// Like [Self::abandon_tool_use], but rather than sending a new prompt, the tool uses that
+    /// weren't executed fail with `reason` so the model can adjust its approach.
+    pub fn decline_tool_use(
+        &mut self,
+        completed: Vec<ToolUseResult>,
+        tools_to_be_declined: Vec<QueuedTool>,
+        reason: &str,
+    ) {
+        let message = UserMessage::new_declined_tool_uses(reason, tools_to_be_declined.iter().map(|t| t.id.as_str()));
+        self.set_cancelled_tool_uses(completed, message);
+    }
+
+    /// Sets the next message to the cancelled tool uses of `message`, preceded by the results of
+    /// the tools that were `completed` before.
+    fn set_cancelled_tool_uses(&mut self, completed: Vec<ToolUseResult>, mut message: UserMessage) {
+        if let UserMessageContent::CancelledToolUses { tool_use_results, .. } = &mut message.content {
+            tool_use_results.splice(0..0, completed);
+        }
+        self.next_message = Some(message);
     }
 
     /// Returns a [FigConversationState] capable of being sent by [api_client::StreamingClient].
@@ -441,27 +692,90 @@ impl ConversationState {
         debug_assert!(self.next_message.is_some());
         self.enforce_conversation_invariants();
         self.history.drain(self.valid_history_range.1..);
-        self.history.drain(..self.valid_history_range.0);
+        self.trim_history(self.valid_history_range.0);
 
         let context = self.backend_conversation_state(run_hooks, false).await;
-        if !context.dropped_context_files.is_empty() {
-            let mut output = SharedWriter::stdout();
-            execute!(
-                output,
-                style::SetForegroundColor(Color::DarkYellow),
-                style::Print("\nSome context files are dropped due to size limit, please run "),
-                style::SetForegroundColor(Color::DarkGreen),
-                style::Print("/context show "),
-                style::SetForegroundColor(Color::DarkYellow),
-                style::Print("to learn more.\n"),
-                style::SetForegroundColor(style::Color::Reset)
-            )
-            .ok();
+        let has_dropped_files = !context.dropped_context_files.is_empty();
+        let omitted_files = context.omitted_context_files.len();
+        let state = context
+            .into_fig_conversation_state()
+            .expect("unable to construct conversation state");
+
+        if let Some(output) = self.updates.as_mut() {
+            if has_dropped_files {
+                execute!(
+                    output,
+                    style::SetForegroundColor(Color::DarkYellow),
+                    style::Print("\nSome context files are dropped due to size limit, please run "),
+                    style::SetForegroundColor(Color::DarkGreen),
+                    style::Print("/context show "),
+                    style::SetForegroundColor(Color::DarkYellow),
+                    style::Print("to learn more.\n"),
+                    style::SetForegroundColor(style::Color::Reset)
+                )
+                .ok();
+            }
+            if omitted_files > 0 {
+                execute!(
+                    output,
+                    style::SetForegroundColor(Color::DarkYellow),
+                    style::Print(format!(
+                        "\n{omitted_files} context file(s) were left out by chat.maxContextFiles, please run "
+                    )),
+                    style::SetForegroundColor(Color::DarkGreen),
+                    style::Print("/context show "),
+                    style::SetForegroundColor(Color::DarkYellow),
+                    style::Print("to learn more.\n"),
+                    style::SetForegroundColor(style::Color::Reset)
+                )
+                .ok();
+            }
         }
 
-        context
-            .into_fig_conversation_state()
-            .expect("unable to construct conversation state")
+        state
+    }
+
+    /// Drops turns from the front of the history according to [Self::history_strategy], where
+    /// `start` is the oldest message that can start a valid history.
+    fn trim_history(&mut self, start: usize) {
+        if start == 0 {
+            return;
+        }
+
+        match self.history_strategy {
+            HistoryStrategy::Recent => {
+                self.history.drain(..start);
+            },
+            HistoryStrategy::FirstAndLast => {
+                // `start` is where the second turn begins, so the turn after it is dropped instead.
+                match self
+                    .history
+                    .iter()
+                    .enumerate()
+                    .skip(start + 1)
+                    .find(|(_, (m, _))| !m.has_tool_use_results())
+                    .map(|v| v.0)
+                {
+                    Some(end) => {
+                        debug!("removing user/assistant response pairs {start}..{end} after the first turn");
+                        self.history.drain(start..end);
+                    },
+                    None => {
+                        self.history.drain(..start);
+                    },
+                }
+            },
+            HistoryStrategy::Summarize => {
+                let notes = self
+                    .history
+                    .drain(..start)
+                    .filter_map(|(user, assistant)| trimmed_turn_note(&user, &assistant))
+                    .collect::<Vec<_>>();
+                self.trimmed_turns.extend(notes);
+                let excess = self.trimmed_turns.len().saturating_sub(MAX_TRIMMED_TURN_NOTES);
+                self.trimmed_turns.drain(..excess);
+            },
+        }
     }
 
     pub async fn update_state(&mut self, force_update: bool) {
@@ -517,7 +831,8 @@ impl ConversationState {
             }
         }
 
-        let (context_messages, dropped_context_files) = self.context_messages(conversation_start_context).await;
+        let (context_messages, dropped_context_files, omitted_context_files) =
+            self.context_messages(conversation_start_context).await;
 
         BackendConversationState {
             conversation_id: self.conversation_id.as_str(),
@@ -527,6 +842,7 @@ impl ConversationState {
                 .range(self.valid_history_range.0..self.valid_history_range.1),
             context_messages,
             dropped_context_files,
+            omitted_context_files,
             tools: &self.tools,
         }
     }
@@ -599,6 +915,7 @@ impl ConversationState {
             user_input_message_context: None,
             user_intent: None,
             images: None,
+            model_id: None,
         };
 
         // If the last message contains tool uses, then add cancelled tool results to the summary
@@ -618,14 +935,17 @@ impl ConversationState {
         }
     }
 
-    pub fn replace_history_with_summary(&mut self, summary: String) {
-        self.history.drain(..(self.history.len().saturating_sub(1)));
+    /// Replaces the history with `summary`, keeping only the most recent `keep_turns` user and
+    /// assistant message pairs.
+    pub fn replace_history_with_summary(&mut self, summary: String, keep_turns: usize) {
+        self.history.drain(..(self.history.len().saturating_sub(keep_turns)));
         self.latest_summary = Some(summary);
-        // If the last message contains tool results, then we add the results to the content field
-        // instead. This is required to avoid validation errors.
+        // If the oldest kept message contains tool results, then we add the results to the content
+        // field instead since its tool uses were dropped. This is required to avoid validation
+        // errors.
         // TODO: this can break since the max user content size is less than the max tool response
         // size! Alternative could be to set the last tool use as part of the context messages.
-        if let Some((user, _)) = self.history.back_mut() {
+        if let Some((user, _)) = self.history.front_mut() {
             if let Some(tool_results) = user.tool_use_results() {
                 let tool_content: Vec<String> = tool_results
                     .iter()
@@ -635,6 +955,7 @@ impl ConversationState {
                                 .map_err(|err| error!(?err, "failed to serialize tool result"))
                                 .unwrap_or_default(),
                             ToolUseResultBlock::Text(s) => s.clone(),
+                            ToolUseResultBlock::Image(_) => IMAGE_RESULT_TEXT.to_string(),
                         })
                     })
                     .collect::<_>();
@@ -648,6 +969,7 @@ impl ConversationState {
                 user.content = UserMessageContent::Prompt { prompt };
             }
         }
+        self.enforce_conversation_invariants();
     }
 
     pub fn current_profile(&self) -> Option<&str> {
@@ -669,9 +991,25 @@ impl ConversationState {
     async fn context_messages(
         &mut self,
         conversation_start_context: Option<String>,
-    ) -> (Option<Vec<(UserMessage, AssistantMessage)>>, Vec<(String, String)>) {
+    ) -> (
+        Option<Vec<(UserMessage, AssistantMessage)>>,
+        Vec<(String, String)>,
+        Vec<String>,
+    ) {
         let mut context_content = String::new();
         let mut dropped_context_files = Vec::new();
+        let mut omitted_context_files = Vec::new();
+        let system_prompt = self
+            .system_prompt
+            .as_deref()
+            .or_else(|| self.context_manager.as_ref().and_then(|cm| cm.system_prompt()));
+        if let Some(prompt) = system_prompt {
+            context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+            context_content.push_str("These are my standing instructions for this project. Follow them for the entire conversation, even where they differ from your usual behavior.\n\n");
+            context_content.push_str(prompt);
+            context_content.push('\n');
+            context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+        }
         if let Some(summary) = &self.latest_summary {
             context_content.push_str(CONTEXT_ENTRY_START_HEADER);
             context_content.push_str("This summary contains ALL relevant information from our previous conversation including tool uses, results, code analysis, and file operations. YOU MUST reference this information when answering questions and explicitly acknowledge specific details from the summary when they're relevant to the current question.\n\n");
@@ -680,14 +1018,24 @@ impl ConversationState {
             context_content.push('\n');
             context_content.push_str(CONTEXT_ENTRY_END_HEADER);
         }
+        if !self.trimmed_turns.is_empty() {
+            context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+            context_content.push_str("These are shortened notes on the earliest turns of our conversation, which were dropped to keep it within size limits. Use them to keep track of what was already discussed.\n\n");
+            for note in &self.trimmed_turns {
+                context_content.push_str(note);
+                context_content.push('\n');
+            }
+            context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+        }
 
         // Add context files if available
         if let Some(context_manager) = self.context_manager.as_mut() {
             match context_manager.collect_context_files_with_limit().await {
-                Ok((files_to_use, files_dropped)) => {
+                Ok((files_to_use, files_dropped, files_omitted)) => {
                     if !files_dropped.is_empty() {
                         dropped_context_files.extend(files_dropped);
                     }
+                    omitted_context_files = files_omitted;
 
                     if !files_to_use.is_empty() {
                         context_content.push_str(CONTEXT_ENTRY_START_HEADER);
@@ -711,9 +1059,13 @@ impl ConversationState {
             self.context_message_length = Some(context_content.len());
             let user_msg = UserMessage::new_prompt(context_content);
             let assistant_msg = AssistantMessage::new_response(None, "I will fully incorporate this information when generating my responses, and explicitly acknowledge relevant parts of the summary when answering questions.".into());
-            (Some(vec![(user_msg, assistant_msg)]), dropped_context_files)
+            (
+                Some(vec![(user_msg, assistant_msg)]),
+                dropped_context_files,
+                omitted_context_files,
+            )
         } else {
-            (None, dropped_context_files)
+            (None, dropped_context_files, omitted_context_files)
         }
     }
 
@@ -829,6 +1181,7 @@ pub struct BackendConversationStateImpl<'a, T, U> {
     pub history: T,
     pub context_messages: U,
     pub dropped_context_files: Vec<(String, String)>,
+    pub omitted_context_files: Vec<String>,
     pub tools: &'a HashMap<ToolOrigin, Vec<Tool>>,
 }
 
@@ -943,6 +1296,34 @@ fn format_hook_context<'a>(hook_results: impl IntoIterator<Item = &'a (Hook, Str
     context_content
 }
 
+/// Whether a transcript entry was created by [ConversationState::append_assistant_transcript].
+fn is_assistant_transcript_entry(entry: &str) -> bool {
+    entry
+        .rsplit_once('\n')
+        .is_some_and(|(_, last_line)| last_line.starts_with("[Tool uses: "))
+}
+
+/// Returns a shortened note on a turn dropped from the history, or [None] if it has no text to
+/// note, e.g. a round trip made up only of tool uses and their results.
+fn trimmed_turn_note(user: &UserMessage, assistant: &AssistantMessage) -> Option<String> {
+    let shorten = |text: &str| {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        match truncate_safe(&text, MAX_TRIMMED_TURN_NOTE_BYTES) {
+            short if short.len() < text.len() => format!("{short}..."),
+            short => short.to_string(),
+        }
+    };
+
+    let mut note = Vec::new();
+    if let Some(prompt) = user.prompt().filter(|p| !p.trim().is_empty()) {
+        note.push(format!("- I asked: {}", shorten(prompt)));
+    }
+    if !assistant.content().trim().is_empty() {
+        note.push(format!("- You answered: {}", shorten(assistant.content())));
+    }
+    (!note.is_empty()).then(|| note.join("\n"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::context::{
@@ -1055,7 +1436,10 @@ mod tests {
         let mut conversation_state = ConversationState::new(
             Context::new(),
             "fake_conv_id",
-            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
             None,
             None,
             tool_manager,
@@ -1074,6 +1458,108 @@ mod tests {
         }
     }
 
+    /// Returns the prompts left in the history after sending a conversation that's too long to
+    /// send whole, trimmed with `strategy`, along with the context message that was sent.
+    async fn trim_long_history(strategy: HistoryStrategy) -> (Vec<String>, String) {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        conversation_state.history_strategy = strategy;
+
+        // The first turn includes a tool use round trip, so that it spans two messages.
+        conversation_state.set_next_user_message("0".to_string()).await;
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_tool_use(None, "0".to_string(), vec![AssistantToolUse {
+                id: "tool_id".to_string(),
+                name: "fs_read".to_string(),
+                ..Default::default()
+            }]),
+            &mut database,
+        );
+        conversation_state.add_tool_results(vec![ToolUseResult {
+            tool_use_id: "tool_id".to_string(),
+            content: vec![ToolUseResultBlock::Text("result".to_string())],
+            status: ToolResultStatus::Success,
+        }]);
+        conversation_state.push_assistant_message(AssistantMessage::new_response(None, "0".to_string()), &mut database);
+        for i in 1..MAX_CONVERSATION_STATE_HISTORY_LEN / 2 {
+            conversation_state.set_next_user_message(i.to_string()).await;
+            conversation_state
+                .push_assistant_message(AssistantMessage::new_response(None, i.to_string()), &mut database);
+        }
+
+        conversation_state.set_next_user_message("next".to_string()).await;
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        let context = match s.history.as_ref().and_then(|h| h.first()) {
+            Some(ChatMessage::UserInputMessage(msg)) => msg.content.clone(),
+            _ => String::new(),
+        };
+        assert_conversation_state_invariants(s, 0);
+
+        let prompts = conversation_state
+            .history
+            .iter()
+            .filter_map(|(user, _)| user.prompt().map(str::to_string))
+            .collect();
+        (prompts, context)
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_history_strategy_recent() {
+        let (prompts, context) = trim_long_history(HistoryStrategy::Recent).await;
+        assert_eq!(prompts[..2], ["1", "2"]);
+        assert_eq!(
+            prompts.last().unwrap(),
+            &(MAX_CONVERSATION_STATE_HISTORY_LEN / 2 - 1).to_string()
+        );
+        assert!(!context.contains("I asked: 0"));
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_history_strategy_first_and_last() {
+        let (prompts, context) = trim_long_history(HistoryStrategy::FirstAndLast).await;
+        assert_eq!(prompts[..3], ["0", "2", "3"]);
+        assert_eq!(
+            prompts.last().unwrap(),
+            &(MAX_CONVERSATION_STATE_HISTORY_LEN / 2 - 1).to_string()
+        );
+        assert!(!context.contains("I asked: 1"));
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_history_strategy_summarize() {
+        let (prompts, context) = trim_long_history(HistoryStrategy::Summarize).await;
+        assert_eq!(prompts[..2], ["1", "2"]);
+        assert!(context.contains("- I asked: 0\n- You answered: 0\n"));
+    }
+
+    #[test]
+    fn test_history_strategy_from_setting() {
+        assert_eq!(HistoryStrategy::from_setting(None), HistoryStrategy::Recent);
+        assert_eq!(
+            HistoryStrategy::from_setting(Some("firstAndLast")),
+            HistoryStrategy::FirstAndLast
+        );
+        assert_eq!(
+            HistoryStrategy::from_setting(Some("summarize")),
+            HistoryStrategy::Summarize
+        );
+        assert_eq!(HistoryStrategy::from_setting(Some("bogus")), HistoryStrategy::Recent);
+    }
+
     #[tokio::test]
     async fn test_conversation_state_history_handling_with_tool_results() {
         let mut database = Database::new().await.unwrap();
@@ -1081,7 +1567,10 @@ mod tests {
 
         // Build a long conversation history of tool use results.
         let mut tool_manager = ToolManager::default();
-        let tool_config = tool_manager.load_tools(&database, &mut output).await.unwrap();
+        let tool_config = tool_manager
+            .load_tools(&Context::new(), &database, &mut output)
+            .await
+            .unwrap();
         let mut conversation_state = ConversationState::new(
             Context::new(),
             "fake_conv_id",
@@ -1161,7 +1650,10 @@ mod tests {
         let mut conversation_state = ConversationState::new(
             ctx,
             "fake_conv_id",
-            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
             None,
             None,
             tool_manager,
@@ -1197,6 +1689,74 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_conversation_state_with_system_prompt() {
+        let database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write(AMAZONQ_FILENAME, "test context").await.unwrap();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            ctx,
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        conversation_state
+            .context_manager
+            .as_mut()
+            .unwrap()
+            .set_system_prompt(Some("Always answer in haiku.".to_string()))
+            .await
+            .unwrap();
+
+        conversation_state.set_next_user_message("hello".to_string()).await;
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        let Some(ChatMessage::UserInputMessage(user)) = s.history.as_ref().and_then(|h| h.first()) else {
+            panic!("Expected the first message to be the context message");
+        };
+        let prompt_at = user.content.find("Always answer in haiku.").unwrap();
+        assert!(prompt_at < user.content.find("test context").unwrap());
+
+        // An empty prompt leaves the context as it was.
+        conversation_state
+            .context_manager
+            .as_mut()
+            .unwrap()
+            .set_system_prompt(Some("  ".to_string()))
+            .await
+            .unwrap();
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        let Some(ChatMessage::UserInputMessage(user)) = s.history.as_ref().and_then(|h| h.first()) else {
+            panic!("Expected the first message to be the context message");
+        };
+        assert!(!user.content.contains("standing instructions"));
+
+        // A prompt from --system-prompt-file is used instead of the profile's.
+        conversation_state
+            .context_manager
+            .as_mut()
+            .unwrap()
+            .set_system_prompt(Some("Always answer in haiku.".to_string()))
+            .await
+            .unwrap();
+        conversation_state.system_prompt = Some("Answer in one sentence.".to_string());
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        let Some(ChatMessage::UserInputMessage(user)) = s.history.as_ref().and_then(|h| h.first()) else {
+            panic!("Expected the first message to be the context message");
+        };
+        assert!(user.content.contains("Answer in one sentence."));
+        assert!(!user.content.contains("Always answer in haiku."));
+    }
+
     #[tokio::test]
     async fn test_conversation_state_additional_context() {
         // tracing_subscriber::fmt::try_init().ok();
@@ -1231,7 +1791,10 @@ mod tests {
         let mut conversation_state = ConversationState::new(
             ctx,
             "fake_conv_id",
-            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
             None,
             Some(SharedWriter::stdout()),
             tool_manager,
@@ -1265,4 +1828,297 @@ mod tests {
             conversation_state.set_next_user_message(i.to_string()).await;
         }
     }
+
+    #[tokio::test]
+    async fn test_conversation_state_prepare_retry() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+
+        // Nothing to retry in an empty conversation.
+        assert!(!conversation_state.prepare_retry());
+
+        conversation_state.append_user_transcript("hello");
+        conversation_state.set_next_user_message("hello".to_string()).await;
+        conversation_state
+            .push_assistant_message(AssistantMessage::new_response(None, "hi".to_string()), &mut database);
+
+        // Retrying a completed turn removes the response and resends the prompt.
+        assert!(conversation_state.prepare_retry());
+        assert!(conversation_state.history.is_empty());
+        assert_eq!(conversation_state.transcript.len(), 1);
+        assert_eq!(
+            conversation_state.next_user_message().and_then(|m| m.prompt()),
+            Some("hello")
+        );
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        assert_conversation_state_invariants(s, 0);
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_response(None, "hi again".to_string()),
+            &mut database,
+        );
+
+        // Retrying after a failed request resends the failed message instead.
+        conversation_state.set_next_user_message("failed".to_string()).await;
+        conversation_state.stash_failed_user_message();
+        assert!(conversation_state.next_user_message().is_none());
+        assert!(conversation_state.prepare_retry());
+        assert_eq!(conversation_state.history.len(), 1);
+        assert_eq!(
+            conversation_state.next_user_message().and_then(|m| m.prompt()),
+            Some("failed")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_redo_failed_tool_uses() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        assert!(conversation_state.failed_tool_uses().is_empty());
+
+        let tool_use = |id: &str| AssistantToolUse {
+            id: id.to_string(),
+            name: "use_aws".to_string(),
+            args: serde_json::Value::Null,
+            ..Default::default()
+        };
+        let result = |id: &str, status| ToolUseResult {
+            tool_use_id: id.to_string(),
+            content: vec![],
+            status,
+        };
+        conversation_state
+            .set_next_user_message("list my buckets".to_string())
+            .await;
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_tool_use(None, String::new(), vec![tool_use("1"), tool_use("2")]),
+            &mut database,
+        );
+        conversation_state.add_tool_results(vec![
+            result("1", ToolResultStatus::Success),
+            result("2", ToolResultStatus::Error),
+        ]);
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_response(None, "The second call failed".to_string()),
+            &mut database,
+        );
+
+        let failed = conversation_state.failed_tool_uses();
+        assert_eq!(failed.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec!["2"]);
+
+        // The redone tool uses get their own turn, ready for their results.
+        conversation_state.push_redo_tool_uses(vec![tool_use("3")], &mut database);
+        conversation_state.add_tool_results(vec![result("3", ToolResultStatus::Success)]);
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        assert_conversation_state_invariants(s, 3);
+        assert!(conversation_state.failed_tool_uses().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_push_interrupted_response() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+
+        conversation_state.set_next_user_message("hello".to_string()).await;
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        assert_conversation_state_invariants(s, 0);
+
+        // Nothing is committed if no text was received yet.
+        conversation_state.push_interrupted_response("  \n".to_string(), &mut database);
+        assert!(conversation_state.history.is_empty());
+
+        conversation_state.push_interrupted_response("Here is the first part\n".to_string(), &mut database);
+        assert_eq!(conversation_state.history.len(), 1);
+        assert_eq!(
+            conversation_state.history[0].1.content(),
+            "Here is the first part\n\n[Response interrupted by the user]"
+        );
+
+        // The follow-up prompt is sent as a regular user message after the partial response.
+        conversation_state.set_next_user_message("go on".to_string()).await;
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        assert_conversation_state_invariants(s, 1);
+        assert_eq!(
+            conversation_state.next_user_message().and_then(|m| m.prompt()),
+            Some("go on")
+        );
+        assert!(!conversation_state.next_user_message().unwrap().has_tool_use_results());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_replace_history_with_summary() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+
+        for i in 0..5 {
+            conversation_state.set_next_user_message(i.to_string()).await;
+            conversation_state
+                .push_assistant_message(AssistantMessage::new_response(None, i.to_string()), &mut database);
+        }
+
+        conversation_state.replace_history_with_summary("summary".to_string(), 2);
+        assert_eq!(conversation_state.latest_summary(), Some("summary"));
+        let prompts = conversation_state
+            .history
+            .iter()
+            .filter_map(|(user, _)| user.prompt())
+            .collect::<Vec<_>>();
+        assert_eq!(prompts, vec!["3", "4"]);
+
+        conversation_state.set_next_user_message("next".to_string()).await;
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        assert_conversation_state_invariants(s, 0);
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_rewind() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+
+        // Turn 1 is a plain response, turn 2 includes a tool use round trip, turn 3 is a plain
+        // response again.
+        conversation_state.append_user_transcript("turn 1");
+        conversation_state.set_next_user_message("turn 1".to_string()).await;
+        conversation_state.push_assistant_message(AssistantMessage::new_response(None, "1".to_string()), &mut database);
+        conversation_state.append_user_transcript("turn 2");
+        conversation_state.set_next_user_message("turn 2".to_string()).await;
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_tool_use(None, "2".to_string(), vec![AssistantToolUse {
+                id: "tool_id".to_string(),
+                name: "tool name".to_string(),
+                args: serde_json::Value::Null,
+                ..Default::default()
+            }]),
+            &mut database,
+        );
+        conversation_state.add_tool_results(vec![ToolUseResult {
+            tool_use_id: "tool_id".to_string(),
+            content: vec![],
+            status: ToolResultStatus::Success,
+        }]);
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_response(None, "2 done".to_string()),
+            &mut database,
+        );
+        conversation_state.append_user_transcript("turn 3");
+        conversation_state.set_next_user_message("turn 3".to_string()).await;
+        conversation_state.push_assistant_message(AssistantMessage::new_response(None, "3".to_string()), &mut database);
+        assert_eq!(conversation_state.turn_count(), 3);
+
+        // Rewinding past the end is a no-op.
+        assert_eq!(conversation_state.rewind(3), 0);
+        assert_eq!(conversation_state.history().len(), 4);
+
+        assert_eq!(conversation_state.rewind(1), 2);
+        assert_eq!(conversation_state.turn_count(), 1);
+        assert_eq!(conversation_state.history().len(), 1);
+        assert_eq!(conversation_state.history()[0].1.content(), "1");
+        assert_eq!(conversation_state.transcript.len(), 2);
+        assert_eq!(conversation_state.transcript[0], "> turn 1");
+
+        // The remaining history must still be valid to send.
+        conversation_state
+            .set_next_user_message("retry turn 2".to_string())
+            .await;
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        assert_conversation_state_invariants(s, 0);
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_version() {
+        let mut output = SharedWriter::null();
+        let database = Database::new().await.unwrap();
+        let mut tool_manager = ToolManager::default();
+        let conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager
+                .load_tools(&Context::new(), &database, &mut output)
+                .await
+                .unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        assert_eq!(conversation_state.version(), CONVERSATION_STATE_VERSION);
+
+        // States serialized before the version field existed should still load.
+        let mut value = serde_json::to_value(&conversation_state).unwrap();
+        assert_eq!(value["version"], CONVERSATION_STATE_VERSION);
+        value.as_object_mut().unwrap().remove("version");
+        let mut legacy: ConversationState = serde_json::from_value(value).unwrap();
+        assert_eq!(legacy.version(), 0);
+        legacy.reload_serialized_state(Context::new(), None).await;
+        assert_eq!(legacy.version(), CONVERSATION_STATE_VERSION);
+    }
 }
@@ -0,0 +1,197 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use eyre::Result;
+use serde::Serialize;
+
+use super::command::ExportFormat;
+use crate::platform::Context;
+
+/// A single transcript entry, reconstructed from the strings stored in
+/// [super::conversation_state::ConversationState::transcript].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+    User {
+        content: String,
+    },
+    Assistant {
+        content: String,
+        tool_uses: Vec<String>,
+    },
+    /// Anything else that was appended to the transcript, e.g. error messages.
+    Other {
+        content: String,
+    },
+}
+
+impl TranscriptEntry {
+    /// Parses an entry in the format written by `append_user_transcript` and
+    /// `append_assistant_transcript`.
+    pub fn parse(entry: &str) -> Self {
+        if let Some(prompt) = entry.strip_prefix("> ") {
+            return Self::User {
+                content: prompt.replace("> \n", "\n"),
+            };
+        }
+
+        match entry.rsplit_once('\n') {
+            Some((content, last_line)) if last_line.starts_with("[Tool uses: ") && last_line.ends_with(']') => {
+                let tool_uses = &last_line["[Tool uses: ".len()..last_line.len() - 1];
+                Self::Assistant {
+                    content: content.to_string(),
+                    tool_uses: match tool_uses {
+                        "none" => Vec::new(),
+                        tool_uses => tool_uses.split(',').map(str::to_string).collect(),
+                    },
+                }
+            },
+            _ => Self::Other {
+                content: entry.to_string(),
+            },
+        }
+    }
+}
+
+/// Renders the transcript as Markdown. User turns are written as `>` blocks, assistant turns as
+/// plain prose (code fences are kept as-is), and tool invocations as collapsible `<details>`
+/// sections.
+pub fn transcript_to_markdown<'a>(transcript: impl IntoIterator<Item = &'a String>) -> String {
+    let mut out = String::new();
+    for entry in transcript {
+        match TranscriptEntry::parse(entry) {
+            TranscriptEntry::User { content } => {
+                for line in content.lines() {
+                    out.push_str(format!("> {line}").trim_end());
+                    out.push('\n');
+                }
+            },
+            TranscriptEntry::Assistant { content, tool_uses } => {
+                out.push_str(content.trim_end());
+                out.push('\n');
+                if !tool_uses.is_empty() {
+                    out.push_str("\n<details>\n<summary>Tool uses</summary>\n\n");
+                    for tool_use in tool_uses {
+                        out.push_str(&format!("- `{tool_use}`\n"));
+                    }
+                    out.push_str("\n</details>\n");
+                }
+            },
+            TranscriptEntry::Other { content } => {
+                out.push_str(content.trim_end());
+                out.push('\n');
+            },
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn transcript_to_json<'a>(transcript: impl IntoIterator<Item = &'a String>) -> Result<String> {
+    let entries = transcript
+        .into_iter()
+        .map(|entry| TranscriptEntry::parse(entry))
+        .collect::<Vec<_>>();
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+/// Writes the transcript to `path` in the given format, returning the absolute path written.
+///
+/// The contents are first written to a sibling temporary file which is then renamed over `path`, so
+/// a failed export never leaves a partially written file behind.
+pub async fn export_transcript<'a>(
+    ctx: &Context,
+    transcript: impl IntoIterator<Item = &'a String>,
+    path: impl AsRef<Path>,
+    format: ExportFormat,
+) -> Result<PathBuf> {
+    let path = path.as_ref();
+    let contents = match format {
+        ExportFormat::Markdown => transcript_to_markdown(transcript),
+        ExportFormat::Json => transcript_to_json(transcript)?,
+    };
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    if let Err(err) = ctx.fs().write(&tmp_path, contents).await {
+        let _ = ctx.fs().remove_file(&tmp_path).await;
+        return Err(err.into());
+    }
+    if let Err(err) = ctx.fs().rename(&tmp_path, path).await {
+        let _ = ctx.fs().remove_file(&tmp_path).await;
+        return Err(err.into());
+    }
+
+    Ok(ctx.fs().canonicalize(path).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript() -> Vec<String> {
+        vec![
+            "> first line> \nsecond line".to_string(),
+            "Here you go:\n```rust\nfn main() {}\n```\n[Tool uses: fs_read,execute_bash]".to_string(),
+            "> thanks".to_string(),
+            "You're welcome!\n[Tool uses: none]".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_transcript_to_markdown() {
+        assert_eq!(
+            transcript_to_markdown(&transcript()),
+            "> first line\n> second line\n\n\
+             Here you go:\n```rust\nfn main() {}\n```\n\n\
+             <details>\n<summary>Tool uses</summary>\n\n- `fs_read`\n- `execute_bash`\n\n</details>\n\n\
+             > thanks\n\n\
+             You're welcome!\n\n"
+        );
+    }
+
+    #[test]
+    fn test_transcript_entry_parse() {
+        assert_eq!(TranscriptEntry::parse("> hi"), TranscriptEntry::User {
+            content: "hi".to_string()
+        });
+        assert_eq!(
+            TranscriptEntry::parse("hello\n[Tool uses: none]"),
+            TranscriptEntry::Assistant {
+                content: "hello".to_string(),
+                tool_uses: vec![],
+            }
+        );
+        assert_eq!(
+            TranscriptEntry::parse("Amazon Q is having trouble"),
+            TranscriptEntry::Other {
+                content: "Amazon Q is having trouble".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_transcript() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/exports").await.unwrap();
+
+        export_transcript(&ctx, &transcript(), "/exports/chat.json", ExportFormat::Json)
+            .await
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&ctx.fs().read_to_string("/exports/chat.json").await.unwrap()).unwrap();
+        assert_eq!(json[0]["role"], "user");
+        assert_eq!(json[1]["tool_uses"][1], "execute_bash");
+        assert!(!ctx.fs().exists("/exports/chat.json.tmp"));
+
+        assert!(
+            export_transcript(&ctx, &transcript(), "/missing/chat.md", ExportFormat::Markdown)
+                .await
+                .is_err()
+        );
+        assert!(!ctx.fs().exists("/missing/chat.md"));
+    }
+}
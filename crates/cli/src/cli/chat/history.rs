@@ -0,0 +1,187 @@
+//! Listing and searching the turns of the current session for `/history`.
+
+use std::ops::Range;
+
+use crossterm::style::Stylize;
+
+use super::export::TranscriptEntry;
+
+/// Number of matching lines shown for each turn when searching.
+const MAX_MATCHING_LINES_PER_TURN: usize = 3;
+
+/// Renders the transcript as styled lines, one header per turn numbered from 1.
+///
+/// Without a query, each turn is shown with its first line of text. With a query, only turns
+/// containing it (case-insensitively) are listed, along with the matching lines with the matches
+/// highlighted.
+pub fn history_lines<'a>(
+    transcript: impl IntoIterator<Item = &'a String>,
+    query: Option<&str>,
+    width: usize,
+) -> Vec<String> {
+    let query = query.filter(|q| !q.is_empty());
+    let mut lines = Vec::new();
+    for (i, entry) in transcript.into_iter().enumerate() {
+        let (role, content) = match TranscriptEntry::parse(entry) {
+            TranscriptEntry::User { content } => ("You", content),
+            TranscriptEntry::Assistant { content, .. } => ("Q", content),
+            TranscriptEntry::Other { content } => ("Info", content),
+        };
+        let header = format!("{} {}", format!("[{}]", i + 1).dark_grey(), role.bold());
+        // Leave room for the indentation in front of each line.
+        let max_chars = width.saturating_sub(6).max(10);
+
+        let Some(query) = query else {
+            let preview = content.lines().find(|line| !line.trim().is_empty()).unwrap_or_default();
+            lines.push(format!("{header}  {}", truncate(preview.trim(), max_chars)));
+            continue;
+        };
+
+        let matching = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !match_ranges(line, query).is_empty())
+            .map(|line| excerpt(line, query, max_chars))
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            continue;
+        }
+
+        lines.push(header);
+        for line in matching.iter().take(MAX_MATCHING_LINES_PER_TURN) {
+            lines.push(format!("    {}", highlight(line, query)));
+        }
+        if matching.len() > MAX_MATCHING_LINES_PER_TURN {
+            lines.push(format!(
+                "    {}",
+                format!("… {} more matching lines", matching.len() - MAX_MATCHING_LINES_PER_TURN).dark_grey()
+            ));
+        }
+    }
+    lines
+}
+
+/// Returns the byte ranges of the non-overlapping, case-insensitive occurrences of `query` in
+/// `text`.
+fn match_ranges(text: &str, query: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut text_chars = text[start..].char_indices();
+        let mut query_chars = query.chars();
+        let end = loop {
+            match (query_chars.next(), text_chars.next()) {
+                (None, Some((i, _))) => break Some(start + i),
+                (None, None) => break Some(text.len()),
+                (Some(q), Some((_, t))) if q.to_lowercase().eq(t.to_lowercase()) => continue,
+                _ => break None,
+            }
+        };
+        let next_char_len = text[start..].chars().next().map_or(1, char::len_utf8);
+        match end {
+            Some(end) if end > start => {
+                ranges.push(start..end);
+                start = end;
+            },
+            _ => start += next_char_len,
+        }
+    }
+    ranges
+}
+
+fn highlight(text: &str, query: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for range in match_ranges(text, query) {
+        out.push_str(&text[last..range.start]);
+        out.push_str(&text[range.clone()].yellow().bold().to_string());
+        last = range.end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((i, _)) => format!("{}…", text[..i].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Shortens `line` to `max_chars` like [truncate], but starts further in if that's needed to keep
+/// the first match of `query` in view.
+fn excerpt(line: &str, query: &str, max_chars: usize) -> String {
+    let Some(first) = match_ranges(line, query).into_iter().next() else {
+        return truncate(line, max_chars);
+    };
+    if line[..first.end].chars().count() <= max_chars {
+        return truncate(line, max_chars);
+    }
+
+    // Keep some of the text in front of the match for context.
+    let skip = line[..first.start].chars().count().saturating_sub(max_chars / 4);
+    let start = line.char_indices().nth(skip).map_or(line.len(), |(i, _)| i);
+    // Start at the next word rather than partway through one.
+    let start = line[start..first.start]
+        .find(char::is_whitespace)
+        .map_or(start, |i| start + i + 1);
+    format!("…{}", truncate(line[start..].trim_start(), max_chars.saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(lines: Vec<String>) -> Vec<String> {
+        lines.into_iter().map(strip_ansi_escapes::strip_str).collect()
+    }
+
+    #[test]
+    fn test_match_ranges() {
+        assert_eq!(match_ranges("Rust is rusty", "rust"), vec![0..4, 8..12]);
+        assert_eq!(match_ranges("aaa", "aa"), vec![0..2]);
+        assert_eq!(match_ranges("héllo HÉLLO", "héllo"), vec![0..6, 7..13]);
+        assert!(match_ranges("hello", "world").is_empty());
+    }
+
+    #[test]
+    fn test_history_lines() {
+        let transcript = vec![
+            "> how do I read a file?".to_string(),
+            "\nUse fs::read_to_string.\nIt reads the whole FILE.\n[Tool uses: none]".to_string(),
+            "> thanks".to_string(),
+        ];
+
+        assert_eq!(strip(history_lines(&transcript, None, 80)), vec![
+            "[1] You  how do I read a file?",
+            "[2] Q  Use fs::read_to_string.",
+            "[3] You  thanks",
+        ]);
+        assert_eq!(strip(history_lines(&transcript, Some("file"), 80)), vec![
+            "[1] You",
+            "    how do I read a file?",
+            "[2] Q",
+            "    It reads the whole FILE.",
+        ]);
+        assert_eq!(strip(history_lines(&transcript, None, 20)), vec![
+            "[1] You  how do I read…",
+            "[2] Q  Use fs::read_t…",
+            "[3] You  thanks",
+        ]);
+    }
+
+    #[test]
+    fn test_history_lines_matches_past_width() {
+        let transcript = vec!["> please explain why the build fails with a linker error".to_string()];
+
+        // The match is past the width of the terminal, so the line is shown from around it.
+        assert_eq!(strip(history_lines(&transcript, Some("linker"), 30)), vec![
+            "[1] You",
+            "    …a linker error",
+        ]);
+        assert_eq!(strip(history_lines(&transcript, Some("please"), 30)), vec![
+            "[1] You",
+            "    please explain why the b…",
+        ]);
+    }
+}
@@ -1,15 +1,39 @@
 use eyre::Result;
 use rustyline::error::ReadlineError;
+use rustyline::{
+    Cmd,
+    ConditionalEventHandler,
+    Event,
+    EventContext,
+    EventHandler,
+    KeyEvent,
+    Movement,
+    RepeatCount,
+};
+use tracing::warn;
 
-use super::prompt::rl;
+use super::prompt::{
+    history_size,
+    rl,
+};
 #[cfg(unix)]
 use super::skim_integration::SkimCommandSelector;
+use super::util::editor::{
+    edit_in_editor,
+    editor_command,
+    with_cooked_terminal,
+};
+use super::util::redact::redact_secrets;
 use crate::database::Database;
+use crate::platform::Context;
+use crate::util::directories;
 
 #[derive(Debug)]
 pub struct InputSource(inner::Inner);
 
 mod inner {
+    use std::path::PathBuf;
+
     use rustyline::Editor;
     use rustyline::history::FileHistory;
 
@@ -17,22 +41,61 @@ mod inner {
 
     #[derive(Debug)]
     pub enum Inner {
-        Readline(Editor<ChatHelper, FileHistory>),
-        #[allow(dead_code)]
-        Mock {
-            index: usize,
-            lines: Vec<String>,
+        Readline {
+            rl: Editor<ChatHelper, FileHistory>,
+            /// Where the history is saved to, if it's persisted across sessions.
+            history_path: Option<PathBuf>,
+            /// Text the next prompt starts out with, see [super::InputSource::set_initial_text].
+            initial_text: Option<String>,
         },
+        /// Lines given up front, read in order.
+        Lines { index: usize, lines: Vec<String> },
     }
 }
 
 impl InputSource {
     pub fn new(
+        ctx: &Context,
         database: &Database,
         sender: std::sync::mpsc::Sender<Option<String>>,
         receiver: std::sync::mpsc::Receiver<Vec<String>>,
     ) -> Result<Self> {
-        Ok(Self(inner::Inner::Readline(rl(database, sender, receiver)?)))
+        let mut rl = rl(database, sender, receiver)?;
+
+        // Prompts from previous sessions are loaded so they can be recalled with the up arrow.
+        // Failing to do so isn't worth interrupting the chat for.
+        let history_path = match directories::chat_history_path(ctx) {
+            Ok(path) if history_size(database) > 0 => {
+                if let Some(parent) = path.parent() {
+                    let _ = std::fs::create_dir_all(parent);
+                }
+                if path.exists() {
+                    if let Err(err) = rl.load_history(&path) {
+                        warn!(?err, ?path, "failed to load the chat history");
+                    }
+                }
+                Some(path)
+            },
+            Ok(_) => None,
+            Err(err) => {
+                warn!(?err, "failed to get the chat history path");
+                None
+            },
+        };
+
+        // Like bash, Ctrl+X Ctrl+E opens the prompt being typed in an editor.
+        rl.bind_sequence(
+            Event::KeySeq(vec![KeyEvent::ctrl('x'), KeyEvent::ctrl('e')]),
+            EventHandler::Conditional(Box::new(EditInEditor {
+                editor: editor_command(ctx.env()),
+            })),
+        );
+
+        Ok(Self(inner::Inner::Readline {
+            rl,
+            history_path,
+            initial_text: None,
+        }))
     }
 
     #[cfg(unix)]
@@ -49,7 +112,7 @@ impl InputSource {
 
         use crate::database::settings::Setting;
 
-        if let inner::Inner::Readline(rl) = &mut self.0 {
+        if let inner::Inner::Readline { rl, .. } = &mut self.0 {
             let key_char = match database.settings.get_string(Setting::SkimCommandKey) {
                 Some(key) if key.len() == 1 => key.chars().next().unwrap_or('s'),
                 _ => 's', // Default to 's' if setting is missing or invalid
@@ -61,26 +124,42 @@ impl InputSource {
         }
     }
 
-    #[allow(dead_code)]
+    /// Reads `lines` in order instead of prompting, e.g. for a [super::session::ChatSession]. The
+    /// input ends once they run out.
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        Self(inner::Inner::Lines { index: 0, lines })
+    }
+
+    #[cfg(test)]
     pub fn new_mock(lines: Vec<String>) -> Self {
-        Self(inner::Inner::Mock { index: 0, lines })
+        Self::from_lines(lines)
+    }
+
+    /// Starts the next prompt out with `text`, ready to be edited before it's sent.
+    pub fn set_initial_text(&mut self, text: String) {
+        if let inner::Inner::Readline { initial_text, .. } = &mut self.0 {
+            *initial_text = Some(text);
+        }
     }
 
     pub fn read_line(&mut self, prompt: Option<&str>) -> Result<Option<String>, ReadlineError> {
         match &mut self.0 {
-            inner::Inner::Readline(rl) => {
+            inner::Inner::Readline { rl, initial_text, .. } => {
                 let prompt = prompt.unwrap_or_default();
-                let curr_line = rl.readline(prompt);
+                let curr_line = match initial_text.take() {
+                    Some(text) => rl.readline_with_initial(prompt, (&text, "")),
+                    None => rl.readline(prompt),
+                };
                 match curr_line {
                     Ok(line) => {
-                        let _ = rl.add_history_entry(line.as_str());
+                        self.add_history_entry(&line);
                         Ok(Some(line))
                     },
                     Err(ReadlineError::Interrupted | ReadlineError::Eof) => Ok(None),
                     Err(err) => Err(err),
                 }
             },
-            inner::Inner::Mock { index, lines } => {
+            inner::Inner::Lines { index, lines } => {
                 *index += 1;
                 Ok(lines.get(*index - 1).cloned())
             },
@@ -90,11 +169,47 @@ impl InputSource {
     // We're keeping this method for potential future use
     #[allow(dead_code)]
     pub fn set_buffer(&mut self, content: &str) {
-        if let inner::Inner::Readline(rl) = &mut self.0 {
+        if let inner::Inner::Readline { rl, .. } = &mut self.0 {
             // Add to history so user can access it with up arrow
             let _ = rl.add_history_entry(content);
         }
     }
+
+    /// Adds a prompt to the history and saves it, unless it looks like it contains a secret,
+    /// since the history file is kept on disk in plain text.
+    fn add_history_entry(&mut self, line: &str) {
+        let inner::Inner::Readline { rl, history_path, .. } = &mut self.0 else {
+            return;
+        };
+        if redact_secrets(line).1 > 0 {
+            return;
+        }
+        if let Ok(true) = rl.add_history_entry(line) {
+            if let Some(path) = history_path {
+                if let Err(err) = rl.append_history(path) {
+                    warn!(?err, ?path, "failed to save the chat history");
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the prompt being typed with what it's saved as in the editor. If the editor can't be
+/// opened, the prompt is left as it is.
+struct EditInEditor {
+    editor: String,
+}
+
+impl ConditionalEventHandler for EditInEditor {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext<'_>) -> Option<Cmd> {
+        match with_cooked_terminal(|| edit_in_editor(&self.editor, ctx.line())) {
+            Ok(content) => Some(Cmd::Replace(Movement::WholeBuffer, Some(content))),
+            Err(err) => {
+                warn!(?err, editor = self.editor, "failed to edit the prompt in an editor");
+                Some(Cmd::Noop)
+            },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -113,4 +228,29 @@ mod tests {
         assert_eq!(input.read_line(None).unwrap().unwrap(), l3);
         assert!(input.read_line(None).unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn test_history_is_persisted() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let database = Database::new().await.unwrap();
+        let new_input = || {
+            let (sender, _) = std::sync::mpsc::channel();
+            let (_, receiver) = std::sync::mpsc::channel();
+            InputSource::new(&ctx, &database, sender, receiver).unwrap()
+        };
+        let history = |input: &InputSource| match &input.0 {
+            inner::Inner::Readline { rl, .. } => rl.history().iter().cloned().collect::<Vec<_>>(),
+            inner::Inner::Lines { .. } => unreachable!(),
+        };
+
+        let mut input = new_input();
+        input.add_history_entry("explain this\nmulti-line prompt");
+        input.add_history_entry("my DB_PASSWORD=hunter2 isn't working");
+        input.add_history_entry("/context show");
+
+        assert_eq!(history(&new_input()), vec![
+            "explain this\nmulti-line prompt".to_string(),
+            "/context show".to_string()
+        ]);
+    }
 }
@@ -0,0 +1,62 @@
+//! Exiting on a second Ctrl+C shortly after the first, whether the first one cancelled a response
+//! or tool use, or was pressed at the prompt.
+
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// How soon a second Ctrl+C has to follow the first to exit when `chat.interruptExitWindowMs`
+/// isn't set.
+pub const DEFAULT_EXIT_WINDOW: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone)]
+pub struct InterruptTracker {
+    window: Duration,
+    last: Option<Instant>,
+}
+
+impl InterruptTracker {
+    pub fn new(window: Duration) -> Self {
+        Self { window, last: None }
+    }
+
+    /// Records a Ctrl+C, returning whether it followed the previous one closely enough that the
+    /// app should exit.
+    pub fn interrupt(&mut self) -> bool {
+        self.interrupt_at(Instant::now())
+    }
+
+    /// Forgets the previous Ctrl+C, e.g. once the user submits a prompt, so that cancelling the
+    /// response to it doesn't count as a second press.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    fn interrupt_at(&mut self, now: Instant) -> bool {
+        let exit = self
+            .last
+            .is_some_and(|last| now.saturating_duration_since(last) <= self.window);
+        self.last = Some(now);
+        exit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_tracker() {
+        let mut tracker = InterruptTracker::new(Duration::from_secs(2));
+        let start = Instant::now();
+        assert!(!tracker.interrupt_at(start));
+        assert!(tracker.interrupt_at(start + Duration::from_secs(1)));
+
+        // Too late, so this counts as a first press again.
+        assert!(!tracker.interrupt_at(start + Duration::from_secs(5)));
+
+        tracker.reset();
+        assert!(!tracker.interrupt_at(start + Duration::from_secs(6)));
+    }
+}
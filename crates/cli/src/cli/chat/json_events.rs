@@ -0,0 +1,91 @@
+//! Newline-delimited JSON events written to STDOUT by `q chat --no-interactive --output-format
+//! json`, in place of the rendered Markdown response.
+//!
+//! Every line is a single JSON object with a `type` field:
+//!
+//! - `assistant_text`: `{"type":"assistant_text","text":"..."}` is a chunk of response text as it
+//!   is streamed. Concatenate the chunks of a response to get its full text.
+//! - `tool_use`: `{"type":"tool_use","id":"...","name":"...","args":{...}}` is a tool use requested
+//!   by the model.
+//! - `tool_result`: `{"type":"tool_result","id":"...","status":"success","content":[...]}` is the
+//!   result sent back to the model for a tool use. `status` is either `success` or `error`, and
+//!   each content item is either a string or a JSON value.
+//! - `end`: `{"type":"end","message_id":"..."}` marks the end of a single response. A session
+//!   contains one response per request, so there are several when tools are used. `message_id` may
+//!   be `null`.
+
+use std::io::Write;
+
+use serde::Serialize;
+
+use super::message::{
+    ToolUseResult,
+    ToolUseResultBlock,
+};
+use super::parser::ResponseEvent;
+use crate::api_client::model::ToolResultStatus;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonEvent {
+    AssistantText {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        args: serde_json::Value,
+    },
+    ToolResult {
+        id: String,
+        status: &'static str,
+        content: Vec<serde_json::Value>,
+    },
+    End {
+        message_id: Option<String>,
+    },
+}
+
+impl JsonEvent {
+    /// Returns [None] for events that aren't part of the schema, e.g. the start of a tool use.
+    pub fn from_response_event(event: &ResponseEvent) -> Option<Self> {
+        match event {
+            ResponseEvent::AssistantText(text) => Some(Self::AssistantText { text: text.clone() }),
+            ResponseEvent::ToolUseStart { .. } => None,
+            ResponseEvent::ToolUse(tool_use) => Some(Self::ToolUse {
+                id: tool_use.id.clone(),
+                name: tool_use.name.clone(),
+                args: tool_use.args.clone(),
+            }),
+            ResponseEvent::EndStream { message, .. } => Some(Self::End {
+                message_id: message.message_id().map(str::to_string),
+            }),
+        }
+    }
+
+    pub fn from_tool_result(result: &ToolUseResult) -> Self {
+        Self::ToolResult {
+            id: result.tool_use_id.clone(),
+            status: match result.status {
+                ToolResultStatus::Success => "success",
+                ToolResultStatus::Error => "error",
+            },
+            content: result
+                .content
+                .iter()
+                .map(|block| match block {
+                    ToolUseResultBlock::Text(text) => serde_json::Value::String(text.clone()),
+                    ToolUseResultBlock::Json(json) => json.clone(),
+                    ToolUseResultBlock::Image(image) => serde_json::json!({ "image": image.format }),
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the event as a single line and flushes, so consumers see it immediately.
+    pub fn write(&self, output: &mut impl Write) -> std::io::Result<()> {
+        serde_json::to_writer(&mut *output, self)?;
+        output.write_all(b"\n")?;
+        output.flush()
+    }
+}
@@ -19,6 +19,7 @@ use super::util::{
 use crate::api_client::model::{
     AssistantResponseMessage,
     EnvState,
+    IMAGE_RESULT_TEXT,
     ImageBlock,
     ToolResult,
     ToolResultContentBlock,
@@ -87,25 +88,38 @@ impl UserMessage {
         }
     }
 
-    pub fn new_tool_use_results(results: Vec<ToolUseResult>) -> Self {
-        Self {
-            additional_context: String::new(),
-            env_context: UserEnvContext::generate_new(),
-            content: UserMessageContent::ToolUseResults {
-                tool_use_results: results,
-            },
-            images: None,
+    /// Creates a [UserMessageContent::CancelledToolUses] without a prompt, where each tool use
+    /// results in an error telling the model why the user declined it.
+    pub fn new_declined_tool_uses<'a>(reason: &str, tool_use_ids: impl Iterator<Item = &'a str>) -> Self {
+        let mut message = Self::new_cancelled_tool_uses(None, tool_use_ids);
+        if let UserMessageContent::CancelledToolUses { tool_use_results, .. } = &mut message.content {
+            for result in tool_use_results {
+                result.content = vec![ToolUseResultBlock::Text(format!(
+                    "The user declined this tool use: {reason}"
+                ))];
+            }
         }
+        message
     }
 
-    pub fn new_tool_use_results_with_images(results: Vec<ToolUseResult>, images: Vec<ImageBlock>) -> Self {
+    /// Images in the results are attached to the message instead, since that's the only place the
+    /// backend accepts them.
+    pub fn new_tool_use_results(mut results: Vec<ToolUseResult>) -> Self {
+        let mut images = Vec::new();
+        for block in results.iter_mut().flat_map(|result| &mut result.content) {
+            if let ToolUseResultBlock::Image(image) = block {
+                images.push(image.clone());
+                *block = ToolUseResultBlock::Text(IMAGE_RESULT_TEXT.to_string());
+            }
+        }
+
         Self {
             additional_context: String::new(),
             env_context: UserEnvContext::generate_new(),
             content: UserMessageContent::ToolUseResults {
                 tool_use_results: results,
             },
-            images: Some(images),
+            images: (!images.is_empty()).then_some(images),
         }
     }
 
@@ -128,6 +142,7 @@ impl UserMessage {
                 ..Default::default()
             }),
             user_intent: None,
+            model_id: None,
         }
     }
 
@@ -158,6 +173,7 @@ impl UserMessage {
                 ..Default::default()
             }),
             user_intent: None,
+            model_id: None,
         }
     }
 
@@ -227,6 +243,7 @@ impl From<ToolUseResult> for ToolResult {
 pub enum ToolUseResultBlock {
     Json(serde_json::Value),
     Text(String),
+    Image(ImageBlock),
 }
 
 impl From<ToolUseResultBlock> for ToolResultContentBlock {
@@ -234,6 +251,7 @@ impl From<ToolUseResultBlock> for ToolResultContentBlock {
         match value {
             ToolUseResultBlock::Json(v) => Self::Json(serde_value_to_document(v)),
             ToolUseResultBlock::Text(s) => Self::Text(s),
+            ToolUseResultBlock::Image(image) => Self::Image(image),
         }
     }
 }
@@ -243,6 +261,7 @@ impl From<ToolResultContentBlock> for ToolUseResultBlock {
         match value {
             ToolResultContentBlock::Json(v) => Self::Json(document_to_serde_value(v)),
             ToolResultContentBlock::Text(s) => Self::Text(s),
+            ToolResultContentBlock::Image(image) => Self::Image(image),
         }
     }
 }
@@ -252,7 +271,7 @@ impl From<InvokeOutput> for ToolUseResultBlock {
         match value.output {
             OutputKind::Text(text) => Self::Text(text),
             OutputKind::Json(value) => Self::Json(value),
-            OutputKind::Images(_) => Self::Text("See images data supplied".to_string()),
+            OutputKind::Images(_) => Self::Text(IMAGE_RESULT_TEXT.to_string()),
         }
     }
 }
@@ -407,4 +426,27 @@ mod tests {
         assert!(env_state.operating_system.as_ref().is_some_and(|os| !os.is_empty()));
         println!("{env_state:?}");
     }
+
+    #[test]
+    fn test_tool_result_images_attached_to_message() {
+        let image = ImageBlock {
+            format: crate::api_client::model::ImageFormat::Png,
+            source: crate::api_client::model::ImageSource::Bytes(vec![1, 2, 3]),
+        };
+        let message = UserMessage::new_tool_use_results(vec![ToolUseResult {
+            tool_use_id: "1".to_string(),
+            content: vec![
+                ToolUseResultBlock::Text("screenshot taken".to_string()),
+                ToolUseResultBlock::Image(image),
+            ],
+            status: ToolResultStatus::Success,
+        }]);
+
+        assert_eq!(message.images.as_ref().map(Vec::len), Some(1));
+        let content = &message.tool_use_results().unwrap()[0].content;
+        assert!(matches!(&content[1], ToolUseResultBlock::Text(text) if text == IMAGE_RESULT_TEXT));
+
+        let message = UserMessage::new_tool_use_results(vec![]);
+        assert!(message.images.is_none());
+    }
 }
@@ -1,19 +1,36 @@
+mod audit_log;
+mod branches;
 pub mod cli;
 mod command;
 mod consts;
 mod context;
 mod conversation_state;
+mod export;
+mod history;
 mod hooks;
 mod input_source;
+mod interrupt;
+mod json_events;
 pub mod mcp;
 mod message;
+mod pager;
 mod parse;
 mod parser;
 mod prompt;
+mod recording;
+mod resize;
 mod server_messenger;
+// Sessions are only driven through the library (see lib.rs), so the binary's own copy of this
+// module is unused outside of its tests.
+#[cfg_attr(not(test), expect(dead_code, reason = "only used through the library"))]
+pub mod session;
 #[cfg(unix)]
 mod skim_integration;
+mod spinner_messages;
+mod stats;
+mod templates;
 mod token_counter;
+mod tool_failures;
 mod tool_manager;
 mod tools;
 pub mod util;
@@ -24,35 +41,56 @@ use std::collections::{
     HashSet,
     VecDeque,
 };
+use std::env;
 use std::io::{
     IsTerminal,
     Read,
     Write,
 };
-use std::process::{
-    Command as ProcessCommand,
-    ExitCode,
+use std::path::{
+    Path,
+    PathBuf,
 };
+use std::process::ExitCode;
 use std::sync::Arc;
-use std::time::Duration;
-use std::{
-    env,
-    fs,
+use std::time::{
+    Duration,
+    Instant,
 };
 
+use audit_log::AuditLog;
+use branches::Branches;
+use cli::OutputFormat;
 use command::{
     Command,
+    CopyTarget,
     PromptsSubcommand,
     ToolsSubcommand,
 };
 use consts::{
     CONTEXT_FILES_MAX_SIZE,
     CONTEXT_WINDOW_SIZE,
+    DEFAULT_COMPACT_KEEP_TURNS,
+    DEFAULT_DIFF_PREVIEW_MAX_LINES,
+    DEFAULT_MAX_CONTEXT_FILE_BYTES,
+    DEFAULT_MAX_RETRIES,
+    DEFAULT_TOOL_TIMEOUT_SECS,
     DUMMY_TOOL_NAME,
+    MODEL_OPTIONS,
+    RETRY_BASE_DELAY,
+    RETRY_MAX_DELAY,
+};
+use context::{
+    ContextFileOrder,
+    ContextManager,
+    PathValidity,
 };
-use context::ContextManager;
 pub use conversation_state::ConversationState;
-use conversation_state::TokenWarningLevel;
+use conversation_state::{
+    CONVERSATION_STATE_VERSION,
+    HistoryStrategy,
+    TokenWarningLevel,
+};
 use crossterm::style::{
     Attribute,
     Color,
@@ -68,6 +106,7 @@ use crossterm::{
 use eyre::{
     ErrReport,
     Result,
+    WrapErr,
     bail,
 };
 use hooks::{
@@ -75,6 +114,8 @@ use hooks::{
     HookTrigger,
 };
 use input_source::InputSource;
+use interrupt::InterruptTracker;
+use json_events::JsonEvent;
 use message::{
     AssistantMessage,
     AssistantToolUse,
@@ -82,29 +123,43 @@ use message::{
     ToolUseResultBlock,
 };
 use parse::{
+    MarkdownStream,
     ParseState,
-    interpret_markdown,
 };
 use parser::{
     RecvErrorKind,
     ResponseParser,
 };
+use prompt::PromptFormat;
 use rand::distr::{
     Alphanumeric,
     SampleString,
 };
+use recording::Recording;
 use regex::Regex;
+use resize::ResizeListener;
 use serde_json::Map;
+use spinner_messages::{
+    SpinnerMessage,
+    SpinnerSettings,
+};
 use spinners::{
     Spinner,
     Spinners,
 };
+use stats::{
+    SessionStats,
+    format_usage,
+};
 use thiserror::Error;
 use token_counter::{
+    CharCount,
+    CharCounter,
     TokenCount,
     TokenCounter,
 };
 use tokio::signal::ctrl_c;
+use tool_failures::ToolFailureTracker;
 use tool_manager::{
     GetPromptError,
     LoadingRecord,
@@ -113,13 +168,21 @@ use tool_manager::{
     ToolManager,
     ToolManagerBuilder,
 };
-use tools::gh_issue::GhIssueContext;
+use tools::approval_file::ApprovalFile;
+use tools::gh_issue::{
+    DEFAULT_TRANSCRIPT_TURNS,
+    GhIssueContext,
+};
+use tools::web_search::WebSearchEndpoint;
 use tools::{
     OutputKind,
     QueuedTool,
     Tool,
+    ToolKind,
     ToolOrigin,
     ToolPermissions,
+    ToolPolicy,
+    ToolProgress,
     ToolSpec,
 };
 use tracing::{
@@ -130,31 +193,52 @@ use tracing::{
     warn,
 };
 use unicode_width::UnicodeWidthStr;
-use util::images::RichImageBlock;
+use util::editor::{
+    edit_in_editor,
+    editor_command,
+};
+use util::images::{
+    image_protocol,
+    print_image,
+};
+use util::redact::Redactor;
 use util::shared_writer::{
     NullWriter,
     SharedWriter,
 };
+use util::shell::UserShell;
 use util::ui::draw_box;
 use util::{
+    TypewriterSpeed,
     animate_output,
+    clipboard,
+    code_blocks,
     drop_matched_context_files,
+    first_code_block,
+    format_bytes,
+    notify_response_complete,
     play_notification_bell,
     region_check,
 };
-use uuid::Uuid;
-use winnow::Partial;
-use winnow::stream::Offset;
 
 use crate::api_client::StreamingClient;
 use crate::api_client::clients::SendMessageOutput;
 use crate::api_client::model::{
     ChatResponseStream,
+    ConversationState as FigConversationState,
+    TokenUsage,
     Tool as FigTool,
     ToolResultStatus,
 };
-use crate::database::Database;
+use crate::auth::builder_id::{
+    BuilderIdToken,
+    TokenType,
+};
 use crate::database::settings::Setting;
+use crate::database::{
+    AuthProfile,
+    Database,
+};
 use crate::mcp_client::{
     Prompt,
     PromptGetResult,
@@ -163,6 +247,10 @@ use crate::platform::Context;
 use crate::telemetry::TelemetryThread;
 use crate::telemetry::core::ToolUseEventBuilder;
 use crate::util::CLI_BINARY_NAME;
+use crate::util::directories::{
+    chat_scratch_dir,
+    chat_templates_path,
+};
 
 /// Help text for the compact command
 fn compact_help_text() -> String {
@@ -178,6 +266,8 @@ that may eventually reach memory constraints.
   <em>/compact</em>                   <black!>Summarize the conversation and clear history</black!>
   <em>/compact [prompt]</em>          <black!>Provide custom guidance for summarization</black!>
 
+The most recent turn is kept as-is by default, set <em>chat.compactKeepTurns</em> to keep more.
+
 <cyan!>When to use</cyan!>
 • When you see the memory constraint warning message
 • When a conversation has been running for a long time
@@ -187,7 +277,7 @@ that may eventually reach memory constraints.
 <cyan!>How it works</cyan!>
 • Creates an AI-generated summary of your conversation
 • Retains key information, code, and tool executions in the summary
-• Clears the conversation history to free up space
+• Clears older conversation history to free up space
 • The assistant will reference the summary context in future responses
 "#
     )
@@ -223,66 +313,173 @@ const ROTATING_TIPS: [&str; 13] = [
 
 const GREETING_BREAK_POINT: usize = 80;
 
-const POPULAR_SHORTCUTS: &str = color_print::cstr! {"<black!><green!>/help</green!> all commands  <em>•</em>  <green!>ctrl + j</green!> new lines  <em>•</em>  <green!>ctrl + s</green!> fuzzy search</black!>"};
-const SMALL_SCREEN_POPULAR_SHORTCUTS: &str = color_print::cstr! {"<black!><green!>/help</green!> all commands
-<green!>ctrl + j</green!> new lines
-<green!>ctrl + s</green!> fuzzy search
-</black!>"};
+/// Keyboard shortcuts listed in the welcome banner after the commands that have a
+/// [command::HelpEntry::shortcut].
+const KEY_SHORTCUTS: [(&str, &str); 2] = [("ctrl + j", "new lines"), ("ctrl + s", "fuzzy search")];
+
+fn popular_shortcuts(small_screen: bool) -> String {
+    let shortcuts = Command::help_entries()
+        .iter()
+        .filter_map(|entry| entry.shortcut.map(|shortcut| (entry.name, shortcut)))
+        .chain(KEY_SHORTCUTS)
+        .map(|(key, description)| color_print::cformat!("<green!>{}</green!> <black!>{}</black!>", key, description))
+        .collect::<Vec<_>>();
+    match small_screen {
+        true => format!("{}\n", shortcuts.join("\n")),
+        false => shortcuts.join(color_print::cstr!("<black!>  <em>•</em>  </black!>")),
+    }
+}
 
-const HELP_TEXT: &str = color_print::cstr! {"
+const HELP_HEADER: &str = color_print::cstr! {"
 
 <magenta,em>q</magenta,em> (Amazon Q Chat)
 
 <cyan,em>Commands:</cyan,em>
-<em>/clear</em>        <black!>Clear the conversation history</black!>
-<em>/issue</em>        <black!>Report an issue or make a feature request</black!>
-<em>/editor</em>       <black!>Open $EDITOR (defaults to vi) to compose a prompt</black!>
-<em>/help</em>         <black!>Show this help dialogue</black!>
-<em>/quit</em>         <black!>Quit the application</black!>
-<em>/compact</em>      <black!>Summarize the conversation to free up context space</black!>
-  <em>help</em>        <black!>Show help for the compact command</black!>
-  <em>[prompt]</em>    <black!>Optional custom prompt to guide summarization</black!>
-<em>/tools</em>        <black!>View and manage tools and permissions</black!>
-  <em>help</em>        <black!>Show an explanation for the trust command</black!>
-  <em>trust</em>       <black!>Trust a specific tool or tools for the session</black!>
-  <em>untrust</em>     <black!>Revert a tool or tools to per-request confirmation</black!>
-  <em>trustall</em>    <black!>Trust all tools (equivalent to deprecated /acceptall)</black!>
-  <em>reset</em>       <black!>Reset all tools to default permission levels</black!>
-<em>/mcp</em>          <black!>See mcp server loaded</black!>
-<em>/profile</em>      <black!>Manage profiles</black!>
-  <em>help</em>        <black!>Show profile help</black!>
-  <em>list</em>        <black!>List profiles</black!>
-  <em>set</em>         <black!>Set the current profile</black!>
-  <em>create</em>      <black!>Create a new profile</black!>
-  <em>delete</em>      <black!>Delete a profile</black!>
-  <em>rename</em>      <black!>Rename a profile</black!>
-<em>/prompts</em>      <black!>View and retrieve prompts</black!>
-  <em>help</em>        <black!>Show prompts help</black!>
-  <em>list</em>        <black!>List or search available prompts</black!>
-  <em>get</em>         <black!>Retrieve and send a prompt</black!>
-<em>/context</em>      <black!>Manage context files and hooks for the chat session</black!>
-  <em>help</em>        <black!>Show context help</black!>
-  <em>show</em>        <black!>Display current context rules configuration [--expand]</black!>
-  <em>add</em>         <black!>Add file(s) to context [--global] [--force]</black!>
-  <em>rm</em>          <black!>Remove file(s) from context [--global]</black!>
-  <em>clear</em>       <black!>Clear all files from current context [--global]</black!>
-  <em>hooks</em>       <black!>View and manage context hooks</black!>
-<em>/usage</em>        <black!>Show current session's context window usage</black!>
-<em>/load</em>         <black!>Load conversation state from a JSON file</black!>
-<em>/save</em>         <black!>Save conversation state to a JSON file</black!>
-
+"};
+const HELP_FOOTER: &str = color_print::cstr! {"
 <cyan,em>MCP:</cyan,em>
 <black!>You can now configure the Amazon Q CLI to use MCP servers. \nLearn how: https://docs.aws.amazon.com/en_us/amazonq/latest/qdeveloper-ug/command-line-mcp.html</black!>
 
 <cyan,em>Tips:</cyan,em>
 <em>!{command}</em>            <black!>Quickly execute a command in your current session</black!>
+<em>@{path}</em>               <black!>Include a file in your message, e.g. explain @src/main.rs</black!>
 <em>Ctrl(^) + j</em>           <black!>Insert new-line to provide multi-line prompt. Alternatively, [Alt(⌥) + Enter(⏎)]</black!>
+<em>Ctrl(^) + x, e</em>        <black!>Edit the prompt in $VISUAL or $EDITOR, then press Enter to send it</black!>
 <em>Ctrl(^) + s</em>           <black!>Fuzzy search commands and context files. Use Tab to select multiple items.</black!>
                       <black!>Change the keybind to ctrl+x with: q settings chat.skimCommandKey x (where x is any key)</black!>
 <em>chat.editMode</em>         <black!>Set editing mode (vim or emacs) using: q settings chat.editMode vi/emacs</black!>
+<em>chat.notifyOnComplete</em> <black!>Notify when a response completes: q settings chat.notifyOnComplete true</black!>
+                      <black!>Run a command instead of ringing the bell with: q settings chat.notifyCmd \"<<command>>\"</black!>
+                      <black!>Only notify after slow responses with: q settings chat.notifyMinDurationSecs 30</black!>
 
 "};
 
+/// Renders `/help` from [Command::help_entries].
+fn help_text() -> String {
+    let mut text = HELP_HEADER.to_string();
+    for entry in Command::help_entries() {
+        text.push_str(&help_line("", entry.name, entry.description));
+        for (name, description) in entry.subcommands {
+            text.push_str(&help_line("  ", name, description));
+        }
+    }
+    text.push_str(HELP_FOOTER);
+    text
+}
+
+/// Width of the name column in `/help`, including the indentation of subcommands.
+const HELP_NAME_WIDTH: usize = 14;
+
+fn help_line(indent: &str, name: &str, description: &str) -> String {
+    let padding = " ".repeat(HELP_NAME_WIDTH.saturating_sub(indent.len() + name.len()).max(1));
+    color_print::cformat!(
+        "{}<em>{}</em>{}<black!>{}</black!>\n",
+        indent,
+        name,
+        padding,
+        description
+    )
+}
+
+/// Describes the identity `/whoami` reports: the kind of login with its start URL and region, and
+/// for IAM Identity Center the selected profile and the account it belongs to.
+fn queue_whoami(
+    output: &mut impl Write,
+    token: Option<&BuilderIdToken>,
+    profile: Option<&AuthProfile>,
+    in_cloudshell: bool,
+) -> Result<(), std::io::Error> {
+    let Some(token) = token else {
+        if in_cloudshell {
+            queue!(
+                output,
+                style::Print("Using the credentials of this CloudShell environment\n"),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("The account is the one CloudShell was opened in.\n"),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        } else {
+            queue!(
+                output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print("Not logged in"),
+                style::SetForegroundColor(Color::Reset),
+                style::Print(format!(
+                    ", run {} to log in\n",
+                    format!("{CLI_BINARY_NAME} login").green()
+                )),
+            )?;
+        }
+        return output.flush();
+    };
+
+    let mut fields = Vec::new();
+    match token.token_type() {
+        TokenType::BuilderId => queue!(output, style::Print("Logged in with Builder ID\n"))?,
+        TokenType::IamIdentityCenter => {
+            queue!(output, style::Print("Logged in with IAM Identity Center\n"))?;
+            if let Some(start_url) = &token.start_url {
+                fields.push(("Start URL", start_url.clone()));
+            }
+        },
+    }
+    if let Some(region) = &token.region {
+        fields.push(("Region", region.clone()));
+    }
+    if let (TokenType::IamIdentityCenter, Some(profile)) = (token.token_type(), profile) {
+        fields.push(("Profile", profile.profile_name.clone()));
+        // Profile ARNs look like `arn:aws:codewhisperer:<region>:<account>:profile/<id>`.
+        if let Some(account) = profile.arn.split(':').nth(4).filter(|account| !account.is_empty()) {
+            fields.push(("Account", account.to_string()));
+        }
+    }
+    for (name, value) in fields {
+        queue!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("  {name}: ")),
+            style::SetForegroundColor(Color::Reset),
+            style::Print(value),
+            style::Print("\n"),
+        )?;
+    }
+    output.flush()
+}
+
+/// Lists `profiles` one per line as `/profile list` and `--list-profiles` show them, marking the
+/// current profile with `*` and overlays with `+`.
+fn queue_profile_list(
+    output: &mut impl Write,
+    context_manager: &ContextManager,
+    profiles: &[String],
+) -> Result<(), std::io::Error> {
+    for profile in profiles {
+        if *profile == context_manager.current_profile {
+            queue!(
+                output,
+                style::SetForegroundColor(Color::Green),
+                style::Print("* "),
+                style::Print(profile),
+                style::SetForegroundColor(Color::Reset),
+                style::Print("\n")
+            )?;
+        } else if context_manager.overlays.iter().any(|overlay| overlay.name == *profile) {
+            queue!(
+                output,
+                style::Print("+ "),
+                style::Print(profile),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(" (overlay)"),
+                style::SetForegroundColor(Color::Reset),
+                style::Print("\n")
+            )?;
+        } else {
+            queue!(output, style::Print("  "), style::Print(profile), style::Print("\n"))?;
+        }
+    }
+    output.flush()
+}
+
 const RESPONSE_TIMEOUT_CONTENT: &str = "Response timed out - message took too long to generate";
 const TRUST_ALL_TEXT: &str = color_print::cstr! {"<green!>All tools are now trusted (<red!>!</red!>). Amazon Q will execute tools <bold>without</bold> asking for confirmation.\
 \nAgents can sometimes do unexpected things so understand the risks.</green!>
@@ -291,6 +488,9 @@ const TRUST_ALL_TEXT: &str = color_print::cstr! {"<green!>All tools are now trus
 const TOOL_BULLET: &str = " ● ";
 const CONTINUATION_LINE: &str = " ⋮ ";
 const PURPOSE_ARROW: &str = " ↳ ";
+const COLLAPSED_ARROW: &str = " ▸ ";
+/// Lines of a tool's output shown after it runs, the rest are left for `/expand`.
+const TOOL_OUTPUT_PREVIEW_LINES: usize = 5;
 
 pub async fn launch_chat(database: &mut Database, telemetry: &TelemetryThread, args: cli::Chat) -> Result<ExitCode> {
     let trust_tools = args.trust_tools.map(|mut tools| {
@@ -304,59 +504,171 @@ pub async fn launch_chat(database: &mut Database, telemetry: &TelemetryThread, a
         database,
         telemetry,
         args.input,
+        args.file,
         args.no_interactive,
+        args.interactive,
         args.resume,
         args.accept_all,
         args.profile,
+        args.region,
         args.trust_all_tools,
         trust_tools,
+        args.approval_file,
+        args.keep_scratch,
+        args.output_format,
+        args.max_turns,
+        args.dry_run,
+        args.quiet,
+        args.no_stream,
+        args.list_profiles,
+        args.record,
+        args.replay,
+        args.system_prompt_file,
     )
     .await
 }
 
+/// Exit code when a tool needed approval in a non-interactive session.
+pub const EXIT_TOOL_APPROVAL_REQUIRED: u8 = 3;
+/// Exit code when the session expired and couldn't be refreshed.
+pub const EXIT_AUTH_FAILURE: u8 = 4;
+/// Exit code when the account's quota has been reached.
+pub const EXIT_QUOTA_BREACH: u8 = 5;
+/// Exit code when the session reached the `--max-turns` limit.
+pub const EXIT_MAX_TURNS: u8 = 6;
+
+/// Runs a chat session, returning an exit code that scripts can branch on:
+///
+/// - `0`: the session ended normally.
+/// - `1`: any other error, reported by `main`.
+/// - [EXIT_TOOL_APPROVAL_REQUIRED] (`3`): the model asked to use a tool that needs approval, but
+///   the session isn't interactive and the tool wasn't trusted.
+/// - [EXIT_AUTH_FAILURE] (`4`): the session expired and couldn't be refreshed.
+/// - [EXIT_QUOTA_BREACH] (`5`): the account's quota has been reached.
+/// - [EXIT_MAX_TURNS] (`6`): the model was still using tools after `max_turns` responses.
+///
+/// Interactive sessions recover from auth and quota failures by returning to the prompt, so only
+/// non-interactive sessions end with those codes.
 #[allow(clippy::too_many_arguments, clippy::fn_params_excessive_bools)]
 pub async fn chat(
     database: &mut Database,
     telemetry: &TelemetryThread,
     input: Option<String>,
+    file: Option<PathBuf>,
     no_interactive: bool,
+    force_interactive: bool,
     resume_conversation: bool,
     accept_all: bool,
     profile: Option<String>,
+    region: Option<String>,
     trust_all_tools: bool,
     trust_tools: Option<Vec<String>>,
+    approval_file: Option<PathBuf>,
+    keep_scratch: bool,
+    output_format: OutputFormat,
+    max_turns: Option<usize>,
+    dry_run: bool,
+    quiet: bool,
+    no_stream: bool,
+    list_profiles: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    system_prompt_file: Option<PathBuf>,
 ) -> Result<ExitCode> {
-    if !crate::util::system_info::in_cloudshell() && !crate::auth::is_logged_in(database).await {
+    if list_profiles {
+        // A fresh context manager starts out on the default profile, so that's the one marked.
+        let context_manager = ContextManager::new(Context::new(), None).await?;
+        let profiles = context_manager.list_profiles().await?;
+        queue_profile_list(&mut std::io::stdout(), &context_manager, &profiles)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // A replayed session never reaches the backend, so it works without logging in.
+    if !dry_run
+        && replay.is_none()
+        && !crate::util::system_info::in_cloudshell()
+        && !crate::auth::is_logged_in(database).await
+    {
         bail!(
             "You are not logged in, please log in with {}",
             format!("{CLI_BINARY_NAME} login").bold()
         );
     }
 
-    region_check("chat")?;
+    region_check("chat", region.as_deref())?;
+
+    let conversation_id = Alphanumeric.sample_string(&mut rand::rng(), 9);
+    info!(?conversation_id, "Generated new conversation id");
 
-    let ctx = Context::new();
+    let scratch_dir = chat_scratch_dir(&conversation_id)?;
+    let ctx = Context::builder().with_scratch_dir(&scratch_dir).build();
+    ctx.fs().create_dir_all(&scratch_dir).await?;
+    let _scratch_dir_guard = ScratchDirGuard {
+        path: scratch_dir,
+        keep: keep_scratch,
+    };
 
     let stdin = std::io::stdin();
     // no_interactive flag or part of a pipe
-    let interactive = !no_interactive && stdin.is_terminal();
-    let input = if !interactive && !stdin.is_terminal() {
-        // append to input string any extra info that was provided, e.g. via pipe
-        let mut input = input.unwrap_or_default();
-        stdin.lock().read_to_string(&mut input)?;
-        Some(input)
+    let stdin_is_terminal = stdin.is_terminal();
+    let mut interactive = !no_interactive && stdin_is_terminal;
+    let piped_input = if !stdin_is_terminal {
+        let mut piped_input = String::new();
+        stdin.lock().read_to_string(&mut piped_input)?;
+        Some(piped_input)
     } else {
-        input
+        None
+    };
+    if force_interactive && !stdin_is_terminal {
+        match attach_stdin_to_terminal() {
+            Ok(()) => interactive = true,
+            Err(err) => {
+                warn!(?err, "Failed to read from the controlling terminal");
+                execute!(
+                    std::io::stderr(),
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print(format!(
+                        "Warning: --interactive needs a terminal to read from ({err}), continuing without interactive mode\n"
+                    )),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+        }
+    }
+    let input = initial_input(&ctx, input, file, piped_input).await?;
+    let system_prompt = match system_prompt_file {
+        Some(path) => Some(read_system_prompt_file(&ctx, &path).await?),
+        None => None,
+    };
+    if dry_run {
+        if input.is_none() {
+            bail!("--dry-run requires a question, either as an argument, with --file, or through STDIN");
+        }
+        interactive = false;
+    }
+
+    let json_output = match output_format {
+        OutputFormat::Json if interactive => bail!("--output-format json requires --no-interactive"),
+        OutputFormat::Json => true,
+        OutputFormat::Text => false,
+    };
+
+    // With JSON output, STDOUT is reserved for the events so nothing else is rendered.
+    let mut output = match (interactive, json_output) {
+        (true, _) => SharedWriter::stderr(),
+        (false, true) => SharedWriter::null(),
+        (false, false) => SharedWriter::stdout(),
     };
 
-    let mut output = match interactive {
-        true => SharedWriter::stderr(),
-        false => SharedWriter::stdout(),
+    let approval_file = match approval_file {
+        Some(path) => Some(ApprovalFile::load(&ctx, path).await?),
+        None => None,
     };
 
-    let client = match ctx.env().get("Q_MOCK_CHAT_RESPONSE") {
-        Ok(json) => create_stream(serde_json::from_str(std::fs::read_to_string(json)?.as_str())?),
-        _ => StreamingClient::new(database).await?,
+    let client = match (&replay, ctx.env().get("Q_MOCK_CHAT_RESPONSE")) {
+        (Some(path), _) => recording::load_replay(path)?,
+        (None, Ok(json)) => create_stream(serde_json::from_str(std::fs::read_to_string(json)?.as_str())?),
+        _ => StreamingClient::new(database, region.as_deref()).await?,
     };
 
     let mcp_server_configs = match McpServerConfig::load_config(&mut output).await {
@@ -399,8 +711,6 @@ pub async fn chat(
         }
     }
 
-    let conversation_id = Alphanumeric.sample_string(&mut rand::rng(), 9);
-    info!(?conversation_id, "Generated new conversation id");
     let (prompt_request_sender, prompt_request_receiver) = std::sync::mpsc::channel::<Option<String>>();
     let (prompt_response_sender, prompt_response_receiver) = std::sync::mpsc::channel::<Vec<String>>();
     let tool_manager_output: Box<dyn Write + Send + Sync + 'static> = if interactive {
@@ -416,13 +726,24 @@ pub async fn chat(
         .interactive(interactive)
         .build(telemetry, tool_manager_output)
         .await?;
-    let tool_config = tool_manager.load_tools(database, &mut output).await?;
+    let tool_config = tool_manager.load_tools(&ctx, database, &mut output).await?;
     let mut tool_permissions = ToolPermissions::new(tool_config.len());
+    if let Some(policy) = database.settings.get(Setting::ChatToolPolicy) {
+        match serde_json::from_value(policy.clone()) {
+            Ok(entries) => tool_permissions.set_policy_entries(entries),
+            Err(err) => {
+                queue!(
+                    output,
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print(format!("\nIgnoring invalid chat.toolPolicy setting: {err}\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+        }
+    }
     if accept_all || trust_all_tools {
         tool_permissions.trust_all = true;
-        for tool in tool_config.values() {
-            tool_permissions.trust_tool(&tool.name);
-        }
+        tool_permissions.trust_all_tools(tool_config.values().map(|tool| tool.name.as_str()));
 
         // Deprecation notice for --accept-all users
         if accept_all && interactive {
@@ -433,8 +754,9 @@ pub async fn chat(
                 style::SetForegroundColor(Color::Reset),
             )?;
         }
-    } else if let Some(trusted) = trust_tools.map(|vec| vec.into_iter().collect::<HashSet<_>>()) {
+    } else if let Some(trust_tools) = trust_tools {
         // --trust-all-tools takes precedence over --trust-tools=...
+        let trusted = trusted_tool_set(trust_tools, tool_config.values().map(|tool| tool.name.as_str()))?;
         for tool in tool_config.values() {
             if trusted.contains(&tool.name) {
                 tool_permissions.trust_tool(&tool.name);
@@ -443,14 +765,15 @@ pub async fn chat(
             }
         }
     }
+    tool_permissions.approval_file = approval_file;
 
     let mut chat = ChatContext::new(
-        ctx,
+        Arc::clone(&ctx),
         database,
         &conversation_id,
         output,
         input,
-        InputSource::new(database, prompt_request_sender, prompt_response_receiver)?,
+        InputSource::new(&ctx, database, prompt_request_sender, prompt_response_receiver)?,
         interactive,
         resume_conversation,
         client,
@@ -461,13 +784,50 @@ pub async fn chat(
         tool_permissions,
     )
     .await?;
-
-    let result = chat.try_chat(database, telemetry).await.map(|_| ExitCode::SUCCESS);
+    if json_output {
+        chat.json_events = Some(SharedWriter::stdout());
+    }
+    chat.max_turns = max_turns;
+    chat.dry_run = dry_run;
+    chat.quiet = quiet;
+    chat.recording = record.map(Recording::new);
+    chat.conversation_state.system_prompt = system_prompt;
+    chat.streaming = !no_stream && database.settings.get_bool(Setting::ChatStreaming).unwrap_or(true);
+
+    let result = match chat.try_chat(database, telemetry).await {
+        Ok(()) => Ok(ExitCode::SUCCESS),
+        Err(err) if is_broken_pipe(&*err) => Ok(ExitCode::SUCCESS),
+        Err(err) => match err.downcast_ref::<ChatError>().and_then(ChatError::exit_code) {
+            Some(code) => {
+                eprintln!("{} {err}", "error:".bold().red());
+                Ok(ExitCode::from(code))
+            },
+            None => Err(err),
+        },
+    };
     drop(chat); // Explicit drop for clarity
 
     result
 }
 
+/// Removes the session's scratch directory when dropped, so that it's cleaned up on every path
+/// out of [chat], unless `--keep-scratch` was given.
+struct ScratchDirGuard {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Drop for ScratchDirGuard {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        if let Err(err) = std::fs::remove_dir_all(&self.path) {
+            warn!(?err, scratch_dir = ?self.path, "Failed to remove the scratch directory");
+        }
+    }
+}
+
 /// Enum used to denote the origin of a tool use event
 enum ToolUseStatus {
     /// Variant denotes that the tool use event associated with chat context is a direct result of
@@ -494,13 +854,29 @@ pub enum ChatError {
     #[error("interrupted")]
     Interrupted { tool_uses: Option<Vec<QueuedTool>> },
     #[error(
-        "Tool approval required but --no-interactive was specified. Use --trust-all-tools to automatically approve tools."
+        "Tool approval required but --no-interactive was specified. Use --trust-all-tools or --approval-file to automatically approve tools."
     )]
     NonInteractiveToolApproval,
+    #[error("Stopped after {0} turns, the limit set by --max-turns")]
+    MaxTurnsExceeded(usize),
     #[error(transparent)]
     GetPromptError(#[from] GetPromptError),
 }
 
+impl ChatError {
+    /// The exit code that [chat] ends with when the session ends with this error, if it has a
+    /// dedicated one.
+    fn exit_code(&self) -> Option<u8> {
+        match self {
+            ChatError::NonInteractiveToolApproval => Some(EXIT_TOOL_APPROVAL_REQUIRED),
+            ChatError::Client(crate::api_client::ApiClientError::AuthExpired) => Some(EXIT_AUTH_FAILURE),
+            ChatError::Client(crate::api_client::ApiClientError::QuotaBreach { .. }) => Some(EXIT_QUOTA_BREACH),
+            ChatError::MaxTurnsExceeded(_) => Some(EXIT_MAX_TURNS),
+            _ => None,
+        }
+    }
+}
+
 pub struct ChatContext {
     ctx: Arc<Context>,
     /// The [Write] destination for printing conversation text.
@@ -508,6 +884,8 @@ pub struct ChatContext {
     initial_input: Option<String>,
     /// Whether we're starting a new conversation or continuing an old one.
     existing_conversation: bool,
+    /// Printed after the greeting when `--resume` couldn't pick up this directory's conversation.
+    resume_notice: Option<String>,
     input_source: InputSource,
     interactive: bool,
     /// The client to use to interact with the model.
@@ -527,6 +905,75 @@ pub struct ChatContext {
     failed_request_ids: Vec<String>,
     /// Pending prompts to be sent
     pending_prompts: VecDeque<Prompt>,
+    /// Model identifier selected with `/model`. When unset, the backend picks its default model.
+    model_id: Option<String>,
+    /// Where to write [JsonEvent]s when `--output-format json` was given.
+    json_events: Option<SharedWriter>,
+    /// Most responses to request from the model before ending the session, from `--max-turns`.
+    max_turns: Option<usize>,
+    /// Whether the first request is printed instead of sent, from `--dry-run`.
+    dry_run: bool,
+    /// Whether status decorations like the spinner, welcome banner, and tool completion lines are
+    /// hidden, from `--quiet`.
+    quiet: bool,
+    /// Whether responses are rendered as they stream in, rather than at once after they ended.
+    /// Turned off with `--no-stream` or the `chat.streaming` setting.
+    streaming: bool,
+    /// Whether the prompt being typed was started from a template with `/templates use`, so
+    /// placeholders left in it are caught before it's sent.
+    from_template: bool,
+    /// When the user last sent a prompt, to tell how long the turn took for
+    /// `chat.notifyMinDurationSecs`.
+    turn_start: Option<Instant>,
+    /// Where prompts, responses, and tool uses are logged to, from the `chat.auditLog` setting.
+    audit_log: Option<AuditLog>,
+    /// Where the model's responses are saved to, from `--record`.
+    recording: Option<Recording>,
+    /// Directory `execute_bash` must stay inside of when given a `cwd`, from the
+    /// `chat.workspaceRoot` setting.
+    workspace_root: Option<PathBuf>,
+    /// Results of the tools executed so far in the current batch of tool uses.
+    batch_tool_results: Vec<ToolUseResult>,
+    /// Whether raw response events are printed as they are received, toggled with `/verbose`.
+    verbose: bool,
+    /// Assistant text received so far from the response currently being streamed, kept so that it
+    /// isn't lost if the response is interrupted.
+    partial_response: String,
+    /// Conversation branches created with `/fork`, other than the active one.
+    branches: Branches<ConversationState>,
+    /// Counters shown by `/stats`.
+    stats: SessionStats,
+    /// Times a message is sent again after a transient failure, see `chat.maxRetries`.
+    max_retries: u32,
+    /// Whether a preview of each tool's output is printed after it runs, from
+    /// `chat.showToolResults`.
+    show_tool_results: bool,
+    /// Full output of the last tool that ran, shown by `/expand`.
+    last_tool_output: Option<String>,
+    /// Command to show output taller than the terminal with, if `chat.pager` is enabled.
+    pager: Option<Vec<String>>,
+    /// Custom prompt from `chat.promptFormat` and `chat.promptColor`.
+    prompt_format: PromptFormat,
+    /// Recent Ctrl+C presses, so that a second one soon after the first exits.
+    interrupts: InterruptTracker,
+    /// Tool uses failing the same way over and over, so that the user is asked how to proceed.
+    tool_failures: ToolFailureTracker,
+    /// Where the `web_search` tool sends queries, from `chat.webSearchEndpoint`.
+    web_search_endpoint: Option<WebSearchEndpoint>,
+    /// Whether output goes to a terminal, which enables highlighting and hyperlinks by default and
+    /// is needed for notifications.
+    terminal: bool,
+    /// Receives the events of every response, for sessions driven through [session::ChatSession].
+    response_events: Option<tokio::sync::mpsc::UnboundedSender<parser::ResponseEvent>>,
+    /// Whether the next issue reported with `report_issue` includes recent turns of the transcript,
+    /// turned off for a single report with `/issue --no-transcript`.
+    issue_transcript: bool,
+    /// Turns of the transcript included in issues, from `chat.issueTranscriptTurns`.
+    issue_transcript_turns: usize,
+    /// Recent turns `/compact` keeps as they are, from `chat.compactKeepTurns`.
+    compact_keep_turns: usize,
+    /// Whether the spinner is shown and its messages, from the `chat.spinner` settings.
+    spinner_settings: SpinnerSettings,
 }
 
 impl ChatContext {
@@ -551,17 +998,27 @@ impl ChatContext {
         let output_clone = output.clone();
 
         let mut existing_conversation = false;
-        let conversation_state = if resume_conversation {
-            let prior = std::env::current_dir()
-                .ok()
-                .and_then(|cwd| database.get_conversation_by_path(cwd).ok())
-                .flatten();
-
+        let mut resume_notice = None;
+        let mut conversation_state = if resume_conversation {
             // Only restore conversations where there were actual messages.
             // Prevents edge case where user clears conversation with --new, then exits without chatting.
-            if prior.as_ref().is_some_and(|cs| !cs.history().is_empty()) {
-                let mut cs = prior.unwrap();
+            let mut prior = std::env::current_dir()
+                .ok()
+                .and_then(|cwd| database.get_conversation_by_path(cwd).ok())
+                .flatten()
+                .filter(|cs| !cs.history().is_empty());
+
+            // Without a conversation from this directory, fall back to the one saved last.
+            if prior.is_none() {
+                if let Some((path, cs)) = database.get_last_conversation().ok().flatten() {
+                    if !cs.history().is_empty() {
+                        resume_notice = Some(format!("Resuming your most recent conversation, from {path}"));
+                        prior = Some(cs);
+                    }
+                }
+            }
 
+            if let Some(mut cs) = prior {
                 existing_conversation = true;
                 cs.reload_serialized_state(Arc::clone(&ctx), Some(output.clone())).await;
                 input = Some(input.unwrap_or("In a few words, summarize our conversation so far.".to_owned()));
@@ -570,6 +1027,7 @@ impl ChatContext {
                 cs.enforce_tool_use_history_invariants();
                 cs
             } else {
+                resume_notice = Some("No previous conversation to resume, starting a new one.".to_string());
                 ConversationState::new(
                     ctx_clone,
                     conversation_id,
@@ -592,11 +1050,56 @@ impl ChatContext {
             .await
         };
 
+        conversation_state.history_strategy =
+            HistoryStrategy::from_setting(database.settings.get_string(Setting::ChatHistoryStrategy).as_deref());
+        if let Some(context_manager) = conversation_state.context_manager.as_mut() {
+            if let Some(max_bytes) = database
+                .settings
+                .get_int(Setting::ChatMaxContextFileBytes)
+                .and_then(|n| usize::try_from(n).ok())
+            {
+                context_manager.max_context_file_bytes = max_bytes;
+            }
+            context_manager.max_context_files = database
+                .settings
+                .get_int(Setting::ChatMaxContextFiles)
+                .and_then(|n| usize::try_from(n).ok());
+            context_manager.context_file_order =
+                ContextFileOrder::from_setting(database.settings.get_string(Setting::ChatContextFilesOrder).as_deref());
+            context_manager.respect_gitignore = database
+                .settings
+                .get_bool(Setting::ChatRespectGitignore)
+                .unwrap_or(false);
+            if database.settings.get_bool(Setting::ChatRedactSecrets).unwrap_or(true) {
+                let patterns = database
+                    .settings
+                    .get(Setting::ChatRedactPatterns)
+                    .and_then(|patterns| serde_json::from_value::<Vec<String>>(patterns.clone()).ok())
+                    .unwrap_or_default();
+                context_manager.redactor = Some(Redactor::new(&patterns));
+            }
+        }
+
+        let mut stats = SessionStats::new();
+        stats.region = client.region().map(ToString::to_string);
+
+        let audit_log = database
+            .settings
+            .get_string(Setting::ChatAuditLog)
+            .filter(|path| !path.trim().is_empty())
+            .map(|path| AuditLog::new(Arc::clone(&ctx), tools::sanitize_path_tool_arg(&ctx, path.trim())));
+        let workspace_root = database
+            .settings
+            .get_string(Setting::ChatWorkspaceRoot)
+            .filter(|root| !root.trim().is_empty())
+            .map(|root| tools::sanitize_path_tool_arg(&ctx, root.trim()));
+
         Ok(Self {
             ctx,
             output,
             initial_input: input,
             existing_conversation,
+            resume_notice,
             input_source,
             interactive,
             client,
@@ -608,8 +1111,79 @@ impl ChatContext {
             tool_use_status: ToolUseStatus::Idle,
             failed_request_ids: Vec::new(),
             pending_prompts: VecDeque::new(),
+            model_id: None,
+            json_events: None,
+            max_turns: None,
+            dry_run: false,
+            quiet: false,
+            streaming: true,
+            from_template: false,
+            turn_start: None,
+            batch_tool_results: Vec::new(),
+            partial_response: String::new(),
+            verbose: false,
+            branches: Branches::new(),
+            stats,
+            max_retries: database
+                .settings
+                .get_int(Setting::ChatMaxRetries)
+                .and_then(|n| u32::try_from(n).ok())
+                .unwrap_or(DEFAULT_MAX_RETRIES),
+            show_tool_results: database.settings.get_bool(Setting::ChatShowToolResults).unwrap_or(true),
+            last_tool_output: None,
+            issue_transcript: true,
+            issue_transcript_turns: database
+                .settings
+                .get_int(Setting::ChatIssueTranscriptTurns)
+                .and_then(|n| usize::try_from(n).ok())
+                .unwrap_or(DEFAULT_TRANSCRIPT_TURNS),
+            compact_keep_turns: database
+                .settings
+                .get_int(Setting::ChatCompactKeepTurns)
+                .and_then(|n| usize::try_from(n).ok())
+                .unwrap_or(DEFAULT_COMPACT_KEEP_TURNS),
+            spinner_settings: SpinnerSettings::new(&database.settings),
+            web_search_endpoint: WebSearchEndpoint::from_settings(database),
+            terminal: std::io::stdout().is_terminal(),
+            response_events: None,
+            interrupts: InterruptTracker::new(
+                database
+                    .settings
+                    .get_int(Setting::ChatInterruptExitWindowMs)
+                    .and_then(|ms| u64::try_from(ms).ok())
+                    .map_or(interrupt::DEFAULT_EXIT_WINDOW, Duration::from_millis),
+            ),
+            tool_failures: ToolFailureTracker::new(
+                database
+                    .settings
+                    .get_int(Setting::ChatMaxRepeatedToolFailures)
+                    .and_then(|n| usize::try_from(n).ok())
+                    .unwrap_or(tool_failures::DEFAULT_MAX_REPEATED_FAILURES),
+            ),
+            prompt_format: PromptFormat::new(
+                database.settings.get_string(Setting::ChatPromptFormat).as_deref(),
+                database.settings.get_string(Setting::ChatPromptColor).as_deref(),
+            ),
+            audit_log,
+            recording: None,
+            workspace_root,
+            pager: match database.settings.get_bool(Setting::ChatPager).unwrap_or(false)
+                && interactive
+                && std::io::stdout().is_terminal()
+            {
+                true => pager::pager_command(env::var("PAGER").ok()),
+                false => None,
+            },
         })
     }
+
+    /// Writes `event` if JSON events were requested, otherwise does nothing.
+    fn write_json_event(&mut self, event: JsonEvent) -> Result<(), ChatError> {
+        if let Some(output) = self.json_events.as_mut() {
+            event.write(output)?;
+        }
+        Ok(())
+    }
 }
 
 impl Drop for ChatContext {
@@ -688,59 +1262,16 @@ impl Default for ChatState {
 
 impl ChatContext {
     /// Opens the user's preferred editor to compose a prompt
-    fn open_editor(initial_text: Option<String>) -> Result<String, ChatError> {
-        // Create a temporary file with a unique name
-        let temp_dir = std::env::temp_dir();
-        let file_name = format!("q_prompt_{}.md", Uuid::new_v4());
-        let temp_file_path = temp_dir.join(file_name);
-
-        // Get the editor from environment variable or use a default
-        let editor_cmd = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-
-        // Parse the editor command to handle arguments
-        let mut parts =
-            shlex::split(&editor_cmd).ok_or_else(|| ChatError::Custom("Failed to parse EDITOR command".into()))?;
-
-        if parts.is_empty() {
-            return Err(ChatError::Custom("EDITOR environment variable is empty".into()));
-        }
-
-        let editor_bin = parts.remove(0);
-
-        // Write initial content to the file if provided
-        let initial_content = initial_text.unwrap_or_default();
-        fs::write(&temp_file_path, &initial_content)
-            .map_err(|e| ChatError::Custom(format!("Failed to create temporary file: {}", e).into()))?;
-
-        // Open the editor with the parsed command and arguments
-        let mut cmd = ProcessCommand::new(editor_bin);
-        // Add any arguments that were part of the EDITOR variable
-        for arg in parts {
-            cmd.arg(arg);
-        }
-        // Add the file path as the last argument
-        let status = cmd
-            .arg(&temp_file_path)
-            .status()
-            .map_err(|e| ChatError::Custom(format!("Failed to open editor: {}", e).into()))?;
-
-        if !status.success() {
-            return Err(ChatError::Custom("Editor exited with non-zero status".into()));
-        }
-
-        // Read the content back
-        let content = fs::read_to_string(&temp_file_path)
-            .map_err(|e| ChatError::Custom(format!("Failed to read temporary file: {}", e).into()))?;
-
-        // Clean up the temporary file
-        let _ = fs::remove_file(&temp_file_path);
-
+    fn open_editor(&self, initial_text: Option<String>) -> Result<String, ChatError> {
+        let editor = editor_command(self.ctx.env());
+        let content = edit_in_editor(&editor, &initial_text.unwrap_or_default())
+            .map_err(|e| ChatError::Custom(format!("Failed to open {editor}: {e}").into()))?;
         Ok(content.trim().to_string())
     }
 
     async fn try_chat(&mut self, database: &mut Database, telemetry: &TelemetryThread) -> Result<()> {
         let is_small_screen = self.terminal_width() < GREETING_BREAK_POINT;
-        if self.interactive && database.settings.get_bool(Setting::ChatGreetingEnabled).unwrap_or(true) {
+        if self.interactive && !self.quiet && database.settings.get_bool(Setting::ChatGreetingEnabled).unwrap_or(true) {
             let welcome_text = match self.existing_conversation {
                 true => RESUME_TEXT,
                 false => match is_small_screen {
@@ -775,10 +1306,7 @@ impl ChatContext {
             execute!(
                 self.output,
                 style::Print("\n"),
-                style::Print(match is_small_screen {
-                    true => SMALL_SCREEN_POPULAR_SHORTCUTS,
-                    false => POPULAR_SHORTCUTS,
-                }),
+                style::Print(popular_shortcuts(is_small_screen)),
                 style::Print("\n"),
                 style::Print(
                     "━"
@@ -789,6 +1317,15 @@ impl ChatContext {
             execute!(self.output, style::Print("\n"), style::SetForegroundColor(Color::Reset))?;
         }
 
+        if let Some(notice) = self.resume_notice.take().filter(|_| !self.quiet) {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("{notice}\n\n")),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+        }
+
         if self.interactive && self.all_tools_trusted() {
             queue!(
                 self.output,
@@ -814,6 +1351,8 @@ impl ChatContext {
             });
         }
 
+        // Responses received from the model, checked against `--max-turns`.
+        let mut turns = 0;
         loop {
             debug_assert!(next_state.is_some());
             let chat_state = next_state.take().unwrap_or_default();
@@ -843,7 +1382,7 @@ impl ChatContext {
                 } => {
                     let tool_uses_clone = tool_uses.clone();
                     tokio::select! {
-                        res = self.handle_input(telemetry, input, tool_uses, pending_tool_index) => res,
+                        res = self.handle_input(database, telemetry, input, tool_uses, pending_tool_index) => res,
                         Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: tool_uses_clone })
                     }
                 },
@@ -861,6 +1400,11 @@ impl ChatContext {
                     }
                 },
                 ChatState::ExecuteTools(tool_uses) => {
+                    // The tool results would be sent back for another turn, so stop here rather
+                    // than spend a request that goes over the limit.
+                    if self.max_turns.is_some_and(|max_turns| turns >= max_turns) {
+                        return Err(ChatError::MaxTurnsExceeded(turns).into());
+                    }
                     let tool_uses_clone = tool_uses.clone();
                     tokio::select! {
                         res = self.tool_use_execute(database, telemetry, tool_uses) => res,
@@ -869,18 +1413,46 @@ impl ChatContext {
                 },
                 ChatState::ValidateTools(tool_uses) => {
                     tokio::select! {
-                        res = self.validate_tools(telemetry, tool_uses) => res,
+                        res = self.validate_tools(database, telemetry, tool_uses) => res,
+                        Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: None })
+                    }
+                },
+                ChatState::HandleResponseStream(response) => {
+                    turns += 1;
+                    if let (Some(audit_log), Some(message)) =
+                        (&self.audit_log, self.conversation_state.next_user_message())
+                    {
+                        audit_log
+                            .record_user_message(self.conversation_state.conversation_id(), message)
+                            .await;
+                    }
+                    let result = tokio::select! {
+                        res = self.handle_response(database, telemetry, response) => res,
                         Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: None })
+                    };
+                    // A completed response moves the message it answers into the history.
+                    if let (Ok(_), Some(audit_log), None) =
+                        (&result, &self.audit_log, self.conversation_state.next_user_message())
+                    {
+                        if let Some((_, message)) = self.conversation_state.history().back() {
+                            audit_log
+                                .record_assistant_message(self.conversation_state.conversation_id(), message)
+                                .await;
+                        }
                     }
+                    result
                 },
-                ChatState::HandleResponseStream(response) => tokio::select! {
-                    res = self.handle_response(database, telemetry, response) => res,
-                    Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: None })
+                ChatState::Exit => {
+                    // Responses are saved as they come in, but e.g. compaction or an interrupted
+                    // response changes the conversation without one.
+                    if !self.conversation_state.history().is_empty() {
+                        self.conversation_state.save(database);
+                    }
+                    return Ok(());
                 },
-                ChatState::Exit => return Ok(()),
             };
 
-            next_state = Some(self.handle_state_execution_result(database, result).await?);
+            next_state = Some(self.handle_state_execution_result(database, telemetry, result).await?);
         }
     }
 
@@ -889,6 +1461,7 @@ impl ChatContext {
     async fn handle_state_execution_result(
         &mut self,
         database: &mut Database,
+        telemetry: &TelemetryThread,
         result: Result<ChatState, ChatError>,
     ) -> Result<ChatState, ChatError> {
         // Remove non-ASCII and ANSI characters.
@@ -906,9 +1479,14 @@ impl ChatContext {
 
                         let report = eyre::Report::from($err);
 
-                        let text = re
+                        let mut text = re
                             .replace_all(&format!("{}: {:?}\n", $prepend_msg, report), "")
                             .into_owned();
+                        if let Some(request_id) = request_id.as_ref().filter(|id| !text.contains(id.as_str())) {
+                            text.push_str(&format!(
+                                "Request ID: {request_id} (include it when contacting support)\n"
+                            ));
+                        }
 
                         queue!(self.output, style::Print(&text),)?;
                         self.conversation_state.append_transcript(text);
@@ -927,7 +1505,28 @@ impl ChatContext {
                     };
                 }
 
+                // Whoever reads the output has gone away, e.g. `head` after enough lines, so
+                // there's nothing left to show anything to.
+                if is_broken_pipe(&e) {
+                    debug!("Output pipe was closed, ending the session");
+                    return Ok(ChatState::Exit);
+                }
                 error!(?e, "An error occurred processing the current state");
+                let request_id = match &e {
+                    ChatError::Client(err) => err.request_id().map(str::to_string),
+                    ChatError::ResponseStream(err) => err.request_id.clone(),
+                    _ => None,
+                };
+                if let Some(request_id) = &request_id {
+                    if self.failed_request_ids.last() != Some(request_id) {
+                        self.failed_request_ids.push(request_id.clone());
+                    }
+                }
+                // There's nobody to sign in again or to approve the tool, so end the session and
+                // let `chat` report the error with its exit code.
+                if !self.interactive && e.exit_code().is_some() {
+                    return Err(e);
+                }
                 if self.interactive && self.spinner.is_some() {
                     drop(self.spinner.take());
                     queue!(
@@ -939,15 +1538,17 @@ impl ChatContext {
                 match e {
                     ChatError::Interrupted { tool_uses: inter } => {
                         execute!(self.output, style::Print("\n\n"))?;
+                        if self.interactive && self.interrupts.interrupt() {
+                            return Ok(ChatState::Exit);
+                        }
                         // If there was an interrupt during tool execution, then we add fake
                         // messages to "reset" the chat state.
                         match inter {
                             Some(tool_uses) if !tool_uses.is_empty() => {
-                                self.conversation_state.abandon_tool_use(
-                                    tool_uses,
-                                    "The user interrupted the tool execution.".to_string(),
-                                );
-                                let _ = self.conversation_state.as_sendable_conversation_state(false).await;
+                                if let Some(state) = self.handle_tool_interrupt(tool_uses)? {
+                                    return Ok(state);
+                                }
+                                let _ = self.sendable_conversation_state(false).await;
                                 self.conversation_state.push_assistant_message(
                                     AssistantMessage::new_response(
                                         None,
@@ -956,7 +1557,14 @@ impl ChatContext {
                                     database,
                                 );
                             },
-                            _ => (),
+                            _ => {
+                                let partial_response = std::mem::take(&mut self.partial_response);
+                                self.conversation_state
+                                    .push_interrupted_response(partial_response, database);
+                            },
+                        }
+                        if self.interactive {
+                            self.print_exit_hint()?;
                         }
                     },
                     ChatError::Client(err) => match err {
@@ -1001,8 +1609,11 @@ impl ChatContext {
                                 help: false,
                             });
                         },
-                        crate::api_client::ApiClientError::QuotaBreach(msg) => {
-                            print_err!(msg, err);
+                        crate::api_client::ApiClientError::QuotaBreach { message, .. } => {
+                            print_err!(message, err);
+                        },
+                        crate::api_client::ApiClientError::AuthExpired => {
+                            self.handle_auth_expired(database, telemetry).await?;
                         },
                         _ => {
                             print_default_error!(err);
@@ -1013,7 +1624,7 @@ impl ChatContext {
                     },
                 }
                 self.conversation_state.enforce_conversation_invariants();
-                self.conversation_state.reset_next_user_message();
+                self.conversation_state.stash_failed_user_message();
                 Ok(ChatState::PromptUser {
                     tool_uses: None,
                     pending_tool_index: None,
@@ -1023,63 +1634,279 @@ impl ChatContext {
         }
     }
 
-    /// Compacts the conversation history, replacing the history with a summary generated by the
-    /// model.
-    ///
-    /// The last two user messages in the history are not included in the compaction process.
-    async fn compact_history(
+    /// Tells the user that their session has expired, and in interactive sessions offers to log in
+    /// again without leaving the chat.
+    async fn handle_auth_expired(
         &mut self,
+        database: &mut Database,
         telemetry: &TelemetryThread,
-        tool_uses: Option<Vec<QueuedTool>>,
-        pending_tool_index: Option<usize>,
-        custom_prompt: Option<String>,
-        show_summary: bool,
-        help: bool,
-    ) -> Result<ChatState, ChatError> {
-        let hist = self.conversation_state.history();
-        debug!(?hist, "compacting history");
+    ) -> Result<(), ChatError> {
+        let message = format!(
+            "Your session has expired. Run {} to sign in again, then {} to resend your message.\n",
+            format!("{CLI_BINARY_NAME} login").bold(),
+            "/retry".green()
+        );
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Red),
+            style::Print(&message),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        self.conversation_state.append_transcript(message);
+        if !self.interactive {
+            return Ok(());
+        }
 
-        // If help flag is set, show compact command help
-        if help {
-            execute!(
-                self.output,
-                style::Print("\n"),
-                style::Print(compact_help_text()),
-                style::Print("\n")
-            )?;
+        let answer = self.input_source.read_line(Some("Sign in again now? [y/N]: "))?;
+        if !answer.is_some_and(|answer| matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")) {
+            execute!(self.output, style::Print("\n"))?;
+            return Ok(());
+        }
 
-            return Ok(ChatState::PromptUser {
-                tool_uses,
-                pending_tool_index,
-                skip_printing_tools: true,
-            });
+        let args = crate::cli::user::LoginArgs {
+            license: None,
+            identity_provider: None,
+            region: None,
+            use_device_flow: false,
+        };
+        match crate::cli::user::login_interactive(database, telemetry, args).await {
+            Ok(()) => execute!(
+                self.output,
+                style::SetForegroundColor(Color::Green),
+                style::Print(format!(
+                    "\nSigned in. Use {} to resend your message.\n\n",
+                    "/retry".bold()
+                )),
+                style::SetForegroundColor(Color::Reset),
+            )?,
+            Err(err) if err.is::<crate::cli::user::LoginCancelled>() => execute!(
+                self.output,
+                style::Print(format!(
+                    "\nSign in cancelled. Run {} when you're ready, then {} to resend your message.\n\n",
+                    format!("{CLI_BINARY_NAME} login").bold(),
+                    "/retry".green()
+                )),
+            )?,
+            Err(err) => {
+                error!(?err, "Failed to sign in again");
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("\nFailed to sign in: {err}\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
         }
+        Ok(())
+    }
 
-        if self.conversation_state.history().len() < 2 {
+    /// Shows `message` next to a spinner while waiting on the backend, or a single static line if
+    /// spinners are disabled with `chat.spinner.enabled`.
+    fn start_spinner(&mut self, message: SpinnerMessage) -> Result<(), ChatError> {
+        if self.quiet {
+            return Ok(());
+        }
+        if self.spinner_settings.enabled {
+            self.spinner = Some(Spinner::new(
+                Spinners::Dots,
+                self.spinner_settings.text(message).to_string(),
+            ));
+        } else {
             execute!(
                 self.output,
-                style::SetForegroundColor(Color::Yellow),
-                style::Print("\nConversation too short to compact.\n\n"),
-                style::SetForegroundColor(Color::Reset)
+                cursor::Show,
+                style::Print(format!("{}\n", spinner_messages::STATIC_MESSAGE))
             )?;
+        }
+        Ok(())
+    }
 
-            return Ok(ChatState::PromptUser {
-                tool_uses,
+    /// Handles Ctrl+C while a batch of tools is executing. Results of tools that already completed
+    /// are kept, and in interactive sessions the user can choose which of the remaining tools to
+    /// still run, with the rest reported to the model as skipped.
+    ///
+    /// Returns [None] if the whole batch was abandoned and the next user message is set.
+    fn handle_tool_interrupt(&mut self, tool_uses: Vec<QueuedTool>) -> Result<Option<ChatState>, ChatError> {
+        let mut completed = std::mem::take(&mut self.batch_tool_results);
+        let remaining = tool_uses
+            .into_iter()
+            .filter(|tool| !completed.iter().any(|result| result.tool_use_id == tool.id))
+            .collect::<Vec<_>>();
+
+        let keep = match self.interactive && !remaining.is_empty() {
+            true => self.prompt_tools_to_keep(&remaining)?,
+            false => HashSet::new(),
+        };
+        if keep.is_empty() {
+            self.conversation_state.abandon_tool_use(
+                completed,
+                remaining,
+                "The user interrupted the tool execution.".to_string(),
+            );
+            return Ok(None);
+        }
+
+        let mut kept = Vec::new();
+        for (i, tool) in remaining.into_iter().enumerate() {
+            if keep.contains(&i) {
+                kept.push(tool);
+            } else {
+                completed.push(ToolUseResult {
+                    tool_use_id: tool.id,
+                    content: vec![ToolUseResultBlock::Text("Tool use was skipped by the user".to_string())],
+                    status: ToolResultStatus::Error,
+                });
+            }
+        }
+        self.batch_tool_results = completed;
+        Ok(Some(ChatState::ExecuteTools(kept)))
+    }
+
+    /// Stops the model from retrying a tool use that keeps failing the same way. The failed results
+    /// are kept in the conversation, but rather than sending them back, the user is asked how to
+    /// proceed.
+    async fn stop_repeated_tool_failure(
+        &mut self,
+        database: &mut Database,
+        tool_name: &str,
+        tool_results: Vec<ToolUseResult>,
+    ) -> Result<ChatState, ChatError> {
+        self.conversation_state.add_tool_results(tool_results);
+        let _ = self.sendable_conversation_state(false).await;
+        self.conversation_state.push_assistant_message(
+            AssistantMessage::new_response(
+                None,
+                format!(
+                    "The tool '{tool_name}' keeps failing the same way, waiting for the user to say how to proceed"
+                ),
+            ),
+            database,
+        );
+        self.tool_failures.reset();
+        self.tool_use_status = ToolUseStatus::Idle;
+
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print(format!(
+                "\n{tool_name} failed the same way {} times in a row, so it won't be retried automatically.\n",
+                self.tool_failures.max()
+            )),
+            style::SetForegroundColor(Color::Reset),
+            style::Print("Let Q know how you'd like to proceed.\n\n"),
+        )?;
+
+        Ok(ChatState::PromptUser {
+            tool_uses: None,
+            pending_tool_index: None,
+            skip_printing_tools: true,
+        })
+    }
+
+    /// Lists the tools that haven't completed and asks which should still run, returning their
+    /// indices. An empty selection or Ctrl+C skips all of them.
+    fn prompt_tools_to_keep(&mut self, remaining: &[QueuedTool]) -> Result<HashSet<usize>, ChatError> {
+        queue!(
+            self.output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print("Tool execution was interrupted. These tools have not completed:\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        for (i, tool) in remaining.iter().enumerate() {
+            queue!(
+                self.output,
+                style::Print(format!("  {}. {}", i + 1, tool.tool.display_name())),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!(" ({})\n", tool.id)),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        }
+        execute!(self.output, style::Print("\n"))?;
+
+        loop {
+            let line = match self
+                .input_source
+                .read_line(Some("Tools to keep running (e.g. 1,3 or all), or Enter to skip all: "))
+            {
+                Ok(Some(line)) => line,
+                _ => return Ok(HashSet::new()),
+            };
+            match parse_tool_selection(&line, remaining.len()) {
+                Some(selection) => return Ok(selection),
+                None => execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("Enter numbers between 1 and {}.\n", remaining.len())),
+                    style::SetForegroundColor(Color::Reset),
+                )?,
+            }
+        }
+    }
+
+    /// Estimated number of tokens that the next request would send, without running hooks.
+    async fn conversation_token_count(&mut self) -> usize {
+        let state = self.conversation_state.backend_conversation_state(false, true).await;
+        TokenCount::from(state.calculate_conversation_size().char_count()).value()
+    }
+
+    /// Compacts the conversation history, replacing the history with a summary generated by the
+    /// model.
+    ///
+    /// The most recent turns are kept as-is, see [DEFAULT_COMPACT_KEEP_TURNS].
+    async fn compact_history(
+        &mut self,
+        telemetry: &TelemetryThread,
+        tool_uses: Option<Vec<QueuedTool>>,
+        pending_tool_index: Option<usize>,
+        custom_prompt: Option<String>,
+        show_summary: bool,
+        help: bool,
+    ) -> Result<ChatState, ChatError> {
+        let hist = self.conversation_state.history();
+        debug!(?hist, "compacting history");
+
+        // If help flag is set, show compact command help
+        if help {
+            execute!(
+                self.output,
+                style::Print("\n"),
+                style::Print(compact_help_text()),
+                style::Print("\n")
+            )?;
+
+            return Ok(ChatState::PromptUser {
+                tool_uses,
+                pending_tool_index,
+                skip_printing_tools: true,
+            });
+        }
+
+        if self.conversation_state.history().len() < 2 {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print("\nConversation too short to compact.\n\n"),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+
+            return Ok(ChatState::PromptUser {
+                tool_uses,
                 pending_tool_index,
                 skip_printing_tools: true,
             });
         }
 
         // Send a request for summarizing the history.
-        let summary_state = self
+        let mut summary_state = self
             .conversation_state
             .create_summary_request(custom_prompt.as_ref())
             .await;
+        summary_state.user_input_message.model_id = self.model_id.clone();
         if self.interactive {
             execute!(self.output, cursor::Hide, style::Print("\n"))?;
-            self.spinner = Some(Spinner::new(Spinners::Dots, "Creating summary...".to_string()));
+            self.start_spinner(SpinnerMessage::Summarizing)?;
         }
-        let response = self.client.send_message(summary_state).await;
+        let response = self.send_message(summary_state).await;
 
         // TODO(brandonskiser): This is a temporary hotfix for failing compaction. We should instead
         // retry except with less context included.
@@ -1113,9 +1940,16 @@ impl ChatContext {
 
         let summary = {
             let mut parser = ResponseParser::new(response);
+            if let Some(recording) = &mut self.recording {
+                recording.begin_response();
+            }
             loop {
-                match parser.recv().await {
-                    Ok(parser::ResponseEvent::EndStream { message }) => {
+                let event = parser.recv().await;
+                if let (Ok(event), Some(recording)) = (&event, &mut self.recording) {
+                    recording.record_event(event);
+                }
+                match event {
+                    Ok(parser::ResponseEvent::EndStream { message, .. }) => {
                         break message.content().to_string();
                     },
                     Ok(_) => (),
@@ -1149,7 +1983,10 @@ impl ChatContext {
                 .ok();
         }
 
-        self.conversation_state.replace_history_with_summary(summary.clone());
+        let tokens_before = self.conversation_token_count().await;
+        self.conversation_state
+            .replace_history_with_summary(summary.clone(), self.compact_keep_turns);
+        let tokens_reclaimed = tokens_before.saturating_sub(self.conversation_token_count().await);
 
         // Print output to the user.
         {
@@ -1161,6 +1998,10 @@ impl ChatContext {
             )?;
 
             let mut output = Vec::new();
+            execute!(
+                output,
+                style::Print(format!("• Reclaimed ~{} tokens\n", tokens_reclaimed))
+            )?;
             if let Some(custom_prompt) = &custom_prompt {
                 execute!(
                     output,
@@ -1209,11 +2050,8 @@ impl ChatContext {
 
         // If a next message is set, then retry the request.
         if self.conversation_state.next_user_message().is_some() {
-            Ok(ChatState::HandleResponseStream(
-                self.client
-                    .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                    .await?,
-            ))
+            let conv_state = self.sendable_conversation_state(false).await;
+            Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?))
         } else {
             // Otherwise, return back to the prompt for any pending tool uses.
             Ok(ChatState::PromptUser {
@@ -1254,7 +2092,11 @@ impl ChatContext {
                 style::SetForegroundColor(Color::Green),
                 style::Print("t"),
                 style::SetForegroundColor(Color::DarkGrey),
-                style::Print("' to trust (always allow) this tool for the session. ["),
+                style::Print("' to trust (always allow) this tool for the session, or '"),
+                style::SetForegroundColor(Color::Green),
+                style::Print("n: <reason>"),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("' to decline it and tell Q why. ["),
                 style::SetForegroundColor(Color::Green),
                 style::Print("y"),
                 style::SetForegroundColor(Color::DarkGrey),
@@ -1292,7 +2134,11 @@ impl ChatContext {
             style::SetForegroundColor(Color::Reset),
             style::SetAttribute(Attribute::Reset)
         )?;
-        let user_input = match self.read_user_input(&self.generate_tool_trust_prompt(), false) {
+        let context_usage_percent = {
+            let token_count: TokenCount = self.conversation_state.calculate_char_count().await.into();
+            token_count.value() * 100 / CONTEXT_WINDOW_SIZE
+        };
+        let user_input = match self.read_user_input(&self.generate_tool_trust_prompt(context_usage_percent), false) {
             Some(input) => input,
             None => return Ok(ChatState::Exit),
         };
@@ -1307,11 +2153,19 @@ impl ChatContext {
 
     async fn handle_input(
         &mut self,
+        database: &mut Database,
         telemetry: &TelemetryThread,
         mut user_input: String,
         tool_uses: Option<Vec<QueuedTool>>,
         pending_tool_index: Option<usize>,
     ) -> Result<ChatState, ChatError> {
+        // Expand aliases ahead of parsing so that `@name` isn't taken for a prompt name. Slash
+        // commands are left as written, so `/alias` keeps any `@` words in the text it's given.
+        if !user_input.trim_start().starts_with('/') {
+            if let Some(context_manager) = &self.conversation_state.context_manager {
+                user_input = context_manager.expand_aliases(&user_input);
+            }
+        }
         let command_result = Command::parse(&user_input, &mut self.output);
 
         if let Err(error_message) = &command_result {
@@ -1335,13 +2189,39 @@ impl ChatContext {
 
         Ok(match command {
             Command::Ask { prompt } => {
+                if pending_tool_index.is_none() && std::mem::take(&mut self.from_template) {
+                    let placeholders = templates::placeholders(&prompt);
+                    if !placeholders.is_empty() {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!(
+                                "\nFill in {} before sending the prompt.\n\n",
+                                placeholders
+                                    .iter()
+                                    .map(|name| format!("{{{{{name}}}}}"))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                        self.input_source.set_initial_text(prompt);
+                        self.from_template = true;
+                        return Ok(ChatState::PromptUser {
+                            tool_uses: Some(tool_uses),
+                            pending_tool_index,
+                            skip_printing_tools: true,
+                        });
+                    }
+                }
+
                 // Check for a pending tool approval
                 if let Some(index) = pending_tool_index {
                     let tool_use = &mut tool_uses[index];
 
                     let is_trust = ["t", "T"].contains(&prompt.as_str());
                     if ["y", "Y"].contains(&prompt.as_str()) || is_trust {
-                        if is_trust {
+                        if is_trust || self.tool_permissions.policy(&tool_use.name) == Some(ToolPolicy::Once) {
                             self.tool_permissions.trust_tool(&tool_use.name);
                         }
                         tool_use.accepted = true;
@@ -1358,14 +2238,49 @@ impl ChatContext {
 
                 // Otherwise continue with normal chat on 'n' or other responses
                 self.tool_use_status = ToolUseStatus::Idle;
+                self.tool_failures.reset();
 
                 if pending_tool_index.is_some() {
-                    self.conversation_state.abandon_tool_use(tool_uses, user_input);
+                    let completed = std::mem::take(&mut self.batch_tool_results);
+                    match decline_reason(&user_input) {
+                        Some(reason) => self.conversation_state.decline_tool_use(completed, tool_uses, reason),
+                        None => self
+                            .conversation_state
+                            .abandon_tool_use(completed, tool_uses, user_input),
+                    }
                 } else {
+                    // Files referenced as `@path` are only inlined into what's sent, the transcript
+                    // keeps the prompt as it was typed.
+                    let context_manager = self.conversation_state.context_manager.as_ref();
+                    let max_file_bytes = context_manager.map_or(DEFAULT_MAX_CONTEXT_FILE_BYTES, |context_manager| {
+                        context_manager.max_context_file_bytes
+                    });
+                    let redactor = context_manager.and_then(|context_manager| context_manager.redactor.as_ref());
+                    let (user_input, unresolved) =
+                        context::inline_file_references(&self.ctx, &user_input, max_file_bytes, redactor).await;
+                    for reference in unresolved {
+                        queue!(
+                            self.output,
+                            style::SetForegroundColor(Color::Yellow),
+                            style::Print(format!(
+                                "Warning: '@{}' was sent as written, {}.\n",
+                                reference.path, reference.reason
+                            )),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    }
                     self.conversation_state.set_next_user_message(user_input).await;
                 }
-
-                let conv_state = self.conversation_state.as_sendable_conversation_state(true).await;
+                self.stats.record_user_message();
+                self.turn_start = Some(Instant::now());
+
+                let conv_state = self.sendable_conversation_state(true).await;
+                if self.dry_run {
+                    let request = serde_json::to_string_pretty(&conv_state)
+                        .map_err(|err| ChatError::Custom(format!("Failed to serialize the request: {err}").into()))?;
+                    writeln!(std::io::stdout(), "{request}")?;
+                    return Ok(ChatState::Exit);
+                }
                 self.send_tool_use_telemetry(telemetry).await;
 
                 if self.interactive {
@@ -1373,14 +2288,156 @@ impl ChatContext {
                     queue!(self.output, style::SetForegroundColor(Color::Reset))?;
                     queue!(self.output, cursor::Hide)?;
                     execute!(self.output, style::Print("\n"))?;
-                    self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_owned()));
+                    self.start_spinner(SpinnerMessage::Thinking)?;
+                }
+
+                ChatState::HandleResponseStream(self.send_message(conv_state).await?)
+            },
+            Command::Retry => {
+                if !self.conversation_state.prepare_retry() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print("\nThere is no previous message to retry.\n\n"),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
+                }
+
+                self.tool_use_status = ToolUseStatus::Idle;
+                let conv_state = self.sendable_conversation_state(true).await;
+                self.send_tool_use_telemetry(telemetry).await;
+
+                if self.interactive {
+                    queue!(self.output, cursor::Hide)?;
+                    execute!(self.output, style::Print("\n"))?;
+                    self.start_spinner(SpinnerMessage::Thinking)?;
+                }
+
+                ChatState::HandleResponseStream(self.send_message(conv_state).await?)
+            },
+            Command::Redo => {
+                let failed_tool_uses = self.conversation_state.failed_tool_uses();
+                if failed_tool_uses.is_empty() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print("\nNo tools failed in the last turn, so there is nothing to redo.\n\n"),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
+                }
+
+                // The failed tool uses already have results in the history, so they're run again
+                // under new ids.
+                let tool_uses = failed_tool_uses
+                    .into_iter()
+                    .map(|tool_use| AssistantToolUse {
+                        id: format!("tooluse_{}", Alphanumeric.sample_string(&mut rand::rng(), 22)),
+                        ..tool_use
+                    })
+                    .collect::<Vec<_>>();
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(
+                        "\nRunning {} failed tool{} again\n",
+                        tool_uses.len(),
+                        if tool_uses.len() == 1 { "" } else { "s" }
+                    )),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+                self.tool_use_status = ToolUseStatus::Idle;
+                self.conversation_state.push_redo_tool_uses(tool_uses.clone(), database);
+                ChatState::ValidateTools(tool_uses)
+            },
+            Command::Fork { name } => {
+                let previous = self.branches.current().to_string();
+                match self.branches.fork(&name, &self.conversation_state) {
+                    Ok(()) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!(
+                            "\nForked '{previous}' into '{name}'. Switch back with /branch {previous}\n\n"
+                        )),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                    Err(err) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\n{err}\n\n")),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                }
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Branch { name: None } => {
+                execute!(self.output, style::Print("\n"))?;
+                for name in self.branches.names() {
+                    if name == self.branches.current() {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print("* "),
+                            style::Print(name),
+                            style::SetForegroundColor(Color::Reset),
+                            style::Print("\n")
+                        )?;
+                    } else {
+                        execute!(self.output, style::Print("  "), style::Print(name), style::Print("\n"))?;
+                    }
+                }
+                execute!(self.output, style::Print("\n"))?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Branch { name: Some(name) } => {
+                if let Err(err) = self.branches.switch(&name, &mut self.conversation_state) {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\n{err}\n\n")),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
                 }
 
-                ChatState::HandleResponseStream(self.client.send_message(conv_state).await?)
+                // Tool uses awaiting approval belong to the branch that was left.
+                self.tool_use_status = ToolUseStatus::Idle;
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\nSwitched to branch '{name}'\n\n")),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
             },
             Command::Execute { command } => {
                 queue!(self.output, style::Print('\n'))?;
-                std::process::Command::new("bash").args(["-c", &command]).status().ok();
+                UserShell::from_env(&self.ctx).command(&command).status().ok();
                 queue!(self.output, style::Print('\n'))?;
                 ChatState::PromptUser {
                     tool_uses: None,
@@ -1434,30 +2491,141 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
-            Command::Compact {
-                prompt,
-                show_summary,
-                help,
-            } => {
-                self.compact_history(
-                    telemetry,
-                    Some(tool_uses),
-                    pending_tool_index,
-                    prompt,
-                    show_summary,
-                    help,
-                )
-                .await?
-            },
-            Command::Help => {
-                execute!(self.output, style::Print(HELP_TEXT))?;
-                ChatState::PromptUser {
-                    tool_uses: Some(tool_uses),
-                    pending_tool_index,
-                    skip_printing_tools: true,
+            Command::Rewind { turn } => {
+                let turn_count = self.conversation_state.turn_count();
+                if turn >= turn_count {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!(
+                            "\nCannot rewind to turn {}: the conversation has {} turn(s).\n\n",
+                            turn, turn_count
+                        )),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
                 }
+
+                execute!(self.output, cursor::Show)?;
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(
+                        "\nAre you sure? This will discard the last {} turn(s) of the conversation. ",
+                        turn_count - turn
+                    )),
+                    style::Print("["),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print("y"),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print("/"),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print("n"),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print("]:\n\n"),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+
+                let user_input = match self.read_user_input("> ".yellow().to_string().as_str(), true) {
+                    Some(input) => input,
+                    None => "".to_string(),
+                };
+
+                if ["y", "Y"].contains(&user_input.as_str()) {
+                    self.conversation_state.rewind(turn);
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\nConversation rewound to turn {}.\n\n", turn)),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Model { name } => {
+                let Some(name) = name else {
+                    execute!(self.output, style::Print("\n"))?;
+                    for (name, model_id) in MODEL_OPTIONS {
+                        let selected = self.model_id.as_deref() == Some(*model_id);
+                        execute!(
+                            self.output,
+                            style::Print(if selected { "* " } else { "  " }),
+                            style::SetForegroundColor(if selected { Color::Green } else { Color::Reset }),
+                            style::Print(name),
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(format!(" ({model_id})\n")),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    }
+                    execute!(self.output, style::Print("\n"))?;
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
+                };
+
+                match MODEL_OPTIONS.iter().find(|(n, id)| *n == name || *id == name) {
+                    Some((name, model_id)) => {
+                        self.model_id = Some((*model_id).to_string());
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print(format!("\nSwitched model to {}.\n\n", name)),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    },
+                    None => {
+                        let valid = MODEL_OPTIONS.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ");
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\nUnknown model '{}'. Valid options are: {}\n\n", name, valid)),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    },
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Compact {
+                prompt,
+                show_summary,
+                help,
+            } => {
+                self.compact_history(
+                    telemetry,
+                    Some(tool_uses),
+                    pending_tool_index,
+                    prompt,
+                    show_summary,
+                    help,
+                )
+                .await?
             },
-            Command::Issue { prompt } => {
+            Command::Help => {
+                execute!(self.output, style::Print(help_text()))?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Issue { prompt, transcript } => {
+                self.issue_transcript = transcript;
                 let input = "I would like to report an issue or make a feature request";
                 ChatState::HandleInput {
                     input: if let Some(prompt) = prompt {
@@ -1469,8 +2637,71 @@ impl ChatContext {
                     pending_tool_index,
                 }
             },
+            Command::Feedback { sentiment, note } => {
+                match self.conversation_state.message_id() {
+                    Some(message_id) => {
+                        telemetry
+                            .send_chat_feedback(
+                                self.conversation_state.conversation_id().to_owned(),
+                                Some(message_id.to_owned()),
+                                sentiment,
+                                note,
+                            )
+                            .ok();
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print("\nThanks for the feedback!\n\n"),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    },
+                    None => {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Yellow),
+                            style::Print("\nThere's no response to give feedback on yet.\n\n"),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    },
+                }
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Replay => {
+                let last_response = self
+                    .conversation_state
+                    .history()
+                    .iter()
+                    .rev()
+                    .map(|(_, assistant)| assistant.content())
+                    .find(|content| !content.trim().is_empty())
+                    .map(str::to_string);
+                match last_response {
+                    Some(response) => {
+                        execute!(self.output, style::Print("\n"))?;
+                        self.render_markdown(database, &response)?;
+                        execute!(self.output, style::Print("\n"))?;
+                    },
+                    None => {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Yellow),
+                            style::Print("\nThere's no response to replay yet.\n\n"),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    },
+                }
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::PromptEditor { initial_text } => {
-                match Self::open_editor(initial_text) {
+                match self.open_editor(initial_text) {
                     Ok(content) => {
                         if content.trim().is_empty() {
                             execute!(
@@ -1558,24 +2789,121 @@ impl ChatContext {
                             };
 
                             execute!(self.output, style::Print("\n"))?;
-                            for profile in profiles {
-                                if profile == context_manager.current_profile {
-                                    execute!(
-                                        self.output,
-                                        style::SetForegroundColor(Color::Green),
-                                        style::Print("* "),
-                                        style::Print(&profile),
-                                        style::SetForegroundColor(Color::Reset),
-                                        style::Print("\n")
-                                    )?;
-                                } else {
-                                    execute!(
+                            queue_profile_list(&mut self.output, context_manager, &profiles)?;
+                            execute!(self.output, style::Print("\n"))?;
+                        },
+                        command::ProfileSubcommand::Show => {
+                            execute!(
+                                self.output,
+                                style::SetAttribute(Attribute::Bold),
+                                style::SetForegroundColor(Color::Magenta),
+                                style::Print(format!("\n👤 profile: {}\n", context_manager.current_profile)),
+                                style::SetAttribute(Attribute::Reset),
+                            )?;
+
+                            for (label, paths) in [
+                                ("🌍 global context", &context_manager.global_config.paths),
+                                ("📁 profile context", &context_manager.profile_config.paths),
+                            ] {
+                                queue!(
+                                    self.output,
+                                    style::SetAttribute(Attribute::Bold),
+                                    style::Print(format!("\n{label}:\n")),
+                                    style::SetAttribute(Attribute::Reset),
+                                )?;
+                                if paths.is_empty() {
+                                    queue!(
                                         self.output,
-                                        style::Print("  "),
-                                        style::Print(&profile),
-                                        style::Print("\n")
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print("    <none>\n"),
+                                        style::SetForegroundColor(Color::Reset)
                                     )?;
                                 }
+                                for path in paths {
+                                    queue!(self.output, style::Print(format!("    {path}\n")))?;
+                                }
+                            }
+
+                            queue!(
+                                self.output,
+                                style::SetAttribute(Attribute::Bold),
+                                style::Print("\n💬 system prompt:\n"),
+                                style::SetAttribute(Attribute::Reset),
+                            )?;
+                            if self.conversation_state.system_prompt.is_some() {
+                                queue!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print("    (from --system-prompt-file)\n"),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                            }
+                            match self
+                                .conversation_state
+                                .system_prompt
+                                .as_deref()
+                                .or(context_manager.system_prompt())
+                            {
+                                Some(prompt) => {
+                                    for line in prompt.lines() {
+                                        queue!(self.output, style::Print(format!("    {line}\n")))?;
+                                    }
+                                },
+                                None => queue!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print("    <none>\n"),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?,
+                            }
+
+                            queue!(
+                                self.output,
+                                style::SetAttribute(Attribute::Bold),
+                                style::Print("\n🛠️ trusted tools:\n"),
+                                style::SetAttribute(Attribute::Reset),
+                            )?;
+                            let configured_tools = self.tool_permissions.configured_tools();
+                            if self.tool_permissions.trust_all {
+                                queue!(self.output, style::Print("    <all tools>\n"))?;
+                            } else if configured_tools.is_empty() {
+                                queue!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print("    <none>\n"),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                            }
+                            for tool_name in configured_tools {
+                                queue!(
+                                    self.output,
+                                    style::Print(format!(
+                                        "    {tool_name}{}\n",
+                                        self.tool_permissions.display_label(tool_name)
+                                    ))
+                                )?;
+                            }
+
+                            queue!(
+                                self.output,
+                                style::SetAttribute(Attribute::Bold),
+                                style::Print("\n🤖 model:\n"),
+                                style::SetAttribute(Attribute::Reset),
+                            )?;
+                            match self.model_id.as_deref() {
+                                Some(model_id) => {
+                                    let name = MODEL_OPTIONS
+                                        .iter()
+                                        .find(|(_, id)| *id == model_id)
+                                        .map_or(model_id, |(name, _)| *name);
+                                    queue!(self.output, style::Print(format!("    {name} ({model_id})\n")))?;
+                                },
+                                None => queue!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print("    <none>\n"),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?,
                             }
                             execute!(self.output, style::Print("\n"))?;
                         },
@@ -1634,6 +2962,67 @@ impl ChatContext {
                                 Err(e) => print_err!(e),
                             }
                         },
+                        command::ProfileSubcommand::Copy { from, to, force } => {
+                            match context_manager.copy_profile(&from, &to, force).await {
+                                Ok(_) => {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::Green),
+                                        style::Print(format!("\nCopied profile: {} -> {}\n\n", from, to)),
+                                        style::SetForegroundColor(Color::Reset)
+                                    )?;
+                                },
+                                Err(e) => print_err!(e),
+                            }
+                        },
+                        command::ProfileSubcommand::Overlay { action } => match action {
+                            command::OverlayAction::List => {
+                                if context_manager.overlays.is_empty() {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print(
+                                            "\nNo profiles are overlaid. Add one with /profile overlay add <name>\n\n"
+                                        ),
+                                        style::SetForegroundColor(Color::Reset)
+                                    )?;
+                                } else {
+                                    queue!(
+                                        self.output,
+                                        style::Print(format!(
+                                            "\nOverlaid on {}, in order:\n",
+                                            context_manager.current_profile
+                                        ))
+                                    )?;
+                                    for (i, overlay) in context_manager.overlays.iter().enumerate() {
+                                        queue!(self.output, style::Print(format!("  {}. {}\n", i + 1, overlay.name)))?;
+                                    }
+                                    execute!(self.output, style::Print("\n"))?;
+                                }
+                            },
+                            command::OverlayAction::Add { name } => match context_manager.add_overlay(&name).await {
+                                Ok(_) => {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::Green),
+                                        style::Print(format!("\nOverlaid profile: {}\n\n", name)),
+                                        style::SetForegroundColor(Color::Reset)
+                                    )?;
+                                },
+                                Err(e) => print_err!(e),
+                            },
+                            command::OverlayAction::Remove { name } => match context_manager.remove_overlay(&name) {
+                                Ok(_) => {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::Green),
+                                        style::Print(format!("\nRemoved overlay: {}\n\n", name)),
+                                        style::SetForegroundColor(Color::Reset)
+                                    )?;
+                                },
+                                Err(e) => print_err!(e),
+                            },
+                        },
                         command::ProfileSubcommand::Help => {
                             execute!(
                                 self.output,
@@ -1652,13 +3041,40 @@ impl ChatContext {
             },
             Command::Context { subcommand } => {
                 if let Some(context_manager) = &mut self.conversation_state.context_manager {
-                    match subcommand {
-                        command::ContextSubcommand::Show { expand } => {
-                            fn map_chat_error(e: ErrReport) -> ChatError {
-                                ChatError::Custom(e.to_string().into())
-                            }
-                            // Display global context
-                            execute!(
+                    // Another profile's rules are edited through a manager loaded with that
+                    // profile, which saves to its config without switching to it.
+                    let mut other_profile = None;
+                    if let Some(name) = subcommand
+                        .target_profile()
+                        .filter(|name| *name != context_manager.current_profile)
+                    {
+                        match context_manager.for_profile(name).await {
+                            Ok(manager) => other_profile = Some(manager),
+                            Err(e) => {
+                                execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Red),
+                                    style::Print(format!("\nError: {}\n\n", e)),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                                return Ok(ChatState::PromptUser {
+                                    tool_uses: Some(tool_uses),
+                                    pending_tool_index,
+                                    skip_printing_tools: true,
+                                });
+                            },
+                        }
+                    }
+                    let context_manager = other_profile.as_mut().unwrap_or(context_manager);
+
+                    match subcommand {
+                        command::ContextSubcommand::Show { expand, by_size } => {
+                            fn map_chat_error(e: ErrReport) -> ChatError {
+                                ChatError::Custom(e.to_string().into())
+                            }
+                            context_manager.mark_paths_shown();
+                            // Display global context
+                            execute!(
                                 self.output,
                                 style::SetAttribute(Attribute::Bold),
                                 style::SetForegroundColor(Color::Magenta),
@@ -1675,8 +3091,14 @@ impl ChatContext {
                                     style::SetForegroundColor(Color::Reset)
                                 )?;
                             } else {
-                                for path in &context_manager.global_config.paths {
-                                    execute!(self.output, style::Print(format!("    {} ", path)))?;
+                                for (i, path) in context_manager.global_config.paths.iter().enumerate() {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print(format!("    {}. ", i + 1)),
+                                        style::SetForegroundColor(Color::Reset),
+                                        style::Print(format!("{} ", path))
+                                    )?;
                                     if let Ok(context_files) = context_manager.get_context_files_by_path(path).await {
                                         execute!(
                                             self.output,
@@ -1733,8 +3155,14 @@ impl ChatContext {
                                     style::SetForegroundColor(Color::Reset)
                                 )?;
                             } else {
-                                for path in &context_manager.profile_config.paths {
-                                    execute!(self.output, style::Print(format!("    {} ", path)))?;
+                                for (i, path) in context_manager.profile_config.paths.iter().enumerate() {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print(format!("    {}. ", i + 1)),
+                                        style::SetForegroundColor(Color::Reset),
+                                        style::Print(format!("{} ", path))
+                                    )?;
                                     if let Ok(context_files) = context_manager.get_context_files_by_path(path).await {
                                         execute!(
                                             self.output,
@@ -1775,6 +3203,53 @@ impl ChatContext {
                                 execute!(self.output, style::Print("\n"))?;
                             }
 
+                            // Overlays come after the profile. Their matches are merged into the
+                            // profile's, so a file matched by both is only listed once.
+                            for overlay in &context_manager.overlays {
+                                execute!(
+                                    self.output,
+                                    style::SetAttribute(Attribute::Bold),
+                                    style::SetForegroundColor(Color::Magenta),
+                                    style::Print(format!("🧩 overlay ({}):\n", overlay.name)),
+                                    style::SetAttribute(Attribute::Reset),
+                                )?;
+                                if overlay.config.paths.is_empty() {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print("    <none>\n"),
+                                        style::SetForegroundColor(Color::Reset)
+                                    )?;
+                                }
+                                for path in &overlay.config.paths {
+                                    execute!(self.output, style::Print(format!("    {} ", path)))?;
+                                    if let Ok(context_files) = context_manager.get_context_files_by_path(path).await {
+                                        execute!(
+                                            self.output,
+                                            style::SetForegroundColor(Color::Green),
+                                            style::Print(format!(
+                                                "({} match{})",
+                                                context_files.len(),
+                                                if context_files.len() == 1 { "" } else { "es" }
+                                            )),
+                                            style::SetForegroundColor(Color::Reset)
+                                        )?;
+                                        profile_context_files.extend(context_files);
+                                    }
+                                    execute!(self.output, style::Print("\n"))?;
+                                }
+                            }
+                            if !context_manager.overlays.is_empty() {
+                                execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(
+                                        "\nOverlay rules are applied after the profile's, and each file is included once. Hooks and the system prompt come from the profile only.\n\n"
+                                    ),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                            }
+
                             if global_context_files.is_empty() && profile_context_files.is_empty() {
                                 execute!(
                                     self.output,
@@ -1805,32 +3280,36 @@ impl ChatContext {
                                     style::SetAttribute(Attribute::Reset)
                                 )?;
 
-                                for (filename, content) in &global_context_files {
-                                    let est_tokens = TokenCounter::count_tokens(content);
-                                    execute!(
-                                        self.output,
-                                        style::Print(format!("🌍 {} ", filename)),
-                                        style::SetForegroundColor(Color::DarkGrey),
-                                        style::Print(format!("(~{} tkns)\n", est_tokens)),
-                                        style::SetForegroundColor(Color::Reset),
-                                    )?;
-                                    if expand {
-                                        execute!(
-                                            self.output,
-                                            style::SetForegroundColor(Color::DarkGrey),
-                                            style::Print(format!("{}\n\n", content)),
-                                            style::SetForegroundColor(Color::Reset)
-                                        )?;
-                                    }
+                                // With --expand, say how many secrets are masked in each file.
+                                let redaction_note = |redactions: usize| match (expand, redactions) {
+                                    (false, _) | (_, 0) => String::new(),
+                                    (true, 1) => ", 1 secret redacted".to_string(),
+                                    (true, n) => format!(", {n} secrets redacted"),
+                                };
+                                let mut listed_files = global_context_files
+                                    .iter()
+                                    .map(|file| ("🌍", file))
+                                    .chain(profile_context_files.iter().map(|file| ("👤", file)))
+                                    .collect::<Vec<_>>();
+                                if by_size {
+                                    listed_files.sort_by(|(_, (a, a_content)), (_, (b, b_content))| {
+                                        b_content.len().cmp(&a_content.len()).then_with(|| a.cmp(b))
+                                    });
                                 }
-
-                                for (filename, content) in &profile_context_files {
-                                    let est_tokens = TokenCounter::count_tokens(content);
+                                for (marker, (filename, content)) in listed_files {
+                                    let size = format_bytes(content.len());
+                                    let (content, redactions) = context_manager.redact(content);
+                                    let est_tokens = TokenCounter::count_tokens(&content);
                                     execute!(
                                         self.output,
-                                        style::Print(format!("👤 {} ", filename)),
+                                        style::Print(format!("{} {} ", marker, filename)),
                                         style::SetForegroundColor(Color::DarkGrey),
-                                        style::Print(format!("(~{} tkns)\n", est_tokens)),
+                                        style::Print(format!(
+                                            "(~{} tkns, {}{})\n",
+                                            est_tokens,
+                                            size,
+                                            redaction_note(redactions)
+                                        )),
                                         style::SetForegroundColor(Color::Reset),
                                     )?;
                                     if expand {
@@ -1856,9 +3335,18 @@ impl ChatContext {
                                 let dropped_files =
                                     drop_matched_context_files(&mut combined_files, CONTEXT_FILES_MAX_SIZE).ok();
 
+                                let total_bytes = global_context_files
+                                    .iter()
+                                    .chain(profile_context_files.iter())
+                                    .map(|(_, content)| content.len())
+                                    .sum::<usize>();
                                 execute!(
                                     self.output,
-                                    style::Print(format!("\nTotal: ~{} tokens\n\n", total_tokens))
+                                    style::Print(format!(
+                                        "\nTotal: ~{} tokens, {}\n\n",
+                                        total_tokens,
+                                        format_bytes(total_bytes)
+                                    ))
                                 )?;
 
                                 if let Some(dropped_files) = dropped_files {
@@ -1899,6 +3387,53 @@ impl ChatContext {
                                 execute!(self.output, style::Print("\n"))?;
                             }
 
+                            let (ignored_files, skipped_files): (Vec<_>, Vec<_>) = context_manager
+                                .get_skipped_context_files()
+                                .await
+                                .unwrap_or_default()
+                                .into_iter()
+                                .partition(|file| file.reason == context::SkipReason::Ignored);
+                            if expand && !ignored_files.is_empty() {
+                                execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(format!(
+                                        "{} matched file{} excluded by {}{}\n\n",
+                                        ignored_files.len(),
+                                        if ignored_files.len() == 1 { " was" } else { "s were" },
+                                        context::QIGNORE_FILENAME,
+                                        if context_manager.respect_gitignore {
+                                            " and .gitignore"
+                                        } else {
+                                            ""
+                                        }
+                                    )),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                            }
+                            if !skipped_files.is_empty() {
+                                queue!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkYellow),
+                                    style::Print(format!(
+                                        "{} matched file{} skipped:\n",
+                                        skipped_files.len(),
+                                        if skipped_files.len() == 1 { " was" } else { "s were" }
+                                    )),
+                                    style::SetForegroundColor(Color::Reset),
+                                )?;
+                                for file in skipped_files {
+                                    queue!(
+                                        self.output,
+                                        style::Print(format!("{} ", file.path)),
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print(format!("({})\n", file.reason)),
+                                        style::SetForegroundColor(Color::Reset),
+                                    )?;
+                                }
+                                execute!(self.output, style::Print("\n"))?;
+                            }
+
                             // Show last cached conversation summary if available, otherwise regenerate it
                             if expand {
                                 if let Some(summary) = self.conversation_state.latest_summary() {
@@ -1921,42 +3456,63 @@ impl ChatContext {
                                 }
                             }
                         },
-                        command::ContextSubcommand::Add { global, force, paths } => {
-                            match context_manager.add_paths(paths.clone(), global, force).await {
-                                Ok(_) => {
-                                    let target = if global { "global" } else { "profile" };
-                                    execute!(
-                                        self.output,
-                                        style::SetForegroundColor(Color::Green),
-                                        style::Print(format!(
-                                            "\nAdded {} path(s) to {} context.\n\n",
-                                            paths.len(),
-                                            target
-                                        )),
-                                        style::SetForegroundColor(Color::Reset)
-                                    )?;
-                                },
-                                Err(e) => {
-                                    execute!(
-                                        self.output,
-                                        style::SetForegroundColor(Color::Red),
-                                        style::Print(format!("\nError: {}\n\n", e)),
-                                        style::SetForegroundColor(Color::Reset)
-                                    )?;
-                                },
-                            }
+                        command::ContextSubcommand::Add {
+                            global,
+                            force,
+                            refresh,
+                            paths,
+                            profile,
+                        } => match context_manager.add_paths(paths.clone(), global, force, refresh).await {
+                            Ok(unmatched) => {
+                                let target = match (global, profile) {
+                                    (true, _) => "global".to_string(),
+                                    (false, Some(profile)) => format!("profile '{profile}'"),
+                                    (false, None) => "profile".to_string(),
+                                };
+                                execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Green),
+                                    style::Print(format!("\nAdded {} path(s) to {} context.\n", paths.len(), target)),
+                                    style::SetForegroundColor(Color::Yellow),
+                                )?;
+                                for path in unmatched {
+                                    let warning = match context::is_url(&path) {
+                                        true => format!("Warning: '{path}' couldn't be fetched.\n"),
+                                        false => format!("Warning: '{path}' doesn't match any files yet.\n"),
+                                    };
+                                    queue!(self.output, style::Print(warning))?;
+                                }
+                                execute!(self.output, style::SetForegroundColor(Color::Reset), style::Print("\n"))?;
+                            },
+                            Err(e) => {
+                                execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Red),
+                                    style::Print(format!("\nError: {}\n\n", e)),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                            },
                         },
-                        command::ContextSubcommand::Remove { global, paths } => {
-                            match context_manager.remove_paths(paths.clone(), global).await {
-                                Ok(_) => {
-                                    let target = if global { "global" } else { "profile" };
+                        command::ContextSubcommand::Remove { global, paths, profile } => {
+                            let result = match context_manager.rule_numbers(&paths, global) {
+                                Some(numbers) => context_manager.remove_paths_by_index(&numbers, global).await,
+                                None => context_manager.remove_paths(paths.clone(), global).await.map(|_| paths),
+                            };
+                            match result {
+                                Ok(paths) => {
+                                    let target = match (global, profile) {
+                                        (true, _) => "global".to_string(),
+                                        (false, Some(profile)) => format!("profile '{profile}'"),
+                                        (false, None) => "profile".to_string(),
+                                    };
                                     execute!(
                                         self.output,
                                         style::SetForegroundColor(Color::Green),
                                         style::Print(format!(
-                                            "\nRemoved {} path(s) from {} context.\n\n",
+                                            "\nRemoved {} path(s) from {} context: {}\n\n",
                                             paths.len(),
-                                            target
+                                            target,
+                                            paths.join(", ")
                                         )),
                                         style::SetForegroundColor(Color::Reset)
                                     )?;
@@ -1971,7 +3527,7 @@ impl ChatContext {
                                 },
                             }
                         },
-                        command::ContextSubcommand::Clear { global } => match context_manager.clear(global).await {
+                        command::ContextSubcommand::Clear { global, .. } => match context_manager.clear(global).await {
                             Ok(_) => {
                                 let target = if global {
                                     "global".to_string()
@@ -1994,6 +3550,222 @@ impl ChatContext {
                                 )?;
                             },
                         },
+                        command::ContextSubcommand::Validate => {
+                            let statuses = match context_manager.validate_paths().await {
+                                Ok(statuses) => statuses,
+                                Err(e) => {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::Red),
+                                        style::Print(format!("\nError: {}\n\n", e)),
+                                        style::SetForegroundColor(Color::Reset)
+                                    )?;
+                                    return Ok(ChatState::PromptUser {
+                                        tool_uses: Some(tool_uses),
+                                        pending_tool_index,
+                                        skip_printing_tools: true,
+                                    });
+                                },
+                            };
+
+                            let width = statuses.iter().map(|status| status.path.width()).max().unwrap_or(0);
+                            queue!(self.output, style::Print("\n"))?;
+                            for status in &statuses {
+                                let (color, mark, description) = match status.validity {
+                                    PathValidity::Valid { files } => (
+                                        Color::Green,
+                                        "✓",
+                                        format!("{files} file{}", if files == 1 { "" } else { "s" }),
+                                    ),
+                                    PathValidity::EmptyGlob => (Color::Yellow, "○", "empty glob".to_string()),
+                                    PathValidity::Missing => (Color::Red, "✗", "missing".to_string()),
+                                };
+                                queue!(
+                                    self.output,
+                                    style::SetForegroundColor(color),
+                                    style::Print(format!("{mark} ")),
+                                    style::SetForegroundColor(Color::Reset),
+                                    style::Print(format!(
+                                        "{}{}  ",
+                                        status.path,
+                                        " ".repeat(width - status.path.width())
+                                    )),
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(description),
+                                    style::Print(if status.global { " (global)\n" } else { "\n" }),
+                                    style::SetForegroundColor(Color::Reset),
+                                )?;
+                            }
+
+                            let count = |f: fn(&PathValidity) -> bool| {
+                                statuses.iter().filter(|status| f(&status.validity)).count()
+                            };
+                            let valid = count(|v| matches!(v, PathValidity::Valid { .. }));
+                            let empty = count(|v| *v == PathValidity::EmptyGlob);
+                            let missing = count(|v| *v == PathValidity::Missing);
+                            execute!(
+                                self.output,
+                                style::Print(format!(
+                                    "\n{valid} valid, {empty} empty glob{}, {missing} missing\n\n",
+                                    if empty == 1 { "" } else { "s" }
+                                )),
+                            )?;
+
+                            let (dead_global, dead_profile): (Vec<_>, Vec<_>) = statuses
+                                .into_iter()
+                                .filter(|status| !matches!(status.validity, PathValidity::Valid { .. }))
+                                .partition(|status| status.global);
+                            if !dead_global.is_empty() || !dead_profile.is_empty() {
+                                execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(format!(
+                                        "Remove the {} rules that match nothing? [",
+                                        dead_global.len() + dead_profile.len()
+                                    )),
+                                    style::SetForegroundColor(Color::Green),
+                                    style::Print("y"),
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print("/"),
+                                    style::SetForegroundColor(Color::Green),
+                                    style::Print("n"),
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print("]:\n\n"),
+                                    style::SetForegroundColor(Color::Reset),
+                                )?;
+                                let answer = self
+                                    .input_source
+                                    .read_line(Some("> ".yellow().to_string().as_str()))
+                                    .ok()
+                                    .flatten()
+                                    .unwrap_or_default();
+
+                                if ["y", "Y"].contains(&answer.trim()) {
+                                    for (dead, global) in [(dead_global, true), (dead_profile, false)] {
+                                        if dead.is_empty() {
+                                            continue;
+                                        }
+                                        let paths = dead.into_iter().map(|status| status.path).collect::<Vec<_>>();
+                                        let removed = paths.len();
+                                        match context_manager.remove_paths(paths, global).await {
+                                            Ok(()) => execute!(
+                                                self.output,
+                                                style::SetForegroundColor(Color::Green),
+                                                style::Print(format!(
+                                                    "\nRemoved {removed} rule(s) from {}\n",
+                                                    if global {
+                                                        "global context".to_string()
+                                                    } else {
+                                                        format!("profile '{}'", context_manager.current_profile)
+                                                    }
+                                                )),
+                                                style::SetForegroundColor(Color::Reset)
+                                            )?,
+                                            Err(e) => execute!(
+                                                self.output,
+                                                style::SetForegroundColor(Color::Red),
+                                                style::Print(format!("\nError: {}\n", e)),
+                                                style::SetForegroundColor(Color::Reset)
+                                            )?,
+                                        }
+                                    }
+                                    execute!(self.output, style::Print("\n"))?;
+                                }
+                            }
+                        },
+                        command::ContextSubcommand::Diff { profile } => {
+                            match context_manager.diff_profile(&profile).await {
+                                Ok(diff) if diff.is_empty() => execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(format!(
+                                        "\nProfiles '{}' and '{profile}' have the same context rules\n\n",
+                                        context_manager.current_profile
+                                    )),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?,
+                                Ok(diff) => {
+                                    queue!(
+                                        self.output,
+                                        style::Print(format!(
+                                            "\nContext rules of profile '{}' compared to '{profile}':\n",
+                                            context_manager.current_profile
+                                        )),
+                                    )?;
+                                    for change in diff {
+                                        let (color, sign, path) = match change {
+                                            context::PathDiff::Added(path) => (Color::Green, '+', path),
+                                            context::PathDiff::Removed(path) => (Color::Red, '-', path),
+                                        };
+                                        queue!(
+                                            self.output,
+                                            style::SetForegroundColor(color),
+                                            style::Print(format!("{sign} {path}\n")),
+                                            style::SetForegroundColor(Color::Reset),
+                                        )?;
+                                    }
+                                    execute!(self.output, style::Print("\n"))?;
+                                },
+                                Err(e) => execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Red),
+                                    style::Print(format!("\nError: {}\n\n", e)),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?,
+                            }
+                        },
+                        command::ContextSubcommand::Prompt { action } => {
+                            let profile = context_manager.current_profile.clone();
+                            let result = match action {
+                                command::SystemPromptAction::Show => {
+                                    match context_manager.system_prompt() {
+                                        Some(prompt) => execute!(self.output, style::Print(format!("\n{prompt}\n\n")))?,
+                                        None => execute!(
+                                            self.output,
+                                            style::SetForegroundColor(Color::DarkGrey),
+                                            style::Print(format!(
+                                                "\nNo system prompt is set for profile '{profile}'\n\n"
+                                            )),
+                                            style::SetForegroundColor(Color::Reset)
+                                        )?,
+                                    }
+                                    Ok(None)
+                                },
+                                command::SystemPromptAction::Set { prompt } => context_manager
+                                    .set_system_prompt(Some(prompt))
+                                    .await
+                                    .map(|_| Some(format!("Set the system prompt for profile '{profile}'"))),
+                                command::SystemPromptAction::Clear => context_manager
+                                    .set_system_prompt(None)
+                                    .await
+                                    .map(|_| Some(format!("Cleared the system prompt for profile '{profile}'"))),
+                            };
+                            match result {
+                                Ok(Some(message)) => execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Green),
+                                    style::Print(format!("\n{message}\n\n")),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?,
+                                Ok(None) => {},
+                                Err(e) => execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Red),
+                                    style::Print(format!("\nError: {}\n\n", e)),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?,
+                            }
+                            if self.conversation_state.system_prompt.is_some() {
+                                execute!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(
+                                        "This session uses the system prompt from --system-prompt-file instead.\n\n"
+                                    ),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                            }
+                        },
                         command::ContextSubcommand::Help => {
                             execute!(
                                 self.output,
@@ -2333,10 +4105,12 @@ impl ChatContext {
                         }
                     },
                     Some(ToolsSubcommand::TrustAll) => {
-                        self.conversation_state.tools.values().flatten().for_each(
-                            |FigTool::ToolSpecification(spec)| {
-                                self.tool_permissions.trust_tool(spec.name.as_str());
-                            },
+                        self.tool_permissions.trust_all_tools(
+                            self.conversation_state
+                                .tools
+                                .values()
+                                .flatten()
+                                .map(|FigTool::ToolSpecification(spec)| spec.name.as_str()),
                         );
                         queue!(self.output, style::Print(TRUST_ALL_TEXT),)?;
                     },
@@ -2700,6 +4474,39 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
+            Command::Expand => {
+                match self.last_tool_output.clone() {
+                    Some(output) => {
+                        let text = format!("\n{}\n\n", output.trim_end());
+                        if !self.page(text.as_bytes())? {
+                            execute!(self.output, style::Print(text))?;
+                        }
+                    },
+                    None => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print("\nNo tool has run yet.\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?,
+                }
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Stats => {
+                execute!(self.output, style::Print("\n"))?;
+                for line in self.stats.lines(self.stats.elapsed()) {
+                    queue!(self.output, style::Print(line), style::Print("\n"))?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Usage => {
                 let state = self.conversation_state.backend_conversation_state(true, true).await;
 
@@ -2856,6 +4663,55 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
+            Command::Tokens => {
+                let state = self.conversation_state.backend_conversation_state(true, true).await;
+                let data = state.calculate_conversation_size();
+
+                // Token counts are estimated from byte lengths by [TokenCounter], so they are only
+                // approximate.
+                let context_files_tokens: TokenCount =
+                    CharCount::from(self.conversation_state.context_message_length().unwrap_or_default()).into();
+                let history_tokens: TokenCount = (data.user_messages + data.assistant_messages).into();
+                let total_tokens: TokenCount = data.char_count().into();
+                let warning_threshold = database
+                    .settings
+                    .get_int(Setting::ChatContextWarningTokens)
+                    .and_then(|v| usize::try_from(v).ok())
+                    .unwrap_or(CONTEXT_WINDOW_SIZE * prompt::CONTEXT_USAGE_INDICATOR_PERCENT / 100);
+
+                queue!(
+                    self.output,
+                    style::Print(format!("\nContext files: ~{} tokens\n", context_files_tokens)),
+                    style::Print(format!("History:       ~{} tokens\n", history_tokens)),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print(format!(
+                        "Total:         ~{} of {}k tokens ({:.1}%)\n",
+                        total_tokens,
+                        CONTEXT_WINDOW_SIZE / 1000,
+                        (total_tokens.value() as f64 / CONTEXT_WINDOW_SIZE as f64) * 100.0
+                    )),
+                    style::SetAttribute(Attribute::Reset),
+                )?;
+                if total_tokens.value() > warning_threshold {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print(format!(
+                            "\n⚠️ The next request exceeds the warning threshold of {} tokens. Use /compact or \
+                             remove context files to free up space.\n",
+                            warning_threshold
+                        )),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Load { path } => {
                 macro_rules! tri {
                     ($v:expr) => {
@@ -2880,9 +4736,21 @@ impl ChatContext {
 
                 let contents = tri!(self.ctx.fs().read_to_string(&path).await);
                 let mut new_state: ConversationState = tri!(serde_json::from_str(&contents));
+                if new_state.version() > CONVERSATION_STATE_VERSION {
+                    tri!(Err::<(), _>(format!(
+                        "the file was saved with a newer format version ({}) than this version of {} supports ({})",
+                        new_state.version(),
+                        CLI_BINARY_NAME,
+                        CONVERSATION_STATE_VERSION
+                    )));
+                }
                 new_state
                     .reload_serialized_state(Arc::clone(&self.ctx), Some(self.output.clone()))
                     .await;
+                new_state.tool_manager = std::mem::take(&mut self.conversation_state.tool_manager);
+                new_state.history_strategy = self.conversation_state.history_strategy;
+                new_state.update_state(true).await;
+                new_state.enforce_conversation_invariants();
                 self.conversation_state = new_state;
 
                 execute!(
@@ -2952,6 +4820,146 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
+            Command::Copy { target } => {
+                let response = self
+                    .conversation_state
+                    .history()
+                    .iter()
+                    .rev()
+                    .map(|(_, assistant)| assistant.content())
+                    .find(|content| !content.trim().is_empty());
+                let text = match (response, target) {
+                    (Some(response), CopyTarget::Last) => Ok(response.to_string()),
+                    (Some(response), CopyTarget::LastCode) => {
+                        first_code_block(response).ok_or("The last response does not contain a code block.")
+                    },
+                    (None, _) => Err("There is no response to copy yet."),
+                };
+
+                match text {
+                    Ok(text) => match clipboard::copy_to_clipboard(&self.ctx, &text).await {
+                        Ok(_) => execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print(match target {
+                                CopyTarget::Last => "\n✔ Copied the last response to the clipboard\n\n",
+                                CopyTarget::LastCode => "\n✔ Copied the code block to the clipboard\n\n",
+                            }),
+                            style::SetForegroundColor(Color::Reset)
+                        )?,
+                        Err(err) => execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\nFailed to copy to the clipboard: {}\n\n", err)),
+                            style::SetForegroundColor(Color::Reset)
+                        )?,
+                    },
+                    Err(msg) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\n{}\n\n", msg)),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Yank { path, index, force } => {
+                let full_path = tools::sanitize_path_tool_arg(&self.ctx, &path);
+                let response = self
+                    .conversation_state
+                    .history()
+                    .iter()
+                    .rev()
+                    .map(|(_, assistant)| assistant.content())
+                    .find(|content| !content.trim().is_empty());
+                let code = match response.map(code_blocks) {
+                    Some(blocks) if blocks.is_empty() => {
+                        Err("The last response does not contain a code block.".to_string())
+                    },
+                    Some(mut blocks) if index <= blocks.len() => Ok(blocks.swap_remove(index - 1)),
+                    Some(blocks) => Err(format!(
+                        "The last response only contains {} code block{}.",
+                        blocks.len(),
+                        if blocks.len() == 1 { "" } else { "s" }
+                    )),
+                    None => Err("There is no response to yank from yet.".to_string()),
+                };
+                let code = code.and_then(|code| {
+                    if self.ctx.fs().exists(&full_path) && !force {
+                        Err(format!(
+                            "File at {} already exists. To overwrite, use -f or --force",
+                            &path
+                        ))
+                    } else {
+                        Ok(code)
+                    }
+                });
+
+                let result = match code {
+                    Ok(mut code) => {
+                        code.push('\n');
+                        let write = async {
+                            if let Some(parent) = full_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                                self.ctx.fs().create_dir_all(parent).await?;
+                            }
+                            self.ctx.fs().write(&full_path, code).await
+                        };
+                        write
+                            .await
+                            .map_err(|err| format!("Failed to write to {}: {}", &path, err))
+                    },
+                    Err(msg) => Err(msg),
+                };
+
+                match result {
+                    Ok(_) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\n✔ Wrote code block {} to {}\n\n", index, &path)),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                    Err(msg) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\n{}\n\n", msg)),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Export { path, format } => {
+                let path = tools::sanitize_path_tool_arg(&self.ctx, &path);
+                match export::export_transcript(&self.ctx, &self.conversation_state.transcript, &path, format).await {
+                    Ok(written) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\n✔ Exported transcript to {}\n\n", written.display())),
+                        style::SetAttribute(Attribute::Reset)
+                    )?,
+                    Err(err) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nFailed to export to {}: {}\n\n", path.display(), err)),
+                        style::SetAttribute(Attribute::Reset)
+                    )?,
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Mcp => {
                 let terminal_width = self.terminal_width();
                 let loaded_servers = self.conversation_state.tool_manager.mcp_load_record.lock().await;
@@ -2999,140 +5007,477 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
-        })
-    }
-
-    async fn tool_use_execute(
-        &mut self,
-        database: &Database,
-        telemetry: &TelemetryThread,
-        mut tool_uses: Vec<QueuedTool>,
-    ) -> Result<ChatState, ChatError> {
-        // Verify tools have permissions.
-        for (index, tool) in tool_uses.iter_mut().enumerate() {
-            // Manually accepted by the user or otherwise verified already.
-            if tool.accepted {
-                continue;
-            }
-
-            // If there is an override, we will use it. Otherwise fall back to Tool's default.
-            let allowed = self.tool_permissions.trust_all
-                || (self.tool_permissions.has(&tool.name) && self.tool_permissions.is_trusted(&tool.name))
-                || !tool.tool.requires_acceptance(&self.ctx);
-
-            if database
-                .settings
-                .get_bool(Setting::ChatEnableNotifications)
-                .unwrap_or(false)
-            {
-                play_notification_bell(!allowed);
-            }
-
-            self.print_tool_descriptions(tool, allowed).await?;
-
-            if allowed {
-                tool.accepted = true;
-                continue;
-            }
-
-            let pending_tool_index = Some(index);
-            if !self.interactive {
-                // Cannot request in non-interactive, so fail.
-                return Err(ChatError::NonInteractiveToolApproval);
-            }
-
-            return Ok(ChatState::PromptUser {
-                tool_uses: Some(tool_uses),
-                pending_tool_index,
-                skip_printing_tools: false,
-            });
-        }
-
-        // Execute the requested tools.
-        let mut tool_results = vec![];
-        let mut image_blocks: Vec<RichImageBlock> = Vec::new();
-
-        for tool in tool_uses {
-            let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
-            tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_accepted = true);
-
-            let tool_start = std::time::Instant::now();
-            let invoke_result = tool.tool.invoke(&self.ctx, &mut self.output).await;
-
-            if self.interactive && self.spinner.is_some() {
-                queue!(
-                    self.output,
-                    terminal::Clear(terminal::ClearType::CurrentLine),
-                    cursor::MoveToColumn(0),
-                    cursor::Show
+            Command::Whoami => {
+                let token = BuilderIdToken::load(database).await.ok().flatten();
+                let profile = database.get_auth_profile().ok().flatten();
+                queue!(self.output, style::Print("\n"))?;
+                queue_whoami(
+                    &mut self.output,
+                    token.as_ref(),
+                    profile.as_ref(),
+                    crate::util::system_info::in_cloudshell(),
                 )?;
-            }
-            execute!(self.output, style::Print("\n"))?;
-
-            let tool_time = std::time::Instant::now().duration_since(tool_start);
-            if let Tool::Custom(ct) = &tool.tool {
-                tool_telemetry = tool_telemetry.and_modify(|ev| {
-                    ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
-                    ev.input_token_size = Some(ct.get_input_token_size());
-                    ev.is_custom_tool = true;
-                });
-            }
-            let tool_time = format!("{}.{}", tool_time.as_secs(), tool_time.subsec_millis());
-            match invoke_result {
-                Ok(result) => {
-                    match result.output {
-                        OutputKind::Text(ref text) => {
-                            debug!("Output is Text: {}", text);
-                        },
-                        OutputKind::Json(ref json) => {
-                            debug!("Output is JSON: {}", json);
-                        },
-                        OutputKind::Images(ref image) => {
-                            image_blocks.extend(image.clone());
-                        },
-                    }
-
-                    debug!("tool result output: {:#?}", result);
+                queue!(self.output, style::Print("\n"))?;
+                self.output.flush()?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::History { query } => {
+                let lines = history::history_lines(
+                    &self.conversation_state.transcript,
+                    query.as_deref(),
+                    self.terminal_width(),
+                );
+                if lines.is_empty() {
                     execute!(
                         self.output,
-                        style::Print(CONTINUATION_LINE),
-                        style::Print("\n"),
-                        style::SetForegroundColor(Color::Green),
-                        style::SetAttribute(Attribute::Bold),
-                        style::Print(format!(" ● Completed in {}s", tool_time)),
-                        style::SetForegroundColor(Color::Reset),
-                        style::Print("\n"),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(match query {
+                            Some(_) => "\nNo turns matched the search.\n\n",
+                            None => "\nThere are no turns in this session yet.\n\n",
+                        }),
+                        style::SetForegroundColor(Color::Reset)
                     )?;
-
-                    tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_success = Some(true));
-                    if let Tool::Custom(_) = &tool.tool {
-                        tool_telemetry
-                            .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(result.as_str())));
-                    }
-                    tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id,
-                        content: vec![result.into()],
-                        status: ToolResultStatus::Success,
-                    });
-                },
-                Err(err) => {
-                    error!(?err, "An error occurred processing the tool");
+                } else {
+                    execute!(self.output, style::Print("\n"))?;
+                    self.print_paginated(&lines)?;
+                    execute!(self.output, style::Print("\n"))?;
+                }
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Verbose { enabled } => {
+                self.verbose = enabled.unwrap_or(!self.verbose);
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!(
+                        "\nVerbose output is now {}.\n\n",
+                        if self.verbose { "on" } else { "off" }
+                    )),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Alias { name, expansion } => {
+                let Some(context_manager) = self.conversation_state.context_manager.as_mut() else {
                     execute!(
                         self.output,
-                        style::Print(CONTINUATION_LINE),
-                        style::Print("\n"),
-                        style::SetAttribute(Attribute::Bold),
-                        style::SetForegroundColor(Color::Red),
-                        style::Print(format!(" ● Execution failed after {}s:\n", tool_time)),
-                        style::SetAttribute(Attribute::Reset),
                         style::SetForegroundColor(Color::Red),
-                        style::Print(&err),
-                        style::SetAttribute(Attribute::Reset),
-                        style::Print("\n\n"),
+                        style::Print("\nAliases are not available without a context profile.\n\n"),
+                        style::SetForegroundColor(Color::Reset)
                     )?;
-
-                    tool_telemetry.and_modify(|ev| ev.is_success = Some(false));
-                    tool_results.push(ToolUseResult {
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
+                };
+                let profile = context_manager.current_profile.clone();
+
+                let result = match name {
+                    None => {
+                        queue!(
+                            self.output,
+                            style::SetAttribute(Attribute::Bold),
+                            style::Print(format!("\n🔖 aliases of profile '{profile}':\n")),
+                            style::SetAttribute(Attribute::Reset),
+                        )?;
+                        if context_manager.aliases().is_empty() {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print("    <none>\n"),
+                                style::SetForegroundColor(Color::Reset)
+                            )?;
+                        }
+                        for (name, expansion) in context_manager.aliases() {
+                            let mut lines = expansion.lines();
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Green),
+                                style::Print(format!("    @{name}")),
+                                style::SetForegroundColor(Color::Reset),
+                                style::Print(format!(" {}\n", lines.next().unwrap_or_default())),
+                            )?;
+                            for line in lines {
+                                queue!(self.output, style::Print(format!("      {line}\n")))?;
+                            }
+                        }
+                        execute!(self.output, style::Print("\n"))?;
+                        Ok(None)
+                    },
+                    Some(name) => match expansion {
+                        Some(expansion) => context_manager.set_alias(&name, Some(expansion)).await.map(|existed| {
+                            Some(format!(
+                                "{} @{name} for profile '{profile}'",
+                                if existed { "Updated" } else { "Defined" }
+                            ))
+                        }),
+                        None => context_manager.set_alias(&name, None).await.map(|existed| {
+                            Some(if existed {
+                                format!("Removed @{name} from profile '{profile}'")
+                            } else {
+                                format!("There is no alias @{name} in profile '{profile}'")
+                            })
+                        }),
+                    },
+                };
+                match result {
+                    Ok(Some(message)) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\n{message}\n\n")),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                    Ok(None) => {},
+                    Err(e) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nError: {}\n\n", e)),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Templates { name } => {
+                match templates::load_templates(&self.ctx).await {
+                    Ok(templates) => match name {
+                        None => {
+                            queue!(
+                                self.output,
+                                style::SetAttribute(Attribute::Bold),
+                                style::Print("\n📝 templates:\n"),
+                                style::SetAttribute(Attribute::Reset),
+                            )?;
+                            for template in &templates {
+                                queue!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Green),
+                                    style::Print(format!("    {}", template.name)),
+                                    style::SetForegroundColor(Color::Reset),
+                                    style::Print(format!(" {}", template.description)),
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(if template.user_defined { " (user)\n" } else { "\n" }),
+                                    style::SetForegroundColor(Color::Reset),
+                                )?;
+                            }
+                            execute!(
+                                self.output,
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print(format!(
+                                    "\nStart a prompt from one with /templates use <name>, add your own to {}\n\n",
+                                    chat_templates_path(&self.ctx)
+                                        .map_or_else(|_| "templates.json".to_string(), |p| p.display().to_string())
+                                )),
+                                style::SetForegroundColor(Color::Reset)
+                            )?;
+                        },
+                        Some(name) => match templates.into_iter().find(|template| template.name == name) {
+                            Some(template) => {
+                                if !templates::placeholders(&template.prompt).is_empty() {
+                                    execute!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print(
+                                            "\nReplace the {{placeholders}} below, then press enter to send.\n\n"
+                                        ),
+                                        style::SetForegroundColor(Color::Reset)
+                                    )?;
+                                }
+                                self.input_source.set_initial_text(template.prompt);
+                                self.from_template = true;
+                            },
+                            None => execute!(
+                                self.output,
+                                style::SetForegroundColor(Color::Red),
+                                style::Print(format!(
+                                    "\nThere is no template '{name}', see /templates for the available ones.\n\n"
+                                )),
+                                style::SetForegroundColor(Color::Reset)
+                            )?,
+                        },
+                    },
+                    Err(e) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nError: {}\n\n", e)),
+                        style::SetForegroundColor(Color::Reset)
+                    )?,
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Debug => {
+                let scratch_dir = self
+                    .ctx
+                    .scratch_dir()
+                    .map_or_else(|| "<none>".to_string(), |dir| dir.display().to_string());
+                queue!(
+                    self.output,
+                    style::Print("\n"),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print("Conversation ID:   "),
+                    style::SetAttribute(Attribute::Reset),
+                    style::Print(format!("{}\n", self.conversation_state.conversation_id())),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print("Profile:           "),
+                    style::SetAttribute(Attribute::Reset),
+                    style::Print(format!(
+                        "{}\n",
+                        self.conversation_state.current_profile().unwrap_or("default")
+                    )),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print("Scratch directory: "),
+                    style::SetAttribute(Attribute::Reset),
+                    style::Print(format!("{}\n\n", scratch_dir)),
+                )?;
+                self.output.flush()?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+        })
+    }
+
+    async fn tool_use_execute(
+        &mut self,
+        database: &mut Database,
+        telemetry: &TelemetryThread,
+        mut tool_uses: Vec<QueuedTool>,
+    ) -> Result<ChatState, ChatError> {
+        // Sum up what a batch will do before asking about its tools one by one, so that e.g. a
+        // destructive command doesn't go unnoticed among many reads.
+        if tool_uses.len() > 1 && tool_uses.iter().all(|tool| !tool.accepted) && !self.quiet {
+            let needs_approval = tool_uses.iter().any(|tool| {
+                !self
+                    .tool_permissions
+                    .is_allowed(&tool.name)
+                    .unwrap_or_else(|| !tool.tool.requires_acceptance(&self.ctx))
+            });
+            if needs_approval {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(
+                        "{} tool uses requested: {}\n\n",
+                        tool_uses.len(),
+                        ToolKind::summarize(tool_uses.iter().map(|tool| tool.tool.kind()))
+                    )),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            }
+        }
+
+        // Verify tools have permissions.
+        for (index, tool) in tool_uses.iter_mut().enumerate() {
+            // Manually accepted by the user or otherwise verified already.
+            if tool.accepted {
+                continue;
+            }
+
+            // If there is an override, we will use it. Otherwise fall back to Tool's default.
+            let allowed = self
+                .tool_permissions
+                .is_allowed(&tool.name)
+                .unwrap_or_else(|| !tool.tool.requires_acceptance(&self.ctx));
+
+            if self.terminal
+                && database
+                    .settings
+                    .get_bool(Setting::ChatEnableNotifications)
+                    .unwrap_or(false)
+            {
+                play_notification_bell(!allowed);
+            }
+
+            self.print_tool_descriptions(database, tool, allowed).await?;
+
+            if allowed {
+                tool.accepted = true;
+                continue;
+            }
+
+            let pending_tool_index = Some(index);
+            if !self.interactive {
+                // Tools listed in the approval file are the only ones we can run without asking.
+                if self.tool_permissions.is_pre_approved(&tool.id, &tool.name) {
+                    tool.accepted = true;
+                    continue;
+                }
+                // Cannot request in non-interactive, so fail.
+                return Err(ChatError::NonInteractiveToolApproval);
+            }
+
+            return Ok(ChatState::PromptUser {
+                tool_uses: Some(tool_uses),
+                pending_tool_index,
+                skip_printing_tools: false,
+            });
+        }
+
+        // Execute the requested tools.
+        let mut repeated_failure = None;
+        let default_timeout_secs = database
+            .settings
+            .get_int(Setting::ChatToolTimeoutSecs)
+            .and_then(|secs| u64::try_from(secs).ok())
+            .unwrap_or(DEFAULT_TOOL_TIMEOUT_SECS);
+
+        let preview_width = self.terminal_width().saturating_sub(3);
+        for tool in tool_uses {
+            let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
+            tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_accepted = true);
+
+            // Tools that know how many files they'll go through get a progress bar in place of the
+            // spinner, which would otherwise draw over it.
+            let mut progress = tool
+                .tool
+                .progress_total()
+                .filter(|_| self.interactive && !self.quiet)
+                .map(ToolProgress::new);
+            if progress.is_some() {
+                drop(self.spinner.take());
+            }
+
+            let tool_start = std::time::Instant::now();
+            let invoke_result = match self.tool_permissions.timeout(&tool.name, default_timeout_secs) {
+                Some(timeout) => tokio::time::timeout(
+                    timeout,
+                    tool.tool.invoke(&self.ctx, &mut self.output, progress.as_mut()),
+                )
+                .await
+                .unwrap_or_else(|_| Err(eyre::eyre!("Tool execution timed out after {}s", timeout.as_secs()))),
+                None => tool.tool.invoke(&self.ctx, &mut self.output, progress.as_mut()).await,
+            };
+
+            if let Some(progress) = &progress {
+                progress.finish(&mut self.output)?;
+            } else if self.interactive && self.spinner.is_some() {
+                queue!(
+                    self.output,
+                    terminal::Clear(terminal::ClearType::CurrentLine),
+                    cursor::MoveToColumn(0),
+                    cursor::Show
+                )?;
+            }
+            execute!(self.output, style::Print("\n"))?;
+
+            let tool_time = std::time::Instant::now().duration_since(tool_start);
+            self.stats
+                .record_tool_execution(&tool.name, tool_time, invoke_result.is_ok());
+            if let Tool::Custom(ct) = &tool.tool {
+                tool_telemetry = tool_telemetry.and_modify(|ev| {
+                    ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
+                    ev.input_token_size = Some(ct.get_input_token_size());
+                    ev.is_custom_tool = true;
+                });
+            }
+            let tool_time = format!("{}.{}", tool_time.as_secs(), tool_time.subsec_millis());
+            match invoke_result {
+                Ok(result) => {
+                    match result.output {
+                        OutputKind::Text(ref text) => {
+                            debug!("Output is Text: {}", text);
+                        },
+                        OutputKind::Json(ref json) => {
+                            debug!("Output is JSON: {}", json);
+                        },
+                        OutputKind::Images(ref images) => {
+                            debug!("Output is {} images", images.len());
+                        },
+                    }
+
+                    debug!("tool result output: {:#?}", result);
+                    if !self.quiet {
+                        execute!(
+                            self.output,
+                            style::Print(CONTINUATION_LINE),
+                            style::Print("\n"),
+                            style::SetForegroundColor(Color::Green),
+                            style::SetAttribute(Attribute::Bold),
+                            style::Print(format!(" ● Completed in {}s", tool_time)),
+                            style::SetForegroundColor(Color::Reset),
+                            style::Print("\n"),
+                        )?;
+                    }
+                    self.last_tool_output = tool_output_text(&result.output);
+                    if let Some(text) = self
+                        .last_tool_output
+                        .as_deref()
+                        .filter(|_| self.interactive && self.show_tool_results && !self.quiet)
+                    {
+                        print_tool_output_preview(&mut self.output, text, preview_width)?;
+                    }
+                    if let OutputKind::Images(images) = &result.output {
+                        if self.interactive && self.show_tool_results && !self.quiet {
+                            let protocol = image_protocol(&self.ctx).filter(|_| self.terminal);
+                            for (image, metadata) in images {
+                                print_image(&mut self.output, protocol, image, &metadata.filepath)?;
+                            }
+                        }
+                    }
+
+                    self.tool_failures.record_success(&tool.id);
+                    tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_success = Some(true));
+                    if let Tool::Custom(_) = &tool.tool {
+                        tool_telemetry
+                            .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(result.as_str())));
+                    }
+                    let content = match result.output {
+                        OutputKind::Images(images) => images
+                            .into_iter()
+                            .map(|(image, _)| ToolUseResultBlock::Image(image))
+                            .collect(),
+                        _ => vec![result.into()],
+                    };
+                    self.batch_tool_results.push(ToolUseResult {
+                        tool_use_id: tool.id,
+                        content,
+                        status: ToolResultStatus::Success,
+                    });
+                },
+                Err(err) => {
+                    error!(?err, "An error occurred processing the tool");
+                    execute!(
+                        self.output,
+                        style::Print(CONTINUATION_LINE),
+                        style::Print("\n"),
+                        style::SetAttribute(Attribute::Bold),
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!(" ● Execution failed after {}s:\n", tool_time)),
+                        style::SetAttribute(Attribute::Reset),
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(&err),
+                        style::SetAttribute(Attribute::Reset),
+                        style::Print("\n\n"),
+                    )?;
+
+                    tool_telemetry.and_modify(|ev| ev.is_success = Some(false));
+                    repeated_failure =
+                        repeated_failure.or(self.tool_failures.record_failure(&tool.id, &err.to_string()));
+                    self.batch_tool_results.push(ToolUseResult {
                         tool_use_id: tool.id,
                         content: vec![ToolUseResultBlock::Text(format!(
                             "An error occurred processing the tool: \n{}",
@@ -3151,31 +5496,56 @@ impl ChatContext {
             }
         }
 
-        if !image_blocks.is_empty() {
-            let images = image_blocks.into_iter().map(|(block, _)| block).collect();
-            self.conversation_state
-                .add_tool_results_with_images(tool_results, images);
-            execute!(
-                self.output,
-                style::SetAttribute(Attribute::Reset),
-                style::SetForegroundColor(Color::Reset),
-                style::Print("\n")
-            )?;
-        } else {
-            self.conversation_state.add_tool_results(tool_results);
+        // Includes results from before an interrupt, see [Self::handle_tool_interrupt].
+        let tool_results = std::mem::take(&mut self.batch_tool_results);
+        for result in &tool_results {
+            self.write_json_event(JsonEvent::from_tool_result(result))?;
+        }
+        if let Some(name) = repeated_failure {
+            self.send_tool_use_telemetry(telemetry).await;
+            return self.stop_repeated_tool_failure(database, &name, tool_results).await;
         }
+        self.conversation_state.add_tool_results(tool_results);
         if self.interactive {
             execute!(self.output, cursor::Hide)?;
             execute!(self.output, style::Print("\n"), style::SetAttribute(Attribute::Reset))?;
-            self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_string()));
+            self.start_spinner(SpinnerMessage::Thinking)?;
         }
 
         self.send_tool_use_telemetry(telemetry).await;
-        return Ok(ChatState::HandleResponseStream(
-            self.client
-                .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                .await?,
-        ));
+        let conv_state = self.sendable_conversation_state(false).await;
+        return Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?));
+    }
+
+    /// The state responses are rendered with, according to the terminal and settings.
+    fn markdown_state(&self, database: &Database) -> ParseState {
+        let mut state = ParseState::new(Some(self.terminal_width()));
+        state.syntax_highlighting = self.interactive
+            && self.terminal
+            && tools::supports_truecolor(&self.ctx)
+            && database
+                .settings
+                .get_bool(Setting::ChatCodeHighlighting)
+                .unwrap_or(true);
+        state.hyperlinks = database
+            .settings
+            .get_bool(Setting::ChatHyperlinks)
+            .unwrap_or_else(|| self.interactive && self.terminal && tools::supports_hyperlinks(&self.ctx));
+        state
+    }
+
+    /// Renders a complete response at once, unlike [Self::handle_response] which renders it as
+    /// it streams in.
+    fn render_markdown(&mut self, database: &Database, text: &str) -> Result<(), ChatError> {
+        let mut state = self.markdown_state(database);
+        let mut markdown = MarkdownStream::new();
+        markdown.push_str(text);
+        while markdown.render_next(&mut self.output, &mut state, true)? {}
+        if !text.ends_with('\n') {
+            queue!(self.output, style::Print("\n"))?;
+        }
+        self.output.flush()?;
+        Ok(())
     }
 
     async fn handle_response(
@@ -3185,16 +5555,31 @@ impl ChatContext {
         response: SendMessageOutput,
     ) -> Result<ChatState, ChatError> {
         let request_id = response.request_id().map(|s| s.to_string());
-        let mut buf = String::new();
-        let mut offset = 0;
+        let mut markdown = MarkdownStream::new();
         let mut ended = false;
         let mut parser = ResponseParser::new(response);
-        let mut state = ParseState::new(Some(self.terminal_width()));
+        let mut state = self.markdown_state(database);
+        let mut resize = ResizeListener::new();
+        let typewriter_speed =
+            TypewriterSpeed::from_setting(database.settings.get_string(Setting::ChatTypewriterSpeed).as_deref());
 
         let mut tool_uses = Vec::new();
         let mut tool_name_being_recvd: Option<String> = None;
+        let mut verbose_text = None;
+        let mut turn_usage = None;
+        let max_response_bytes = database
+            .settings
+            .get_int(Setting::ChatMaxResponseBytes)
+            .and_then(|bytes| usize::try_from(bytes).ok())
+            .filter(|bytes| *bytes > 0);
+        let mut truncated = false;
+        self.partial_response.clear();
+        if let Some(recording) = &mut self.recording {
+            recording.begin_response();
+        }
 
-        if self.interactive && self.spinner.is_some() {
+        // Without streaming, the spinner keeps going until the whole response can be rendered.
+        if self.interactive && self.streaming && self.spinner.is_some() {
             drop(self.spinner.take());
             queue!(
                 self.output,
@@ -3211,15 +5596,45 @@ impl ChatContext {
             match parser.recv().await {
                 Ok(msg_event) => {
                     trace!("Consumed: {:?}", msg_event);
+                    if let Some(event) = JsonEvent::from_response_event(&msg_event) {
+                        self.write_json_event(event)?;
+                    }
+                    if let Some(sender) = &self.response_events {
+                        let _ = sender.send(msg_event.clone());
+                    }
+                    if let Some(recording) = &mut self.recording {
+                        recording.record_event(&msg_event);
+                    }
+                    if self.verbose {
+                        verbose_text = verbose_event_text(&msg_event);
+                    }
                     match msg_event {
                         parser::ResponseEvent::ToolUseStart { name } => {
                             // We need to flush the buffer here, otherwise text will not be
                             // printed while we are receiving tool use events.
-                            buf.push('\n');
+                            markdown.push('\n');
                             tool_name_being_recvd = Some(name);
                         },
-                        parser::ResponseEvent::AssistantText(text) => {
-                            buf.push_str(&text);
+                        parser::ResponseEvent::AssistantText(mut text) => {
+                            // Past the limit the rest of the stream is dropped, and the text up to
+                            // it is committed as the whole response.
+                            if let Some(max_bytes) = max_response_bytes {
+                                let remaining = max_bytes.saturating_sub(self.partial_response.len());
+                                if text.len() > remaining {
+                                    let mut end = remaining;
+                                    while !text.is_char_boundary(end) {
+                                        end -= 1;
+                                    }
+                                    text.truncate(end);
+                                    truncated = true;
+                                }
+                            }
+                            self.partial_response.push_str(&text);
+                            // The text was already written as an event, so there is nothing to
+                            // render.
+                            if self.json_events.is_none() {
+                                markdown.push_str(&text);
+                            }
                         },
                         parser::ResponseEvent::ToolUse(tool_use) => {
                             if self.interactive && self.spinner.is_some() {
@@ -3234,13 +5649,29 @@ impl ChatContext {
                             tool_uses.push(tool_use);
                             tool_name_being_recvd = None;
                         },
-                        parser::ResponseEvent::EndStream { message } => {
+                        parser::ResponseEvent::EndStream { message, usage } => {
                             // This log is attempting to help debug instances where users encounter
                             // the response timeout message.
                             if message.content() == RESPONSE_TIMEOUT_CONTENT {
                                 error!(?request_id, ?message, "Encountered an unexpected model response");
                             }
+                            // Estimated from the size of the request, which is still pending until
+                            // the response is pushed, when the service doesn't report usage.
+                            let usage = match usage {
+                                Some(usage) => (usage, false),
+                                None => (
+                                    TokenUsage {
+                                        input_tokens: self.conversation_token_count().await,
+                                        output_tokens: TokenCounter::count_tokens(message.content()),
+                                    },
+                                    true,
+                                ),
+                            };
+                            self.stats.record_usage(usage.0, usage.1);
+                            turn_usage = Some(usage);
                             self.conversation_state.push_assistant_message(message, database);
+                            self.partial_response.clear();
+                            self.stats.record_assistant_message();
                             ended = true;
                         },
                     }
@@ -3260,8 +5691,7 @@ impl ChatContext {
                             );
                             if self.interactive {
                                 execute!(self.output, cursor::Hide)?;
-                                self.spinner =
-                                    Some(Spinner::new(Spinners::Dots, "Dividing up the work...".to_string()));
+                                self.start_spinner(SpinnerMessage::DividingWork)?;
                             }
                             // For stream timeouts, we'll tell the model to try and split its response into
                             // smaller chunks.
@@ -3269,6 +5699,7 @@ impl ChatContext {
                                 AssistantMessage::new_response(None, RESPONSE_TIMEOUT_CONTENT.to_string()),
                                 database,
                             );
+                            self.partial_response.clear();
                             self.conversation_state
                                 .set_next_user_message(
                                     "You took too long to respond - try to split up the work into smaller steps."
@@ -3276,11 +5707,8 @@ impl ChatContext {
                                 )
                                 .await;
                             self.send_tool_use_telemetry(telemetry).await;
-                            return Ok(ChatState::HandleResponseStream(
-                                self.client
-                                    .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                                    .await?,
-                            ));
+                            let conv_state = self.sendable_conversation_state(false).await;
+                            return Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?));
                         },
                         RecvErrorKind::UnexpectedToolUseEos {
                             tool_use_id,
@@ -3312,13 +5740,11 @@ impl ChatContext {
                                     )?;
                                 }
                                 execute!(self.output, style::Print("\n\n"), style::SetAttribute(Attribute::Reset))?;
-                                self.spinner = Some(Spinner::new(
-                                    Spinners::Dots,
-                                    "Trying to divide up the work...".to_string(),
-                                ));
+                                self.start_spinner(SpinnerMessage::RetryingWork)?;
                             }
 
                             self.conversation_state.push_assistant_message(*message, database);
+                            self.partial_response.clear();
                             let tool_results = vec![ToolUseResult {
                                     tool_use_id,
                                     content: vec![ToolUseResultBlock::Text(
@@ -3328,25 +5754,46 @@ impl ChatContext {
                                 }];
                             self.conversation_state.add_tool_results(tool_results);
                             self.send_tool_use_telemetry(telemetry).await;
-                            return Ok(ChatState::HandleResponseStream(
-                                self.client
-                                    .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                                    .await?,
-                            ));
+                            let conv_state = self.sendable_conversation_state(false).await;
+                            return Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?));
                         },
                         _ => return Err(recv_error.into()),
                     }
                 },
             }
 
+            if truncated && !ended {
+                let partial_response = std::mem::take(&mut self.partial_response);
+                self.conversation_state.push_truncated_response(
+                    partial_response,
+                    max_response_bytes.unwrap_or_default(),
+                    database,
+                );
+                self.stats.record_assistant_message();
+                if let Some(recording) = &mut self.recording {
+                    recording.finish_response();
+                }
+                tool_uses.clear();
+                tool_name_being_recvd = None;
+                ended = true;
+            }
+
             // Fix for the markdown parser copied over from q chat:
             // this is a hack since otherwise the parser might report Incomplete with useful data
             // still left in the buffer. I'm not sure how this is intended to be handled.
             if ended {
-                buf.push('\n');
+                markdown.push('\n');
             }
 
-            if tool_name_being_recvd.is_none() && !buf.is_empty() && self.interactive && self.spinner.is_some() {
+            // The text is only buffered until the end of the response without streaming.
+            let render = self.streaming || ended;
+
+            if render
+                && tool_name_being_recvd.is_none()
+                && !markdown.is_empty()
+                && self.interactive
+                && self.spinner.is_some()
+            {
                 drop(self.spinner.take());
                 queue!(
                     self.output,
@@ -3356,31 +5803,41 @@ impl ChatContext {
                 )?;
             }
 
+            // Text that was already printed keeps its wrapping, only what follows uses the new
+            // width.
+            if resize.resized() {
+                state.terminal_width = Some(self.terminal_width());
+            }
+
             // Print the response for normal cases
-            loop {
-                let input = Partial::new(&buf[offset..]);
-                match interpret_markdown(input, &mut self.output, &mut state) {
-                    Ok(parsed) => {
-                        offset += parsed.offset_from(&input);
-                        self.output.flush()?;
-                        state.newline = state.set_newline;
-                        state.set_newline = false;
-                    },
-                    Err(err) => match err.into_inner() {
-                        Some(err) => return Err(ChatError::Custom(err.to_string().into())),
-                        None => break, // Data was incomplete
-                    },
+            // Once nothing else is coming, multiline patterns like tables finish instead of waiting
+            // on more input.
+            while render && markdown.render_next(&mut self.output, &mut state, ended)? {
+                self.output.flush()?;
+
+                let delay = match self.streaming {
+                    true => typewriter_speed.delay(markdown.pending()),
+                    false => Duration::ZERO,
+                };
+                if !delay.is_zero() {
+                    std::thread::sleep(delay);
                 }
+            }
 
-                // TODO: We should buffer output based on how much we have to parse, not as a constant
-                // Do not remove unless you are nabochay :)
-                std::thread::sleep(Duration::from_millis(8));
+            // Printed after the text received before the event so the output stays in order.
+            if let Some(text) = verbose_text.take() {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!("{}\n", text)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
             }
 
             // Set spinner after showing all of the assistant text content so far.
             if let (Some(_name), true) = (&tool_name_being_recvd, self.interactive) {
                 queue!(self.output, cursor::Hide)?;
-                self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_string()));
+                self.start_spinner(SpinnerMessage::Thinking)?;
             }
 
             if ended {
@@ -3395,6 +5852,29 @@ impl ChatContext {
                 }
 
                 if self.interactive
+                    && self.terminal
+                    && tool_uses.is_empty()
+                    && database
+                        .settings
+                        .get_bool(Setting::ChatNotifyOnComplete)
+                        .unwrap_or(false)
+                {
+                    // Quick turns are left out, the user is likely still watching them.
+                    let elapsed = self.turn_start.map(|start| start.elapsed()).unwrap_or_default();
+                    let min_duration = database
+                        .settings
+                        .get_int(Setting::ChatNotifyMinDurationSecs)
+                        .and_then(|secs| u64::try_from(secs).ok())
+                        .unwrap_or(0);
+                    if elapsed >= Duration::from_secs(min_duration) {
+                        notify_response_complete(
+                            &UserShell::from_env(&self.ctx),
+                            database.settings.get_string(Setting::ChatNotifyCmd).as_deref(),
+                            &format!("Amazon Q responded after {}s", elapsed.as_secs()),
+                        );
+                    }
+                } else if self.interactive
+                    && self.terminal
                     && database
                         .settings
                         .get_bool(Setting::ChatEnableNotifications)
@@ -3408,12 +5888,12 @@ impl ChatContext {
                     queue!(self.output, style::ResetColor, style::SetAttribute(Attribute::Reset))?;
                     execute!(self.output, style::Print("\n"))?;
 
-                    for (i, citation) in &state.citations {
+                    for (i, citation) in state.citations.iter().enumerate() {
                         queue!(
                             self.output,
                             style::Print("\n"),
                             style::SetForegroundColor(Color::Blue),
-                            style::Print(format!("[^{i}]: ")),
+                            style::Print(format!("[^{}]: ", i + 1)),
                             style::SetForegroundColor(Color::DarkGrey),
                             style::Print(format!("{citation}\n")),
                             style::SetForegroundColor(Color::Reset)
@@ -3421,6 +5901,30 @@ impl ChatContext {
                     }
                 }
 
+                if truncated {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print(format!(
+                            "\nThe response was stopped after {} (chat.maxResponseBytes), the model has been told it \
+                             was cut off.\n",
+                            format_bytes(max_response_bytes.unwrap_or_default())
+                        )),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                }
+
+                if let Some((usage, estimated)) = turn_usage {
+                    if database.settings.get_bool(Setting::ChatShowUsage).unwrap_or(false) {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(format!("Tokens: {}\n", format_usage(usage, estimated))),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    }
+                }
+
                 break;
             }
         }
@@ -3438,17 +5942,22 @@ impl ChatContext {
 
     async fn validate_tools(
         &mut self,
+        database: &mut Database,
         telemetry: &TelemetryThread,
         tool_uses: Vec<AssistantToolUse>,
     ) -> Result<ChatState, ChatError> {
         let conv_id = self.conversation_state.conversation_id().to_owned();
         debug!(?tool_uses, "Validating tool uses");
+        // A new batch starts here. Results left over from a batch that ended with an error must not
+        // be sent along with this one.
+        self.batch_tool_results.clear();
         let mut queued_tools: Vec<QueuedTool> = Vec::new();
         let mut tool_results: Vec<ToolUseResult> = Vec::new();
 
         for tool_use in tool_uses {
             let tool_use_id = tool_use.id.clone();
             let tool_use_name = tool_use.name.clone();
+            self.tool_failures.track(&tool_use_id, &tool_use_name, &tool_use.args);
             let mut tool_telemetry = ToolUseEventBuilder::new(conv_id.clone(), tool_use.id.clone())
                 .set_tool_use_id(tool_use_id.clone())
                 .set_tool_name(tool_use.name.clone())
@@ -3459,6 +5968,16 @@ impl ChatContext {
                     self.contextualize_tool(&mut tool);
 
                     match tool.validate(&self.ctx).await {
+                        Ok(()) if self.tool_permissions.policy(&tool_use_name) == Some(ToolPolicy::Never) => {
+                            tool_telemetry.is_valid = Some(false);
+                            tool_results.push(ToolUseResult {
+                                tool_use_id: tool_use_id.clone(),
+                                content: vec![ToolUseResultBlock::Text(format!(
+                                    "The tool '{tool_use_name}' is not allowed by the user's tool policy"
+                                ))],
+                                status: ToolResultStatus::Error,
+                            });
+                        },
                         Ok(()) => {
                             tool_telemetry.is_valid = Some(true);
                             queued_tools.push(QueuedTool {
@@ -3491,6 +6010,9 @@ impl ChatContext {
         // If we have any validation errors, then return them immediately to the model.
         if !tool_results.is_empty() {
             debug!(?tool_results, "Error found in the model tools");
+            for result in &tool_results {
+                self.write_json_event(JsonEvent::from_tool_result(result))?;
+            }
             queue!(
                 self.output,
                 style::SetAttribute(Attribute::Bold),
@@ -3505,6 +6027,7 @@ impl ChatContext {
                             .map_err(|err| error!(?err, "failed to serialize tool result content"))
                             .map(Into::into)
                             .ok(),
+                        ToolUseResultBlock::Image(_) => None,
                     };
                     if let Some(content) = content {
                         queue!(
@@ -3517,8 +6040,26 @@ impl ChatContext {
                     }
                 }
             }
-            self.conversation_state.add_tool_results(tool_results);
             self.send_tool_use_telemetry(telemetry).await;
+
+            let mut repeated_failure = None;
+            for result in &tool_results {
+                let error = result
+                    .content
+                    .iter()
+                    .filter_map(|block| match block {
+                        ToolUseResultBlock::Text(text) => Some(text.clone()),
+                        ToolUseResultBlock::Json(json) => Some(json.to_string()),
+                        ToolUseResultBlock::Image(_) => None,
+                    })
+                    .collect::<String>();
+                repeated_failure = repeated_failure.or(self.tool_failures.record_failure(&result.tool_use_id, &error));
+            }
+            if let Some(name) = repeated_failure {
+                return self.stop_repeated_tool_failure(database, &name, tool_results).await;
+            }
+
+            self.conversation_state.add_tool_results(tool_results);
             if let ToolUseStatus::Idle = self.tool_use_status {
                 self.tool_use_status = ToolUseStatus::RetryInProgress(
                     self.conversation_state
@@ -3527,10 +6068,8 @@ impl ChatContext {
                 );
             }
 
-            let response = self
-                .client
-                .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                .await?;
+            let conv_state = self.sendable_conversation_state(false).await;
+            let response = self.send_message(conv_state).await?;
             return Ok(ChatState::HandleResponseStream(response));
         }
 
@@ -3541,10 +6080,11 @@ impl ChatContext {
     // We cannot attach this any other way because Tools are constructed by deserializing
     // output from Amazon Q.
     // TODO: Is there a better way?
-    fn contextualize_tool(&self, tool: &mut Tool) {
-        #[allow(clippy::single_match)]
+    fn contextualize_tool(&mut self, tool: &mut Tool) {
         match tool {
             Tool::GhIssue(gh_issue) => {
+                // `/issue --no-transcript` only applies to the report it asked for.
+                let include_transcript = std::mem::replace(&mut self.issue_transcript, true);
                 gh_issue.set_context(GhIssueContext {
                     // Ideally we avoid cloning, but this function is not called very often.
                     // Using references with lifetimes requires a large refactor, and Arc<Mutex<T>>
@@ -3554,13 +6094,26 @@ impl ChatContext {
                     failed_request_ids: self.failed_request_ids.clone(),
                     tool_permissions: self.tool_permissions.permissions.clone(),
                     interactive: self.interactive,
+                    transcript_turns: if include_transcript {
+                        self.issue_transcript_turns
+                    } else {
+                        0
+                    },
                 });
             },
+            Tool::ExecuteBash(execute_bash) => execute_bash.workspace_root = self.workspace_root.clone(),
+            Tool::UserInput(user_input) => user_input.interactive = self.interactive,
+            Tool::WebSearch(web_search) => web_search.endpoint = self.web_search_endpoint.clone(),
             _ => (),
         };
     }
 
-    async fn print_tool_descriptions(&mut self, tool_use: &QueuedTool, trusted: bool) -> Result<(), ChatError> {
+    async fn print_tool_descriptions(
+        &mut self,
+        database: &Database,
+        tool_use: &QueuedTool,
+        trusted: bool,
+    ) -> Result<(), ChatError> {
         queue!(
             self.output,
             style::SetForegroundColor(Color::Magenta),
@@ -3587,53 +6140,130 @@ impl ChatContext {
 
         self.output.flush()?;
 
+        let max_diff_lines = database
+            .settings
+            .get_int(Setting::ChatDiffPreviewMaxLines)
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(DEFAULT_DIFF_PREVIEW_MAX_LINES);
+        let mut description = Vec::new();
         tool_use
             .tool
-            .queue_description(&self.ctx, &mut self.output)
+            .queue_description(&self.ctx, &mut description, max_diff_lines)
             .await
             .map_err(|e| ChatError::Custom(format!("failed to print tool, `{}`: {}", tool_use.name, e).into()))?;
+        if !self.page(&description)? {
+            self.output.write_all(&description)?;
+        }
 
         Ok(())
     }
 
     /// Helper function to read user input with a prompt and Ctrl+C handling
     fn read_user_input(&mut self, prompt: &str, exit_on_single_ctrl_c: bool) -> Option<String> {
-        let mut ctrl_c = false;
         loop {
-            match (self.input_source.read_line(Some(prompt)), ctrl_c) {
-                (Ok(Some(line)), _) => {
+            match self.input_source.read_line(Some(prompt)) {
+                Ok(Some(line)) => {
                     if line.trim().is_empty() {
                         continue; // Reprompt if the input is empty
                     }
+                    self.interrupts.reset();
                     return Some(line);
                 },
-                (Ok(None), false) => {
-                    if exit_on_single_ctrl_c {
+                Ok(None) => {
+                    // Exit if Ctrl+C was pressed twice, including when the first press cancelled
+                    // a response.
+                    if exit_on_single_ctrl_c || self.interrupts.interrupt() {
                         return None;
                     }
+                    execute!(self.output, style::Print("\n")).unwrap_or_default();
+                    self.print_exit_hint().unwrap_or_default();
+                },
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn print_exit_hint(&mut self) -> Result<(), ChatError> {
+        execute!(
+            self.output,
+            style::Print(format!(
+                "(To exit the CLI, press Ctrl+C or Ctrl+D again or type {})\n\n",
+                "/quit".green()
+            ))
+        )?;
+        Ok(())
+    }
+
+    /// Helper function to generate a prompt based on the current context
+    fn generate_tool_trust_prompt(&self, context_usage_percent: usize) -> String {
+        let model = self.model_id.as_deref().map(|model_id| {
+            MODEL_OPTIONS
+                .iter()
+                .find(|(_, id)| *id == model_id)
+                .map_or(model_id, |(name, _)| *name)
+        });
+        self.prompt_format.render(
+            self.conversation_state.current_profile(),
+            model,
+            self.conversation_state.turn_count() + 1,
+            context_usage_percent,
+            self.all_tools_trusted(),
+        )
+    }
+
+    /// Sends `conv_state` to the model, retrying with exponential backoff while the request fails
+    /// with a retryable error, up to [Self::max_retries] times.
+    async fn send_message(
+        &mut self,
+        conv_state: FigConversationState,
+    ) -> Result<SendMessageOutput, crate::api_client::ApiClientError> {
+        let mut attempt = 0;
+        loop {
+            if attempt == self.max_retries {
+                return self.client.send_message(conv_state).await;
+            }
+            match self.client.send_message(conv_state.clone()).await {
+                Err(err) if err.is_retryable() => {
+                    let delay = retry_delay(attempt);
+                    attempt += 1;
+                    warn!(?err, attempt, "retrying a transient send_message failure");
+                    if self.interactive && self.spinner.is_some() {
+                        queue!(
+                            self.output,
+                            terminal::Clear(terminal::ClearType::CurrentLine),
+                            cursor::MoveToColumn(0),
+                        )
+                        .ok();
+                    }
                     execute!(
                         self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
                         style::Print(format!(
-                            "\n(To exit the CLI, press Ctrl+C or Ctrl+D again or type {})\n\n",
-                            "/quit".green()
-                        ))
+                            "Request failed, retrying in {:.1}s… ({attempt}/{})\n",
+                            delay.as_secs_f64(),
+                            self.max_retries
+                        )),
+                        style::SetForegroundColor(Color::Reset),
                     )
-                    .unwrap_or_default();
-                    ctrl_c = true;
+                    .ok();
+                    tokio::time::sleep(delay).await;
                 },
-                (Ok(None), true) => return None, // Exit if Ctrl+C was pressed twice
-                (Err(_), _) => return None,
+                result => return result,
             }
         }
     }
 
-    /// Helper function to generate a prompt based on the current context
-    fn generate_tool_trust_prompt(&self) -> String {
-        prompt::generate_prompt(self.conversation_state.current_profile(), self.all_tools_trusted())
+    /// Returns the next request to send from [Self::conversation_state], using the model selected
+    /// with `/model` if any.
+    async fn sendable_conversation_state(&mut self, run_hooks: bool) -> FigConversationState {
+        let mut state = self.conversation_state.as_sendable_conversation_state(run_hooks).await;
+        state.user_input_message.model_id = self.model_id.clone();
+        state
     }
 
     async fn send_tool_use_telemetry(&mut self, telemetry: &TelemetryThread) {
         for (_, mut event) in self.tool_use_telemetry_events.drain() {
+            self.stats.record_tool_use_event(&event);
             event.user_input_id = match self.tool_use_status {
                 ToolUseStatus::Idle => self.conversation_state.message_id(),
                 ToolUseStatus::RetryInProgress(ref id) => Some(id.as_str()),
@@ -3648,6 +6278,65 @@ impl ChatContext {
         (self.terminal_width_provider)().unwrap_or(80)
     }
 
+    /// Prints `lines`, pausing after each screenful in interactive sessions until the user
+    /// continues or quits.
+    fn print_paginated(&mut self, lines: &[String]) -> Result<(), ChatError> {
+        if self.page(
+            lines
+                .iter()
+                .map(|line| format!("{line}\n"))
+                .collect::<String>()
+                .as_bytes(),
+        )? {
+            return Ok(());
+        }
+
+        let page_size = match (self.interactive, terminal::size()) {
+            // Leave room for the pager prompt and the line it was run from.
+            (true, Ok((_, height))) if height > 3 => height as usize - 2,
+            _ => usize::MAX,
+        };
+
+        for (i, chunk) in lines.chunks(page_size).enumerate() {
+            if i > 0 {
+                match self
+                    .input_source
+                    .read_line(Some("-- More -- (Enter to continue, q to quit) "))
+                {
+                    Ok(Some(line)) if !line.trim().eq_ignore_ascii_case("q") => (),
+                    _ => break,
+                }
+            }
+            for line in chunk {
+                queue!(self.output, style::Print(format!("{line}\n")))?;
+            }
+            self.output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Shows `text` in the pager if `chat.pager` is enabled and `text` is taller than the terminal.
+    ///
+    /// Returns `false` if `text` wasn't shown and should be printed instead.
+    fn page(&mut self, text: &[u8]) -> Result<bool, ChatError> {
+        let Some(command) = &self.pager else {
+            return Ok(false);
+        };
+        let height = terminal::size().map_or(usize::MAX, |(_, height)| height as usize);
+        if text.split(|b| *b == b'\n').count() <= height {
+            return Ok(false);
+        }
+
+        self.output.flush()?;
+        match pager::page(command, text) {
+            Ok(shown) => Ok(shown),
+            Err(err) => {
+                warn!(?err, "failed to run the pager");
+                Ok(false)
+            },
+        }
+    }
+
     fn all_tools_trusted(&self) -> bool {
         self.conversation_state.tools.values().flatten().all(|t| match t {
             FigTool::ToolSpecification(t) => self.tool_permissions.is_trusted(&t.name),
@@ -3718,74 +6407,1648 @@ fn print_hook_section(output: &mut impl Write, hooks: &HashMap<String, Hook>, tr
             }
         }
     }
-    Ok(())
-}
+    Ok(())
+}
+
+/// Testing helper
+fn split_tool_use_event(value: &Map<String, serde_json::Value>) -> Vec<ChatResponseStream> {
+    let tool_use_id = value.get("tool_use_id").unwrap().as_str().unwrap().to_string();
+    let name = value.get("name").unwrap().as_str().unwrap().to_string();
+    let args_str = value.get("args").unwrap().to_string();
+    let split_point = args_str.len() / 2;
+    vec![
+        ChatResponseStream::ToolUseEvent {
+            tool_use_id: tool_use_id.clone(),
+            name: name.clone(),
+            input: None,
+            stop: None,
+        },
+        ChatResponseStream::ToolUseEvent {
+            tool_use_id: tool_use_id.clone(),
+            name: name.clone(),
+            input: Some(args_str.split_at(split_point).0.to_string()),
+            stop: None,
+        },
+        ChatResponseStream::ToolUseEvent {
+            tool_use_id: tool_use_id.clone(),
+            name: name.clone(),
+            input: Some(args_str.split_at(split_point).1.to_string()),
+            stop: None,
+        },
+        ChatResponseStream::ToolUseEvent {
+            tool_use_id: tool_use_id.clone(),
+            name: name.clone(),
+            input: None,
+            stop: Some(true),
+        },
+    ]
+}
+
+/// Builds a client that answers with `model_responses` in order, for tests, `Q_MOCK_CHAT_RESPONSE`
+/// and `--replay`. See [recording] for the format.
+fn create_stream(model_responses: serde_json::Value) -> StreamingClient {
+    let mut mock = Vec::new();
+    for response in model_responses.as_array().unwrap() {
+        let mut stream = Vec::new();
+        for event in response.as_array().unwrap() {
+            match event {
+                serde_json::Value::String(assistant_text) => {
+                    stream.push(ChatResponseStream::AssistantResponseEvent {
+                        content: assistant_text.to_string(),
+                    });
+                },
+                serde_json::Value::Object(tool_use) => {
+                    stream.append(&mut split_tool_use_event(tool_use));
+                },
+                other => panic!("Unexpected value: {:?}", other),
+            }
+        }
+        mock.push(stream);
+    }
+    StreamingClient::mock(mock)
+}
+
+/// Replaces STDIN with the controlling terminal, so that the session can stay interactive after
+/// piped input has been read.
+fn attach_stdin_to_terminal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+
+        let tty = std::fs::File::open("/dev/tty")?;
+        nix::unistd::dup2(tty.as_raw_fd(), libc::STDIN_FILENO)?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "no controlling terminal on this platform",
+        ))
+    }
+}
+
+/// Whether `err` was caused by writing to a pipe that was closed by its reader.
+fn is_broken_pipe(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::BrokenPipe)
+        {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Returns the first question to ask, from either `--file` or the positional input followed by
+/// anything piped through STDIN. The file can't be combined with the other two.
+async fn initial_input(
+    ctx: &Context,
+    input: Option<String>,
+    file: Option<PathBuf>,
+    piped_input: Option<String>,
+) -> Result<Option<String>> {
+    let Some(path) = file else {
+        // append to input string any extra info that was provided, e.g. via pipe
+        return Ok(match piped_input {
+            Some(piped_input) => {
+                let mut input = input.unwrap_or_default();
+                input.push_str(&piped_input);
+                Some(input)
+            },
+            None => input,
+        });
+    };
+
+    if input.is_some() {
+        bail!("--file cannot be combined with a positional input");
+    }
+    if piped_input.is_some_and(|piped_input| !piped_input.trim().is_empty()) {
+        bail!("--file cannot be combined with input piped through STDIN");
+    }
+    let contents = ctx
+        .fs()
+        .read_to_string(&path)
+        .await
+        .wrap_err_with(|| format!("Failed to read '{}'", path.display()))?;
+    Ok(Some(contents))
+}
+
+/// Reads the system prompt given with `--system-prompt-file`, which must not be empty.
+async fn read_system_prompt_file(ctx: &Context, path: &Path) -> Result<String> {
+    let prompt = ctx
+        .fs()
+        .read_to_string(path)
+        .await
+        .wrap_err_with(|| format!("Failed to read the system prompt file '{}'", path.display()))?;
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        bail!("The system prompt file '{}' is empty", path.display());
+    }
+    Ok(prompt.to_string())
+}
+
+/// The delay before retry number `attempt + 1` of a message: [RETRY_BASE_DELAY] doubled for each
+/// earlier retry, capped at [RETRY_MAX_DELAY].
+fn retry_delay(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt)
+        .and_then(|factor| RETRY_BASE_DELAY.checked_mul(factor))
+        .map_or(RETRY_MAX_DELAY, |delay| delay.min(RETRY_MAX_DELAY))
+}
+
+/// Collects the tools named by `--trust-tools`, failing with the list of valid names if any of
+/// them isn't one of the `available` tools.
+fn trusted_tool_set<'a>(
+    requested: Vec<String>,
+    available: impl IntoIterator<Item = &'a str>,
+) -> Result<HashSet<String>> {
+    let mut available = available.into_iter().collect::<Vec<_>>();
+    let unknown = requested
+        .iter()
+        .filter(|name| !available.contains(&name.as_str()))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        available.sort_unstable();
+        bail!(
+            "Unknown tool(s) passed to --trust-tools: {}. Valid tools: {}",
+            unknown.join(", "),
+            available.join(", ")
+        );
+    }
+    Ok(requested.into_iter().collect())
+}
+
+/// Describes a response event for `/verbose` output. Assistant text is already displayed as it
+/// streams, so it is not repeated.
+fn verbose_event_text(event: &parser::ResponseEvent) -> Option<String> {
+    match event {
+        parser::ResponseEvent::AssistantText(_) => None,
+        parser::ResponseEvent::ToolUseStart { name } => Some(format!("[tool_use_start] {name}")),
+        parser::ResponseEvent::ToolUse(tool_use) => Some(format!(
+            "[tool_use] {} ({})\n{}",
+            tool_use.name,
+            tool_use.id,
+            serde_json::to_string_pretty(&tool_use.args).unwrap_or_else(|_| tool_use.args.to_string())
+        )),
+        parser::ResponseEvent::EndStream { message, .. } => Some(format!(
+            "[end_stream] message_id: {}",
+            message.message_id().unwrap_or("<none>")
+        )),
+    }
+}
+
+/// Prints the first few lines of a tool's output, dimmed, under a note saying how to see the rest.
+fn print_tool_output_preview(output: &mut impl Write, text: &str, width: usize) -> Result<(), ChatError> {
+    let (lines, hidden) = tool_output_preview(text, TOOL_OUTPUT_PREVIEW_LINES, width);
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let header = match hidden {
+        0 => "Output".to_string(),
+        hidden => format!(
+            "Output, {hidden} more line{} hidden (/expand to show all)",
+            if hidden == 1 { "" } else { "s" }
+        ),
+    };
+    queue!(
+        output,
+        style::Print(COLLAPSED_ARROW),
+        style::SetAttribute(Attribute::Dim),
+        style::Print(header),
+        style::Print("\n"),
+    )?;
+    for line in lines {
+        queue!(output, style::Print("   "), style::Print(line), style::Print("\n"))?;
+    }
+    execute!(output, style::SetAttribute(Attribute::Reset))?;
+    Ok(())
+}
+
+/// Returns the output of a tool as the user would read it, or [None] for images.
+fn tool_output_text(output: &OutputKind) -> Option<String> {
+    match output {
+        OutputKind::Text(text) => Some(text.clone()),
+        OutputKind::Json(serde_json::Value::String(text)) => Some(text.clone()),
+        OutputKind::Json(json) => Some(serde_json::to_string_pretty(json).unwrap_or_else(|_| json.to_string())),
+        OutputKind::Images(_) => None,
+    }
+}
+
+/// Returns the first `max_lines` lines of `output`, each cut to `width` characters, and how many
+/// lines were left out.
+fn tool_output_preview(output: &str, max_lines: usize, width: usize) -> (Vec<String>, usize) {
+    let total = output.trim_end().lines().count();
+    let lines = output
+        .trim_end()
+        .lines()
+        .take(max_lines)
+        .map(|line| match line.chars().count() > width {
+            true => format!("{}…", line.chars().take(width.saturating_sub(1)).collect::<String>()),
+            false => line.to_string(),
+        })
+        .collect::<Vec<_>>();
+    let hidden = total - lines.len();
+    (lines, hidden)
+}
+
+/// Returns the reason given when declining a tool with `n: <reason>` at the acceptance prompt.
+fn decline_reason(input: &str) -> Option<&str> {
+    let (answer, reason) = input.split_once(':')?;
+    let reason = reason.trim();
+    (answer.trim().eq_ignore_ascii_case("n") && !reason.is_empty()).then_some(reason)
+}
+
+/// Parses a selection of 1-based indices like `1,3` or `1 3` (or `all`) into 0-based indices,
+/// returning [None] if any index is out of range.
+fn parse_tool_selection(input: &str, count: usize) -> Option<HashSet<usize>> {
+    if input.trim().eq_ignore_ascii_case("all") {
+        return Some((0..count).collect());
+    }
+    input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => Some(n - 1),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use util::shared_writer::TestWriterWithSink;
+
+    use super::*;
+    use crate::platform::Env;
+
+    #[tokio::test]
+    async fn test_flow() {
+        // let _ = tracing_subscriber::fmt::try_init();
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Hope that looks good to you!",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::stdout(),
+            None,
+            InputSource::new_mock(vec![
+                "create a new file".to_string(),
+                "y".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+    }
+
+    #[tokio::test]
+    async fn test_flow_json_events() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Creating ",
+                "the file",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let events = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            Some("create a new file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        chat.json_events = Some(SharedWriter::new(events.clone()));
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        let events = String::from_utf8(events.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let types = events.iter().map(|e| e["type"].as_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(types, vec![
+            "assistant_text",
+            "assistant_text",
+            "tool_use",
+            "end",
+            "tool_result",
+            "assistant_text",
+            "end"
+        ]);
+        assert_eq!(events[0]["text"], "Creating ");
+        assert_eq!(events[2]["name"], "fs_write");
+        assert_eq!(events[2]["args"]["path"], "/file.txt");
+        assert_eq!(events[4]["id"], "1");
+        assert_eq!(events[4]["status"], "success");
+        assert_eq!(events[5]["text"], "Done");
+    }
+
+    #[tokio::test]
+    async fn test_initial_input() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/prompt.md", "# Task\nExplain this repo").await.unwrap();
+        let file = || Some(PathBuf::from("/prompt.md"));
+
+        assert_eq!(
+            initial_input(&ctx, None, file(), None).await.unwrap(),
+            Some("# Task\nExplain this repo".to_string())
+        );
+        // An empty pipe, e.g. from /dev/null, doesn't count as input.
+        assert_eq!(
+            initial_input(&ctx, None, file(), Some(String::new())).await.unwrap(),
+            Some("# Task\nExplain this repo".to_string())
+        );
+        assert!(initial_input(&ctx, Some("hi".to_string()), file(), None).await.is_err());
+        assert!(
+            initial_input(&ctx, None, file(), Some("piped".to_string()))
+                .await
+                .is_err()
+        );
+        assert!(
+            initial_input(&ctx, None, Some(PathBuf::from("/missing.md")), None)
+                .await
+                .is_err()
+        );
+
+        assert_eq!(
+            initial_input(&ctx, Some("hi ".to_string()), None, Some("piped".to_string()))
+                .await
+                .unwrap(),
+            Some("hi piped".to_string())
+        );
+        assert_eq!(initial_input(&ctx, None, None, None).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_scratch_dir_guard() {
+        let dir = tempfile::tempdir().unwrap();
+        let removed = dir.path().join("removed");
+        let kept = dir.path().join("kept");
+        for path in [&removed, &kept] {
+            std::fs::create_dir_all(path.join("artifacts")).unwrap();
+        }
+
+        drop(ScratchDirGuard {
+            path: removed.clone(),
+            keep: false,
+        });
+        drop(ScratchDirGuard {
+            path: kept.clone(),
+            keep: true,
+        });
+        assert!(!removed.exists());
+        assert!(kept.exists());
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(0), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(2), RETRY_BASE_DELAY * 4);
+        assert_eq!(retry_delay(10), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(32), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_read_system_prompt_file() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs()
+            .write("/prompt.txt", "\nYou are a terse reviewer.\n")
+            .await
+            .unwrap();
+        ctx.fs().write("/empty.txt", " \n").await.unwrap();
+
+        assert_eq!(
+            read_system_prompt_file(&ctx, Path::new("/prompt.txt")).await.unwrap(),
+            "You are a terse reviewer."
+        );
+        let err = read_system_prompt_file(&ctx, Path::new("/empty.txt"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("is empty"));
+        let err = read_system_prompt_file(&ctx, Path::new("/missing.txt"))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Failed to read the system prompt file '/missing.txt'")
+        );
+    }
+
+    #[test]
+    fn test_verbose_event_text() {
+        assert_eq!(
+            verbose_event_text(&parser::ResponseEvent::AssistantText("hi".to_string())),
+            None
+        );
+        assert_eq!(
+            verbose_event_text(&parser::ResponseEvent::ToolUseStart {
+                name: "fs_read".to_string()
+            }),
+            Some("[tool_use_start] fs_read".to_string())
+        );
+        let tool_use = AssistantToolUse {
+            id: "1".to_string(),
+            name: "fs_read".to_string(),
+            orig_name: "fs_read".to_string(),
+            args: serde_json::json!({ "path": "/" }),
+            orig_args: serde_json::json!({ "path": "/" }),
+        };
+        assert_eq!(
+            verbose_event_text(&parser::ResponseEvent::ToolUse(tool_use)),
+            Some("[tool_use] fs_read (1)\n{\n  \"path\": \"/\"\n}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_tool_selection() {
+        assert_eq!(parse_tool_selection("", 3), Some(HashSet::new()));
+        assert_eq!(parse_tool_selection("1, 3", 3), Some(HashSet::from([0, 2])));
+        assert_eq!(parse_tool_selection("2 2", 3), Some(HashSet::from([1])));
+        assert_eq!(parse_tool_selection("ALL", 2), Some(HashSet::from([0, 1])));
+        assert_eq!(parse_tool_selection("4", 3), None);
+        assert_eq!(parse_tool_selection("0", 3), None);
+        assert_eq!(parse_tool_selection("one", 3), None);
+    }
+
+    #[test]
+    fn test_decline_reason() {
+        assert_eq!(decline_reason("n: use ripgrep instead"), Some("use ripgrep instead"));
+        assert_eq!(decline_reason(" N :don't touch main.rs "), Some("don't touch main.rs"));
+        assert_eq!(decline_reason("n:"), None);
+        assert_eq!(decline_reason("n"), None);
+        assert_eq!(decline_reason("note: this is a prompt"), None);
+    }
+
+    #[test]
+    fn test_trusted_tool_set() {
+        let available = ["fs_read", "fs_write", "execute_bash"];
+        assert_eq!(
+            trusted_tool_set(vec!["fs_read".to_string(), "fs_write".to_string()], available).unwrap(),
+            HashSet::from(["fs_read".to_string(), "fs_write".to_string()])
+        );
+        assert!(trusted_tool_set(vec![], available).unwrap().is_empty());
+
+        let err = trusted_tool_set(vec!["fs_read".to_string(), "fs_delete".to_string()], available)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("fs_delete"));
+        assert!(err.contains("Valid tools: execute_bash, fs_read, fs_write"));
+    }
+
+    #[tokio::test]
+    async fn test_queue_profile_list() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let context_manager = ContextManager::new(ctx, None).await.unwrap();
+        context_manager.create_profile("rust").await.unwrap();
+
+        let mut output = Vec::new();
+        let profiles = context_manager.list_profiles().await.unwrap();
+        queue_profile_list(&mut output, &context_manager, &profiles).unwrap();
+        assert_eq!(
+            strip_ansi_escapes::strip_str(String::from_utf8(output).unwrap()),
+            "* default\n  rust\n"
+        );
+    }
+
+    #[test]
+    fn test_queue_whoami() {
+        let whoami = |token: Option<&BuilderIdToken>, profile: Option<&AuthProfile>, in_cloudshell: bool| {
+            let mut output = Vec::new();
+            queue_whoami(&mut output, token, profile, in_cloudshell).unwrap();
+            strip_ansi_escapes::strip_str(String::from_utf8(output).unwrap())
+        };
+
+        assert!(whoami(None, None, false).starts_with("Not logged in, run "));
+        assert!(whoami(None, None, true).starts_with("Using the credentials of this CloudShell environment\n"));
+
+        let mut token = BuilderIdToken {
+            access_token: crate::database::Secret("access".to_string()),
+            expires_at: time::OffsetDateTime::now_utc(),
+            refresh_token: None,
+            region: Some("us-east-1".to_string()),
+            start_url: None,
+            oauth_flow: crate::auth::builder_id::OAuthFlow::DeviceCode,
+            scopes: None,
+        };
+        assert_eq!(
+            whoami(Some(&token), None, false),
+            "Logged in with Builder ID\n  Region: us-east-1\n"
+        );
+
+        token.start_url = Some("https://example.awsapps.com/start".to_string());
+        let profile = AuthProfile {
+            arn: "arn:aws:codewhisperer:us-east-1:123456789012:profile/ABC".to_string(),
+            profile_name: "dev".to_string(),
+        };
+        assert_eq!(
+            whoami(Some(&token), Some(&profile), false),
+            "Logged in with IAM Identity Center\n  Start URL: https://example.awsapps.com/start\n  Region: \
+             us-east-1\n  Profile: dev\n  Account: 123456789012\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flow_audit_log() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Creating the file",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            Some("create a new file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        chat.audit_log = Some(AuditLog::new(Arc::clone(&ctx), PathBuf::from("/logs/audit.jsonl")));
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        let events = ctx
+            .fs()
+            .read_to_string("/logs/audit.jsonl")
+            .await
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let types = events.iter().map(|e| e["type"].as_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(types, vec!["prompt", "response", "tool_use", "tool_result", "response"]);
+        assert!(events.iter().all(|e| e["conversation_id"] == "fake_conv_id"));
+        assert_eq!(events[0]["prompt"], "create a new file");
+        assert_eq!(events[1]["text"], "Creating the file");
+        assert_eq!(events[2]["name"], "fs_write");
+        assert_eq!(events[3]["status"], "success");
+        assert_eq!(events[4]["text"], "Done");
+    }
+
+    #[tokio::test]
+    async fn test_flow_non_interactive_tool_approval() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let err = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            Some("create a new file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<ChatError>().and_then(ChatError::exit_code),
+            Some(EXIT_TOOL_APPROVAL_REQUIRED)
+        );
+        assert!(!ctx.fs().exists("/file.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_flow_quiet() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            Some("create a new file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        chat.quiet = true;
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("Sure, I'll create a file for you"));
+        assert!(output.contains("Done"));
+        assert!(!output.contains("Completed in"));
+    }
+
+    #[test]
+    fn test_is_broken_pipe() {
+        let broken_pipe = || std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        assert!(is_broken_pipe(&broken_pipe()));
+        assert!(is_broken_pipe(&ChatError::Std(broken_pipe())));
+        assert!(is_broken_pipe(&*eyre::Report::from(ChatError::Std(broken_pipe()))));
+        assert!(!is_broken_pipe(&ChatError::Std(std::io::ErrorKind::NotFound.into())));
+        assert!(!is_broken_pipe(&ChatError::Custom("broken pipe".into())));
+    }
+
+    #[tokio::test]
+    async fn test_flow_closed_output() {
+        struct ClosedPipe;
+
+        impl Write for ClosedPipe {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([["Hello, world!\n".repeat(100)]]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(ClosedPipe),
+            Some("say hello".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flow_no_stream() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Done with **the file**",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            Some("create a new file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        chat.streaming = false;
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        let create = output.find("Sure, I'll create a file for you").unwrap();
+        let done = output.find("Done with the file").unwrap();
+        assert!(create < done);
+    }
+
+    #[tokio::test]
+    async fn test_flow_templates() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([["It parses the arguments",],]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "/templates".to_string(),
+                "/templates use nope".to_string(),
+                "/templates use explain".to_string(),
+                "Explain how {{file or function}} works, step by step.".to_string(),
+                "Explain how main.rs works, step by step.".to_string(),
+                "/quit".to_string(),
+            ]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("explain Explain how a piece of code works"));
+        assert!(output.contains("There is no template 'nope'"));
+        assert!(output.contains("Fill in {{file or function}} before sending the prompt."));
+        assert_eq!(output.matches("It parses the arguments").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_flow_show_usage() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = StreamingClient::mock(vec![
+            vec![
+                ChatResponseStream::AssistantResponseEvent {
+                    content: "Hello".to_string(),
+                },
+                ChatResponseStream::MessageMetadataEvent {
+                    conversation_id: None,
+                    utterance_id: None,
+                    usage: Some(TokenUsage {
+                        input_tokens: 1200,
+                        output_tokens: 80,
+                    }),
+                },
+            ],
+            vec![ChatResponseStream::AssistantResponseEvent {
+                content: "Hello again".to_string(),
+            }],
+        ]);
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        database.settings.set(Setting::ChatShowUsage, true).await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec!["hi".to_string(), "hi again".to_string(), "exit".to_string()]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("Tokens: 1200 in, 80 out"), "{output}");
+        // The second response didn't report usage, so it's estimated.
+        assert!(output.contains("Tokens: ~"), "{output}");
+        assert!(chat.stats.lines(Duration::ZERO).iter().any(|line| line.contains("~")));
+    }
+
+    #[tokio::test]
+    async fn test_flow_replay() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([["Use **tar -xzf** to extract it",],]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "/replay".to_string(),
+                "how do I extract a tarball".to_string(),
+                "/replay".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("There's no response to replay yet."));
+        assert_eq!(output.matches("Use tar -xzf to extract it").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_flow_yank() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([[
+            "Build it with:\n```sh\ncargo build\n```\nthen run:\n```rust\nfn main() {}\n```\n",
+        ],]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "/yank /out/build.sh".to_string(),
+                "how do I run this".to_string(),
+                "/yank /out/build.sh".to_string(),
+                "/yank /out/src/main.rs 2".to_string(),
+                "/yank /out/src/main.rs 3".to_string(),
+                "/yank /out/src/main.rs".to_string(),
+                "/yank /out/build.sh 2 --force".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("There is no response to yank from yet."));
+        assert!(output.contains("The last response only contains 2 code blocks."));
+        assert!(output.contains("File at /out/src/main.rs already exists. To overwrite, use -f or --force"));
+        assert_eq!(
+            ctx.fs().read_to_string("/out/src/main.rs").await.unwrap(),
+            "fn main() {}\n"
+        );
+        assert_eq!(
+            ctx.fs().read_to_string("/out/build.sh").await.unwrap(),
+            "fn main() {}\n"
+        );
+    }
+
+    async fn resume_chat(
+        ctx: &Arc<Context>,
+        database: &mut Database,
+        output: &TestWriterWithSink,
+        responses: serde_json::Value,
+        input: &[&str],
+    ) -> ChatContext {
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        ChatContext::new(
+            Arc::clone(ctx),
+            database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(input.iter().map(|line| line.to_string()).collect()),
+            true,
+            true,
+            create_stream(responses),
+            || Some(80),
+            ToolManager::default(),
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_flow_resume() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        // Nothing to resume yet.
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut first = resume_chat(&ctx, &mut database, &output, serde_json::json!([["Hi there"]]), &[
+            "hello", "exit",
+        ])
+        .await;
+        first.try_chat(&mut database, &telemetry).await.unwrap();
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("No previous conversation to resume, starting a new one."));
+
+        // The conversation saved for another directory is picked up when this one has none.
+        let cwd = std::env::current_dir().unwrap();
+        let saved = database.get_conversation_by_path(&cwd).unwrap().unwrap();
+        let mut cleared = saved.clone();
+        cleared.clear(false);
+        database.set_conversation_by_path(&cwd, &cleared).unwrap();
+        database.set_conversation_by_path("/elsewhere", &saved).unwrap();
+
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut second = resume_chat(
+            &ctx,
+            &mut database,
+            &output,
+            serde_json::json!([["You said hello"]]),
+            &["exit"],
+        )
+        .await;
+        second.try_chat(&mut database, &telemetry).await.unwrap();
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("Resuming your most recent conversation, from /elsewhere"));
+        assert!(output.contains("You said hello"));
+        assert_eq!(second.conversation_state.history().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_flow_repeated_tool_failures() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let read_missing = |id: &str| {
+            serde_json::json!([
+                "Let me read that",
+                {
+                    "tool_use_id": id,
+                    "name": "fs_read",
+                    "args": {
+                        "mode": "Line",
+                        "path": "/missing.txt",
+                    }
+                }
+            ])
+        };
+        let test_client = create_stream(serde_json::json!([
+            read_missing("1"),
+            read_missing("2"),
+            read_missing("3"),
+            ["Sorry, I'll stop"],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "read missing.txt".to_string(),
+                "never mind".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("fs_read failed the same way 3 times in a row"));
+        assert!(output.contains("Sorry, I'll stop"));
+        // The failed results are kept, followed by the user's answer.
+        let history = chat.conversation_state.history();
+        assert_eq!(history.len(), 5);
+        assert!(history[3].0.has_tool_use_results());
+        assert_eq!(history[4].0.prompt(), Some("never mind"));
+    }
+
+    #[tokio::test]
+    async fn test_flow_decline_tool_with_reason() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            ["Got it, I'll use /notes.txt"],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "create a new file".to_string(),
+                "n: put it in /notes.txt instead".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        assert!(!ctx.fs().exists("/file.txt"));
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("Got it, I'll use /notes.txt"));
+        // The reason is the result of the declined tool rather than a new prompt.
+        let history = chat.conversation_state.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].0.prompt(), None);
+        let results = history[1].0.tool_use_results().unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0].content.as_slice(),
+            [message::ToolUseResultBlock::Text(text)]
+                if text == "The user declined this tool use: put it in /notes.txt instead"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_flow_max_response_bytes() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "First part. ",
+                "Second part that rambles on",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            ["Sorry about that"],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        database.settings.set(Setting::ChatMaxResponseBytes, 16).await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "create a new file".to_string(),
+                "that was cut off".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // The tool use after the limit is never run.
+        assert!(!ctx.fs().exists("/file.txt"));
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("First part. Seco"));
+        assert!(!output.contains("Second"));
+        assert!(output.contains("The response was stopped after 16 B (chat.maxResponseBytes)"));
+        assert!(output.contains("Sorry about that"));
+        let history = chat.conversation_state.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            history[0].1.content(),
+            "First part. Seco\n\n[Response truncated after 16 bytes by the user's chat.maxResponseBytes setting]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flow_record() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let responses = serde_json::json!([
+            [
+                "Creating the file",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            ["Done"],
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::stdout(),
+            None,
+            InputSource::new_mock(vec![
+                "create a new file".to_string(),
+                "y".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            create_stream(responses.clone()),
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat.recording = Some(Recording::new(path.clone()));
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // The recording plays back the same session.
+        let recorded = serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(recorded, responses);
+        assert!(recording::load_replay(&path).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_flow_inline_file_references() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/notes.md", "buy milk").await.unwrap();
+        let test_client = create_stream(serde_json::json!([["You need milk"]]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "summarize @/notes.md and @/todo.md".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(output.contains("Warning: '@/todo.md' was sent as written, no such file."));
+        let history = chat.conversation_state.history();
+        assert_eq!(
+            history[0].0.prompt(),
+            Some("summarize /notes.md and @/todo.md\n\n[/notes.md]\nbuy milk")
+        );
+        assert!(
+            chat.conversation_state
+                .transcript
+                .iter()
+                .any(|entry| entry.contains("summarize @/notes.md and @/todo.md"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flow_context_validate() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/keep.md", "keep").await.unwrap();
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "/context add --force /keep.md /gone.md".to_string(),
+                "/context validate".to_string(),
+                "y".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            create_stream(serde_json::json!([])),
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        let has_line = |start: &str, end: &str| {
+            output
+                .lines()
+                .any(|line| line.starts_with(start) && line.ends_with(end))
+        };
+        assert!(has_line("✓ /keep.md", " 1 file"));
+        assert!(has_line("✗ /gone.md", " missing"));
+        assert!(has_line("✗ README.md", " missing (global)"));
+        assert!(output.contains("Removed 1 rule(s) from profile 'default'"));
+        let context_manager = chat.conversation_state.context_manager.as_ref().unwrap();
+        assert_eq!(context_manager.profile_config.paths, vec!["/keep.md".to_string()]);
+    }
 
-/// Testing helper
-fn split_tool_use_event(value: &Map<String, serde_json::Value>) -> Vec<ChatResponseStream> {
-    let tool_use_id = value.get("tool_use_id").unwrap().as_str().unwrap().to_string();
-    let name = value.get("name").unwrap().as_str().unwrap().to_string();
-    let args_str = value.get("args").unwrap().to_string();
-    let split_point = args_str.len() / 2;
-    vec![
-        ChatResponseStream::ToolUseEvent {
-            tool_use_id: tool_use_id.clone(),
-            name: name.clone(),
-            input: None,
-            stop: None,
-        },
-        ChatResponseStream::ToolUseEvent {
-            tool_use_id: tool_use_id.clone(),
-            name: name.clone(),
-            input: Some(args_str.split_at(split_point).0.to_string()),
-            stop: None,
-        },
-        ChatResponseStream::ToolUseEvent {
-            tool_use_id: tool_use_id.clone(),
-            name: name.clone(),
-            input: Some(args_str.split_at(split_point).1.to_string()),
-            stop: None,
-        },
-        ChatResponseStream::ToolUseEvent {
-            tool_use_id: tool_use_id.clone(),
-            name: name.clone(),
-            input: None,
-            stop: Some(true),
-        },
-    ]
-}
+    #[tokio::test]
+    async fn test_flow_context_show_by_size() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/small.md", "small").await.unwrap();
+        ctx.fs().write("/big.md", "x".repeat(2048)).await.unwrap();
 
-/// Testing helper
-fn create_stream(model_responses: serde_json::Value) -> StreamingClient {
-    let mut mock = Vec::new();
-    for response in model_responses.as_array().unwrap() {
-        let mut stream = Vec::new();
-        for event in response.as_array().unwrap() {
-            match event {
-                serde_json::Value::String(assistant_text) => {
-                    stream.push(ChatResponseStream::AssistantResponseEvent {
-                        content: assistant_text.to_string(),
-                    });
-                },
-                serde_json::Value::Object(tool_use) => {
-                    stream.append(&mut split_tool_use_event(tool_use));
-                },
-                other => panic!("Unexpected value: {:?}", other),
-            }
-        }
-        mock.push(stream);
-    }
-    StreamingClient::mock(mock)
-}
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::platform::Env;
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "/context add /small.md /big.md".to_string(),
+                "/context show --by-size".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            create_stream(serde_json::json!([])),
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        let big = output.find("👤 /big.md (~").unwrap();
+        let small = output.find("👤 /small.md (~").unwrap();
+        assert!(big < small);
+        assert!(output[big..].lines().next().unwrap().ends_with(", 2.0 KB)"));
+        assert!(output[small..].lines().next().unwrap().ends_with(", 5 B)"));
+        assert!(output.contains("tokens, 2.0 KB\n"));
+    }
 
     #[tokio::test]
-    async fn test_flow() {
-        // let _ = tracing_subscriber::fmt::try_init();
+    async fn test_flow_dry_run() {
         let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
         let test_client = create_stream(serde_json::json!([
             [
@@ -3800,8 +8063,76 @@ mod tests {
                     }
                 }
             ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            Some("create a new file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            false,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        chat.dry_run = true;
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // The response was never requested, so the tool it asks for didn't run.
+        assert!(!ctx.fs().exists("/file.txt"));
+        assert_eq!(
+            strip_ansi_escapes::strip_str(&chat.stats.lines(std::time::Duration::ZERO)[1]),
+            "Messages        1 sent, 0 received"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flow_max_turns() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Creating the first file",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "first",
+                        "path": "/file1.txt",
+                    }
+                }
+            ],
             [
-                "Hope that looks good to you!",
+                "Creating the second file",
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "second",
+                        "path": "/file2.txt",
+                    }
+                }
+            ],
+            [
+                "Done!",
             ],
         ]));
 
@@ -3812,33 +8143,86 @@ mod tests {
         let tool_manager = ToolManager::default();
         let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
             .expect("Tools failed to load");
-        ChatContext::new(
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let mut chat = ChatContext::new(
             Arc::clone(&ctx),
             &mut database,
             "fake_conv_id",
-            SharedWriter::stdout(),
-            None,
-            InputSource::new_mock(vec![
-                "create a new file".to_string(),
-                "y".to_string(),
-                "exit".to_string(),
-            ]),
-            true,
+            SharedWriter::null(),
+            Some("create two files".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
             false,
             test_client,
             || Some(80),
             tool_manager,
             None,
             tool_config,
-            ToolPermissions::new(0),
+            tool_permissions,
         )
         .await
-        .unwrap()
-        .try_chat(&mut database, &telemetry)
-        .await
         .unwrap();
+        chat.max_turns = Some(2);
+        let err = chat.try_chat(&mut database, &telemetry).await.unwrap_err();
 
-        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        assert_eq!(
+            err.downcast_ref::<ChatError>().and_then(ChatError::exit_code),
+            Some(EXIT_MAX_TURNS)
+        );
+        assert_eq!(ctx.fs().read_to_string("/file1.txt").await.unwrap(), "first\n");
+        assert!(!ctx.fs().exists("/file2.txt"));
+    }
+
+    #[test]
+    fn test_tool_output_preview() {
+        let output = "one\ntwo\nthree\nfour\n\n";
+        assert_eq!(
+            tool_output_preview(output, 5, 80),
+            (
+                vec![
+                    "one".to_string(),
+                    "two".to_string(),
+                    "three".to_string(),
+                    "four".to_string()
+                ],
+                0
+            )
+        );
+        assert_eq!(
+            tool_output_preview(output, 2, 80),
+            (vec!["one".to_string(), "two".to_string()], 2)
+        );
+        assert_eq!(tool_output_preview("abcdefgh", 5, 4), (vec!["abc…".to_string()], 0));
+        assert_eq!(tool_output_preview("", 5, 80), (vec![], 0));
+
+        assert_eq!(
+            tool_output_text(&OutputKind::Json(serde_json::json!({ "a": 1 }))),
+            Some("{\n  \"a\": 1\n}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_chat_error_exit_code() {
+        use crate::api_client::ApiClientError;
+
+        assert_eq!(
+            ChatError::Client(ApiClientError::AuthExpired).exit_code(),
+            Some(EXIT_AUTH_FAILURE)
+        );
+        assert_eq!(
+            ChatError::Client(ApiClientError::QuotaBreach {
+                message: "quota has reached its limit",
+                request_id: None,
+            })
+            .exit_code(),
+            Some(EXIT_QUOTA_BREACH)
+        );
+        assert_eq!(
+            ChatError::Client(ApiClientError::ContextWindowOverflow).exit_code(),
+            None
+        );
+        assert_eq!(ChatError::Custom("oops".into()).exit_code(), None);
     }
 
     #[tokio::test]
@@ -4089,6 +8473,88 @@ mod tests {
         assert_eq!(ctx.fs().read_to_string("/file4.txt").await.unwrap(), "Hello, world!\n");
     }
 
+    #[tokio::test]
+    async fn test_flow_batch_summary() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/notes.txt", "notes").await.unwrap();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll copy your notes",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_read",
+                    "args": {
+                        "mode": "Line",
+                        "path": "/notes.txt",
+                    }
+                },
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "notes",
+                        "path": "/copy1.txt",
+                    }
+                },
+                {
+                    "tool_use_id": "3",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "notes",
+                        "path": "/copy2.txt",
+                    }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            None,
+            InputSource::new_mock(vec![
+                "copy my notes twice".to_string(),
+                "y".to_string(),
+                "y".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            ToolManager::default(),
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        let summary = "3 tool uses requested: 2 files will be modified, 1 file will be read";
+        assert_eq!(output.matches(summary).count(), 1);
+        assert!(output.find(summary) < output.find("Using tool: fs_read"));
+        assert_eq!(ctx.fs().read_to_string("/copy2.txt").await.unwrap(), "notes\n");
+    }
+
     #[tokio::test]
     async fn test_flow_tools_trust_all() {
         // let _ = tracing_subscriber::fmt::try_init();
@@ -0,0 +1,67 @@
+//! Showing output that doesn't fit on the screen in an external pager, enabled with the
+//! `chat.pager` setting.
+
+use std::io::{
+    ErrorKind,
+    Write,
+};
+use std::process::{
+    Command,
+    Stdio,
+};
+
+/// Pager used when `$PAGER` isn't set. `-R` passes ANSI colors through.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Returns the program and arguments to page with, given the value of `$PAGER`. An empty `$PAGER`
+/// disables paging.
+pub fn pager_command(pager_env: Option<String>) -> Option<Vec<String>> {
+    let pager = pager_env.unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    shlex::split(&pager).filter(|parts| !parts.is_empty())
+}
+
+/// Writes `text` to the pager and waits for the user to close it.
+///
+/// Returns `false` without showing anything if the pager couldn't be started, e.g. because it
+/// isn't installed, so that the caller can print `text` instead.
+pub fn page(command: &[String], text: &[u8]) -> std::io::Result<bool> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(false);
+    };
+    let mut child = match Command::new(program).args(args).stdin(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closes its input if the user quits before reaching the end.
+        match stdin.write_all(text) {
+            Err(err) if err.kind() != ErrorKind::BrokenPipe => return Err(err),
+            _ => (),
+        }
+    }
+    child.wait()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pager_command() {
+        assert_eq!(pager_command(None), Some(vec!["less".to_string(), "-R".to_string()]));
+        assert_eq!(
+            pager_command(Some("bat --paging 'always'".to_string())),
+            Some(vec!["bat".to_string(), "--paging".to_string(), "always".to_string()])
+        );
+        assert_eq!(pager_command(Some(String::new())), None);
+    }
+
+    #[test]
+    fn test_page_missing_pager() {
+        let command = vec!["this-pager-does-not-exist".to_string()];
+        assert!(!page(&command, b"text").unwrap());
+    }
+}
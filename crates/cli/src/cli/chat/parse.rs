@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io::Write;
 
 use crossterm::style::{
@@ -9,6 +10,9 @@ use crossterm::{
     Command,
     style,
 };
+use syntect::easy::HighlightLines;
+use syntect::util::as_24_bit_terminal_escaped;
+use tracing::debug;
 use unicode_width::{
     UnicodeWidthChar,
     UnicodeWidthStr,
@@ -24,6 +28,8 @@ use winnow::ascii::{
 use winnow::combinator::{
     alt,
     delimited,
+    opt,
+    peek,
     preceded,
     repeat,
     terminated,
@@ -36,7 +42,9 @@ use winnow::error::{
 use winnow::prelude::*;
 use winnow::stream::{
     AsChar,
+    Offset,
     Stream,
+    StreamIsPartial,
 };
 use winnow::token::{
     any,
@@ -45,6 +53,11 @@ use winnow::token::{
     take_while,
 };
 
+use super::tools::fs_write::{
+    SYNTAX_SET,
+    THEME_SET,
+};
+
 const CODE_COLOR: Color = Color::Green;
 const HEADING_COLOR: Color = Color::Magenta;
 const BLOCKQUOTE_COLOR: Color = Color::DarkGrey;
@@ -52,6 +65,14 @@ const URL_TEXT_COLOR: Color = Color::Blue;
 const URL_LINK_COLOR: Color = Color::DarkGrey;
 
 const DEFAULT_RULE_WIDTH: usize = 40;
+/// Bullets used for each level of nested lists, repeating for deeper levels.
+const BULLETS: [char; 3] = ['•', '◦', '▪'];
+/// Columns each level of a nested list is indented by.
+const LIST_INDENT_WIDTH: usize = 2;
+/// Columns are never shrunk below this width when fitting a table to the terminal.
+const MIN_TABLE_COLUMN_WIDTH: usize = 3;
+
+const CODE_HIGHLIGHT_THEME: &str = "base16-ocean.dark";
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error<'a> {
@@ -83,13 +104,38 @@ impl<'a> ParserError<Partial<&'a str>> for Error<'a> {
 pub struct ParseState {
     pub terminal_width: Option<usize>,
     pub column: usize,
+    /// Column that wrapped lines continue at, so that e.g. list items keep their indentation.
+    pub indent: usize,
     pub in_codeblock: bool,
     pub bold: bool,
     pub italic: bool,
     pub strikethrough: bool,
     pub set_newline: bool,
     pub newline: bool,
-    pub citations: Vec<(String, String)>,
+    /// URLs cited by the response, footnote `[^n]` being `citations[n - 1]`. A URL cited more
+    /// than once keeps the footnote it was first given.
+    pub citations: Vec<String>,
+    /// Whether code blocks with a recognized language should be syntax highlighted. Requires a
+    /// terminal with 24-bit color support.
+    pub syntax_highlighting: bool,
+    /// Whether links are printed as clickable OSC 8 hyperlinks instead of showing the URL or
+    /// collecting them as [ParseState::citations].
+    pub hyperlinks: bool,
+    /// Highlighter for the code block currently being printed, if it is being highlighted.
+    code_highlighter: Option<CodeHighlighter>,
+    /// How far the marker of each open list level is indented in the source, innermost last.
+    list_indents: Vec<usize>,
+    /// Blockquote nesting of the current line, which is kept through a code block started in a
+    /// blockquote so that its lines keep the quote prefix.
+    quote_depth: usize,
+    /// The table being received, which is rendered once all of its rows are in.
+    table: Option<Table>,
+    /// Set by parsers that can't make progress until a line ending arrives, so that a
+    /// [MarkdownStream] doesn't retry them on every chunk of a long line.
+    awaiting_line_ending: bool,
+    /// The footnote each citation number used by the model was given, so that a later `[[n]]`
+    /// without a link refers to the same footnote.
+    citation_numbers: HashMap<String, usize>,
 }
 
 impl ParseState {
@@ -97,6 +143,7 @@ impl ParseState {
         Self {
             terminal_width,
             column: 0,
+            indent: 0,
             in_codeblock: false,
             bold: false,
             italic: false,
@@ -104,10 +151,59 @@ impl ParseState {
             set_newline: false,
             newline: true,
             citations: vec![],
+            syntax_highlighting: false,
+            hyperlinks: false,
+            code_highlighter: None,
+            list_indents: Vec::new(),
+            quote_depth: 0,
+            table: None,
+            awaiting_line_ending: false,
+            citation_numbers: HashMap::new(),
+        }
+    }
+
+    /// Returns how deeply a list item whose marker is indented by `indent` columns is nested,
+    /// closing any deeper levels.
+    fn list_depth(&mut self, indent: &str) -> usize {
+        let indent = indent.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum();
+        while self.list_indents.last().is_some_and(|&last| last > indent) {
+            self.list_indents.pop();
+        }
+        if self.list_indents.last() != Some(&indent) {
+            self.list_indents.push(indent);
+        }
+        self.list_indents.len() - 1
+    }
+}
+
+/// Highlights the lines of a single fenced code block, keeping parse state between lines.
+struct CodeHighlighter(HighlightLines<'static>);
+
+impl CodeHighlighter {
+    /// Returns [None] if `language` (the fence info string) isn't a known syntax.
+    fn new(language: &str) -> Option<Self> {
+        let token = language.split_whitespace().next()?;
+        let syntax = SYNTAX_SET.find_syntax_by_token(token)?;
+        let theme = THEME_SET.themes.get(CODE_HIGHLIGHT_THEME)?;
+        Some(Self(HighlightLines::new(syntax, theme)))
+    }
+
+    /// Returns `line` with 24-bit terminal escape codes, falling back to the plain line if it
+    /// couldn't be highlighted.
+    fn highlight(&mut self, line: &str) -> String {
+        match self.0.highlight_line(line, &SYNTAX_SET) {
+            Ok(ranges) => as_24_bit_terminal_escaped(&ranges, false),
+            Err(_) => line.to_string(),
         }
     }
 }
 
+impl std::fmt::Debug for CodeHighlighter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CodeHighlighter").finish_non_exhaustive()
+    }
+}
+
 pub fn interpret_markdown<'a, 'b>(
     mut i: Partial<&'a str>,
     mut o: impl Write + 'b,
@@ -120,6 +216,7 @@ pub fn interpret_markdown<'a, 'b>(
         ($($fns:ident),*) => {
             $({
                 i.reset(&start);
+                state.awaiting_line_ending = false;
                 match $fns(&mut o, state).parse_next(&mut i) {
                     Err(ErrMode::Backtrack(e)) => {
                         error = match error {
@@ -135,13 +232,21 @@ pub fn interpret_markdown<'a, 'b>(
         };
     }
 
+    if state.table.is_some() {
+        stateful_alt!(table_body);
+    }
+
     match state.in_codeblock {
         false => {
             stateful_alt!(
+                // Numbered items start with alphanumeric text, so they have to be tried first for
+                // their nesting to be tracked
+                numbered_item,
                 // This pattern acts as a short circuit for alphanumeric plaintext
                 // More importantly, it's needed to support manual wordwrapping
                 text,
                 // multiline patterns
+                table,
                 blockquote,
                 // linted_codeblock,
                 codeblock_begin,
@@ -149,7 +254,6 @@ pub fn interpret_markdown<'a, 'b>(
                 horizontal_rule,
                 heading,
                 bulleted_item,
-                numbered_item,
                 // inline patterns
                 code,
                 citation,
@@ -169,11 +273,13 @@ pub fn interpret_markdown<'a, 'b>(
         },
         true => {
             stateful_alt!(
+                codeblock_blockquote,
+                codeblock_end,
+                codeblock_highlighted_line,
                 codeblock_less_than,
                 codeblock_greater_than,
                 codeblock_ampersand,
                 codeblock_quot,
-                codeblock_end,
                 codeblock_line_ending,
                 codeblock_fallback
             );
@@ -186,15 +292,109 @@ pub fn interpret_markdown<'a, 'b>(
     }
 }
 
+/// Markdown that arrives in chunks, such as a streamed response, along with how much of it has been
+/// rendered.
+///
+/// Constructs that need a whole line, like highlighted code and tables, aren't parsed again until
+/// a line ending arrives. Otherwise a long line would be re-parsed on every chunk, making the work
+/// per chunk grow with the length of the line rather than with the chunk.
+#[derive(Debug, Default)]
+pub struct MarkdownStream {
+    buf: String,
+    offset: usize,
+    /// How far the buffer has been searched for a line ending, while waiting on one.
+    line_ending_search: Option<usize>,
+    /// Bytes parsed or searched for a line ending without anything being rendered, which tests use
+    /// to check that the work per chunk doesn't grow with what has been received.
+    #[cfg(test)]
+    rescanned: usize,
+}
+
+impl MarkdownStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.buf.push_str(text);
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.buf.push(c);
+    }
+
+    /// Whether anything has been received at all.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// The number of bytes received that haven't been rendered yet.
+    pub fn pending(&self) -> usize {
+        self.buf.len() - self.offset
+    }
+
+    /// Renders the next markdown element, returning `false` if nothing can be rendered until more
+    /// text arrives. Once `complete`, multiline patterns like tables are finished with what has
+    /// been received instead of waiting on more.
+    pub fn render_next(&mut self, o: impl Write, state: &mut ParseState, complete: bool) -> std::io::Result<bool> {
+        // A table that ends the text is only rendered once it's known that no rows follow.
+        if self.offset >= self.buf.len() && !(complete && state.table.is_some()) {
+            return Ok(false);
+        }
+        if let Some(searched) = self.line_ending_search {
+            if !complete && !self.buf[searched..].contains('\n') {
+                #[cfg(test)]
+                {
+                    self.rescanned += self.buf.len() - searched;
+                }
+                self.line_ending_search = Some(self.buf.len());
+                return Ok(false);
+            }
+            self.line_ending_search = None;
+        }
+
+        let mut input = Partial::new(&self.buf[self.offset..]);
+        if complete {
+            let _ = input.complete();
+        }
+        match interpret_markdown(input, o, state) {
+            Ok(parsed) => {
+                self.offset += parsed.offset_from(&input);
+                state.newline = state.set_newline;
+                state.set_newline = false;
+                Ok(true)
+            },
+            Err(ErrMode::Incomplete(_)) => {
+                #[cfg(test)]
+                {
+                    self.rescanned += self.buf.len() - self.offset;
+                }
+                if state.awaiting_line_ending {
+                    self.line_ending_search = Some(self.buf.len());
+                }
+                Ok(false)
+            },
+            Err(err) => match err.into_inner() {
+                Some(Error::Stdio(err)) => Err(err),
+                Some(err) => Err(std::io::Error::other(err.to_string())),
+                None => Ok(false),
+            },
+        }
+    }
+}
+
 fn text<'a, 'b>(
     mut o: impl Write + 'b,
     state: &'b mut ParseState,
 ) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
     move |i| {
-        let content = take_while(1.., |t| AsChar::is_alphanum(t) || "+,.!?\"".contains(t)).parse_next(i)?;
-        queue_newline_or_advance(&mut o, state, content.width())?;
-        queue(&mut o, style::Print(content))?;
-        Ok(())
+        let content = (
+            take_while(1, |t| AsChar::is_alphanum(t) || "+,.!?\"/".contains(t)),
+            take_while(0.., is_word_char),
+        )
+            .recognize()
+            .parse_next(i)?;
+        queue_wrapped(&mut o, state, content)
     }
 }
 
@@ -209,6 +409,7 @@ fn heading<'a, 'b>(
 
         let level = terminated(take_while(1.., |c| c == '#'), space1).parse_next(i)?;
         let print = format!("{level} ");
+        state.list_indents.clear();
 
         queue_newline_or_advance(&mut o, state, print.width())?;
         queue(&mut o, style::SetForegroundColor(HEADING_COLOR))?;
@@ -227,9 +428,15 @@ fn bulleted_item<'a, 'b>(
         }
 
         let ws = (space0, alt(("-", "*")), space1).parse_next(i)?.0;
-        let print = format!("{ws}• ");
+        let depth = state.list_depth(ws);
+        let print = format!(
+            "{}{} ",
+            " ".repeat(depth * LIST_INDENT_WIDTH),
+            BULLETS[depth % BULLETS.len()]
+        );
 
         queue_newline_or_advance(&mut o, state, print.width())?;
+        state.indent = state.column;
         queue(&mut o, style::Print(print))
     }
 }
@@ -244,9 +451,11 @@ fn numbered_item<'a, 'b>(
         }
 
         let (ws, digits, _, _) = (space0, digit1, ".", space1).parse_next(i)?;
-        let print = format!("{ws}{digits}. ");
+        let depth = state.list_depth(ws);
+        let print = format!("{}{digits}. ", " ".repeat(depth * LIST_INDENT_WIDTH));
 
         queue_newline_or_advance(&mut o, state, print.width())?;
+        state.indent = state.column;
         queue(&mut o, style::Print(print))
     }
 }
@@ -265,6 +474,7 @@ fn horizontal_rule<'a, 'b>(
             alt((take_while(3.., '-'), take_while(3.., '*'), take_while(3.., '_'))),
         )
             .parse_next(i)?;
+        state.list_indents.clear();
 
         state.column = 0;
         state.set_newline = true;
@@ -283,9 +493,8 @@ fn code<'a, 'b>(
         let code = terminated(take_until(0.., "`"), "`").parse_next(i)?;
         let out = code.replace("&amp;", "&").replace("&gt;", ">").replace("&lt;", "<");
 
-        queue_newline_or_advance(&mut o, state, out.width())?;
         queue(&mut o, style::SetForegroundColor(Color::Green))?;
-        queue(&mut o, style::Print(out))?;
+        queue_wrapped(&mut o, state, &out)?;
         queue(&mut o, style::ResetColor)
     }
 }
@@ -299,17 +508,279 @@ fn blockquote<'a, 'b>(
             return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
         }
 
-        let level = repeat::<_, _, Vec<&'_ str>, _, _>(1.., terminated(">", space0))
+        // Only a single space belongs to the prefix, the rest is e.g. the indentation of a list.
+        let level = repeat::<_, _, Vec<&'_ str>, _, _>(1.., terminated(">", opt(' ')))
             .parse_next(i)?
             .len();
         let print = "│ ".repeat(level);
 
         queue(&mut o, style::SetForegroundColor(BLOCKQUOTE_COLOR))?;
         queue_newline_or_advance(&mut o, state, print.width())?;
+        state.indent = state.column;
+        state.quote_depth = level;
+        // Lists, headings and code blocks can follow the prefix as if the line started there.
+        state.set_newline = true;
         queue(&mut o, style::Print(print))
     }
 }
 
+/// A table whose rows are still being received.
+#[derive(Debug)]
+struct Table {
+    header: Vec<String>,
+    alignments: Vec<TableAlignment>,
+    rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// Starts a GFM table at its header and delimiter rows. The rows that follow are collected by
+/// [table_body], which renders the table once it ends.
+fn table<'a, 'b>(
+    _o: impl Write + 'b,
+    state: &'b mut ParseState,
+) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
+    move |i| {
+        if !state.newline {
+            return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
+        }
+
+        state.awaiting_line_ending = true;
+        let header = split_table_row(table_row.parse_next(i)?);
+        let delimiter = terminated(till_line_ending, ascii::line_ending).parse_next(i)?;
+        let alignments = match parse_table_alignments(delimiter) {
+            Some(alignments) if alignments.len() == header.len() => alignments,
+            _ => return Err(ErrMode::from_error_kind(i, ErrorKind::Fail)),
+        };
+
+        state.table = Some(Table {
+            header,
+            alignments,
+            rows: Vec::new(),
+        });
+        state.set_newline = true;
+        Ok(())
+    }
+}
+
+/// Collects the rows of a table for as long as lines keep starting with a pipe, then renders it.
+/// Each row is only parsed once, however long the table gets.
+fn table_body<'a, 'b>(
+    mut o: impl Write + 'b,
+    state: &'b mut ParseState,
+) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
+    move |i| {
+        // Only the start of the next line is needed to tell whether the table goes on.
+        let start = i.checkpoint();
+        let row = match peek::<_, _, Error<'a>, _>((space0, "|")).parse_next(i) {
+            Ok(_) => {
+                state.awaiting_line_ending = true;
+                match table_row.parse_next(i) {
+                    Ok(row) => Some(row),
+                    // A last row without a line ending is left as text.
+                    Err(ErrMode::Backtrack(_)) => None,
+                    Err(err) => return Err(err),
+                }
+            },
+            Err(ErrMode::Backtrack(_)) => None,
+            Err(err) => return Err(err),
+        };
+
+        match (row, state.table.as_mut()) {
+            (Some(row), Some(table)) => {
+                let mut row = split_table_row(row);
+                row.resize(table.header.len(), String::new());
+                table.rows.push(row);
+                state.set_newline = true;
+                Ok(())
+            },
+            _ => {
+                i.reset(&start);
+                match state.table.take() {
+                    Some(table) => queue_table(&mut o, state, &table),
+                    None => Ok(()),
+                }
+            },
+        }
+    }
+}
+
+/// Renders a table with box-drawing borders. Columns are shrunk to fit the terminal width and
+/// cell contents are wrapped within their column.
+fn queue_table<'a>(o: &mut impl Write, state: &mut ParseState, table: &Table) -> PResult<(), Error<'a>> {
+    let Table {
+        header,
+        alignments,
+        rows,
+    } = table;
+
+    let mut widths = vec![1; header.len()];
+    for row in std::iter::once(header).chain(rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    if let Some(terminal_width) = state.terminal_width {
+        // Each column is padded by a space on either side, plus one border per column and one
+        // closing border.
+        let available = terminal_width.saturating_sub(3 * widths.len() + 1);
+        while widths.iter().sum::<usize>() > available {
+            match widths.iter_mut().max() {
+                Some(widest) if *widest > MIN_TABLE_COLUMN_WIDTH => *widest -= 1,
+                _ => break,
+            }
+        }
+    }
+
+    let border = |left: &str, middle: &str, right: &str| {
+        let segments = widths.iter().map(|w| "─".repeat(w + 2)).collect::<Vec<_>>();
+        format!("{left}{}{right}\n", segments.join(middle))
+    };
+
+    queue(o, style::Print(border("┌", "┬", "┐")))?;
+    queue_table_row(o, header, &widths, alignments, true)?;
+    queue(o, style::Print(border("├", "┼", "┤")))?;
+    for row in rows {
+        queue_table_row(o, row, &widths, alignments, false)?;
+    }
+    queue(o, style::Print(border("└", "┴", "┘")))?;
+
+    state.column = 0;
+    state.set_newline = true;
+
+    Ok(())
+}
+
+fn table_row<'a>(i: &mut Partial<&'a str>) -> PResult<&'a str, Error<'a>> {
+    delimited((space0, "|"), till_line_ending, ascii::line_ending).parse_next(i)
+}
+
+/// Splits a table row (without its leading pipe) into trimmed cells, honoring `\|` escapes.
+fn split_table_row(row: &str) -> Vec<String> {
+    let row = row.trim_end();
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            },
+            '|' => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+    }
+    if !row.ends_with('|') || row.ends_with("\\|") {
+        cells.push(cell);
+    }
+    cells.iter().map(|cell| unescape_entities(cell.trim())).collect()
+}
+
+/// Parses a delimiter row such as `|:---|:-:|--:|`, returning [None] if it isn't one.
+fn parse_table_alignments(line: &str) -> Option<Vec<TableAlignment>> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|')
+        .map(|cell| {
+            let cell = cell.trim();
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => TableAlignment::Center,
+                (false, true) => TableAlignment::Right,
+                _ => TableAlignment::Left,
+            })
+        })
+        .collect()
+}
+
+fn queue_table_row<'a>(
+    o: &mut impl Write,
+    cells: &[String],
+    widths: &[usize],
+    alignments: &[TableAlignment],
+    bold: bool,
+) -> Result<(), ErrMode<Error<'a>>> {
+    let wrapped = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| wrap_table_cell(cell, *width))
+        .collect::<Vec<_>>();
+    let height = wrapped.iter().map(Vec::len).max().unwrap_or(1);
+
+    for line in 0..height {
+        queue(o, style::Print("│"))?;
+        for ((cell, width), alignment) in wrapped.iter().zip(widths).zip(alignments) {
+            let text = cell.get(line).map(String::as_str).unwrap_or_default();
+            let padding = width.saturating_sub(text.width());
+            let (left, right) = match alignment {
+                TableAlignment::Left => (0, padding),
+                TableAlignment::Center => (padding / 2, padding - padding / 2),
+                TableAlignment::Right => (padding, 0),
+            };
+            queue(o, style::Print(format!(" {}", " ".repeat(left))))?;
+            if bold {
+                queue(o, style::SetAttribute(Attribute::Bold))?;
+            }
+            queue(o, style::Print(text))?;
+            if bold {
+                queue(o, style::SetAttribute(Attribute::NormalIntensity))?;
+            }
+            queue(o, style::Print(format!("{} │", " ".repeat(right))))?;
+        }
+        queue(o, style::Print("\n"))?;
+    }
+
+    Ok(())
+}
+
+/// Word wraps `text` to `width` columns, splitting words that are wider than the column.
+fn wrap_table_cell(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+    for word in text.split_whitespace() {
+        let word_width = word.width();
+        if line_width > 0 && line_width + 1 + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        if line_width > 0 {
+            line.push(' ');
+            line_width += 1;
+        }
+        for c in word.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if line_width > 0 && line_width + char_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            line.push(c);
+            line_width += char_width;
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn unescape_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
 fn bold<'a, 'b>(
     mut o: impl Write + 'b,
     state: &'b mut ParseState,
@@ -387,14 +858,52 @@ fn citation<'a, 'b>(
     state: &'b mut ParseState,
 ) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
     move |i| {
+        let start = i.checkpoint();
         let num = delimited("[[", digit1, "]]").parse_next(i)?;
-        let link = delimited("(", take_till(0.., ')'), ")").parse_next(i)?;
+        let link = opt(delimited("(", take_till(0.., ')'), ")")).parse_next(i)?;
 
-        state.citations.push((num.to_owned(), link.to_owned()));
+        if state.hyperlinks {
+            let Some(link) = link else {
+                i.reset(&start);
+                return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
+            };
+            let text = format!("[{num}]");
+            queue_newline_or_advance(&mut o, state, text.width())?;
+            queue(&mut o, style::SetForegroundColor(URL_TEXT_COLOR))?;
+            queue_hyperlink(&mut o, link, &text)?;
+            return queue(&mut o, style::ResetColor);
+        }
+
+        let index = match link {
+            Some(link) => {
+                let index = match state.citations.iter().position(|cited| cited == link) {
+                    Some(position) => position + 1,
+                    None => {
+                        state.citations.push(link.to_owned());
+                        state.citations.len()
+                    },
+                };
+                if let Some(previous) = state.citation_numbers.insert(num.to_owned(), index) {
+                    if previous != index {
+                        debug!(num, link, "Citation number reused for a different link");
+                    }
+                }
+                index
+            },
+            None => match state.citation_numbers.get(num) {
+                Some(index) => *index,
+                None => {
+                    debug!(num, "Citation references a number that was never given a link");
+                    i.reset(&start);
+                    return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
+                },
+            },
+        };
 
-        queue_newline_or_advance(&mut o, state, num.width() + 1)?;
+        let text = format!("[^{index}]");
+        queue_newline_or_advance(&mut o, state, text.width())?;
         queue(&mut o, style::SetForegroundColor(URL_TEXT_COLOR))?;
-        queue(&mut o, style::Print(format!("[^{num}]")))?;
+        queue(&mut o, style::Print(text))?;
         queue(&mut o, style::ResetColor)
     }
 }
@@ -428,6 +937,13 @@ fn url<'a, 'b>(
         };
 
         // Only generate output if the complete URL pattern matches
+        if state.hyperlinks {
+            queue_newline_or_advance(&mut o, state, display.width())?;
+            queue(&mut o, style::SetForegroundColor(URL_TEXT_COLOR))?;
+            queue_hyperlink(&mut o, link, display)?;
+            return queue(&mut o, style::ResetColor);
+        }
+
         queue_newline_or_advance(&mut o, state, display.width() + 1)?;
         queue(&mut o, style::SetForegroundColor(URL_TEXT_COLOR))?;
         queue(&mut o, style::Print(format!("{display} ")))?;
@@ -438,6 +954,13 @@ fn url<'a, 'b>(
     }
 }
 
+/// Prints `text` as an OSC 8 hyperlink to `url`.
+fn queue_hyperlink<'a>(o: &mut impl Write, url: &str, text: &str) -> Result<(), ErrMode<Error<'a>>> {
+    // Control characters would end the escape sequence early.
+    let url = url.chars().filter(|c| !c.is_control()).collect::<String>();
+    queue(o, style::Print(format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\")))
+}
+
 fn less_than<'a, 'b>(
     mut o: impl Write + 'b,
     state: &'b mut ParseState,
@@ -490,6 +1013,8 @@ fn line_ending<'a, 'b>(
         ascii::line_ending.parse_next(i)?;
 
         state.column = 0;
+        state.indent = 0;
+        state.quote_depth = 0;
         state.set_newline = true;
 
         queue(&mut o, style::ResetColor)?;
@@ -505,8 +1030,11 @@ fn fallback<'a, 'b>(
     move |i| {
         let fallback = any.parse_next(i)?;
         if let Some(width) = fallback.width() {
-            queue_newline_or_advance(&mut o, state, width)?;
-            if fallback != ' ' || state.column != 1 {
+            let wrapped = queue_newline_or_advance(&mut o, state, width)?;
+            if fallback == ' ' && wrapped {
+                // Don't start the continued line with the space that wrapped it.
+                state.column -= width;
+            } else if fallback != ' ' || state.column != 1 {
                 queue(&mut o, style::Print(fallback))?;
             }
         }
@@ -515,23 +1043,56 @@ fn fallback<'a, 'b>(
     }
 }
 
-fn queue_newline_or_advance<'a, 'b>(
-    mut o: impl Write + 'b,
-    state: &'b mut ParseState,
+/// Characters that continue a word started by [text], so that words like `don't` or paths and
+/// URLs are only ever wrapped as a whole.
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+        || "+,.!?\"'/-:@=%#;$^(){}".contains(c)
+        || (!c.is_ascii() && c.is_alphanumeric() && c.width() == Some(1))
+}
+
+/// Queues `text`, moving it to the next line if it doesn't fit on the current one. Text that is
+/// wider than a whole line is instead broken wherever the line runs out.
+fn queue_wrapped<'a>(mut o: impl Write, state: &mut ParseState, text: &str) -> Result<(), ErrMode<Error<'a>>> {
+    let available = state
+        .terminal_width
+        .map_or(usize::MAX, |width| width.saturating_sub(state.indent));
+    if text.width() <= available {
+        queue_newline_or_advance(&mut o, state, text.width())?;
+        return queue(&mut o, style::Print(text));
+    }
+
+    for c in text.chars() {
+        queue_newline_or_advance(&mut o, state, c.width().unwrap_or(0))?;
+        queue(&mut o, style::Print(c))?;
+    }
+    Ok(())
+}
+
+/// Advances the column by `width`, first starting a new line at [ParseState::indent] if that
+/// would overflow the terminal. Returns whether a new line was started.
+fn queue_newline_or_advance<'a>(
+    mut o: impl Write,
+    state: &mut ParseState,
     width: usize,
-) -> Result<(), ErrMode<Error<'a>>> {
+) -> Result<bool, ErrMode<Error<'a>>> {
     if let Some(terminal_width) = state.terminal_width {
-        if state.column > 0 && state.column + width > terminal_width {
-            state.column = width;
+        if state.column > state.indent && state.column + width > terminal_width {
+            state.column = state.indent + width;
             queue(&mut o, style::Print('\n'))?;
-            return Ok(());
+            let quote = "│ ".repeat(state.quote_depth);
+            let padding = state.indent.saturating_sub(quote.width());
+            if state.indent > 0 {
+                queue(&mut o, style::Print(format!("{quote}{}", " ".repeat(padding))))?;
+            }
+            return Ok(true);
         }
     }
 
     // else
     state.column += width;
 
-    Ok(())
+    Ok(false)
 }
 
 fn queue<'a>(o: &mut impl Write, command: impl Command) -> Result<(), ErrMode<Error<'a>>> {
@@ -555,17 +1116,45 @@ fn codeblock_begin<'a, 'b>(
         ascii::line_ending.parse_next(i)?;
 
         state.in_codeblock = true;
+        state.set_newline = true;
+        state.code_highlighter = match state.syntax_highlighting {
+            true => CodeHighlighter::new(language),
+            false => None,
+        };
 
         if !language.is_empty() {
             queue(&mut o, style::Print(format!("{}\n", language).bold()))?;
         }
 
-        queue(&mut o, style::SetForegroundColor(CODE_COLOR))?;
+        if state.code_highlighter.is_none() {
+            queue(&mut o, style::SetForegroundColor(CODE_COLOR))?;
+        }
 
         Ok(())
     }
 }
 
+/// Replaces the `>` prefix of a line in a code block that was started in a blockquote.
+fn codeblock_blockquote<'a, 'b>(
+    mut o: impl Write + 'b,
+    state: &'b mut ParseState,
+) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
+    move |i| {
+        if !state.newline || state.quote_depth == 0 {
+            return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
+        }
+
+        repeat::<_, _, (), _, _>(1..=state.quote_depth, terminated(">", opt(' '))).parse_next(i)?;
+
+        queue(&mut o, style::SetForegroundColor(BLOCKQUOTE_COLOR))?;
+        queue(&mut o, style::Print("│ ".repeat(state.quote_depth)))?;
+        match state.code_highlighter {
+            Some(_) => queue(&mut o, style::ResetColor),
+            None => queue(&mut o, style::SetForegroundColor(CODE_COLOR)),
+        }
+    }
+}
+
 fn codeblock_end<'a, 'b>(
     mut o: impl Write + 'b,
     state: &'b mut ParseState,
@@ -573,6 +1162,35 @@ fn codeblock_end<'a, 'b>(
     move |i| {
         "```".parse_next(i)?;
         state.in_codeblock = false;
+        state.code_highlighter = None;
+        queue(&mut o, style::ResetColor)
+    }
+}
+
+/// Prints a whole line of a highlighted code block at once, since highlighting needs the full
+/// line. Lines containing a closing fence are left to the other codeblock parsers.
+fn codeblock_highlighted_line<'a, 'b>(
+    mut o: impl Write + 'b,
+    state: &'b mut ParseState,
+) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
+    move |i| {
+        state.awaiting_line_ending = true;
+        let Some(highlighter) = state.code_highlighter.as_mut() else {
+            return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
+        };
+
+        let line = till_line_ending.parse_next(i)?;
+        if line.contains("```") {
+            return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
+        }
+        let line_ending = ascii::line_ending.parse_next(i)?;
+        state.set_newline = true;
+
+        let line = unescape_entities(line);
+        queue(
+            &mut o,
+            style::Print(highlighter.highlight(&format!("{line}{line_ending}"))),
+        )?;
         queue(&mut o, style::ResetColor)
     }
 }
@@ -619,10 +1237,11 @@ fn codeblock_quot<'a, 'b>(
 
 fn codeblock_line_ending<'a, 'b>(
     mut o: impl Write + 'b,
-    _state: &'b mut ParseState,
+    state: &'b mut ParseState,
 ) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
     move |i| {
         ascii::line_ending.parse_next(i)?;
+        state.set_newline = true;
         queue(&mut o, style::Print("\n"))
     }
 }
@@ -641,7 +1260,10 @@ fn codeblock_fallback<'a, 'b>(
 mod tests {
     use std::io::Write;
 
-    use winnow::stream::Offset;
+    use winnow::stream::{
+        Offset,
+        StreamIsPartial,
+    };
 
     use super::*;
 
@@ -688,6 +1310,288 @@ mod tests {
         };
     }
 
+    /// Renders `input` as a complete response, returning the raw terminal output.
+    fn render(input: &str, mut state: ParseState) -> String {
+        let mut output = vec![];
+        let mut markdown = MarkdownStream::new();
+        markdown.push_str(input);
+        while markdown.render_next(&mut output, &mut state, true).unwrap() {}
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Renders `input` as it would be streamed in `chunk_size` byte chunks.
+    fn render_streamed(input: &str, chunk_size: usize, mut state: ParseState) -> String {
+        let mut output = vec![];
+        let mut markdown = MarkdownStream::new();
+        let mut rest = input;
+        while !rest.is_empty() {
+            let mut end = chunk_size.min(rest.len());
+            while !rest.is_char_boundary(end) {
+                end += 1;
+            }
+            markdown.push_str(&rest[..end]);
+            rest = &rest[end..];
+            while markdown.render_next(&mut output, &mut state, rest.is_empty()).unwrap() {}
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Renders `input` with syntax highlighting enabled, returning the raw terminal output.
+    fn render_highlighted(input: &str) -> String {
+        let mut state = ParseState::new(Some(80));
+        state.syntax_highlighting = true;
+        render(&format!("{input}\n"), state)
+    }
+
+    /// Renders `input` with the given terminal width, stripping the bold header styling.
+    fn render_table(input: &str, terminal_width: usize) -> String {
+        let mut bold = String::new();
+        style::SetAttribute(Attribute::Bold).write_ansi(&mut bold).unwrap();
+        let mut normal = String::new();
+        style::SetAttribute(Attribute::NormalIntensity)
+            .write_ansi(&mut normal)
+            .unwrap();
+
+        render(input, ParseState::new(Some(terminal_width)))
+            .replace(&bold, "")
+            .replace(&normal, "")
+    }
+
+    #[test]
+    fn test_table() {
+        let output = render_table(
+            "| Name | Count | Notes |\n|:-----|------:|:-----:|\n| apple | 1 | red &amp; round |\n| kiwi | 10 |\nafter",
+            80,
+        );
+        assert_eq!(
+            output,
+            "┌───────┬───────┬─────────────┐\n\
+             │ Name  │ Count │    Notes    │\n\
+             ├───────┼───────┼─────────────┤\n\
+             │ apple │     1 │ red & round │\n\
+             │ kiwi  │    10 │             │\n\
+             └───────┴───────┴─────────────┘\n\
+             after"
+        );
+    }
+
+    #[test]
+    fn test_table_wraps_to_terminal_width() {
+        let output = render_table(
+            "| Key | Description |\n|---|---|\n| a | the quick brown fox jumps over the lazy dog |\n",
+            30,
+        );
+        assert_eq!(
+            output,
+            "┌─────┬──────────────────────┐\n\
+             │ Key │ Description          │\n\
+             ├─────┼──────────────────────┤\n\
+             │ a   │ the quick brown fox  │\n\
+             │     │ jumps over the lazy  │\n\
+             │     │ dog                  │\n\
+             └─────┴──────────────────────┘\n"
+        );
+        assert!(output.lines().all(|line| line.width() <= 30));
+    }
+
+    #[test]
+    fn test_table_requires_delimiter_row() {
+        let output = render_table("| not | a table |\nplain text\n", 80);
+        assert!(output.starts_with("| not | a table |"));
+        assert!(!output.contains('┌'));
+    }
+
+    #[test]
+    fn test_streamed_matches_complete() {
+        let input = "Some **bold** text and `code`.\n\n```rust\nfn main() {\n    let x = 1;\n}\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\nafter\n";
+        let state = || {
+            let mut state = ParseState::new(Some(80));
+            state.syntax_highlighting = true;
+            state
+        };
+        let complete = render(input, state());
+        for chunk_size in [1, 3, 16] {
+            assert_eq!(render_streamed(input, chunk_size, state()), complete);
+        }
+    }
+
+    /// Streams documents of increasing size in small chunks, checking that the parsing work grows
+    /// in proportion. Re-parsing everything that's pending on each chunk would make the larger
+    /// documents take quadratically more, mostly due to long highlighted lines and big tables.
+    #[test]
+    fn test_streaming_scales_linearly() {
+        let document = |n: usize| {
+            format!(
+                "{}\n\n```rust\nlet s = \"{}\";\n```\n\n| key | value |\n|---|---|\n{}\n",
+                "Some *streamed* text with `code` in it. ".repeat(n),
+                "x".repeat(n * 20),
+                "| k | v |\n".repeat(n)
+            )
+        };
+        // Bytes that had to be looked at again because they couldn't be rendered yet, rather than
+        // the time taken, so that the result doesn't depend on how busy the machine is.
+        let rescanned = |input: &str| {
+            let mut state = ParseState::new(Some(80));
+            state.syntax_highlighting = true;
+            let mut markdown = MarkdownStream::new();
+            for chunk in input.as_bytes().chunks(8) {
+                markdown.push_str(std::str::from_utf8(chunk).unwrap());
+                while markdown.render_next(std::io::sink(), &mut state, false).unwrap() {}
+            }
+            while markdown.render_next(std::io::sink(), &mut state, true).unwrap() {}
+            markdown.rescanned
+        };
+
+        let (small, large) = (rescanned(&document(200)), rescanned(&document(1600)));
+        // The large document is 8 times the size, re-parsing long lines on every chunk would make
+        // it around 64 times the work.
+        assert!(
+            large < small * 12,
+            "rendering 8x the input re-scanned {:.1}x as much ({small} vs {large} bytes)",
+            large as f64 / small as f64
+        );
+    }
+
+    #[test]
+    fn test_split_table_row() {
+        assert_eq!(split_table_row(" a | b \\| c |"), vec!["a", "b | c"]);
+        assert_eq!(split_table_row(" a | b"), vec!["a", "b"]);
+        assert_eq!(wrap_table_cell("abcdefgh", 3), vec!["abc", "def", "gh"]);
+    }
+
+    #[test]
+    fn test_codeblock_syntax_highlighting() {
+        let output = render_highlighted("```rust\nlet x = 1 &lt; 2;\n```");
+        assert!(output.contains("\x1b[38;2;"), "expected 24-bit colors: {output:?}");
+        assert!(output.contains("let"));
+        assert!(output.contains('<'));
+        assert!(!output.contains("```"));
+    }
+
+    #[test]
+    fn test_codeblock_unknown_language_not_highlighted() {
+        let output = render_highlighted("```notalanguage\nlet x = 1;\n```");
+        assert!(!output.contains("\x1b[38;2;"));
+        assert!(output.contains("let x = 1;"));
+    }
+
+    /// Renders `input` at the given terminal width with the styling and trailing spaces removed.
+    fn render_plain(input: &str, terminal_width: usize) -> String {
+        strip_ansi_escapes::strip_str(render(input, ParseState::new(Some(terminal_width))))
+            .split('\n')
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_wrap_at_word_boundaries() {
+        assert_eq!(
+            render_plain("the quick brown fox jumps over the lazy dog\n", 20),
+            "the quick brown fox\njumps over the lazy\ndog\n"
+        );
+        // Paths and URLs aren't split at punctuation. They're moved to the next line if they fit
+        // there, and otherwise broken where the line runs out.
+        assert_eq!(
+            render_plain("see /usr/local/bin now\n", 20),
+            "see /usr/local/bin\nnow\n"
+        );
+        assert_eq!(render_plain("a b c /usr/local/bin\n", 15), "a b c\n/usr/local/bin\n");
+        assert_eq!(
+            render_plain("see https://example.com/a/b/c/d/e/f now\n", 20),
+            "see https://example.\ncom/a/b/c/d/e/f now\n"
+        );
+        // Wrapped list items keep their indentation.
+        assert_eq!(
+            render_plain("- first second third fourth\n", 15),
+            "• first second\n  third fourth\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_after_terminal_width_change() {
+        let input = "the quick brown fox\njumps over the lazy dog\n";
+        let mut state = ParseState::new(Some(80));
+        let mut output = vec![];
+        let mut offset = 0;
+        while offset < input.len() {
+            // The terminal is resized once the first line has been printed.
+            if input[..offset].contains('\n') {
+                state.terminal_width = Some(10);
+            }
+            let mut partial = Partial::new(&input[offset..]);
+            let _ = partial.complete();
+            let parsed = interpret_markdown(partial, &mut output, &mut state).unwrap();
+            offset += parsed.offset_from(&partial);
+            state.newline = state.set_newline;
+            state.set_newline = false;
+        }
+
+        let output = strip_ansi_escapes::strip_str(String::from_utf8(output).unwrap())
+            .split('\n')
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(output, "the quick brown fox\njumps over\nthe lazy\ndog\n");
+    }
+
+    #[test]
+    fn test_wrap_cjk_width() {
+        let output = render_plain("漢字と仮名が混ざった長い文章を折り返す\n", 10);
+        assert_eq!(output, "漢字と仮名\nが混ざった\n長い文章を\n折り返す\n");
+        assert!(output.lines().all(|line| line.width() <= 10));
+    }
+
+    #[test]
+    fn test_wrap_inline_code() {
+        // Spans wider than a line are broken where the line runs out.
+        assert_eq!(
+            render_plain("run `cargo build --release` first\n", 20),
+            "run cargo build --re\nlease first\n"
+        );
+        assert_eq!(render_plain("run `cargo test` first\n", 12), "run\ncargo test\nfirst\n");
+        // Code blocks are never wrapped.
+        assert_eq!(
+            render_plain("```\nlet some_long_identifier = 1;\n```\n", 12),
+            "let some_long_identifier = 1;\n\n"
+        );
+    }
+
+    #[test]
+    fn test_hyperlinks() {
+        let mut state = ParseState::new(Some(80));
+        state.hyperlinks = true;
+        let output = render("see [docs](https://example.com) and [[1]](https://a.b/c)\n", state);
+        assert!(output.contains("\x1b]8;;https://example.com\x1b\\docs\x1b]8;;\x1b\\"));
+        assert!(output.contains("\x1b]8;;https://a.b/c\x1b\\[1]\x1b]8;;\x1b\\"));
+        assert!(!output.contains("[^1]"));
+
+        // Without hyperlinks the URL is shown and citations become footnotes.
+        let output = strip_ansi_escapes::strip_str(render(
+            "[docs](https://example.com) [[1]](https://a.b/c)\n",
+            ParseState::new(Some(80)),
+        ));
+        assert_eq!(output, "docs https://example.com [^1]\n");
+    }
+
+    #[test]
+    fn test_citations_deduplicated() {
+        let mut state = ParseState::new(Some(80));
+        let mut output = vec![];
+        let mut markdown = MarkdownStream::new();
+        markdown.push_str(
+            "a [[1]](https://a.com) b [[2]](https://b.com) c [[3]](https://a.com) d [[2]] e [[9]] f [[1]](https://c.com)\n",
+        );
+        while markdown.render_next(&mut output, &mut state, true).unwrap() {}
+
+        // The same URL keeps its footnote, and markers are numbered by the footnotes printed.
+        assert_eq!(state.citations, vec!["https://a.com", "https://b.com", "https://c.com"]);
+        assert_eq!(
+            strip_ansi_escapes::strip_str(String::from_utf8(output).unwrap()),
+            "a [^1] b [^2] c [^1] d [^2] e [[9]] f [^3]\n"
+        );
+    }
+
     validate!(text_1, "hello world!", [style::Print("hello world!")]);
     validate!(linted_codeblock_1, "```java\nhello world!```", [
         style::SetAttribute(Attribute::Bold),
@@ -759,4 +1663,47 @@ mod tests {
     validate!(square_bracket_url_like_2, "[text](without url part", [style::Print(
         "[text](without url part"
     )]);
+
+    #[test]
+    fn test_nested_lists() {
+        assert_eq!(
+            render_plain("- one\n  - two\n    - three\n  - four\n- five\n", 80),
+            "• one\n  ◦ two\n    ▪ three\n  ◦ four\n• five\n"
+        );
+        // Levels are told apart by how far they're indented, not by how many spaces that is.
+        assert_eq!(
+            render_plain("1. first\n    - nested\n2. second\n", 80),
+            "1. first\n  ◦ nested\n2. second\n"
+        );
+        // A heading ends the list, so the next item starts at the top level again.
+        assert_eq!(
+            render_plain("  - indented\n# Title\n- top\n", 80),
+            "• indented\n# Title\n• top\n"
+        );
+    }
+
+    #[test]
+    fn test_blockquote_nesting() {
+        assert_eq!(
+            render_plain("> quoted\n> > twice\n>> again\nplain\n", 80),
+            "│ quoted\n│ │ twice\n│ │ again\nplain\n"
+        );
+        assert_eq!(
+            render_plain("> - item\n>   - nested\n> 1. number\n", 80),
+            "│ • item\n│   ◦ nested\n│ 1. number\n"
+        );
+        assert_eq!(
+            render_plain("> run this:\n> ```sh\n> ls -la\n> ```\n", 80),
+            "│ run this:\n│ sh\n│ ls -la\n│\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_in_blockquote() {
+        // Wrapped lines keep the quote prefix as well as the indentation of the item.
+        assert_eq!(
+            render_plain("> - a long item that wraps around\n", 20),
+            "│ • a long item that\n│   wraps around\n"
+        );
+    }
 }
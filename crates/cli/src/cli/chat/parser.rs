@@ -20,7 +20,10 @@ use super::message::{
     AssistantToolUse,
 };
 use crate::api_client::clients::SendMessageOutput;
-use crate::api_client::model::ChatResponseStream;
+use crate::api_client::model::{
+    ChatResponseStream,
+    TokenUsage,
+};
 
 #[derive(Debug, Error)]
 pub struct RecvError {
@@ -94,6 +97,8 @@ pub struct ResponseParser {
     /// Whether or not we are currently receiving tool use delta events. Tuple of
     /// `Some((tool_use_id, name))` if true, [None] otherwise.
     parsing_tool_use: Option<(String, String)>,
+    /// Token usage reported by the service, if any.
+    usage: Option<TokenUsage>,
 }
 
 impl ResponseParser {
@@ -107,6 +112,7 @@ impl ResponseParser {
             assistant_text: String::new(),
             tool_uses: Vec::new(),
             parsing_tool_use: None,
+            usage: None,
         }
     }
 
@@ -141,6 +147,9 @@ impl ResponseParser {
                         self.assistant_text.push_str(&content);
                         return Ok(ResponseEvent::AssistantText(content));
                     },
+                    ChatResponseStream::MessageMetadataEvent { usage: Some(usage), .. } => {
+                        self.usage = Some(usage);
+                    },
                     ChatResponseStream::InvalidStateEvent { reason, message } => {
                         error!(%reason, %message, "invalid state event");
                     },
@@ -172,7 +181,10 @@ impl ResponseParser {
                             self.tool_uses.clone().into_iter().collect(),
                         )
                     };
-                    return Ok(ResponseEvent::EndStream { message });
+                    return Ok(ResponseEvent::EndStream {
+                        message,
+                        usage: self.usage.take(),
+                    });
                 },
                 Err(err) => return Err(err),
             }
@@ -306,7 +318,7 @@ impl ResponseParser {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ResponseEvent {
     /// Text returned by the assistant. This should be displayed to the user as it is received.
     AssistantText(String),
@@ -321,6 +333,8 @@ pub enum ResponseEvent {
         /// previously emitted. This should be stored in the conversation history and sent in
         /// subsequent requests.
         message: AssistantMessage,
+        /// Tokens used by the request, if the service reported them.
+        usage: Option<TokenUsage>,
     },
 }
 
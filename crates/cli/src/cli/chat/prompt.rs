@@ -1,6 +1,9 @@
 use std::borrow::Cow;
 
-use crossterm::style::Stylize;
+use crossterm::style::{
+    Color,
+    Stylize,
+};
 use eyre::Result;
 use rustyline::completion::{
     Completer,
@@ -33,6 +36,7 @@ use rustyline::{
     KeyEvent,
     Modifiers,
 };
+use tracing::warn;
 use winnow::stream::AsChar;
 
 use crate::database::Database;
@@ -43,6 +47,8 @@ pub const COMMANDS: &[&str] = &[
     "/help",
     "/editor",
     "/issue",
+    "/feedback",
+    "/replay",
     // "/acceptall", /// Functional, but deprecated in favor of /tools trustall
     "/quit",
     "/tools",
@@ -50,22 +56,37 @@ pub const COMMANDS: &[&str] = &[
     "/tools untrust",
     "/tools trustall",
     "/tools reset",
+    "/mcp",
+    "/prompts",
+    "/prompts help",
+    "/prompts list",
+    "/prompts get",
     "/profile",
     "/profile help",
     "/profile list",
+    "/profile show",
     "/profile create",
     "/profile delete",
     "/profile rename",
+    "/profile copy",
+    "/profile overlay",
     "/profile set",
+    "/context",
     "/context help",
     "/context show",
     "/context show --expand",
+    "/context show --by-size",
     "/context add",
     "/context add --global",
     "/context rm",
     "/context rm --global",
     "/context clear",
     "/context clear --global",
+    "/context validate",
+    "/context diff",
+    "/context prompt",
+    "/context prompt set",
+    "/context prompt clear",
     "/context hooks help",
     "/context hooks add",
     "/context hooks rm",
@@ -76,30 +97,209 @@ pub const COMMANDS: &[&str] = &[
     "/compact",
     "/compact help",
     "/usage",
+    "/whoami",
     "/save",
     "/load",
+    "/debug",
+    "/rewind",
+    "/model",
+    "/export",
+    "/tokens",
+    "/retry",
+    "/copy",
+    "/copy code",
+    "/yank",
+    "/verbose",
+    "/history",
+    "/alias",
+    "/alias --remove",
+    "/templates",
+    "/templates use",
+    "/redo",
+    "/fork",
+    "/branch",
+    "/stats",
+    "/expand",
 ];
 
-pub fn generate_prompt(current_profile: Option<&str>, warning: bool) -> String {
+/// Context window usage, as a percentage, at which the prompt starts showing a usage indicator.
+pub const CONTEXT_USAGE_INDICATOR_PERCENT: usize = 75;
+
+pub fn generate_prompt(
+    current_profile: Option<&str>,
+    model: Option<&str>,
+    context_usage_percent: usize,
+    warning: bool,
+) -> String {
+    styled_default_prompt(current_profile, model, context_usage_percent, warning, Color::Magenta)
+}
+
+fn styled_default_prompt(
+    current_profile: Option<&str>,
+    model: Option<&str>,
+    context_usage_percent: usize,
+    warning: bool,
+    color: Color,
+) -> String {
     let warning_symbol = if warning { "!".red().to_string() } else { "".to_string() };
     let profile_part = current_profile
         .filter(|&p| p != "default")
         .map(|p| format!("[{p}] ").cyan().to_string())
         .unwrap_or_default();
+    let model_part = model
+        .map(|m| format!("({m}) ").dark_grey().to_string())
+        .unwrap_or_default();
+    let usage_part = if context_usage_percent >= CONTEXT_USAGE_INDICATOR_PERCENT {
+        format!("{context_usage_percent}% ").yellow().to_string()
+    } else {
+        String::new()
+    };
+
+    format!(
+        "{profile_part}{model_part}{usage_part}{warning_symbol}{}",
+        "> ".with(color)
+    )
+}
+
+/// Prompt built from the `chat.promptFormat` and `chat.promptColor` settings, e.g.
+/// `{profile}:{turn} ❯ `.
+///
+/// `{profile}`, `{model}`, `{turn}` and `{usage}` are replaced with the current profile, the
+/// selected model, the number of the turn being typed and the context window usage percentage.
+/// `{{` and `}}` print literal braces.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptFormat {
+    segments: Option<Vec<PromptSegment>>,
+    color: Option<Color>,
+}
 
-    format!("{profile_part}{warning_symbol}{}", "> ".magenta())
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PromptSegment {
+    Text(String),
+    Profile,
+    Model,
+    Turn,
+    Usage,
 }
 
-/// Complete commands that start with a slash
-fn complete_command(word: &str, start: usize) -> (usize, Vec<String>) {
-    (
-        start,
-        COMMANDS
+impl PromptFormat {
+    /// Parses the settings, ignoring either of them if it's invalid so that the default prompt is
+    /// used instead.
+    pub fn new(format: Option<&str>, color: Option<&str>) -> Self {
+        let segments = format.and_then(|format| {
+            let segments = parse_prompt_format(format);
+            if segments.is_none() {
+                warn!(?format, "invalid chat.promptFormat, using the default prompt");
+            }
+            segments
+        });
+        let color = color.and_then(|color| {
+            let parsed = Color::try_from(color).ok();
+            if parsed.is_none() {
+                warn!(?color, "invalid chat.promptColor, using the default color");
+            }
+            parsed
+        });
+        Self { segments, color }
+    }
+
+    /// Renders the prompt, falling back to the default layout when no format is set. The `!` shown
+    /// while all tools are trusted is kept in front of a custom prompt.
+    pub fn render(
+        &self,
+        current_profile: Option<&str>,
+        model: Option<&str>,
+        turn: usize,
+        context_usage_percent: usize,
+        warning: bool,
+    ) -> String {
+        let Some(segments) = &self.segments else {
+            return match self.color {
+                Some(color) => styled_default_prompt(current_profile, model, context_usage_percent, warning, color),
+                None => generate_prompt(current_profile, model, context_usage_percent, warning),
+            };
+        };
+
+        let prompt = segments
             .iter()
-            .filter(|p| p.starts_with(word))
-            .map(|s| (*s).to_owned())
-            .collect(),
-    )
+            .map(|segment| match segment {
+                PromptSegment::Text(text) => text.clone(),
+                PromptSegment::Profile => current_profile.unwrap_or("default").to_string(),
+                PromptSegment::Model => model.unwrap_or_default().to_string(),
+                PromptSegment::Turn => turn.to_string(),
+                PromptSegment::Usage => context_usage_percent.to_string(),
+            })
+            .collect::<String>();
+        let warning_symbol = if warning { "!".red().to_string() } else { String::new() };
+        format!("{warning_symbol}{}", prompt.with(self.color.unwrap_or(Color::Magenta)))
+    }
+}
+
+/// Splits a `chat.promptFormat` string into text and placeholders. Returns `None` for unknown
+/// placeholders and unmatched braces.
+fn parse_prompt_format(format: &str) -> Option<Vec<PromptSegment>> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                text.push('{');
+            },
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                text.push('}');
+            },
+            '{' => {
+                let (name, rest) = chars.as_str().split_once('}')?;
+                let segment = match name {
+                    "profile" => PromptSegment::Profile,
+                    "model" => PromptSegment::Model,
+                    "turn" => PromptSegment::Turn,
+                    "usage" => PromptSegment::Usage,
+                    _ => return None,
+                };
+                if !text.is_empty() {
+                    segments.push(PromptSegment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(segment);
+                chars = rest.chars();
+            },
+            '}' => return None,
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(PromptSegment::Text(text));
+    }
+    Some(segments)
+}
+
+/// Commands whose arguments are file paths, which are completed from the filesystem.
+const PATH_COMMANDS: &[&str] = &["/context add", "/context rm", "/save", "/load", "/export", "/yank"];
+
+/// Complete the word starting at `start` of a slash command typed up to the cursor as `typed`,
+/// from the commands and subcommands in [COMMANDS].
+fn complete_command(typed: &str, start: usize) -> (usize, Vec<String>) {
+    let mut completions = Vec::<String>::new();
+    for command in COMMANDS.iter().filter(|command| command.starts_with(typed)) {
+        if let Some(word) = command[start..].split_whitespace().next() {
+            if !completions.iter().any(|completion| completion == word) {
+                completions.push(word.to_owned());
+            }
+        }
+    }
+    (start, completions)
+}
+
+/// Whether the words of a slash command before the cursor, `typed`, are one of [PATH_COMMANDS]
+/// followed by its arguments.
+fn takes_paths(typed: &str) -> bool {
+    let words = typed.split_whitespace().collect::<Vec<_>>();
+    PATH_COMMANDS
+        .iter()
+        .any(|command| words.starts_with(&command.split_whitespace().collect::<Vec<_>>()))
 }
 
 /// A wrapper around FilenameCompleter that provides enhanced path detection
@@ -186,13 +386,17 @@ impl Completer for ChatCompleter {
         &self,
         line: &str,
         pos: usize,
-        _ctx: &Context<'_>,
+        ctx: &Context<'_>,
     ) -> Result<(usize, Vec<Self::Candidate>), ReadlineError> {
-        let (start, word) = extract_word(line, pos, None, |c| c.is_space());
+        let (start, _) = extract_word(line, pos, None, |c| c.is_space());
 
-        // Handle command completion
-        if word.starts_with('/') {
-            return Ok(complete_command(word, start));
+        // Slash commands complete their subcommands, and only take paths where they expect them.
+        if line.starts_with('/') {
+            let (start, completions) = complete_command(&line[..pos], start);
+            if !completions.is_empty() || !takes_paths(&line[..start]) {
+                return Ok((start, completions));
+            }
+            return self.path_completer.complete_path(line, pos, ctx);
         }
 
         if line.starts_with('@') {
@@ -205,7 +409,7 @@ impl Completer for ChatCompleter {
         }
 
         // Handle file path completion as fallback
-        if let Ok((pos, completions)) = self.path_completer.complete_path(line, pos, _ctx) {
+        if let Ok((pos, completions)) = self.path_completer.complete_path(line, pos, ctx) {
             if !completions.is_empty() {
                 return Ok((pos, completions));
             }
@@ -266,6 +470,17 @@ impl Highlighter for ChatHelper {
     }
 }
 
+/// How many prompts are kept in the input history when `chat.historySize` isn't set.
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// How many prompts to keep in the input history, where 0 disables it.
+pub fn history_size(database: &Database) -> usize {
+    database
+        .settings
+        .get_int(Setting::ChatHistorySize)
+        .map_or(DEFAULT_HISTORY_SIZE, |size| size.max(0) as usize)
+}
+
 pub fn rl(
     database: &Database,
     sender: std::sync::mpsc::Sender<Option<String>>,
@@ -277,8 +492,12 @@ pub fn rl(
     };
     let config = Config::builder()
         .history_ignore_space(true)
+        .max_history_size(history_size(database))?
         .completion_type(CompletionType::List)
         .edit_mode(edit_mode)
+        // Pasted text arrives between bracketed paste markers and is inserted as a whole, so
+        // newlines in a multi-line paste don't submit it. Typing Enter still does.
+        .bracketed_paste(true)
         .build();
     let h = ChatHelper {
         completer: ChatCompleter::new(sender, receiver),
@@ -310,21 +529,69 @@ mod tests {
     #[test]
     fn test_generate_prompt() {
         // Test default prompt (no profile)
-        assert_eq!(generate_prompt(None, false), "> ".magenta().to_string());
+        assert_eq!(generate_prompt(None, None, 0, false), "> ".magenta().to_string());
         // Test default prompt with warning
-        assert_eq!(generate_prompt(None, true), format!("{}{}", "!".red(), "> ".magenta()));
+        assert_eq!(
+            generate_prompt(None, None, 0, true),
+            format!("{}{}", "!".red(), "> ".magenta())
+        );
         // Test default profile (should be same as no profile)
-        assert_eq!(generate_prompt(Some("default"), false), "> ".magenta().to_string());
+        assert_eq!(
+            generate_prompt(Some("default"), None, 0, false),
+            "> ".magenta().to_string()
+        );
         // Test custom profile
         assert_eq!(
-            generate_prompt(Some("test-profile"), false),
+            generate_prompt(Some("test-profile"), None, 0, false),
             format!("{}{}", "[test-profile] ".cyan(), "> ".magenta())
         );
         // Test another custom profile with warning
         assert_eq!(
-            generate_prompt(Some("dev"), true),
+            generate_prompt(Some("dev"), None, 0, true),
             format!("{}{}{}", "[dev] ".cyan(), "!".red(), "> ".magenta())
         );
+        // Test selected model
+        assert_eq!(
+            generate_prompt(Some("dev"), Some("claude-3.7-sonnet"), 0, false),
+            format!(
+                "{}{}{}",
+                "[dev] ".cyan(),
+                "(claude-3.7-sonnet) ".dark_grey(),
+                "> ".magenta()
+            )
+        );
+    }
+
+    #[test]
+    fn test_prompt_format() {
+        let format = PromptFormat::new(Some("{profile}@{model} #{turn} {{{usage}%}} ❯ "), Some("blue"));
+        assert_eq!(
+            format.render(Some("dev"), Some("claude-3.7-sonnet"), 3, 42, false),
+            "dev@claude-3.7-sonnet #3 {42%} ❯ ".blue().to_string()
+        );
+        assert_eq!(
+            format.render(None, None, 1, 0, true),
+            format!("{}{}", "!".red(), "default@ #1 {0%} ❯ ".blue())
+        );
+
+        // Only the color is overridden.
+        assert_eq!(
+            PromptFormat::new(None, Some("green")).render(Some("dev"), None, 1, 0, false),
+            format!("{}{}", "[dev] ".cyan(), "> ".green())
+        );
+
+        // Invalid settings fall back to the default prompt.
+        for (format, color) in [
+            (Some("{unknown} > "), None),
+            (Some("{profile > "), None),
+            (Some("profile} > "), None),
+            (None, Some("not-a-color")),
+        ] {
+            assert_eq!(
+                PromptFormat::new(format, color).render(Some("dev"), None, 1, 0, false),
+                generate_prompt(Some("dev"), None, 0, false)
+            );
+        }
     }
 
     #[test]
@@ -349,6 +616,54 @@ mod tests {
         assert!(completions.contains(&"/help".to_string()));
     }
 
+    #[test]
+    fn test_chat_completer_subcommand_completion() {
+        let (prompt_request_sender, _) = std::sync::mpsc::channel::<Option<String>>();
+        let (_, prompt_response_receiver) = std::sync::mpsc::channel::<Vec<String>>();
+        let completer = ChatCompleter::new(prompt_request_sender, prompt_response_receiver);
+        let empty_history = DefaultHistory::new();
+        let ctx = Context::new(&empty_history);
+
+        // Each command is offered once even though it has several subcommands.
+        assert_eq!(
+            completer.complete("/prof", 5, &ctx).unwrap(),
+            (0, vec!["/profile".to_string()])
+        );
+        assert_eq!(
+            completer.complete("/context sh", 11, &ctx).unwrap(),
+            (9, vec!["show".to_string()])
+        );
+        assert_eq!(
+            completer.complete("/context show --b", 17, &ctx).unwrap(),
+            (14, vec!["--by-size".to_string()])
+        );
+        // Arguments of commands that don't take paths aren't completed.
+        assert_eq!(completer.complete("/profile set /", 14, &ctx).unwrap(), (13, vec![]));
+    }
+
+    #[test]
+    fn test_chat_completer_path_completion() {
+        let (prompt_request_sender, _) = std::sync::mpsc::channel::<Option<String>>();
+        let (_, prompt_response_receiver) = std::sync::mpsc::channel::<Vec<String>>();
+        let completer = ChatCompleter::new(prompt_request_sender, prompt_response_receiver);
+        let empty_history = DefaultHistory::new();
+        let ctx = Context::new(&empty_history);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.md"), "").unwrap();
+        let dir = format!("{}/", dir.path().display());
+
+        for command in ["/context add ", "/context add --global ", "/save "] {
+            let line = format!("{command}{dir}no");
+            let (start, completions) = completer.complete(&line, line.len(), &ctx).unwrap();
+            assert_eq!(start, command.len(), "{line}");
+            assert_eq!(completions, vec![format!("{dir}notes.md")], "{line}");
+        }
+        assert!(takes_paths("/yank "));
+        assert!(!takes_paths("/context "));
+        assert!(!takes_paths("/model "));
+    }
+
     #[test]
     fn test_chat_completer_no_completion() {
         let (prompt_request_sender, _) = std::sync::mpsc::channel::<Option<String>>();
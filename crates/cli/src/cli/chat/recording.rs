@@ -0,0 +1,160 @@
+//! The model's responses saved by `q chat --record` and played back by `q chat --replay`.
+//!
+//! A recording is a JSON array with an array of events for each response, in the order the
+//! requests were sent. A string is assistant text and an object
+//! `{"tool_use_id":"...","name":"...","args":{...}}` is a tool use. This is the format the chat
+//! tests build their mock responses from, see [super::create_stream].
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use serde_json::Value;
+use tracing::warn;
+
+use super::parser::ResponseEvent;
+use crate::api_client::StreamingClient;
+
+#[derive(Debug)]
+pub struct Recording {
+    path: PathBuf,
+    responses: Vec<Value>,
+    /// Events of the response being received.
+    current: Vec<Value>,
+}
+
+impl Recording {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            responses: Vec::new(),
+            current: Vec::new(),
+        }
+    }
+
+    /// Drops the events of a response that never ended, e.g. because it was interrupted.
+    pub fn begin_response(&mut self) {
+        self.current.clear();
+    }
+
+    /// Adds `event` to the response being received. The recording is written out once the
+    /// response ends, so nothing but collecting the events happens while it streams.
+    pub fn record_event(&mut self, event: &ResponseEvent) {
+        match event {
+            ResponseEvent::AssistantText(text) => self.current.push(Value::String(text.clone())),
+            ResponseEvent::ToolUse(tool_use) => self.current.push(serde_json::json!({
+                "tool_use_id": tool_use.id,
+                "name": tool_use.name,
+                "args": tool_use.args,
+            })),
+            ResponseEvent::ToolUseStart { .. } => {},
+            ResponseEvent::EndStream { .. } => self.finish_response(),
+        }
+    }
+
+    /// Ends the response being received, even if the stream hasn't, and saves the recording.
+    /// Failures are only logged, the recording must never interrupt the session.
+    pub fn finish_response(&mut self) {
+        self.responses.push(Value::Array(std::mem::take(&mut self.current)));
+        let contents = Value::Array(self.responses.clone()).to_string();
+        if let Err(err) = std::fs::write(&self.path, contents) {
+            warn!(?err, path = ?self.path, "Failed to write the recording");
+        }
+    }
+}
+
+/// Returns a client that answers with the responses recorded at `path`, in order.
+pub fn load_replay(path: &Path) -> Result<StreamingClient> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|err| eyre!("Failed to read the recording {}: {err}", path.display()))?;
+    let responses =
+        serde_json::from_str::<Value>(&contents).map_err(|err| eyre!("Invalid recording {}: {err}", path.display()))?;
+    validate(&responses).map_err(|err| eyre!("Invalid recording {}: {err}", path.display()))?;
+    Ok(super::create_stream(responses))
+}
+
+/// Checks `responses` has the shape [super::create_stream] expects, which panics otherwise.
+fn validate(responses: &Value) -> Result<()> {
+    let Some(responses) = responses.as_array() else {
+        bail!("expected an array of responses");
+    };
+    for (i, response) in responses.iter().enumerate() {
+        let Some(events) = response.as_array() else {
+            bail!("response {} isn't an array of events", i + 1);
+        };
+        for event in events {
+            let is_tool_use = |event: &Value| {
+                event.get("tool_use_id").is_some_and(Value::is_string)
+                    && event.get("name").is_some_and(Value::is_string)
+                    && event.get("args").is_some()
+            };
+            if !event.is_string() && !is_tool_use(event) {
+                bail!(
+                    "response {} has an event that is neither text nor a tool use: {event}",
+                    i + 1
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::chat::message::{
+        AssistantMessage,
+        AssistantToolUse,
+    };
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let mut recording = Recording::new(path.clone());
+
+        recording.begin_response();
+        recording.record_event(&ResponseEvent::AssistantText("Hello".to_string()));
+        recording.begin_response();
+        recording.record_event(&ResponseEvent::AssistantText("Listing ".to_string()));
+        recording.record_event(&ResponseEvent::AssistantText("files".to_string()));
+        recording.record_event(&ResponseEvent::ToolUseStart {
+            name: "execute_bash".to_string(),
+        });
+        recording.record_event(&ResponseEvent::ToolUse(AssistantToolUse {
+            id: "1".to_string(),
+            name: "execute_bash".to_string(),
+            args: serde_json::json!({ "command": "ls" }),
+            ..Default::default()
+        }));
+        recording.record_event(&ResponseEvent::EndStream {
+            message: AssistantMessage::new_response(None, "Listing files".to_string()),
+            usage: None,
+        });
+        recording.begin_response();
+        recording.record_event(&ResponseEvent::AssistantText("Cut".to_string()));
+        recording.finish_response();
+
+        let recorded = serde_json::from_str::<Value>(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            recorded,
+            serde_json::json!([
+                ["Listing ", "files", { "tool_use_id": "1", "name": "execute_bash", "args": { "command": "ls" } }],
+                ["Cut"],
+            ])
+        );
+        assert!(load_replay(&path).is_ok());
+
+        std::fs::write(&path, r#"[["text", {"name": "execute_bash"}]]"#).unwrap();
+        assert!(load_replay(&path).is_err());
+        std::fs::write(&path, r#"{"responses": []}"#).unwrap();
+        assert!(load_replay(&path).is_err());
+        assert!(load_replay(&dir.path().join("missing.json")).is_err());
+    }
+}
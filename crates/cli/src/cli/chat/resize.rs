@@ -0,0 +1,59 @@
+//! Noticing terminal resizes while a response streams, so that the text printed afterwards wraps
+//! at the new width.
+
+#[cfg(unix)]
+use futures::FutureExt;
+
+/// Listens for `SIGWINCH` on Unix. Elsewhere the terminal is never reported as resized.
+pub struct ResizeListener {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl ResizeListener {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change())
+                .map_err(|err| tracing::warn!(?err, "failed to listen for terminal resizes"))
+                .ok(),
+        }
+    }
+
+    /// Returns whether the terminal was resized since the last call, without waiting.
+    pub fn resized(&mut self) -> bool {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            let mut resized = false;
+            while let Some(Some(())) = signal.recv().now_or_never() {
+                resized = true;
+            }
+            return resized;
+        }
+        false
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resized() {
+        let mut listener = ResizeListener::new();
+        assert!(!listener.resized());
+
+        nix::sys::signal::raise(nix::sys::signal::Signal::SIGWINCH).unwrap();
+        // The signal is delivered to the listener by the runtime.
+        let mut resized = false;
+        for _ in 0..50 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            if listener.resized() {
+                resized = true;
+                break;
+            }
+        }
+        assert!(resized);
+        assert!(!listener.resized());
+    }
+}
@@ -0,0 +1,213 @@
+//! Driving a chat from other code, without `q chat`'s terminal handling.
+//!
+//! A [ChatSession] runs the same loop as `q chat`, but reads prompts (and answers to tool
+//! confirmations) from a list given up front and writes the rendered output to any [Write]. The
+//! session ends once the input runs out.
+//!
+//! ```ignore
+//! let mut session = ChatSession::builder(client)
+//!     .input(["explain this error", "y"])
+//!     .output(std::io::sink())
+//!     .build(&mut database)
+//!     .await?;
+//! let mut events = session.subscribe();
+//! session.run(&mut database, &telemetry).await?;
+//! while let Ok(event) = events.try_recv() {
+//!     println!("{event:?}");
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use eyre::Result;
+use rand::distr::{
+    Alphanumeric,
+    SampleString,
+};
+use tokio::sync::mpsc;
+
+use super::ChatContext;
+use super::input_source::InputSource;
+pub use super::parser::ResponseEvent;
+use super::tool_manager::ToolManager;
+use super::tools::{
+    ToolPermissions,
+    ToolSpec,
+};
+use super::util::shared_writer::SharedWriter;
+use crate::api_client::StreamingClient;
+use crate::database::Database;
+use crate::platform::Context;
+use crate::telemetry::TelemetryThread;
+
+pub struct ChatSessionBuilder {
+    client: StreamingClient,
+    ctx: Option<Arc<Context>>,
+    input: Vec<String>,
+    output: Option<SharedWriter>,
+    trust_all_tools: bool,
+}
+
+impl ChatSessionBuilder {
+    /// The lines entered at the prompt, in order. These also answer tool confirmations, e.g. `y`.
+    pub fn input<I, S>(mut self, lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.input = lines.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Where the rendered responses and tool output are written. Defaults to discarding them.
+    pub fn output<W>(mut self, output: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.output = Some(SharedWriter::new(output));
+        self
+    }
+
+    /// The context tools run in. Defaults to the real filesystem and environment.
+    pub fn context(mut self, ctx: Arc<Context>) -> Self {
+        self.ctx = Some(ctx);
+        self
+    }
+
+    /// Runs tools without asking for confirmation.
+    pub fn trust_all_tools(mut self, trust_all_tools: bool) -> Self {
+        self.trust_all_tools = trust_all_tools;
+        self
+    }
+
+    pub async fn build(self, database: &mut Database) -> Result<ChatSession> {
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))?;
+        let mut tool_permissions = ToolPermissions::new(tool_config.len());
+        tool_permissions.trust_all = self.trust_all_tools;
+
+        let mut chat = ChatContext::new(
+            self.ctx.unwrap_or_else(Context::new),
+            database,
+            &Alphanumeric.sample_string(&mut rand::rng(), 9),
+            self.output.unwrap_or_else(|| SharedWriter::new(std::io::sink())),
+            None,
+            InputSource::from_lines(self.input),
+            true,
+            false,
+            self.client,
+            || None,
+            ToolManager::default(),
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await?;
+        // Nothing is drawn that only makes sense in a terminal, like spinners and pagers.
+        chat.quiet = true;
+        chat.terminal = false;
+        chat.pager = None;
+
+        Ok(ChatSession { chat })
+    }
+}
+
+pub struct ChatSession {
+    chat: ChatContext,
+}
+
+impl ChatSession {
+    pub fn builder(client: StreamingClient) -> ChatSessionBuilder {
+        ChatSessionBuilder {
+            client,
+            ctx: None,
+            input: Vec::new(),
+            output: None,
+            trust_all_tools: false,
+        }
+    }
+
+    /// Returns a receiver for the events of every response, as they're streamed. Only the latest
+    /// receiver gets events.
+    pub fn subscribe(&mut self) -> mpsc::UnboundedReceiver<ResponseEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.chat.response_events = Some(sender);
+        receiver
+    }
+
+    /// Runs the chat until the input runs out or `/quit` is entered.
+    pub async fn run(&mut self, database: &mut Database, telemetry: &TelemetryThread) -> Result<()> {
+        self.chat.try_chat(database, telemetry).await
+    }
+
+    /// The prompts and responses so far, in the same form as the transcript shown by `/issue`.
+    pub fn transcript(&self) -> Vec<String> {
+        self.chat.conversation_state.transcript.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::chat::create_stream;
+    use crate::cli::chat::util::shared_writer::TestWriterWithSink;
+    use crate::platform::Env;
+
+    #[tokio::test]
+    async fn test_chat_session() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&Env::new(), &mut database).await.unwrap();
+        let client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+
+        let mut session = ChatSession::builder(client)
+            .context(Arc::clone(&ctx))
+            .input(["create a file", "y"])
+            .output(output.clone())
+            .build(&mut database)
+            .await
+            .unwrap();
+        let mut events = session.subscribe();
+        session.run(&mut database, &telemetry).await.unwrap();
+
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        let output = String::from_utf8(output.get_content()).unwrap();
+        assert!(output.contains("Done"));
+
+        let mut text = String::new();
+        let mut tool_uses = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            match event {
+                ResponseEvent::AssistantText(chunk) => text.push_str(&chunk),
+                ResponseEvent::ToolUse(tool_use) => tool_uses.push(tool_use.name),
+                _ => (),
+            }
+        }
+        assert_eq!(text, "Sure, I'll create a file for youDone");
+        assert_eq!(tool_uses, vec!["fs_write".to_string()]);
+
+        let transcript = session.transcript();
+        assert_eq!(transcript.first().map(String::as_str), Some("> create a file"));
+        assert!(transcript.last().unwrap().contains("Done"));
+    }
+}
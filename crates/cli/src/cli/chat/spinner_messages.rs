@@ -0,0 +1,121 @@
+//! Messages shown next to the spinner while waiting on the backend.
+//!
+//! Each message can be overridden with its own setting, e.g. `q settings chat.spinner.thinking
+//! "Pondering..."`. Setting `chat.spinner.enabled` to `false` replaces the animated spinner with a
+//! single static line, which works better with screen readers.
+
+use crate::database::settings::{
+    Setting,
+    Settings,
+};
+
+/// Printed instead of the spinner when `chat.spinner.enabled` is `false`.
+pub const STATIC_MESSAGE: &str = "Working…";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpinnerMessage {
+    /// Waiting on a response from the model.
+    Thinking,
+    /// Waiting on the summary requested by `/compact`.
+    Summarizing,
+    /// Retrying after the response stream timed out.
+    DividingWork,
+    /// Retrying after a tool use was too large to be received.
+    RetryingWork,
+}
+
+impl SpinnerMessage {
+    fn setting(&self) -> Setting {
+        match self {
+            Self::Thinking => Setting::ChatSpinnerThinking,
+            Self::Summarizing => Setting::ChatSpinnerSummarizing,
+            Self::DividingWork => Setting::ChatSpinnerDividingWork,
+            Self::RetryingWork => Setting::ChatSpinnerRetryingWork,
+        }
+    }
+
+    fn default_text(&self) -> &'static str {
+        match self {
+            Self::Thinking => "Thinking...",
+            Self::Summarizing => "Creating summary...",
+            Self::DividingWork => "Dividing up the work...",
+            Self::RetryingWork => "Trying to divide up the work...",
+        }
+    }
+
+    /// Returns the message to display, preferring a non-empty override from `settings`.
+    pub fn text(&self, settings: &Settings) -> String {
+        settings
+            .get_string(self.setting())
+            .filter(|text| !text.trim().is_empty())
+            .unwrap_or_else(|| self.default_text().to_string())
+    }
+}
+
+/// The spinner settings, read once when the chat starts.
+#[derive(Debug, Clone)]
+pub struct SpinnerSettings {
+    /// Whether an animated spinner is shown, see [spinner_enabled].
+    pub enabled: bool,
+    thinking: String,
+    summarizing: String,
+    dividing_work: String,
+    retrying_work: String,
+}
+
+impl SpinnerSettings {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            enabled: spinner_enabled(settings),
+            thinking: SpinnerMessage::Thinking.text(settings),
+            summarizing: SpinnerMessage::Summarizing.text(settings),
+            dividing_work: SpinnerMessage::DividingWork.text(settings),
+            retrying_work: SpinnerMessage::RetryingWork.text(settings),
+        }
+    }
+
+    /// The text shown next to the spinner for `message`.
+    pub fn text(&self, message: SpinnerMessage) -> &str {
+        match message {
+            SpinnerMessage::Thinking => &self.thinking,
+            SpinnerMessage::Summarizing => &self.summarizing,
+            SpinnerMessage::DividingWork => &self.dividing_work,
+            SpinnerMessage::RetryingWork => &self.retrying_work,
+        }
+    }
+}
+
+/// Whether an animated spinner should be shown, see `chat.spinner.enabled`.
+pub fn spinner_enabled(settings: &Settings) -> bool {
+    settings.get_bool(Setting::ChatSpinnerEnabled).unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spinner_message_text() {
+        let mut settings = Settings::default();
+        assert_eq!(SpinnerMessage::Thinking.text(&settings), "Thinking...");
+        assert!(spinner_enabled(&settings));
+
+        settings
+            .set(Setting::ChatSpinnerThinking, "Pondering...")
+            .await
+            .unwrap();
+        settings.set(Setting::ChatSpinnerSummarizing, " ").await.unwrap();
+        settings.set(Setting::ChatSpinnerEnabled, false).await.unwrap();
+        assert_eq!(SpinnerMessage::Thinking.text(&settings), "Pondering...");
+        assert_eq!(SpinnerMessage::Summarizing.text(&settings), "Creating summary...");
+        assert!(!spinner_enabled(&settings));
+
+        let spinner_settings = SpinnerSettings::new(&settings);
+        assert!(!spinner_settings.enabled);
+        assert_eq!(spinner_settings.text(SpinnerMessage::Thinking), "Pondering...");
+        assert_eq!(
+            spinner_settings.text(SpinnerMessage::Summarizing),
+            "Creating summary..."
+        );
+    }
+}
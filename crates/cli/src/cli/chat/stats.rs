@@ -0,0 +1,219 @@
+//! Metrics accumulated over the session for `/stats`.
+
+use std::collections::BTreeMap;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use crossterm::style::Stylize;
+
+use crate::api_client::model::TokenUsage;
+use crate::telemetry::core::ToolUseEventBuilder;
+
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    started: Instant,
+    /// Prompts sent by the user, not counting tool results.
+    user_messages: usize,
+    /// Responses received in full from the model.
+    assistant_messages: usize,
+    /// Tool uses requested by the model.
+    tools_suggested: usize,
+    /// Tool uses that were requested but never run, e.g. because the user declined them.
+    tools_declined: usize,
+    tools: BTreeMap<String, ToolStats>,
+    /// Tokens used by every request, some of which may be estimates.
+    usage: TokenUsage,
+    usage_estimated: bool,
+    /// The region requests are sent to.
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ToolStats {
+    invocations: usize,
+    failures: usize,
+    total_time: Duration,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            user_messages: 0,
+            assistant_messages: 0,
+            tools_suggested: 0,
+            tools_declined: 0,
+            tools: BTreeMap::new(),
+            usage: TokenUsage::default(),
+            usage_estimated: false,
+            region: None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn record_user_message(&mut self) {
+        self.user_messages += 1;
+    }
+
+    pub fn record_assistant_message(&mut self) {
+        self.assistant_messages += 1;
+    }
+
+    /// Adds the tokens used by a request, which are `estimated` when the service didn't report
+    /// them.
+    pub fn record_usage(&mut self, usage: TokenUsage, estimated: bool) {
+        self.usage.input_tokens += usage.input_tokens;
+        self.usage.output_tokens += usage.output_tokens;
+        self.usage_estimated |= estimated;
+    }
+
+    /// Records the outcome of a tool use from its telemetry event, once it's done with.
+    pub fn record_tool_use_event(&mut self, event: &ToolUseEventBuilder) {
+        self.tools_suggested += 1;
+        if !event.is_accepted {
+            self.tools_declined += 1;
+        }
+    }
+
+    pub fn record_tool_execution(&mut self, name: &str, time: Duration, success: bool) {
+        let stats = self.tools.entry(name.to_string()).or_default();
+        stats.invocations += 1;
+        stats.total_time += time;
+        if !success {
+            stats.failures += 1;
+        }
+    }
+
+    /// Renders the summary as styled lines, with a line per tool that was run.
+    pub fn lines(&self, elapsed: Duration) -> Vec<String> {
+        let invocations = self.tools.values().map(|t| t.invocations).sum::<usize>();
+        let failures = self.tools.values().map(|t| t.failures).sum::<usize>();
+        let mut lines = vec![
+            format!("{}{}", label("Session time"), format_duration(elapsed)),
+            format!(
+                "{}{} sent, {} received",
+                label("Messages"),
+                self.user_messages,
+                self.assistant_messages
+            ),
+            format!("{}{}", label("Tokens"), format_usage(self.usage, self.usage_estimated)),
+            format!(
+                "{}{} requested, {} declined",
+                label("Tool uses"),
+                self.tools_suggested,
+                self.tools_declined
+            ),
+            format!(
+                "{}{} run, {} succeeded, {} failed",
+                label("Tool runs"),
+                invocations,
+                invocations - failures,
+                failures
+            ),
+        ];
+        if let Some(region) = &self.region {
+            lines.insert(1, format!("{}{}", label("Region"), region));
+        }
+
+        let name_width = self.tools.keys().map(|name| name.len()).max().unwrap_or_default();
+        for (name, stats) in &self.tools {
+            let average = stats.total_time / stats.invocations as u32;
+            let mut line = format!(
+                "  {name:<name_width$}  {} run{}, avg {:.2}s",
+                stats.invocations,
+                if stats.invocations == 1 { "" } else { "s" },
+                average.as_secs_f64()
+            );
+            if stats.failures > 0 {
+                line.push_str(&format!(", {} failed", stats.failures).red().to_string());
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+fn label(text: &str) -> String {
+    format!("{text:<16}").bold().to_string()
+}
+
+/// Formats token counts like `1200 in, 80 out`, marking estimates with `~`.
+pub fn format_usage(usage: TokenUsage, estimated: bool) -> String {
+    let approx = if estimated { "~" } else { "" };
+    format!("{approx}{} in, {approx}{} out", usage.input_tokens, usage.output_tokens)
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s}s"),
+        (h, m, s) => format!("{h}h {m}m {s}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_stats_lines() {
+        let mut stats = SessionStats::new();
+        stats.region = Some("eu-central-1".to_string());
+        stats.record_user_message();
+        stats.record_user_message();
+        stats.record_assistant_message();
+        stats.record_usage(
+            TokenUsage {
+                input_tokens: 1200,
+                output_tokens: 80,
+            },
+            false,
+        );
+        stats.record_tool_execution("fs_read", Duration::from_millis(100), true);
+        stats.record_tool_execution("fs_read", Duration::from_millis(300), true);
+        stats.record_tool_execution("execute_bash", Duration::from_secs(2), false);
+        let mut event = ToolUseEventBuilder::new("conversation".to_string(), "tooluse".to_string());
+        event.is_accepted = true;
+        stats.record_tool_use_event(&event);
+        event.is_accepted = false;
+        stats.record_tool_use_event(&event);
+
+        let lines = stats
+            .lines(Duration::from_secs(3725))
+            .into_iter()
+            .map(strip_ansi_escapes::strip_str)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![
+            "Session time    1h 2m 5s",
+            "Region          eu-central-1",
+            "Messages        2 sent, 1 received",
+            "Tokens          1200 in, 80 out",
+            "Tool uses       2 requested, 1 declined",
+            "Tool runs       3 run, 2 succeeded, 1 failed",
+            "  execute_bash  1 run, avg 2.00s, 1 failed",
+            "  fs_read       2 runs, avg 0.20s",
+        ]);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(61)), "1m 1s");
+    }
+
+    #[test]
+    fn test_format_usage() {
+        let usage = TokenUsage {
+            input_tokens: 500,
+            output_tokens: 20,
+        };
+        assert_eq!(format_usage(usage, false), "500 in, 20 out");
+        assert_eq!(format_usage(usage, true), "~500 in, ~20 out");
+    }
+}
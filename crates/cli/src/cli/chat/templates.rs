@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use eyre::{
+    Result,
+    eyre,
+};
+use serde::Deserialize;
+
+use crate::platform::Context;
+use crate::util::directories;
+
+/// Starter prompts that come with the CLI, as `(name, description, prompt)`.
+const BUNDLED_TEMPLATES: [(&str, &str, &str); 6] = [
+    (
+        "explain",
+        "Explain how a piece of code works",
+        "Explain how {{file or function}} works, step by step.",
+    ),
+    (
+        "review",
+        "Review a file for bugs and style issues",
+        "Review {{file}} for bugs, unhandled edge cases, and style issues, and suggest concrete fixes.",
+    ),
+    (
+        "test",
+        "Write unit tests for some code",
+        "Write unit tests for {{file or function}} that cover its edge cases.",
+    ),
+    (
+        "debug",
+        "Find the cause of an error",
+        "I get this error when running {{command}}:\n\n{{error}}\n\nWhat's causing it and how do I fix it?",
+    ),
+    (
+        "commit",
+        "Write a commit message for the staged changes",
+        "Look at `git diff --staged` and write a concise commit message for the changes.",
+    ),
+    (
+        "aws",
+        "Summarize resources in an AWS account",
+        "List my {{resource type}} in {{region}} and summarize how they're configured.",
+    ),
+];
+
+/// A named starter prompt listed by `/templates`. Parts of the prompt the user is meant to fill in
+/// are written as `{{placeholder}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub name: String,
+    pub description: String,
+    pub prompt: String,
+    /// Whether the template comes from [directories::chat_templates_path] rather than the CLI.
+    pub user_defined: bool,
+}
+
+/// An entry of [directories::chat_templates_path], keyed by the template's name.
+#[derive(Debug, Deserialize)]
+struct TemplateEntry {
+    prompt: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// Returns the bundled templates along with the user's, sorted by name. User templates replace
+/// bundled ones of the same name.
+pub async fn load_templates(ctx: &Context) -> Result<Vec<Template>> {
+    let mut templates = BUNDLED_TEMPLATES
+        .iter()
+        .map(|(name, description, prompt)| {
+            ((*name).to_string(), Template {
+                name: (*name).to_string(),
+                description: (*description).to_string(),
+                prompt: (*prompt).to_string(),
+                user_defined: false,
+            })
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let path = directories::chat_templates_path(ctx)?;
+    if ctx.fs().exists(&path) {
+        let contents = ctx.fs().read_to_string(&path).await?;
+        let entries = serde_json::from_str::<BTreeMap<String, TemplateEntry>>(&contents)
+            .map_err(|err| eyre!("invalid templates in {}: {err}", path.display()))?;
+        for (name, entry) in entries {
+            templates.insert(name.clone(), Template {
+                name,
+                description: entry.description,
+                prompt: entry.prompt,
+                user_defined: true,
+            });
+        }
+    }
+
+    Ok(templates.into_values().collect())
+}
+
+/// Returns the names of the `{{placeholder}}`s left in `prompt`, in the order they first appear.
+pub fn placeholders(prompt: &str) -> Vec<&str> {
+    let mut placeholders = Vec::new();
+    let mut rest = prompt;
+    while let Some((_, after)) = rest.split_once("{{") {
+        let Some((name, after)) = after.split_once("}}") else {
+            break;
+        };
+        let name = name.trim();
+        if !name.is_empty() && !placeholders.contains(&name) {
+            placeholders.push(name);
+        }
+        rest = after;
+    }
+    placeholders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(placeholders("Explain {{file}} and {{ function }} in {{file}}"), vec![
+            "file", "function"
+        ]);
+        assert!(placeholders("Explain main.rs").is_empty());
+        assert!(placeholders("Explain {{}} and {{unclosed").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_templates() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let bundled = load_templates(&ctx).await.unwrap();
+        assert_eq!(bundled.len(), BUNDLED_TEMPLATES.len());
+        assert!(bundled.windows(2).all(|pair| pair[0].name < pair[1].name));
+
+        let path = directories::chat_templates_path(&ctx).unwrap();
+        ctx.fs().create_dir_all(path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(
+                &path,
+                serde_json::json!({
+                    "review": { "prompt": "Review {{file}} like a pedant" },
+                    "standup": { "prompt": "Summarize what changed since {{day}}", "description": "Standup notes" },
+                })
+                .to_string(),
+            )
+            .await
+            .unwrap();
+        let templates = load_templates(&ctx).await.unwrap();
+        assert_eq!(templates.len(), BUNDLED_TEMPLATES.len() + 1);
+        let review = templates.iter().find(|t| t.name == "review").unwrap();
+        assert_eq!(review.prompt, "Review {{file}} like a pedant");
+        assert!(review.user_defined);
+        assert!(
+            templates
+                .iter()
+                .any(|t| t.name == "standup" && t.description == "Standup notes")
+        );
+
+        ctx.fs().write(&path, "[]").await.unwrap();
+        assert!(load_templates(&ctx).await.is_err());
+    }
+}
@@ -11,6 +11,7 @@ use super::message::{
     UserMessage,
     UserMessageContent,
 };
+use crate::api_client::model::IMAGE_RESULT_TEXT;
 
 #[derive(Debug, Clone, Copy)]
 pub struct CharCount(usize);
@@ -161,6 +162,7 @@ impl CharCounter for &[ToolUseResult] {
                 acc + match v {
                     ToolUseResultBlock::Json(v) => calculate_value_char_count(v),
                     ToolUseResultBlock::Text(s) => s.len(),
+                    ToolUseResultBlock::Image(_) => IMAGE_RESULT_TEXT.len(),
                 }
             })
             .into()
@@ -0,0 +1,111 @@
+//! Noticing when the model keeps retrying a tool use that fails the same way every time, so that
+//! the user can step in instead of the retries going on indefinitely.
+
+use std::collections::HashMap;
+
+/// How many times in a row a tool use can fail identically before the user is asked how to
+/// proceed, when `chat.maxRepeatedToolFailures` isn't set.
+pub const DEFAULT_MAX_REPEATED_FAILURES: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct ToolFailureTracker {
+    /// 0 disables the check.
+    max: usize,
+    /// The tool name and arguments of tool uses that haven't completed yet, by ID.
+    pending: HashMap<String, (String, String)>,
+    /// The tool use that failed last along with its error, and how many times in a row it did.
+    last: Option<(String, usize)>,
+}
+
+impl ToolFailureTracker {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            pending: HashMap::new(),
+            last: None,
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Remembers what a tool use was called with, for comparing its outcome to the previous ones.
+    pub fn track(&mut self, tool_use_id: &str, name: &str, args: &serde_json::Value) {
+        self.pending
+            .insert(tool_use_id.to_string(), (name.to_string(), args.to_string()));
+    }
+
+    pub fn record_success(&mut self, tool_use_id: &str) {
+        if self.pending.remove(tool_use_id).is_some() {
+            self.last = None;
+        }
+    }
+
+    /// Records a failed tool use, returning the tool's name if it has now failed the same way as
+    /// many times in a row as is allowed.
+    pub fn record_failure(&mut self, tool_use_id: &str, error: &str) -> Option<String> {
+        let (name, args) = self.pending.remove(tool_use_id)?;
+        let failure = format!("{name}\n{args}\n{error}");
+        let count = match self.last.take() {
+            Some((last, count)) if last == failure => count + 1,
+            _ => 1,
+        };
+        self.last = Some((failure, count));
+
+        (self.max > 0 && count >= self.max).then_some(name)
+    }
+
+    /// Starts counting from zero again, e.g. once the user has said how to proceed.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_repeated_failures() {
+        let mut tracker = ToolFailureTracker::new(3);
+        let mut fail = |id: &str, args: serde_json::Value, error: &str| {
+            tracker.track(id, "fs_read", &args);
+            tracker.record_failure(id, error)
+        };
+
+        assert_eq!(fail("1", json!({ "path": "/a" }), "not found"), None);
+        assert_eq!(fail("2", json!({ "path": "/a" }), "not found"), None);
+        assert_eq!(
+            fail("3", json!({ "path": "/a" }), "not found"),
+            Some("fs_read".to_string())
+        );
+
+        // Different arguments or errors start a new streak.
+        let mut tracker = ToolFailureTracker::new(2);
+        tracker.track("1", "fs_read", &json!({ "path": "/a" }));
+        assert_eq!(tracker.record_failure("1", "not found"), None);
+        tracker.track("2", "fs_read", &json!({ "path": "/b" }));
+        assert_eq!(tracker.record_failure("2", "not found"), None);
+        tracker.track("3", "fs_read", &json!({ "path": "/b" }));
+        assert_eq!(tracker.record_failure("3", "permission denied"), None);
+
+        // So does a success.
+        tracker.track("4", "fs_read", &json!({ "path": "/b" }));
+        tracker.record_success("4");
+        tracker.track("5", "fs_read", &json!({ "path": "/b" }));
+        assert_eq!(tracker.record_failure("5", "permission denied"), None);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut tracker = ToolFailureTracker::new(0);
+        for id in ["1", "2", "3", "4"] {
+            tracker.track(id, "execute_bash", &json!({ "command": "false" }));
+            assert_eq!(tracker.record_failure(id, "exit status 1"), None);
+        }
+    }
+}
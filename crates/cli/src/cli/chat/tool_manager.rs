@@ -82,6 +82,12 @@ use crate::cli::chat::tools::fs_write::FsWrite;
 use crate::cli::chat::tools::gh_issue::GhIssue;
 use crate::cli::chat::tools::thinking::Thinking;
 use crate::cli::chat::tools::use_aws::UseAws;
+use crate::cli::chat::tools::user_input::UserInput;
+use crate::cli::chat::tools::user_tool::UserTool;
+use crate::cli::chat::tools::web_search::{
+    WebSearch,
+    WebSearchEndpoint,
+};
 use crate::cli::chat::tools::{
     Tool,
     ToolOrigin,
@@ -112,6 +118,39 @@ pub fn global_mcp_config_path(ctx: &Context) -> eyre::Result<PathBuf> {
     Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("mcp.json"))
 }
 
+/// User-defined tool specs, in the same format as the built-in `tool_index.json` with the addition
+/// of the `command` that runs each tool.
+pub fn global_tools_path(ctx: &Context) -> eyre::Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("tools.json"))
+}
+
+/// An entry of [global_tools_path].
+#[derive(Debug, Deserialize)]
+struct UserToolSpec {
+    #[serde(flatten)]
+    spec: ToolSpec,
+    /// Run with the user's shell, see [UserTool].
+    command: String,
+}
+
+/// Reads the user-defined tool specs at `path`, if the file exists. Errors name the file so that
+/// it's clear which one to fix.
+async fn load_user_tool_specs(ctx: &Context, path: &Path) -> eyre::Result<Vec<UserToolSpec>> {
+    if !ctx.fs().exists(path) {
+        return Ok(Vec::new());
+    }
+    let contents = ctx
+        .fs()
+        .read_to_string(path)
+        .await
+        .map_err(|err| eyre::eyre!("failed to read {}: {err}", path.display()))?;
+    let specs = serde_json::from_str::<HashMap<String, UserToolSpec>>(&contents)
+        .map_err(|err| eyre::eyre!("invalid tool specs in {}: {err}", path.display()))?;
+    let mut specs = specs.into_values().collect::<Vec<_>>();
+    specs.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+    Ok(specs)
+}
+
 #[derive(Debug, Error)]
 pub enum GetPromptError {
     #[error("Prompt with name {0} does not exist")]
@@ -768,6 +807,9 @@ pub struct ToolManager {
     /// model.
     pub schema: HashMap<String, ToolSpec>,
 
+    /// Mapping from the names of the user-defined tools in [global_tools_path] to their commands.
+    pub user_tools: HashMap<String, String>,
+
     is_interactive: bool,
 
     /// This serves as a record of the loading of mcp servers.
@@ -788,6 +830,7 @@ impl Clone for ToolManager {
             prompts: self.prompts.clone(),
             tn_map: self.tn_map.clone(),
             schema: self.schema.clone(),
+            user_tools: self.user_tools.clone(),
             is_interactive: self.is_interactive,
             mcp_load_record: self.mcp_load_record.clone(),
             ..Default::default()
@@ -796,8 +839,11 @@ impl Clone for ToolManager {
 }
 
 impl ToolManager {
+    /// Loads the built-in tools and the user-defined ones from [global_tools_path], then waits
+    /// for the MCP servers' tools.
     pub async fn load_tools(
         &mut self,
+        ctx: &Context,
         database: &Database,
         output: &mut SharedWriter,
     ) -> eyre::Result<HashMap<String, ToolSpec>> {
@@ -806,9 +852,48 @@ impl ToolManager {
         self.schema = {
             let mut tool_specs =
                 serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))?;
+            let user_specs = match global_tools_path(ctx) {
+                Ok(path) => load_user_tool_specs(ctx, &path).await,
+                Err(err) => Err(err),
+            };
+            match user_specs {
+                Ok(specs) => {
+                    for UserToolSpec { spec, command } in specs {
+                        if tool_specs.contains_key(&spec.name) {
+                            queue!(
+                                output,
+                                style::SetForegroundColor(style::Color::Yellow),
+                                style::Print("WARNING: "),
+                                style::ResetColor,
+                                style::Print("User tool "),
+                                style::SetForegroundColor(style::Color::Green),
+                                style::Print(&spec.name),
+                                style::ResetColor,
+                                style::Print(" has the same name as a built-in tool. Ignoring it.\n")
+                            )?;
+                            continue;
+                        }
+                        self.user_tools.insert(spec.name.clone(), command);
+                        tool_specs.insert(spec.name.clone(), spec);
+                    }
+                },
+                Err(err) => {
+                    queue!(
+                        output,
+                        style::SetForegroundColor(style::Color::Yellow),
+                        style::Print("WARNING: "),
+                        style::ResetColor,
+                        style::Print(format!("Error loading user tools: {err}\n")),
+                        style::Print("Please check to make sure the specs are correct. Discarding.\n"),
+                    )?;
+                },
+            }
             if !crate::cli::chat::tools::thinking::Thinking::is_enabled(database) {
                 tool_specs.remove("thinking");
             }
+            if WebSearchEndpoint::from_settings(database).is_none() {
+                tool_specs.remove("web_search");
+            }
             tool_specs
         };
         let load_tools = self
@@ -918,6 +1003,13 @@ impl ToolManager {
             "use_aws" => Tool::UseAws(serde_json::from_value::<UseAws>(value.args).map_err(map_err)?),
             "report_issue" => Tool::GhIssue(serde_json::from_value::<GhIssue>(value.args).map_err(map_err)?),
             "thinking" => Tool::Thinking(serde_json::from_value::<Thinking>(value.args).map_err(map_err)?),
+            "user_input" => Tool::UserInput(serde_json::from_value::<UserInput>(value.args).map_err(map_err)?),
+            "web_search" => Tool::WebSearch(serde_json::from_value::<WebSearch>(value.args).map_err(map_err)?),
+            name if self.user_tools.contains_key(name) => Tool::User(UserTool {
+                name: name.to_string(),
+                command: self.user_tools[name].clone(),
+                input: value.args,
+            }),
             // Note that this name is namespaced with server_name{DELIMITER}tool_name
             name => {
                 // Note: tn_map also has tools that underwent no transformation. In otherwords, if
@@ -1484,4 +1576,123 @@ mod tests {
         let sanitized = sanitize_name(with_delim, &regex, &mut hasher);
         assert_eq!(sanitized, "abc");
     }
+
+    #[tokio::test]
+    async fn test_load_user_tools() {
+        use crate::cli::chat::util::shared_writer::TestWriterWithSink;
+
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let database = Database::new().await.unwrap();
+        let path = global_tools_path(&ctx).unwrap();
+        ctx.fs().create_dir_all(path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(
+                &path,
+                serde_json::json!({
+                    "deploy": {
+                        "name": "deploy",
+                        "description": "Deploys the current project",
+                        "input_schema": { "type": "object", "properties": {} },
+                        "command": "./deploy.sh",
+                    },
+                    "fs_read": {
+                        "name": "fs_read",
+                        "description": "Not the real fs_read",
+                        "input_schema": { "type": "object", "properties": {} },
+                        "command": "cat",
+                    },
+                })
+                .to_string(),
+            )
+            .await
+            .unwrap();
+
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut shared = SharedWriter::new(output.clone());
+        let mut tool_manager = ToolManager::default();
+        let specs = tool_manager.load_tools(&ctx, &database, &mut shared).await.unwrap();
+        assert_eq!(specs["deploy"].description, "Deploys the current project");
+        assert_eq!(tool_manager.user_tools["deploy"], "./deploy.sh");
+        assert!(!tool_manager.user_tools.contains_key("fs_read"));
+        assert_ne!(specs["fs_read"].description, "Not the real fs_read");
+        let printed = strip_ansi_escapes::strip_str(String::from_utf8(output.get_content()).unwrap());
+        assert!(printed.contains("User tool fs_read has the same name as a built-in tool"));
+
+        // A file that isn't valid is reported and skipped, keeping the built-in tools.
+        ctx.fs()
+            .write(&path, r#"{ "deploy": { "name": "deploy" } }"#)
+            .await
+            .unwrap();
+        let output = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+        let mut shared = SharedWriter::new(output.clone());
+        let specs = ToolManager::default()
+            .load_tools(&ctx, &database, &mut shared)
+            .await
+            .unwrap();
+        assert!(!specs.contains_key("deploy"));
+        assert!(specs.contains_key("fs_read"));
+        let printed = String::from_utf8(output.get_content()).unwrap();
+        assert!(printed.contains(&format!("invalid tool specs in {}", path.display())));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_user_tool() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let database = Database::new().await.unwrap();
+        let path = global_tools_path(&ctx).unwrap();
+        ctx.fs().create_dir_all(path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(
+                &path,
+                serde_json::json!({
+                    "echo_input": {
+                        "name": "echo_input",
+                        "description": "Prints its input",
+                        "input_schema": { "type": "object", "properties": { "text": { "type": "string" } } },
+                        "command": "cat",
+                    },
+                    "always_fails": {
+                        "name": "always_fails",
+                        "description": "Exits with an error",
+                        "input_schema": { "type": "object", "properties": {} },
+                        "command": "echo broken >&2; exit 3",
+                    },
+                })
+                .to_string(),
+            )
+            .await
+            .unwrap();
+
+        let mut tool_manager = ToolManager::default();
+        let mut output = SharedWriter::null();
+        tool_manager.load_tools(&ctx, &database, &mut output).await.unwrap();
+
+        let tool_use = |name: &str, args: serde_json::Value| AssistantToolUse {
+            id: "1".to_string(),
+            name: name.to_string(),
+            orig_name: name.to_string(),
+            args: args.clone(),
+            orig_args: args,
+        };
+        let tool = tool_manager
+            .get_tool_from_tool_use(tool_use("echo_input", serde_json::json!({ "text": "hello" })))
+            .unwrap();
+        assert!(matches!(tool, Tool::User(_)));
+        assert!(tool.requires_acceptance(&ctx));
+        let result = tool.invoke(&ctx, &mut std::io::sink(), None).await.unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(result.as_str()).unwrap(),
+            serde_json::json!({ "text": "hello" })
+        );
+
+        let tool = tool_manager
+            .get_tool_from_tool_use(tool_use("always_fails", serde_json::json!({})))
+            .unwrap();
+        let err = tool.invoke(&ctx, &mut std::io::sink(), None).await.unwrap_err();
+        assert!(err.to_string().contains("broken"), "{err}");
+    }
 }
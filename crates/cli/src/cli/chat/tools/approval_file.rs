@@ -0,0 +1,82 @@
+//! Pre-approved tool uses for non-interactive sessions.
+//!
+//! An approval file is a plain text file passed via `q chat --no-interactive --approval-file
+//! <PATH>`. Each non-empty line is either a tool name (e.g. `fs_read`, `execute_bash`, or the name
+//! of an MCP tool) or a specific `tool_use_id` as reported by the model. Surrounding whitespace is
+//! ignored, and lines starting with `#` are treated as comments:
+//!
+//! ```text
+//! # Allow all reads for this run
+//! fs_read
+//! # Allow exactly one specific tool use
+//! tooluse_Xb3o9YxCQMyMbD9Fy2ZB8w
+//! ```
+//!
+//! Tool uses that match neither a listed name nor a listed id are still rejected with
+//! [super::super::ChatError::NonInteractiveToolApproval].
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use eyre::{
+    Result,
+    WrapErr,
+};
+
+use crate::platform::Context;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApprovalFile {
+    entries: HashSet<String>,
+}
+
+impl ApprovalFile {
+    /// Reads and parses the approval file at `path`.
+    pub async fn load(ctx: &Context, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = ctx
+            .fs()
+            .read_to_string(path)
+            .await
+            .wrap_err_with(|| format!("Failed to read approval file '{}'", path.display()))?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let entries = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        Self { entries }
+    }
+
+    /// Whether a tool use is approved, either by its id or by the name of the tool.
+    pub fn is_approved(&self, tool_use_id: &str, tool_name: &str) -> bool {
+        self.entries.contains(tool_use_id) || self.entries.contains(tool_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_approval_file() {
+        let file = ApprovalFile::parse("# comment\n\n  fs_read  \ntooluse_abc\n#execute_bash\n");
+        assert!(file.is_approved("tooluse_other", "fs_read"));
+        assert!(file.is_approved("tooluse_abc", "execute_bash"));
+        assert!(!file.is_approved("tooluse_other", "execute_bash"));
+        assert_eq!(ApprovalFile::parse("# only comments\n"), ApprovalFile::default());
+    }
+
+    #[tokio::test]
+    async fn test_load_approval_file() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/approvals.txt", "use_aws\n").await.unwrap();
+        let file = ApprovalFile::load(&ctx, "/approvals.txt").await.unwrap();
+        assert!(file.is_approved("tooluse_1", "use_aws"));
+        assert!(ApprovalFile::load(&ctx, "/missing.txt").await.is_err());
+    }
+}
@@ -1,10 +1,15 @@
 use std::collections::VecDeque;
 use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::process::{
     ExitStatus,
     Stdio,
 };
 use std::str::from_utf8;
+use std::time::Duration;
 
 use crossterm::queue;
 use crossterm::style::{
@@ -14,6 +19,7 @@ use crossterm::style::{
 use eyre::{
     Context as EyreContext,
     Result,
+    bail,
 };
 use serde::Deserialize;
 use tokio::io::AsyncBufReadExt;
@@ -25,18 +31,33 @@ use super::{
     InvokeOutput,
     MAX_TOOL_RESPONSE_SIZE,
     OutputKind,
+    sanitize_path_tool_arg,
 };
 use crate::cli::chat::{
     CONTINUATION_LINE,
     PURPOSE_ARROW,
 };
 use crate::platform::Context;
+
+/// How long to keep reading output after the command exits. Background processes started by the
+/// command may keep its output pipes open, so we can't always wait until they are closed.
+const POST_EXIT_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
 const READONLY_COMMANDS: &[&str] = &["ls", "cat", "echo", "pwd", "which", "head", "tail", "find", "grep"];
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ExecuteBash {
     pub command: String,
     pub summary: Option<String>,
+    /// Directory to run the command in instead of the current working directory.
+    pub cwd: Option<String>,
+
+    /// Set by the program from settings, `cwd` must be inside of it if set.
+    #[serde(skip_deserializing)]
+    pub workspace_root: Option<PathBuf>,
+    /// The directory `cwd` resolves to, set by [Self::validate].
+    #[serde(skip_deserializing)]
+    pub working_dir: Option<PathBuf>,
 }
 
 impl ExecuteBash {
@@ -98,7 +119,13 @@ impl ExecuteBash {
     }
 
     pub async fn invoke(&self, updates: impl Write) -> Result<InvokeOutput> {
-        let output = run_command(&self.command, MAX_TOOL_RESPONSE_SIZE / 3, Some(updates)).await?;
+        let output = run_command(
+            &self.command,
+            self.working_dir.as_deref(),
+            MAX_TOOL_RESPONSE_SIZE / 3,
+            Some(updates),
+        )
+        .await?;
         let result = serde_json::json!({
             "exit_status": output.exit_status.unwrap_or(0).to_string(),
             "stdout": output.stdout,
@@ -126,6 +153,20 @@ impl ExecuteBash {
             style::ResetColor
         )?;
 
+        if let Some(working_dir) = &self.working_dir {
+            queue!(
+                updates,
+                style::Print(CONTINUATION_LINE),
+                style::Print("\n"),
+                style::Print(PURPOSE_ARROW),
+                style::SetForegroundColor(Color::Blue),
+                style::Print("Working directory: "),
+                style::ResetColor,
+                style::Print(working_dir.display()),
+                style::Print("\n"),
+            )?;
+        }
+
         // Add the summary if available
         if let Some(summary) = &self.summary {
             queue!(
@@ -146,8 +187,38 @@ impl ExecuteBash {
         Ok(())
     }
 
-    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
         // TODO: probably some small amount of PATH checking
+        let Some(cwd) = &self.cwd else {
+            return Ok(());
+        };
+        let working_dir = match ctx.fs().canonicalize(sanitize_path_tool_arg(ctx, cwd)).await {
+            Ok(dir)
+                if ctx
+                    .fs()
+                    .symlink_metadata(&dir)
+                    .await
+                    .is_ok_and(|metadata| metadata.is_dir()) =>
+            {
+                dir
+            },
+            Ok(_) => bail!("The working directory '{cwd}' is not a directory"),
+            Err(err) => bail!("The working directory '{cwd}' doesn't exist: {err}"),
+        };
+        if let Some(root) = &self.workspace_root {
+            let root = ctx
+                .fs()
+                .canonicalize(root)
+                .await
+                .wrap_err_with(|| format!("The workspace root {} doesn't exist", root.display()))?;
+            if !working_dir.starts_with(&root) {
+                bail!(
+                    "The working directory '{cwd}' is outside of the workspace root {}",
+                    root.display()
+                );
+            }
+        }
+        self.working_dir = Some(working_dir);
         Ok(())
     }
 }
@@ -162,22 +233,30 @@ pub struct CommandResult {
 
 /// Run a bash command.
 /// # Arguments
+/// * `cwd` - directory to run the command in, the current working directory if [None]
 /// * `max_result_size` - max size of output streams, truncating if required
 /// * `updates` - output stream to push informational messages about the progress
 /// # Returns
 /// A [`CommandResult`]
 pub async fn run_command<W: Write>(
     command: &str,
+    cwd: Option<&Path>,
     max_result_size: usize,
     mut updates: Option<W>,
 ) -> Result<CommandResult> {
     // We need to maintain a handle on stderr and stdout, but pipe it to the terminal as well
-    let mut child = tokio::process::Command::new("bash")
+    let mut child = tokio::process::Command::new("bash");
+    if let Some(cwd) = cwd {
+        child.current_dir(cwd);
+    }
+    let mut child = child
         .arg("-c")
         .arg(command)
         .stdin(Stdio::inherit())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
+        // Makes sure the command doesn't outlive a timeout or interrupt that drops this future.
+        .kill_on_drop(true)
         .spawn()
         .wrap_err_with(|| format!("Unable to spawn command '{}'", command))?;
 
@@ -199,41 +278,55 @@ pub async fn run_command<W: Write>(
         let mut stdout_buf = VecDeque::with_capacity(LINE_COUNT);
         let mut stderr_buf = VecDeque::with_capacity(LINE_COUNT);
 
+        // Each line is written and flushed as soon as it is read so that long running commands
+        // can be followed live, while the last LINE_COUNT lines are kept for the result.
+        let mut push_line = |buf: &mut VecDeque<String>, line: String| -> Result<()> {
+            writeln!(u, "{line}")?;
+            u.flush()?;
+            if buf.len() >= LINE_COUNT {
+                buf.pop_front();
+            }
+            buf.push_back(line);
+            Ok(())
+        };
+
         let mut stdout_done = false;
         let mut stderr_done = false;
-        exit_status = loop {
+        let mut status = None;
+        let drain_deadline = tokio::time::sleep(Duration::MAX);
+        tokio::pin!(drain_deadline);
+        // Keep reading after the command exits, since output may still be buffered in the pipes.
+        while status.is_none() || !(stdout_done && stderr_done) {
             select! {
                 biased;
                 line = stdout.next_line(), if !stdout_done => match line {
-                    Ok(Some(line)) => {
-                        writeln!(u, "{line}")?;
-                        if stdout_buf.len() >= LINE_COUNT {
-                            stdout_buf.pop_front();
-                        }
-                        stdout_buf.push_back(line);
-                    },
+                    Ok(Some(line)) => push_line(&mut stdout_buf, line)?,
                     Ok(None) => stdout_done = true,
-                    Err(err) => error!(%err, "Failed to read stdout of child process"),
+                    Err(err) => {
+                        error!(%err, "Failed to read stdout of child process");
+                        stdout_done = true;
+                    },
                 },
                 line = stderr.next_line(), if !stderr_done => match line {
-                    Ok(Some(line)) => {
-                        writeln!(u, "{line}")?;
-                        if stderr_buf.len() >= LINE_COUNT {
-                            stderr_buf.pop_front();
-                        }
-                        stderr_buf.push_back(line);
-                    },
+                    Ok(Some(line)) => push_line(&mut stderr_buf, line)?,
                     Ok(None) => stderr_done = true,
-                    Err(err) => error!(%err, "Failed to read stderr of child process"),
+                    Err(err) => {
+                        error!(%err, "Failed to read stderr of child process");
+                        stderr_done = true;
+                    },
                 },
-                exit_status = child.wait() => {
-                    break exit_status;
+                exit_status = child.wait(), if status.is_none() => {
+                    status = Some(exit_status);
+                    drain_deadline
+                        .as_mut()
+                        .reset(tokio::time::Instant::now() + POST_EXIT_DRAIN_TIMEOUT);
                 },
+                () = &mut drain_deadline => break,
             };
         }
-        .wrap_err_with(|| format!("No exit status for '{}'", command))?;
-
-        u.flush()?;
+        exit_status = status
+            .expect("the loop only ends once the command has exited")
+            .wrap_err_with(|| format!("No exit status for '{}'", command))?;
 
         stdout_final = stdout_buf.into_iter().collect::<Vec<_>>().join("\n");
         stderr_final = stderr_buf.into_iter().collect::<Vec<_>>().join("\n");
@@ -280,6 +373,67 @@ pub async fn run_command<W: Write>(
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_run_command_streams_output() {
+        let mut updates = Vec::new();
+        let output = run_command(
+            "for i in 1 2 3; do echo out$i; done; echo err >&2; exit 3",
+            None,
+            MAX_TOOL_RESPONSE_SIZE,
+            Some(&mut updates),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(output.exit_status, Some(3));
+        assert_eq!(output.stdout, "out1\nout2\nout3");
+        assert_eq!(output.stderr, "err");
+        let updates = String::from_utf8(updates).unwrap();
+        for line in ["out1\n", "out2\n", "out3\n", "err\n"] {
+            assert!(updates.contains(line), "{line:?} missing from {updates:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_bash_cwd() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/project/sub").await.unwrap();
+        ctx.fs().write("/project/file.txt", "").await.unwrap();
+
+        let tool = |cwd: &str| {
+            serde_json::from_value::<ExecuteBash>(serde_json::json!({
+                "command": "pwd",
+                "cwd": cwd,
+            }))
+            .unwrap()
+        };
+
+        let mut bash = tool("/project/sub");
+        bash.validate(&ctx).await.unwrap();
+        let working_dir = bash.working_dir.clone().unwrap();
+        assert_eq!(working_dir, ctx.fs().canonicalize("/project/sub").await.unwrap());
+        let mut description = Vec::new();
+        bash.queue_description(&mut description).unwrap();
+        assert!(String::from_utf8(description).unwrap().contains("Working directory: "));
+        let out = bash.invoke(std::io::sink()).await.unwrap();
+        let OutputKind::Json(json) = out.output else {
+            panic!("Expected JSON output");
+        };
+        assert_eq!(json["stdout"], working_dir.to_string_lossy().as_ref());
+
+        assert!(tool("/missing").validate(&ctx).await.is_err());
+        assert!(tool("/project/file.txt").validate(&ctx).await.is_err());
+
+        // With a workspace root, the directory can't be outside of it.
+        let mut bash = tool("/project/sub/..");
+        bash.workspace_root = Some(ctx.fs().chroot_path("/project"));
+        bash.validate(&ctx).await.unwrap();
+        let mut bash = tool("/project/..");
+        bash.workspace_root = Some(ctx.fs().chroot_path("/project"));
+        let err = bash.validate(&ctx).await.unwrap_err();
+        assert!(err.to_string().contains("outside of the workspace root"));
+    }
+
     #[ignore = "todo: fix failing on musl for some reason"]
     #[tokio::test]
     async fn test_execute_bash_tool() {
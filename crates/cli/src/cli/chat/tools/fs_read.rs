@@ -25,6 +25,7 @@ use super::{
     InvokeOutput,
     MAX_TOOL_RESPONSE_SIZE,
     OutputKind,
+    ToolProgress,
     format_path,
     sanitize_path_tool_arg,
 };
@@ -63,12 +64,26 @@ impl FsRead {
         }
     }
 
-    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+    /// The number of files that will be read, if it's known up front.
+    pub fn progress_total(&self) -> Option<usize> {
+        match self {
+            FsRead::Line(_) | FsRead::Search(_) => Some(1),
+            FsRead::Directory(_) => None,
+            FsRead::Image(fs_image) => Some(fs_image.image_paths.len()),
+        }
+    }
+
+    pub async fn invoke(
+        &self,
+        ctx: &Context,
+        updates: &mut impl Write,
+        progress: Option<&mut ToolProgress>,
+    ) -> Result<InvokeOutput> {
         match self {
             FsRead::Line(fs_line) => fs_line.invoke(ctx, updates).await,
             FsRead::Directory(fs_directory) => fs_directory.invoke(ctx, updates).await,
             FsRead::Search(fs_search) => fs_search.invoke(ctx, updates).await,
-            FsRead::Image(fs_image) => fs_image.invoke(ctx, updates).await,
+            FsRead::Image(fs_image) => fs_image.invoke(ctx, updates, progress).await,
         }
     }
 }
@@ -99,9 +114,14 @@ impl FsImage {
         Ok(())
     }
 
-    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+    pub async fn invoke(
+        &self,
+        ctx: &Context,
+        updates: &mut impl Write,
+        progress: Option<&mut ToolProgress>,
+    ) -> Result<InvokeOutput> {
         let pre_processed_paths: Vec<String> = self.image_paths.iter().map(|path| pre_process(ctx, path)).collect();
-        let valid_images = handle_images_from_paths(updates, &pre_processed_paths);
+        let valid_images = handle_images_from_paths(updates, &pre_processed_paths, progress);
         Ok(InvokeOutput {
             output: OutputKind::Images(valid_images),
         })
@@ -615,7 +635,7 @@ mod tests {
                 });
                 let output = serde_json::from_value::<FsRead>(v)
                     .unwrap()
-                    .invoke(&ctx, &mut stdout)
+                    .invoke(&ctx, &mut stdout, None)
                     .await
                     .unwrap();
 
@@ -649,7 +669,7 @@ mod tests {
         assert!(
             serde_json::from_value::<FsRead>(v)
                 .unwrap()
-                .invoke(&ctx, &mut stdout)
+                .invoke(&ctx, &mut stdout, None)
                 .await
                 .is_err()
         );
@@ -680,7 +700,7 @@ mod tests {
         });
         let output = serde_json::from_value::<FsRead>(v)
             .unwrap()
-            .invoke(&ctx, &mut stdout)
+            .invoke(&ctx, &mut stdout, None)
             .await
             .unwrap();
 
@@ -698,7 +718,7 @@ mod tests {
         });
         let output = serde_json::from_value::<FsRead>(v)
             .unwrap()
-            .invoke(&ctx, &mut stdout)
+            .invoke(&ctx, &mut stdout, None)
             .await
             .unwrap();
 
@@ -724,7 +744,7 @@ mod tests {
                 let v = serde_json::json!($value);
                 let output = serde_json::from_value::<FsRead>(v)
                     .unwrap()
-                    .invoke(&ctx, &mut stdout)
+                    .invoke(&ctx, &mut stdout, None)
                     .await
                     .unwrap();
 
@@ -1,4 +1,5 @@
 use std::io::Write;
+use std::ops::Range;
 use std::path::Path;
 use std::sync::LazyLock;
 
@@ -29,14 +30,15 @@ use tracing::{
 
 use super::{
     InvokeOutput,
+    OutputKind,
     format_path,
     sanitize_path_tool_arg,
     supports_truecolor,
 };
 use crate::platform::Context;
 
-static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
-static THEME_SET: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+pub static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+pub static THEME_SET: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "command")]
@@ -63,6 +65,25 @@ pub enum FsWrite {
     },
     #[serde(rename = "append")]
     Append { path: String, new_str: String },
+    /// Applies several edits to one file at once. Either all of them are applied or, if any of
+    /// them doesn't apply cleanly, none are.
+    #[serde(rename = "patch")]
+    Patch { path: String, edits: Vec<FileEdit> },
+}
+
+/// An edit of a [FsWrite::Patch].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FileEdit {
+    /// Replaces `old_str`, which must occur exactly once in the file.
+    Snippet { old_str: String, new_str: String },
+    /// Replaces the 1-indexed lines `[start_line, end_line]`, or deletes them if `new_str` is
+    /// empty.
+    Lines {
+        start_line: usize,
+        end_line: usize,
+        new_str: String,
+    },
 }
 
 impl FsWrite {
@@ -160,10 +181,39 @@ impl FsWrite {
                 write_to_file(ctx, path, file).await?;
                 Ok(Default::default())
             },
+            FsWrite::Patch { path, edits } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                let file = fs.read_to_string(&path).await?;
+                let relative_path = format_path(cwd, &path);
+                queue!(
+                    updates,
+                    style::Print("Updating: "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(&relative_path),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+
+                let mut patched = file.clone();
+                for (range, new_str) in locate_edits(&file, edits)?.into_iter().rev() {
+                    patched.replace_range(range, &new_str);
+                }
+                fs.write(&path, &patched).await?;
+
+                let diff = similar::TextDiff::from_lines(&file, &patched)
+                    .unified_diff()
+                    .context_radius(3)
+                    .header(&relative_path, &relative_path)
+                    .to_string();
+                Ok(InvokeOutput {
+                    output: OutputKind::Text(diff),
+                })
+            },
         }
     }
 
-    pub fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+    /// Prints a diff of the change, showing at most `max_diff_lines` lines of it.
+    pub fn queue_description(&self, ctx: &Context, updates: &mut impl Write, max_diff_lines: usize) -> Result<()> {
         let cwd = ctx.env().current_dir()?;
         self.print_relative_path(ctx, updates)?;
         match self {
@@ -177,7 +227,7 @@ impl FsWrite {
                     Default::default()
                 };
                 let new = stylize_output_if_able(ctx, &relative_path, &file_text);
-                print_diff(updates, &prev, &new, 1)?;
+                print_diff(updates, &prev, &new, 1, max_diff_lines)?;
                 Ok(())
             },
             FsWrite::Insert {
@@ -200,7 +250,7 @@ impl FsWrite {
 
                 let old = stylize_output_if_able(ctx, &relative_path, &old);
                 let new = stylize_output_if_able(ctx, &relative_path, &new);
-                print_diff(updates, &old, &new, start_line)?;
+                print_diff(updates, &old, &new, start_line, max_diff_lines)?;
                 Ok(())
             },
             FsWrite::StrReplace { path, old_str, new_str } => {
@@ -212,7 +262,7 @@ impl FsWrite {
                 };
                 let old_str = stylize_output_if_able(ctx, &relative_path, old_str);
                 let new_str = stylize_output_if_able(ctx, &relative_path, new_str);
-                print_diff(updates, &old_str, &new_str, start_line)?;
+                print_diff(updates, &old_str, &new_str, start_line, max_diff_lines)?;
 
                 Ok(())
             },
@@ -220,7 +270,18 @@ impl FsWrite {
                 let relative_path = format_path(cwd, path);
                 let start_line = ctx.fs().read_to_string_sync(&relative_path)?.lines().count() + 1;
                 let file = stylize_output_if_able(ctx, &relative_path, new_str);
-                print_diff(updates, &Default::default(), &file, start_line)?;
+                print_diff(updates, &Default::default(), &file, start_line, max_diff_lines)?;
+                Ok(())
+            },
+            FsWrite::Patch { path, edits } => {
+                let relative_path = format_path(cwd, path);
+                let file = ctx.fs().read_to_string_sync(&relative_path)?;
+                for (range, new_str) in locate_edits(&file, edits)? {
+                    let start_line = file[..range.start].matches('\n').count() + 1;
+                    let old_str = stylize_output_if_able(ctx, &relative_path, &file[range]);
+                    let new_str = stylize_output_if_able(ctx, &relative_path, &new_str);
+                    print_diff(updates, &old_str, &new_str, start_line, max_diff_lines)?;
+                }
                 Ok(())
             },
         }
@@ -247,6 +308,17 @@ impl FsWrite {
                     bail!("Content to append must not be empty")
                 };
             },
+            FsWrite::Patch { path, edits } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                if !path.exists() {
+                    bail!("The provided path must exist in order to patch it")
+                }
+                if edits.is_empty() {
+                    bail!("At least one edit must be provided")
+                }
+                // Reject edits that wouldn't apply before asking the user about them.
+                locate_edits(&ctx.fs().read_to_string(&path).await?, edits)?;
+            },
         }
 
         Ok(())
@@ -259,6 +331,7 @@ impl FsWrite {
             FsWrite::StrReplace { path, .. } => path,
             FsWrite::Insert { path, .. } => path,
             FsWrite::Append { path, .. } => path,
+            FsWrite::Patch { path, .. } => path,
         };
         let relative_path = format_path(cwd, path);
         queue!(
@@ -292,6 +365,66 @@ impl FsWrite {
     }
 }
 
+/// Finds the byte range of `file` that each edit replaces, along with its replacement, ordered by
+/// where they are in the file. Fails if any snippet doesn't occur exactly once, a line range is
+/// out of bounds, or two edits overlap.
+fn locate_edits(file: &str, edits: &[FileEdit]) -> Result<Vec<(Range<usize>, String)>> {
+    let line_count = file.lines().count();
+    let mut located = Vec::with_capacity(edits.len());
+    for (i, edit) in edits.iter().enumerate() {
+        let n = i + 1;
+        match edit {
+            FileEdit::Snippet { old_str, new_str } => {
+                if old_str.is_empty() {
+                    bail!("edit {n}: old_str must not be empty");
+                }
+                let matches = file.match_indices(old_str.as_str()).map(|(i, _)| i).collect::<Vec<_>>();
+                let start = match matches[..] {
+                    [start] => start,
+                    [] => bail!("edit {n}: no occurrences of \"{old_str}\" were found"),
+                    _ => bail!(
+                        "edit {n}: {} occurrences of old_str were found when only 1 is expected",
+                        matches.len()
+                    ),
+                };
+                located.push((n, start..start + old_str.len(), new_str.clone()));
+            },
+            FileEdit::Lines {
+                start_line,
+                end_line,
+                new_str,
+            } => {
+                if *start_line == 0 || start_line > end_line || *end_line > line_count {
+                    bail!("edit {n}: lines {start_line}-{end_line} are out of range, the file has {line_count} lines");
+                }
+                let mut lines = LinesWithEndings::from(file);
+                let start = lines.by_ref().take(start_line - 1).map(str::len).sum::<usize>();
+                let len = lines.take(end_line - start_line + 1).map(str::len).sum::<usize>();
+                let range = start..start + len;
+
+                // Keep the line break after the replaced lines, unless they're being deleted.
+                let mut new_str = new_str.clone();
+                if !new_str.is_empty() && !new_str.ends_with('\n') && file[range.clone()].ends_with('\n') {
+                    new_str.push('\n');
+                }
+                located.push((n, range, new_str));
+            },
+        }
+    }
+
+    located.sort_by_key(|(_, range, _)| range.start);
+    for pair in located.windows(2) {
+        let ((a, first, _), (b, second, _)) = (&pair[0], &pair[1]);
+        if first.end > second.start {
+            bail!("edits {} and {} overlap", a.min(b), a.max(b));
+        }
+    }
+    Ok(located
+        .into_iter()
+        .map(|(_, range, new_str)| (range, new_str))
+        .collect())
+}
+
 /// Writes `content` to `path`, adding a newline if necessary.
 async fn write_to_file(ctx: &Context, path: impl AsRef<Path>, mut content: String) -> Result<()> {
     if !content.ends_with_newline() {
@@ -365,8 +498,10 @@ fn print_diff(
     old_str: &StylizedFile,
     new_str: &StylizedFile,
     start_line: usize,
+    max_lines: usize,
 ) -> Result<()> {
     let diff = similar::TextDiff::from_lines(&old_str.content, &new_str.content);
+    let total_lines = diff.iter_all_changes().count();
 
     // First, get the gutter width required for both the old and new lines.
     let (mut max_old_i, mut max_new_i) = (1, 1);
@@ -388,7 +523,7 @@ fn print_diff(
             _ => " ".to_string(),
         }
     }
-    for change in diff.iter_all_changes() {
+    for change in diff.iter_all_changes().take(max_lines) {
         // Define the colors per line.
         let (text_color, gutter_bg_color, line_bg_color) = match (change.tag(), new_str.truecolor) {
             (similar::ChangeTag::Equal, true) => (style::Color::Reset, new_str.gutter_bg, new_str.line_bg),
@@ -457,6 +592,14 @@ fn print_diff(
             style::ResetColor,
         )?;
     }
+    if total_lines > max_lines {
+        queue!(
+            updates,
+            style::SetForegroundColor(style::Color::DarkGrey),
+            style::Print(format!("… {} more lines\n", total_lines - max_lines)),
+            style::ResetColor,
+        )?;
+    }
     queue!(
         updates,
         crossterm::terminal::Clear(crossterm::terminal::ClearType::UntilNewLine),
@@ -661,6 +804,73 @@ mod tests {
         });
         let fw = serde_json::from_value::<FsWrite>(v).unwrap();
         assert!(matches!(fw, FsWrite::Append { .. }));
+
+        // patch
+        let v = serde_json::json!({
+            "path": path,
+            "command": "patch",
+            "edits": [
+                { "old_str": "prev string", "new_str": "new string" },
+                { "start_line": 2, "end_line": 3, "new_str": "new lines" },
+            ],
+        });
+        let fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        let FsWrite::Patch { edits, .. } = fw else {
+            panic!("expected a patch command");
+        };
+        assert!(matches!(edits[0], FileEdit::Snippet { .. }));
+        assert!(matches!(edits[1], FileEdit::Lines { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_fs_write_tool_patch() {
+        let ctx = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+
+        let patch = |edits: serde_json::Value| {
+            serde_json::from_value::<FsWrite>(serde_json::json!({
+                "path": TEST_FILE_PATH,
+                "command": "patch",
+                "edits": edits,
+            }))
+            .unwrap()
+        };
+
+        // Nothing is written when any of the edits doesn't apply.
+        for edits in [
+            serde_json::json!([
+                { "old_str": "3: asdf", "new_str": "3: qwerty" },
+                { "old_str": "Hello world!", "new_str": "Goodbye world!" },
+            ]),
+            serde_json::json!([{ "old_str": "asjidfopjaieopr", "new_str": "1623749" }]),
+            serde_json::json!([{ "start_line": 4, "end_line": 5, "new_str": "" }]),
+            serde_json::json!([
+                { "old_str": "2: This is line 2", "new_str": "" },
+                { "start_line": 1, "end_line": 2, "new_str": "" },
+            ]),
+        ] {
+            let mut fw = patch(edits);
+            assert!(fw.validate(&ctx).await.is_err());
+            assert!(fw.invoke(&ctx, &mut stdout).await.is_err());
+            assert_eq!(
+                ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+                TEST_FILE_CONTENTS
+            );
+        }
+
+        let mut fw = patch(serde_json::json!([
+            { "start_line": 4, "end_line": 4, "new_str": "4: Goodbye world!" },
+            { "old_str": "line 2\n3: asdf", "new_str": "the second line" },
+            { "start_line": 1, "end_line": 1, "new_str": "" },
+        ]));
+        fw.validate(&ctx).await.unwrap();
+        let output = fw.invoke(&ctx, &mut stdout).await.unwrap();
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            "2: This is the second line\n4: Goodbye world!\n"
+        );
+        assert!(output.as_str().contains("-1: Hello world!"));
+        assert!(output.as_str().contains("+4: Goodbye world!"));
     }
 
     #[tokio::test]
@@ -950,4 +1160,25 @@ mod tests {
         assert_eq!(terminal_width_required_for_line_count(100), 3);
         assert_eq!(terminal_width_required_for_line_count(999), 3);
     }
+
+    #[test]
+    fn test_print_diff_truncates() {
+        let plain = |content: &str| StylizedFile {
+            truecolor: false,
+            content: content.to_string(),
+            ..Default::default()
+        };
+        let new = (1..=10).map(|i| format!("line {i}\n")).collect::<String>();
+
+        let mut output = vec![];
+        print_diff(&mut output, &plain(""), &plain(&new), 1, 3).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("line 3"));
+        assert!(!output.contains("line 4"));
+        assert!(output.contains("… 7 more lines"));
+
+        let mut output = vec![];
+        print_diff(&mut output, &plain(""), &plain(&new), 1, 10).unwrap();
+        assert!(!String::from_utf8(output).unwrap().contains("more lines"));
+    }
 }
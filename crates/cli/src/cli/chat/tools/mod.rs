@@ -1,3 +1,4 @@
+pub mod approval_file;
 pub mod custom_tool;
 pub mod execute_bash;
 pub mod fs_read;
@@ -5,6 +6,9 @@ pub mod fs_write;
 pub mod gh_issue;
 pub mod thinking;
 pub mod use_aws;
+pub mod user_input;
+pub mod user_tool;
+pub mod web_search;
 
 use std::collections::HashMap;
 use std::io::Write;
@@ -12,8 +16,19 @@ use std::path::{
     Path,
     PathBuf,
 };
+use std::time::Duration;
 
-use crossterm::style::Stylize;
+use approval_file::ApprovalFile;
+use crossterm::style::{
+    self,
+    Color,
+    Stylize,
+};
+use crossterm::{
+    cursor,
+    queue,
+    terminal,
+};
 use custom_tool::CustomTool;
 use execute_bash::ExecuteBash;
 use eyre::Result;
@@ -26,6 +41,9 @@ use serde::{
 };
 use thinking::Thinking;
 use use_aws::UseAws;
+use user_input::UserInput;
+use user_tool::UserTool;
+use web_search::WebSearch;
 
 use super::consts::MAX_TOOL_RESPONSE_SIZE;
 use super::util::images::RichImageBlocks;
@@ -42,6 +60,9 @@ pub enum Tool {
     Custom(CustomTool),
     GhIssue(GhIssue),
     Thinking(Thinking),
+    UserInput(UserInput),
+    WebSearch(WebSearch),
+    User(UserTool),
 }
 
 impl Tool {
@@ -55,10 +76,24 @@ impl Tool {
             Tool::Custom(custom_tool) => &custom_tool.name,
             Tool::GhIssue(_) => "gh_issue",
             Tool::Thinking(_) => "thinking (prerelease)",
+            Tool::UserInput(_) => "user_input",
+            Tool::WebSearch(_) => "web_search",
+            Tool::User(user_tool) => &user_tool.name,
         }
         .to_owned()
     }
 
+    pub fn kind(&self) -> ToolKind {
+        match self {
+            Tool::FsRead(_) => ToolKind::Read,
+            Tool::FsWrite(_) => ToolKind::Write,
+            Tool::ExecuteBash(_) | Tool::User(_) => ToolKind::Command,
+            Tool::UseAws(_) => ToolKind::Aws,
+            Tool::Custom(_) => ToolKind::Custom,
+            Tool::GhIssue(_) | Tool::Thinking(_) | Tool::UserInput(_) | Tool::WebSearch(_) => ToolKind::Other,
+        }
+    }
+
     /// Whether or not the tool should prompt the user to accept before [Self::invoke] is called.
     pub fn requires_acceptance(&self, _ctx: &Context) -> bool {
         match self {
@@ -69,32 +104,72 @@ impl Tool {
             Tool::Custom(_) => true,
             Tool::GhIssue(_) => false,
             Tool::Thinking(_) => false,
+            Tool::UserInput(_) => true,
+            Tool::WebSearch(_) => true,
+            Tool::User(_) => true,
         }
     }
 
-    /// Invokes the tool asynchronously
-    pub async fn invoke(&self, context: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+    /// The number of steps the tool works through, such as files to read, if it's known up front
+    /// and there's more than one. Tools without it are shown with the spinner instead of a
+    /// [ToolProgress] bar.
+    pub fn progress_total(&self) -> Option<usize> {
+        let total = match self {
+            Tool::FsRead(fs_read) => fs_read.progress_total(),
+            Tool::FsWrite(_)
+            | Tool::ExecuteBash(_)
+            | Tool::UseAws(_)
+            | Tool::Custom(_)
+            | Tool::GhIssue(_)
+            | Tool::Thinking(_)
+            | Tool::UserInput(_)
+            | Tool::WebSearch(_)
+            | Tool::User(_) => None,
+        };
+        total.filter(|total| *total > 1)
+    }
+
+    /// Invokes the tool asynchronously, reporting to `progress` as it works through its files if
+    /// the tool supports it.
+    pub async fn invoke(
+        &self,
+        context: &Context,
+        updates: &mut impl Write,
+        progress: Option<&mut ToolProgress>,
+    ) -> Result<InvokeOutput> {
         match self {
-            Tool::FsRead(fs_read) => fs_read.invoke(context, updates).await,
+            Tool::FsRead(fs_read) => fs_read.invoke(context, updates, progress).await,
             Tool::FsWrite(fs_write) => fs_write.invoke(context, updates).await,
             Tool::ExecuteBash(execute_bash) => execute_bash.invoke(updates).await,
             Tool::UseAws(use_aws) => use_aws.invoke(context, updates).await,
             Tool::Custom(custom_tool) => custom_tool.invoke(context, updates).await,
             Tool::GhIssue(gh_issue) => gh_issue.invoke(updates).await,
             Tool::Thinking(think) => think.invoke(updates).await,
+            Tool::UserInput(user_input) => user_input.invoke(context, updates).await,
+            Tool::WebSearch(web_search) => web_search.invoke(updates).await,
+            Tool::User(user_tool) => user_tool.invoke(context, updates).await,
         }
     }
 
-    /// Queues up a tool's intention in a human readable format
-    pub async fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+    /// Queues up a tool's intention in a human readable format. File edits are previewed as a diff
+    /// of at most `max_diff_lines` lines.
+    pub async fn queue_description(
+        &self,
+        ctx: &Context,
+        updates: &mut impl Write,
+        max_diff_lines: usize,
+    ) -> Result<()> {
         match self {
             Tool::FsRead(fs_read) => fs_read.queue_description(ctx, updates).await,
-            Tool::FsWrite(fs_write) => fs_write.queue_description(ctx, updates),
+            Tool::FsWrite(fs_write) => fs_write.queue_description(ctx, updates, max_diff_lines),
             Tool::ExecuteBash(execute_bash) => execute_bash.queue_description(updates),
             Tool::UseAws(use_aws) => use_aws.queue_description(updates),
             Tool::Custom(custom_tool) => custom_tool.queue_description(updates),
             Tool::GhIssue(gh_issue) => gh_issue.queue_description(updates),
             Tool::Thinking(thinking) => thinking.queue_description(updates),
+            Tool::UserInput(user_input) => user_input.queue_description(updates),
+            Tool::WebSearch(web_search) => web_search.queue_description(updates),
+            Tool::User(user_tool) => user_tool.queue_description(updates),
         }
     }
 
@@ -108,6 +183,9 @@ impl Tool {
             Tool::Custom(custom_tool) => custom_tool.validate(ctx).await,
             Tool::GhIssue(gh_issue) => gh_issue.validate(ctx).await,
             Tool::Thinking(think) => think.validate(ctx).await,
+            Tool::UserInput(user_input) => user_input.validate(ctx).await,
+            Tool::WebSearch(web_search) => web_search.validate(ctx).await,
+            Tool::User(user_tool) => user_tool.validate(ctx).await,
         }
     }
 }
@@ -117,6 +195,36 @@ pub struct ToolPermission {
     pub trusted: bool,
 }
 
+/// A per-tool policy loaded from the `chat.toolPolicy` setting, e.g.
+/// `{"fs_read": "always", "execute_bash": "once"}`.
+///
+/// Policies take precedence over trusting all tools, so `/tools trustall` and `--trust-all-tools`
+/// only affect tools without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolPolicy {
+    /// Always run the tool without asking for confirmation.
+    Always,
+    /// Never run the tool; tool uses are rejected without prompting.
+    Never,
+    /// Ask for confirmation the first time the tool is used, then trust it for the session.
+    Once,
+}
+
+/// An entry of the `chat.toolPolicy` setting. Either just a [ToolPolicy], or an object that can
+/// also limit how long the tool may run, e.g. `{"policy": "once", "timeoutSecs": 60}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ToolPolicyEntry {
+    Policy(ToolPolicy),
+    Detailed {
+        #[serde(default)]
+        policy: Option<ToolPolicy>,
+        #[serde(default, rename = "timeoutSecs")]
+        timeout_secs: Option<u64>,
+    },
+}
+
 #[derive(Debug, Clone)]
 /// Holds overrides for tool permissions.
 /// Tools that do not have an associated ToolPermission should use
@@ -125,6 +233,11 @@ pub struct ToolPermissions {
     // We need this field for any stragglers
     pub trust_all: bool,
     pub permissions: HashMap<String, ToolPermission>,
+    /// Tool uses pre-approved for non-interactive sessions, see [ApprovalFile].
+    pub approval_file: Option<ApprovalFile>,
+    pub policies: HashMap<String, ToolPolicy>,
+    /// Per-tool execution timeouts in seconds from `chat.toolPolicy`, where zero means no limit.
+    pub timeouts: HashMap<String, u64>,
 }
 
 impl ToolPermissions {
@@ -132,15 +245,86 @@ impl ToolPermissions {
         Self {
             trust_all: false,
             permissions: HashMap::with_capacity(capacity),
+            approval_file: None,
+            policies: HashMap::new(),
+            timeouts: HashMap::new(),
+        }
+    }
+
+    /// Applies the entries of the `chat.toolPolicy` setting.
+    pub fn set_policy_entries(&mut self, entries: HashMap<String, ToolPolicyEntry>) {
+        for (tool_name, entry) in entries {
+            let (policy, timeout_secs) = match entry {
+                ToolPolicyEntry::Policy(policy) => (Some(policy), None),
+                ToolPolicyEntry::Detailed { policy, timeout_secs } => (policy, timeout_secs),
+            };
+            if let Some(policy) = policy {
+                self.policies.insert(tool_name.clone(), policy);
+            }
+            if let Some(timeout_secs) = timeout_secs {
+                self.timeouts.insert(tool_name, timeout_secs);
+            }
+        }
+    }
+
+    /// How long the tool may run, using `default_secs` unless the tool has its own timeout.
+    /// Returns [None] if there is no limit.
+    pub fn timeout(&self, tool_name: &str, default_secs: u64) -> Option<Duration> {
+        match self.timeouts.get(tool_name).copied().unwrap_or(default_secs) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
         }
     }
 
+    pub fn policy(&self, tool_name: &str) -> Option<ToolPolicy> {
+        self.policies.get(tool_name).copied()
+    }
+
+    /// Whether the tool can run without asking for confirmation, or [None] if this should be
+    /// decided by the tool's own [Tool::requires_acceptance].
+    pub fn is_allowed(&self, tool_name: &str) -> Option<bool> {
+        match self.policy(tool_name) {
+            Some(ToolPolicy::Always) => Some(true),
+            Some(ToolPolicy::Never) => Some(false),
+            // Only an approval of this specific tool counts, trusting all tools does not.
+            Some(ToolPolicy::Once) => Some(self.permissions.get(tool_name).is_some_and(|perm| perm.trusted)),
+            None if self.trust_all || (self.has(tool_name) && self.is_trusted(tool_name)) => Some(true),
+            None => None,
+        }
+    }
+
+    /// Trusts every tool in `tool_names` that doesn't have a policy of its own.
+    pub fn trust_all_tools<'a>(&mut self, tool_names: impl IntoIterator<Item = &'a str>) {
+        for tool_name in tool_names {
+            if self.policy(tool_name).is_none() {
+                self.trust_tool(tool_name);
+            }
+        }
+    }
+
+    /// Whether the tool use was listed in the approval file, if one was provided.
+    pub fn is_pre_approved(&self, tool_use_id: &str, tool_name: &str) -> bool {
+        self.approval_file
+            .as_ref()
+            .is_some_and(|file| file.is_approved(tool_use_id, tool_name))
+    }
+
     pub fn is_trusted(&self, tool_name: &str) -> bool {
         self.trust_all || self.permissions.get(tool_name).is_some_and(|perm| perm.trusted)
     }
 
     /// Returns a label to describe the permission status for a given tool.
     pub fn display_label(&self, tool_name: &str) -> String {
+        if let Some(policy) = self.policy(tool_name) {
+            return match policy {
+                ToolPolicy::Always => format!("  {}", "always trusted (policy)".dark_green().bold()),
+                ToolPolicy::Never => format!("  {}", "never allowed (policy)".red()),
+                ToolPolicy::Once if self.permissions.get(tool_name).is_some_and(|perm| perm.trusted) => {
+                    format!("  {}", "trusted".dark_green().bold())
+                },
+                ToolPolicy::Once => format!("  {}", "ask once (policy)".dark_grey()),
+            };
+        }
         if self.has(tool_name) || self.trust_all {
             if self.is_trusted(tool_name) {
                 format!("  {}", "trusted".dark_green().bold())
@@ -173,6 +357,20 @@ impl ToolPermissions {
         self.permissions.remove(tool_name);
     }
 
+    /// Names of the tools that were explicitly trusted or given a policy, sorted by name.
+    pub fn configured_tools(&self) -> Vec<&str> {
+        let mut names = self
+            .permissions
+            .iter()
+            .filter(|(_, perm)| perm.trusted)
+            .map(|(name, _)| name.as_str())
+            .chain(self.policies.keys().map(String::as_str))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
     pub fn has(&self, tool_name: &str) -> bool {
         self.permissions.contains_key(tool_name)
     }
@@ -187,6 +385,8 @@ impl ToolPermissions {
             "use_aws" => "trust read-only commands".dark_grey(),
             "report_issue" => "trusted".dark_green().bold(),
             "thinking" => "trusted (prerelease)".dark_green().bold(),
+            "user_input" => "not trusted".dark_grey(),
+            "web_search" => "not trusted".dark_grey(),
             _ if self.trust_all => "trusted".dark_grey().bold(),
             _ => "not trusted".dark_grey(),
         };
@@ -252,6 +452,100 @@ fn tool_origin() -> ToolOrigin {
     ToolOrigin::Native
 }
 
+/// The kind of operation a tool use performs, ordered from most to least likely to change things.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ToolKind {
+    Write,
+    Command,
+    Aws,
+    Custom,
+    Read,
+    Other,
+}
+
+impl ToolKind {
+    /// Tallies a batch of tool uses by kind, e.g. `3 files will be modified, 1 command will run`.
+    pub fn summarize(kinds: impl IntoIterator<Item = ToolKind>) -> String {
+        let mut counts = std::collections::BTreeMap::new();
+        for kind in kinds {
+            *counts.entry(kind).or_insert(0_usize) += 1;
+        }
+
+        counts
+            .into_iter()
+            .map(|(kind, n)| {
+                let (singular, plural) = match kind {
+                    ToolKind::Write => ("file will be modified", "files will be modified"),
+                    ToolKind::Command => ("command will run", "commands will run"),
+                    ToolKind::Aws => ("AWS call will be made", "AWS calls will be made"),
+                    ToolKind::Custom => ("MCP tool will run", "MCP tools will run"),
+                    ToolKind::Read => ("file will be read", "files will be read"),
+                    ToolKind::Other => ("other tool will run", "other tools will run"),
+                };
+                format!("{n} {}", if n == 1 { singular } else { plural })
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Width of the bar drawn by [ToolProgress], not counting the count and label after it.
+const PROGRESS_BAR_WIDTH: usize = 20;
+
+/// A progress bar for tools that work through a known number of files, redrawn in place on a
+/// single line as each one is started.
+#[derive(Debug)]
+pub struct ToolProgress {
+    total: usize,
+    completed: usize,
+}
+
+impl ToolProgress {
+    pub fn new(total: usize) -> Self {
+        Self { total, completed: 0 }
+    }
+
+    /// Redraws the bar while `label` is being worked on, then counts it as completed.
+    pub fn advance(&mut self, updates: &mut impl Write, label: &str) -> std::io::Result<()> {
+        queue!(
+            updates,
+            cursor::MoveToColumn(0),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!(
+                "{} {label}",
+                progress_bar(self.completed, self.total, PROGRESS_BAR_WIDTH)
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        updates.flush()?;
+        self.completed = (self.completed + 1).min(self.total);
+        Ok(())
+    }
+
+    /// Clears the bar once the tool is done.
+    pub fn finish(&self, updates: &mut impl Write) -> std::io::Result<()> {
+        queue!(
+            updates,
+            cursor::MoveToColumn(0),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+        )
+    }
+}
+
+/// Renders e.g. `[█████░░░░░] 1/2`.
+fn progress_bar(completed: usize, total: usize, width: usize) -> String {
+    let filled = match total {
+        0 => width,
+        total => completed.min(total) * width / total,
+    };
+    format!(
+        "[{}{}] {completed}/{total}",
+        "█".repeat(filled),
+        "░".repeat(width - filled)
+    )
+}
+
 #[derive(Debug, Clone)]
 pub struct QueuedTool {
     pub id: String,
@@ -362,17 +656,151 @@ fn format_path(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
         .unwrap_or(path.as_ref().to_string_lossy().to_string())
 }
 
-fn supports_truecolor(ctx: &Context) -> bool {
+pub fn supports_truecolor(ctx: &Context) -> bool {
     // Simple override to disable truecolor since shell_color doesn't use Context.
     !ctx.env().get("Q_DISABLE_TRUECOLOR").is_ok_and(|s| !s.is_empty())
         && shell_color::get_color_support().contains(shell_color::ColorSupport::TERM24BIT)
 }
 
+/// Whether the terminal is known to support OSC 8 hyperlinks. There's no way to query this, so
+/// it's guessed from the environment variables set by terminals that support them.
+pub fn supports_hyperlinks(ctx: &Context) -> bool {
+    let env = ctx.env();
+    let var = |key: &str| env.get(key).ok().filter(|value| !value.is_empty());
+
+    if let Some(force) = var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if var("CI").is_some() || var("TERM").is_some_and(|term| term == "dumb") {
+        return false;
+    }
+    var("WT_SESSION").is_some()
+        || var("KONSOLE_VERSION").is_some()
+        || var("DOMTERM").is_some()
+        || var("VTE_VERSION").is_some_and(|version| version.parse::<u32>().is_ok_and(|v| v >= 5000))
+        || var("TERM_PROGRAM").is_some_and(|program| {
+            matches!(
+                program.as_str(),
+                "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper" | "Tabby" | "rio"
+            )
+        })
+        || var("TERM").is_some_and(|term| term.contains("kitty") || term.contains("alacritty") || term.contains("foot"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::platform::EnvProvider;
 
+    #[test]
+    fn test_supports_hyperlinks() {
+        let supports = |vars: &[(&str, &str)]| {
+            supports_hyperlinks(
+                &Context::builder()
+                    .with_env(crate::platform::Env::from_slice(vars))
+                    .build(),
+            )
+        };
+        assert!(!supports(&[]));
+        assert!(!supports(&[("TERM_PROGRAM", "Apple_Terminal")]));
+        assert!(supports(&[("TERM_PROGRAM", "iTerm.app")]));
+        assert!(supports(&[("TERM", "xterm-kitty")]));
+        assert!(supports(&[("VTE_VERSION", "7600")]));
+        assert!(!supports(&[("VTE_VERSION", "4000")]));
+        assert!(!supports(&[("TERM_PROGRAM", "WezTerm"), ("CI", "true")]));
+        assert!(supports(&[("FORCE_HYPERLINK", "1")]));
+        assert!(!supports(&[("FORCE_HYPERLINK", "0"), ("TERM_PROGRAM", "WezTerm")]));
+    }
+
+    #[test]
+    fn test_tool_kind_summarize() {
+        assert_eq!(
+            ToolKind::summarize([
+                ToolKind::Read,
+                ToolKind::Write,
+                ToolKind::Command,
+                ToolKind::Write,
+                ToolKind::Write,
+            ]),
+            "3 files will be modified, 1 command will run, 1 file will be read"
+        );
+        assert_eq!(ToolKind::summarize([]), "");
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(0, 4, 8), "[░░░░░░░░] 0/4");
+        assert_eq!(progress_bar(1, 4, 8), "[██░░░░░░] 1/4");
+        assert_eq!(progress_bar(4, 4, 8), "[████████] 4/4");
+        assert_eq!(progress_bar(5, 4, 8), "[████████] 5/4");
+        assert_eq!(progress_bar(0, 0, 4), "[████] 0/0");
+
+        let mut progress = ToolProgress::new(2);
+        let mut output = Vec::new();
+        progress.advance(&mut output, "a.png").unwrap();
+        progress.advance(&mut output, "b.png").unwrap();
+        progress.advance(&mut output, "c.png").unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("0/2 a.png"));
+        assert!(output.contains("1/2 b.png"));
+        assert!(output.contains("2/2 c.png"));
+    }
+
+    #[test]
+    fn test_progress_total() {
+        let fs_read = |args: serde_json::Value| Tool::FsRead(serde_json::from_value(args).unwrap());
+        assert_eq!(
+            fs_read(serde_json::json!({ "mode": "Image", "image_paths": ["a.png", "b.png"] })).progress_total(),
+            Some(2)
+        );
+        assert_eq!(
+            fs_read(serde_json::json!({ "mode": "Image", "image_paths": ["a.png"] })).progress_total(),
+            None
+        );
+        assert_eq!(
+            fs_read(serde_json::json!({ "mode": "Line", "path": "a.txt" })).progress_total(),
+            None
+        );
+        assert_eq!(
+            fs_read(serde_json::json!({ "mode": "Directory", "path": "/" })).progress_total(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tool_policy() {
+        let mut permissions = ToolPermissions::new(0);
+        permissions.set_policy_entries(
+            serde_json::from_str(
+                r#"{"fs_read":"always","execute_bash":{"policy":"once","timeoutSecs":0},"use_aws":"never"}"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(permissions.timeout("execute_bash", 600), None);
+        assert_eq!(permissions.timeout("fs_read", 600), Some(Duration::from_secs(600)));
+        assert_eq!(permissions.timeout("fs_read", 0), None);
+
+        permissions.trust_all = true;
+        permissions.trust_all_tools(["fs_read", "execute_bash", "use_aws", "fs_write"]);
+        assert_eq!(permissions.is_allowed("fs_read"), Some(true));
+        assert_eq!(permissions.is_allowed("execute_bash"), Some(false));
+        assert_eq!(permissions.is_allowed("use_aws"), Some(false));
+        assert_eq!(permissions.is_allowed("fs_write"), Some(true));
+
+        permissions.trust_tool("execute_bash");
+        assert_eq!(permissions.is_allowed("execute_bash"), Some(true));
+        assert_eq!(permissions.configured_tools(), vec![
+            "execute_bash",
+            "fs_read",
+            "fs_write",
+            "use_aws"
+        ]);
+
+        permissions.reset();
+        assert_eq!(permissions.is_allowed("fs_write"), None);
+        assert_eq!(permissions.is_allowed("fs_read"), Some(true));
+    }
+
     #[tokio::test]
     async fn test_tilde_path_expansion() {
         let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
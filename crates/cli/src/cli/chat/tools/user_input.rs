@@ -0,0 +1,127 @@
+use std::io::{
+    Read,
+    Write,
+};
+
+use crossterm::style::Color;
+use crossterm::{
+    queue,
+    style,
+};
+use eyre::{
+    Result,
+    bail,
+};
+use serde::Deserialize;
+
+use super::super::util::clipboard::paste_from_clipboard;
+use super::{
+    InvokeOutput,
+    OutputKind,
+};
+use crate::platform::Context;
+
+/// Asks the user for text the model can't get by itself, either what's on their clipboard or a
+/// block they paste in.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserInput {
+    pub source: UserInputSource,
+    /// Why the model needs the input, shown to the user.
+    pub reason: String,
+
+    /// Set by the program, since the user can only be asked in an interactive session.
+    #[serde(skip_deserializing)]
+    pub interactive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserInputSource {
+    Clipboard,
+    Paste,
+}
+
+impl UserInput {
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        let action = match self.source {
+            UserInputSource::Clipboard => "Reading your clipboard",
+            UserInputSource::Paste => "Asking you to paste some input",
+        };
+        queue!(
+            updates,
+            style::Print(action),
+            style::Print(": "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.reason),
+            style::SetForegroundColor(Color::Reset),
+            style::Print("\n"),
+        )?;
+        Ok(())
+    }
+
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        if !self.interactive {
+            bail!("user_input can only ask for input in an interactive session");
+        }
+
+        let text = match self.source {
+            UserInputSource::Clipboard => paste_from_clipboard(ctx).await?,
+            UserInputSource::Paste => {
+                queue!(
+                    updates,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print("Paste the input, then press "),
+                    style::Print(if cfg!(windows) { "Ctrl+Z and Enter" } else { "Ctrl+D" }),
+                    style::Print(" on an empty line to send it:\n"),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                updates.flush()?;
+                tokio::task::spawn_blocking(|| read_paste(std::io::stdin().lock())).await??
+            },
+        };
+
+        if text.trim().is_empty() {
+            bail!("The user didn't provide any input");
+        }
+        Ok(InvokeOutput {
+            output: OutputKind::Text(text),
+        })
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        if self.reason.trim().is_empty() {
+            bail!("reason must explain to the user what input is needed");
+        }
+        Ok(())
+    }
+}
+
+/// Reads everything up to end of input, which the user sends with Ctrl+D.
+fn read_paste(mut reader: impl Read) -> std::io::Result<String> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_user_input() {
+        let ctx = Context::new();
+        let mut tool = serde_json::from_value::<UserInput>(serde_json::json!({
+            "source": "paste",
+            "reason": "the failing test's output",
+        }))
+        .unwrap();
+        assert_eq!(tool.source, UserInputSource::Paste);
+        assert!(tool.validate(&ctx).await.is_ok());
+
+        // Never set for non-interactive sessions.
+        assert!(!tool.interactive);
+        assert!(tool.invoke(&ctx, &mut std::io::sink()).await.is_err());
+
+        assert_eq!(read_paste(&b"line 1\nline 2\n"[..]).unwrap(), "line 1\nline 2\n");
+    }
+}
@@ -0,0 +1,98 @@
+//! Tools defined by the user in `~/.aws/amazonq/tools.json`. Each one runs its `command` with the
+//! user's shell, passing the tool input as JSON on stdin and returning what it prints to stdout.
+
+use std::io::Write;
+use std::process::Stdio;
+
+use crossterm::{
+    queue,
+    style,
+};
+use eyre::{
+    Context as EyreContext,
+    Result,
+    bail,
+};
+use tokio::io::AsyncWriteExt;
+
+use super::{
+    InvokeOutput,
+    MAX_TOOL_RESPONSE_SIZE,
+    OutputKind,
+};
+use crate::cli::chat::CONTINUATION_LINE;
+use crate::cli::chat::util::shell::UserShell;
+use crate::cli::chat::util::truncate_safe;
+use crate::platform::Context;
+
+#[derive(Debug, Clone)]
+pub struct UserTool {
+    pub name: String,
+    /// The shell command from the tool's spec.
+    pub command: String,
+    /// The input supplied by the model, written to the command's stdin.
+    pub input: serde_json::Value,
+}
+
+impl UserTool {
+    pub async fn invoke(&self, ctx: &Context, _updates: impl Write) -> Result<InvokeOutput> {
+        let shell = UserShell::from_env(ctx);
+        let mut child = tokio::process::Command::from(shell.command(&self.command))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .wrap_err_with(|| format!("Unable to spawn command '{}'", self.command))?;
+
+        let input = serde_json::to_vec(&self.input)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // The command doesn't have to read its input, so a closed pipe isn't an error. The input
+        // is written alongside waiting so that a command printing a lot first can't block on it.
+        let (_, output) = tokio::join!(
+            async move {
+                stdin.write_all(&input).await.ok();
+            },
+            child.wait_with_output()
+        );
+        let output = output?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() {
+            bail!(
+                "{} failed with {}: {}",
+                self.name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(truncate_safe(&stdout, MAX_TOOL_RESPONSE_SIZE).to_string()),
+        })
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Running "),
+            style::SetForegroundColor(style::Color::Green),
+            style::Print(&self.command),
+            style::ResetColor,
+        )?;
+        let input = match serde_json::to_string_pretty(&self.input) {
+            Ok(input) => input
+                .split("\n")
+                .map(|p| format!("{CONTINUATION_LINE} {p}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => format!("{:?}", self.input),
+        };
+        queue!(updates, style::Print(" with the input:\n"), style::Print(input))?;
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        Ok(())
+    }
+}
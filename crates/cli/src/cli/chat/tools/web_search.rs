@@ -0,0 +1,225 @@
+use std::io::Write;
+
+use crossterm::style::Color;
+use crossterm::{
+    queue,
+    style,
+};
+use eyre::{
+    Result,
+    bail,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{
+    InvokeOutput,
+    OutputKind,
+};
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+
+/// Results returned when the model doesn't ask for a number.
+const DEFAULT_MAX_RESULTS: usize = 5;
+/// Upper bound on the results the model can ask for, to keep the tool result small.
+const MAX_RESULTS: usize = 10;
+
+/// Searches the web through the endpoint configured with `chat.webSearchEndpoint`.
+///
+/// The endpoint is sent a `GET` request with the query in the `q` parameter and the number of
+/// results wanted in `count`, along with `chat.webSearchApiKey` as a bearer token if set. It should
+/// respond with JSON results that have a `title`, a `url` (or `link`) and a `snippet` (or
+/// `description`), either as an array or under a `results` key.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebSearch {
+    pub query: String,
+    pub max_results: Option<usize>,
+
+    /// Set by the program from settings.
+    #[serde(skip_deserializing)]
+    pub endpoint: Option<WebSearchEndpoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSearchEndpoint {
+    pub url: String,
+    pub api_key: Option<String>,
+}
+
+impl WebSearchEndpoint {
+    /// Reads the endpoint from settings, returning `None` if web search isn't configured.
+    pub fn from_settings(database: &Database) -> Option<Self> {
+        let url = database
+            .settings
+            .get_string(Setting::ChatWebSearchEndpoint)
+            .filter(|url| !url.trim().is_empty())?;
+        Some(Self {
+            url,
+            api_key: database
+                .settings
+                .get_string(Setting::ChatWebSearchApiKey)
+                .filter(|key| !key.is_empty()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SearchResult {
+    title: String,
+    url: String,
+    snippet: String,
+}
+
+impl WebSearch {
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Searching the web for: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.query),
+            style::SetForegroundColor(Color::Reset),
+            style::Print("\n"),
+        )?;
+        Ok(())
+    }
+
+    pub async fn invoke(&self, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let Some(endpoint) = &self.endpoint else {
+            bail!("web_search isn't configured, set chat.webSearchEndpoint to enable it");
+        };
+        let count = self.max_results.unwrap_or(DEFAULT_MAX_RESULTS).clamp(1, MAX_RESULTS);
+
+        let mut request = crate::request::new_client()?
+            .get(&endpoint.url)
+            .query(&[("q", self.query.as_str()), ("count", &count.to_string())]);
+        if let Some(api_key) = &endpoint.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let body = request.send().await?.error_for_status()?.json::<Value>().await?;
+
+        let mut results = parse_results(&body);
+        results.truncate(count);
+        queue!(
+            updates,
+            style::Print(format!(
+                "Found {} result{}\n",
+                results.len(),
+                if results.len() == 1 { "" } else { "s" }
+            ))
+        )?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(format_results(&self.query, &results)),
+        })
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        if self.query.trim().is_empty() {
+            bail!("query must not be empty");
+        }
+        Ok(())
+    }
+}
+
+/// Extracts results from the common shapes of search API responses.
+fn parse_results(body: &Value) -> Vec<SearchResult> {
+    let items = match body {
+        Value::Array(items) => items,
+        _ => match body.get("results").or_else(|| body.pointer("/web/results")) {
+            Some(Value::Array(items)) => items,
+            _ => return Vec::new(),
+        },
+    };
+
+    let field = |item: &Value, names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| item.get(name).and_then(Value::as_str))
+            .map(str::to_string)
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            Some(SearchResult {
+                url: field(item, &["url", "link"])?,
+                title: field(item, &["title", "name"]).unwrap_or_default(),
+                snippet: field(item, &["snippet", "description", "content"]).unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Formats results as numbered sources, which the model cites as `[[n]](url)` so they're shown as
+/// citations.
+fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No results found for \"{query}\".");
+    }
+
+    let mut text = format!(
+        "Results for \"{query}\". When using a result, cite it inline as [[n]](url) with its number \
+         and URL.\n"
+    );
+    for (i, result) in results.iter().enumerate() {
+        text.push_str(&format!(
+            "\n[{}] {}\n{}\n{}\n",
+            i + 1,
+            result.title,
+            result.url,
+            result.snippet
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_results() {
+        let expected = vec![SearchResult {
+            title: "Rust".to_string(),
+            url: "https://www.rust-lang.org".to_string(),
+            snippet: "A language empowering everyone".to_string(),
+        }];
+        assert_eq!(
+            parse_results(&json!({ "results": [
+                { "title": "Rust", "url": "https://www.rust-lang.org", "snippet": "A language empowering everyone" },
+                { "title": "No URL" },
+            ]})),
+            expected
+        );
+        assert_eq!(
+            parse_results(&json!({ "web": { "results": [
+                { "title": "Rust", "url": "https://www.rust-lang.org", "description": "A language empowering everyone" },
+            ]}})),
+            expected
+        );
+        assert_eq!(
+            parse_results(&json!([
+                { "title": "Rust", "link": "https://www.rust-lang.org", "content": "A language empowering everyone" },
+            ])),
+            expected
+        );
+        assert!(parse_results(&json!({ "error": "rate limited" })).is_empty());
+    }
+
+    #[test]
+    fn test_format_results() {
+        let results = vec![SearchResult {
+            title: "Rust".to_string(),
+            url: "https://www.rust-lang.org".to_string(),
+            snippet: "A language empowering everyone".to_string(),
+        }];
+        assert_eq!(
+            format_results("rust", &results),
+            "Results for \"rust\". When using a result, cite it inline as [[n]](url) with its number and URL.\n\
+             \n[1] Rust\nhttps://www.rust-lang.org\nA language empowering everyone\n"
+        );
+        assert_eq!(format_results("rust", &[]), "No results found for \"rust\".");
+    }
+}
@@ -0,0 +1,179 @@
+//! Copying text to and reading it from the system clipboard by shelling out to the platform's
+//! clipboard utilities.
+
+use std::io::ErrorKind;
+use std::process::Stdio;
+
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+use crate::platform::{
+    Context,
+    Os,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardBackend {
+    Pbcopy,
+    WlCopy,
+    Xclip,
+    Xsel,
+    Clip,
+}
+
+impl ClipboardBackend {
+    /// Returns the backends to try for the current platform, in order of preference.
+    pub fn candidates(ctx: &Context) -> Vec<Self> {
+        match ctx.platform().os() {
+            Os::Mac => vec![Self::Pbcopy],
+            Os::Windows => vec![Self::Clip],
+            Os::Linux => {
+                let mut candidates = Vec::new();
+                if ctx.env().get("WAYLAND_DISPLAY").is_ok_and(|v| !v.is_empty()) {
+                    candidates.push(Self::WlCopy);
+                }
+                candidates.extend([Self::Xclip, Self::Xsel]);
+                candidates
+            },
+        }
+    }
+
+    pub fn program(&self) -> &'static str {
+        match self {
+            Self::Pbcopy => "pbcopy",
+            Self::WlCopy => "wl-copy",
+            Self::Xclip => "xclip",
+            Self::Xsel => "xsel",
+            Self::Clip => "clip",
+        }
+    }
+
+    fn args(&self) -> &'static [&'static str] {
+        match self {
+            Self::Xclip => &["-selection", "clipboard"],
+            Self::Xsel => &["--clipboard", "--input"],
+            Self::Pbcopy | Self::WlCopy | Self::Clip => &[],
+        }
+    }
+
+    /// The program and arguments that print the clipboard contents, the counterpart to
+    /// [Self::program].
+    fn paste_command(&self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Self::Pbcopy => ("pbpaste", &[]),
+            Self::WlCopy => ("wl-paste", &["--no-newline"]),
+            Self::Xclip => ("xclip", &["-selection", "clipboard", "-out"]),
+            Self::Xsel => ("xsel", &["--clipboard", "--output"]),
+            Self::Clip => ("powershell", &["-NoProfile", "-Command", "Get-Clipboard"]),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error("no clipboard utility found (tried {0})")]
+    NoBackend(String),
+    #[error("{0} exited with {1}")]
+    Failed(&'static str, std::process::ExitStatus),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Copies `text` to the clipboard using the first available backend, returning the backend used.
+pub async fn copy_to_clipboard(ctx: &Context, text: &str) -> Result<ClipboardBackend, ClipboardError> {
+    let candidates = ClipboardBackend::candidates(ctx);
+    for backend in &candidates {
+        let mut child = match tokio::process::Command::new(backend.program())
+            .args(backend.args())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).await?;
+        }
+        let status = child.wait().await?;
+        return match status.success() {
+            true => Ok(*backend),
+            false => Err(ClipboardError::Failed(backend.program(), status)),
+        };
+    }
+
+    Err(ClipboardError::NoBackend(
+        candidates.iter().map(|b| b.program()).collect::<Vec<_>>().join(", "),
+    ))
+}
+
+/// Returns the clipboard contents, read with the first available backend.
+pub async fn paste_from_clipboard(ctx: &Context) -> Result<String, ClipboardError> {
+    let candidates = ClipboardBackend::candidates(ctx);
+    for backend in &candidates {
+        let (program, args) = backend.paste_command();
+        let output = match tokio::process::Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .await
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        return match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+            false => Err(ClipboardError::Failed(program, output.status)),
+        };
+    }
+
+    Err(ClipboardError::NoBackend(
+        candidates
+            .iter()
+            .map(|b| b.paste_command().0)
+            .collect::<Vec<_>>()
+            .join(", "),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{
+        Env,
+        Platform,
+    };
+
+    #[test]
+    fn test_clipboard_candidates() {
+        let ctx = Context::builder()
+            .with_platform(Platform::new_fake(Os::Mac))
+            .build_fake();
+        assert_eq!(ClipboardBackend::candidates(&ctx), vec![ClipboardBackend::Pbcopy]);
+
+        let ctx = Context::builder()
+            .with_platform(Platform::new_fake(Os::Linux))
+            .with_env(Env::from_slice(&[("WAYLAND_DISPLAY", "wayland-0")]))
+            .build_fake();
+        assert_eq!(ClipboardBackend::candidates(&ctx), vec![
+            ClipboardBackend::WlCopy,
+            ClipboardBackend::Xclip,
+            ClipboardBackend::Xsel
+        ]);
+
+        let ctx = Context::builder()
+            .with_platform(Platform::new_fake(Os::Linux))
+            .with_env(Env::from_slice(&[]))
+            .build_fake();
+        assert_eq!(ClipboardBackend::candidates(&ctx), vec![
+            ClipboardBackend::Xclip,
+            ClipboardBackend::Xsel
+        ]);
+    }
+}
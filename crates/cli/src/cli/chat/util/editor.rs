@@ -0,0 +1,111 @@
+//! Writing prompts in the user's editor, for `/editor` and Ctrl+X Ctrl+E at the prompt.
+
+use std::io::Write;
+use std::process::Command;
+
+use eyre::{
+    Result,
+    bail,
+};
+
+use crate::platform::Env;
+
+/// Returns the editor to edit prompts with, from `$VISUAL` or `$EDITOR`, falling back to `vi`.
+pub fn editor_command(env: &Env) -> String {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|var| env.get(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Opens `text` in `editor` and returns what it was saved as, without the trailing newlines most
+/// editors add.
+pub fn edit_in_editor(editor: &str, text: &str) -> Result<String> {
+    let Some((program, args)) = shlex::split(editor).and_then(|mut parts| {
+        let program = (!parts.is_empty()).then(|| parts.remove(0))?;
+        Some((program, parts))
+    }) else {
+        bail!("failed to parse the editor command: {editor}");
+    };
+
+    let mut file = tempfile::Builder::new().prefix("q_prompt_").suffix(".md").tempfile()?;
+    file.write_all(text.as_bytes())?;
+    file.flush()?;
+
+    let status = Command::new(program).args(args).arg(file.path()).status()?;
+    if !status.success() {
+        bail!("the editor exited with {status}");
+    }
+
+    let content = std::fs::read_to_string(file.path())?;
+    Ok(content.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Runs `f` with the terminal out of raw mode, e.g. to start an editor from a key binding while
+/// the line editor is reading input, and puts it back in the mode it was in afterwards.
+#[cfg(unix)]
+pub fn with_cooked_terminal<T>(f: impl FnOnce() -> T) -> T {
+    use nix::sys::termios::{
+        InputFlags,
+        LocalFlags,
+        OutputFlags,
+        SetArg,
+        tcgetattr,
+        tcsetattr,
+    };
+
+    let stdin = std::io::stdin();
+    let saved = tcgetattr(&stdin).ok();
+    if let Some(raw) = &saved {
+        let mut cooked = raw.clone();
+        cooked.local_flags |= LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG | LocalFlags::IEXTEN;
+        cooked.input_flags |= InputFlags::ICRNL | InputFlags::IXON;
+        cooked.output_flags |= OutputFlags::OPOST;
+        tcsetattr(&stdin, SetArg::TCSADRAIN, &cooked).ok();
+    }
+
+    let result = f();
+
+    if let Some(raw) = &saved {
+        tcsetattr(&stdin, SetArg::TCSADRAIN, raw).ok();
+    }
+    result
+}
+
+#[cfg(not(unix))]
+pub fn with_cooked_terminal<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_command() {
+        assert_eq!(editor_command(&Env::from_slice(&[])), "vi");
+        assert_eq!(editor_command(&Env::from_slice(&[("EDITOR", "nano")])), "nano");
+        assert_eq!(
+            editor_command(&Env::from_slice(&[("VISUAL", "code --wait"), ("EDITOR", "nano")])),
+            "code --wait"
+        );
+        assert_eq!(
+            editor_command(&Env::from_slice(&[("VISUAL", " "), ("EDITOR", "nano")])),
+            "nano"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_in_editor() {
+        assert_eq!(
+            edit_in_editor("sed -i s/draft/final/", "a draft\nprompt").unwrap(),
+            "a final\nprompt"
+        );
+        assert!(edit_in_editor("false", "unchanged").is_err());
+        assert!(edit_in_editor("", "unchanged").is_err());
+        // Outside of a terminal there's no mode to change.
+        assert_eq!(with_cooked_terminal(|| 1), 1);
+    }
+}
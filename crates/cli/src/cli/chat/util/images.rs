@@ -3,6 +3,8 @@ use std::io::Write;
 use std::path::Path;
 use std::str::FromStr;
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use crossterm::execute;
 use crossterm::style::{
     self,
@@ -22,6 +24,7 @@ use crate::cli::chat::consts::{
     MAX_IMAGE_SIZE,
     MAX_NUMBER_OF_IMAGES_PER_REQUEST,
 };
+use crate::cli::chat::tools::ToolProgress;
 use crate::platform::{
     self,
     Context,
@@ -60,11 +63,20 @@ pub fn pre_process(ctx: &Context, path: &str) -> String {
     path.to_string()
 }
 
-pub fn handle_images_from_paths(output: &mut impl Write, paths: &[String]) -> RichImageBlocks {
+/// Loads the supported images among `paths`, reporting each one to `progress` as it's read.
+pub fn handle_images_from_paths(
+    output: &mut impl Write,
+    paths: &[String],
+    mut progress: Option<&mut ToolProgress>,
+) -> RichImageBlocks {
     let mut extracted_images = Vec::new();
     let mut seen_args = std::collections::HashSet::new();
 
     for path in paths.iter() {
+        if let Some(progress) = progress.as_deref_mut() {
+            let filename = Path::new(path).file_name().unwrap_or_default().to_string_lossy();
+            progress.advance(output, &filename).ok();
+        }
         if seen_args.contains(path) {
             continue;
         }
@@ -87,6 +99,10 @@ pub fn handle_images_from_paths(output: &mut impl Write, paths: &[String]) -> Ri
             }
         }
     }
+    // Anything printed from here on goes on its own lines rather than after the bar.
+    if let Some(progress) = progress {
+        progress.finish(output).ok();
+    }
 
     let (mut valid_images, images_exceeding_size_limit): (RichImageBlocks, RichImageBlocks) = extracted_images
         .into_iter()
@@ -186,6 +202,86 @@ pub fn get_image_block_from_file_path(maybe_file_path: &str) -> Option<ImageBloc
     Some(image_block)
 }
 
+/// Escape sequences a terminal can draw images with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    /// iTerm2's inline images, also supported by e.g. WezTerm.
+    Iterm2,
+    /// kitty's graphics protocol, also supported by e.g. Ghostty.
+    Kitty,
+}
+
+/// Returns the protocol images can be drawn with in the terminal, if any. There's no way to query
+/// this, so it's guessed from the environment variables set by terminals that support one. Callers
+/// check that the output is a terminal at all.
+pub fn image_protocol(ctx: &Context) -> Option<ImageProtocol> {
+    let env = ctx.env();
+    let term_program = env.get("TERM_PROGRAM").unwrap_or_default();
+    if matches!(term_program.as_str(), "iTerm.app" | "WezTerm") {
+        Some(ImageProtocol::Iterm2)
+    } else if term_program == "ghostty"
+        || env.get("KITTY_WINDOW_ID").is_ok()
+        || env.get("TERM").is_ok_and(|term| term == "xterm-kitty")
+    {
+        Some(ImageProtocol::Kitty)
+    } else {
+        None
+    }
+}
+
+/// Draws an image read by `fs_read`, or prints where it's saved if the terminal can't draw it.
+///
+/// Images in the results of MCP tools aren't drawn: they're replaced with a short description
+/// before the result reaches the chat, and there's no file to point to.
+pub fn print_image(
+    output: &mut impl Write,
+    protocol: Option<ImageProtocol>,
+    image: &ImageBlock,
+    path: &str,
+) -> std::io::Result<()> {
+    let bytes = match &image.source {
+        ImageSource::Bytes(bytes) => Some(bytes),
+        ImageSource::Unknown => None,
+    };
+
+    match (protocol, bytes) {
+        (Some(ImageProtocol::Iterm2), Some(bytes)) => {
+            writeln!(
+                output,
+                "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+                bytes.len(),
+                BASE64.encode(bytes)
+            )
+        },
+        // Other formats would have to be decoded first.
+        (Some(ImageProtocol::Kitty), Some(bytes)) if image.format == ImageFormat::Png => {
+            // The data is sent in chunks of at most 4096 bytes, each one saying whether more follow.
+            let encoded = BASE64.encode(bytes);
+            let mut chunks = encoded.as_bytes().chunks(4096).peekable();
+            let mut first = true;
+            while let Some(chunk) = chunks.next() {
+                let more = u8::from(chunks.peek().is_some());
+                let control = if first {
+                    format!("f=100,a=T,m={more}")
+                } else {
+                    format!("m={more}")
+                };
+                output.write_all(format!("\x1b_G{control};").as_bytes())?;
+                output.write_all(chunk)?;
+                output.write_all(b"\x1b\\")?;
+                first = false;
+            }
+            writeln!(output)
+        },
+        _ => execute!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("[image: {path}]\n")),
+            style::SetForegroundColor(Color::Reset)
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -236,9 +332,9 @@ mod tests {
         let image_path = temp_dir.path().join("test_image.jpg");
         std::fs::write(&image_path, b"fake_image_data").unwrap();
 
-        let mut output = SharedWriter::stdout();
+        let mut output = SharedWriter::null();
 
-        let images = handle_images_from_paths(&mut output, &[image_path.to_string_lossy().to_string()]);
+        let images = handle_images_from_paths(&mut output, &[image_path.to_string_lossy().to_string()], None);
 
         assert_eq!(images.len(), 1);
         assert_eq!(images[0].1.filename, "test_image.jpg");
@@ -272,7 +368,7 @@ mod tests {
         let test_writer = TestWriterWithSink { sink: buf.clone() };
         let mut output = SharedWriter::new(test_writer.clone());
 
-        let images = handle_images_from_paths(&mut output, &[large_image_path.to_string_lossy().to_string()]);
+        let images = handle_images_from_paths(&mut output, &[large_image_path.to_string_lossy().to_string()], None);
         let content = test_writer.get_content();
         let output_str = content.to_str_lossy();
         print!("{}", output_str);
@@ -292,10 +388,73 @@ mod tests {
             std::fs::write(&image_path, b"fake_image_data").unwrap();
         }
 
-        let mut output = SharedWriter::stdout();
+        let mut output = SharedWriter::null();
 
-        let images = handle_images_from_paths(&mut output, &paths);
+        let images = handle_images_from_paths(&mut output, &paths, None);
 
         assert_eq!(images.len(), MAX_NUMBER_OF_IMAGES_PER_REQUEST);
     }
+
+    #[test]
+    fn test_image_protocol() {
+        let detect = |vars: &[(&str, &str)]| {
+            image_protocol(&Context::builder().with_env(platform::Env::from_slice(vars)).build())
+        };
+        assert_eq!(detect(&[("TERM_PROGRAM", "iTerm.app")]), Some(ImageProtocol::Iterm2));
+        assert_eq!(detect(&[("TERM_PROGRAM", "WezTerm")]), Some(ImageProtocol::Iterm2));
+        assert_eq!(detect(&[("TERM", "xterm-kitty")]), Some(ImageProtocol::Kitty));
+        assert_eq!(detect(&[("KITTY_WINDOW_ID", "1")]), Some(ImageProtocol::Kitty));
+        assert_eq!(detect(&[("TERM_PROGRAM", "Apple_Terminal")]), None);
+        assert_eq!(detect(&[]), None);
+    }
+
+    #[test]
+    fn test_print_image() {
+        let png = ImageBlock {
+            format: ImageFormat::Png,
+            source: ImageSource::Bytes(b"png".to_vec()),
+        };
+        let print = |protocol, image: &ImageBlock| {
+            let mut output = vec![];
+            print_image(&mut output, protocol, image, "/tmp/shot.png").unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            print(Some(ImageProtocol::Iterm2), &png),
+            "\x1b]1337;File=inline=1;size=3;preserveAspectRatio=1:cG5n\x07\n"
+        );
+        assert_eq!(
+            print(Some(ImageProtocol::Kitty), &png),
+            "\x1b_Gf=100,a=T,m=0;cG5n\x1b\\\n"
+        );
+
+        // kitty only takes PNGs as they are, so other formats fall back to the path.
+        let jpeg = ImageBlock {
+            format: ImageFormat::Jpeg,
+            source: ImageSource::Bytes(b"jpeg".to_vec()),
+        };
+        assert_eq!(
+            strip_ansi_escapes::strip_str(print(Some(ImageProtocol::Kitty), &jpeg)),
+            "[image: /tmp/shot.png]\n"
+        );
+        assert_eq!(
+            strip_ansi_escapes::strip_str(print(None, &png)),
+            "[image: /tmp/shot.png]\n"
+        );
+    }
+
+    #[test]
+    fn test_print_image_kitty_chunks() {
+        let image = ImageBlock {
+            format: ImageFormat::Png,
+            source: ImageSource::Bytes(vec![0; 6000]),
+        };
+        let mut output = vec![];
+        print_image(&mut output, Some(ImageProtocol::Kitty), &image, "").unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("\x1b_G").count(), 2);
+        assert!(output.starts_with("\x1b_Gf=100,a=T,m=1;"));
+        assert!(output.contains("\x1b_Gm=0;"));
+    }
 }
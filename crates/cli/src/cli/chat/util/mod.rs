@@ -1,9 +1,14 @@
+pub mod clipboard;
+pub mod editor;
 pub mod images;
 pub mod issue;
+pub mod redact;
 pub mod shared_writer;
+pub mod shell;
 pub mod ui;
 
 use std::io::Write;
+use std::process::Stdio;
 use std::time::Duration;
 
 use aws_smithy_types::{
@@ -11,15 +16,32 @@ use aws_smithy_types::{
     Number as SmithyNumber,
 };
 use eyre::Result;
+use tracing::warn;
+use unicode_segmentation::UnicodeSegmentation;
 
+use self::shell::UserShell;
 use super::ChatError;
 use super::token_counter::TokenCounter;
+use crate::api_client::Endpoint;
 use crate::util::system_info::in_cloudshell;
 
 const GOV_REGIONS: &[&str] = &["us-gov-east-1", "us-gov-west-1"];
 
-pub fn region_check(capability: &'static str) -> eyre::Result<()> {
-    let Ok(region) = std::env::var("AWS_REGION") else {
+/// Checks that `capability` is available in the session's region, which is `region` when one was
+/// picked with `--region` and `AWS_REGION` otherwise. A picked region must also have an endpoint.
+pub fn region_check(capability: &'static str, region: Option<&str>) -> eyre::Result<()> {
+    if let Some(region) = region {
+        let endpoints = Endpoint::available();
+        if !endpoints.iter().any(|e| e.region().as_ref() == region) {
+            let valid = endpoints.iter().map(|e| e.region().to_string()).collect::<Vec<_>>();
+            eyre::bail!(
+                "Region {region} is not supported for {capability}. Valid regions are: {}",
+                valid.join(", ")
+            );
+        }
+    }
+
+    let Some(region) = region.map(str::to_owned).or_else(|| std::env::var("AWS_REGION").ok()) else {
         return Ok(());
     };
 
@@ -30,22 +52,110 @@ pub fn region_check(capability: &'static str) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Truncates `s` to at most `max_bytes` bytes without splitting an extended grapheme cluster, so
+/// that e.g. emoji with modifiers or letters with combining marks are kept whole or dropped.
 pub fn truncate_safe(s: &str, max_bytes: usize) -> &str {
     if s.len() <= max_bytes {
         return s;
     }
 
-    let mut byte_count = 0;
-    let mut char_indices = s.char_indices();
+    let end = s
+        .grapheme_indices(true)
+        .map(|(i, grapheme)| i + grapheme.len())
+        .take_while(|&end| end <= max_bytes)
+        .last()
+        .unwrap_or(0);
+
+    &s[..end]
+}
+
+/// Returns the contents of every fenced code block in `text`, in order and without the fences. An
+/// unclosed block extends to the end of the text.
+pub fn code_blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines();
+    while lines.any(|line| line.trim_start().starts_with("```")) {
+        let code = lines
+            .by_ref()
+            .take_while(|line| !line.trim_start().starts_with("```"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        blocks.push(code);
+    }
+    blocks
+}
+
+/// Returns the contents of the first fenced code block in `text`, without the fences.
+pub fn first_code_block(text: &str) -> Option<String> {
+    code_blocks(text).into_iter().next()
+}
+
+/// Controls the pacing used when printing a streamed response, set with `chat.typewriterSpeed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypewriterSpeed {
+    Off,
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+}
+
+impl TypewriterSpeed {
+    /// Backlog of unprinted bytes past which output is printed without any delay.
+    const MAX_BACKLOG: usize = 2048;
+    /// Backlog of unprinted bytes up to which the full per-chunk delay is used.
+    const STEADY_BACKLOG: usize = 64;
+
+    pub fn from_setting(value: Option<&str>) -> Self {
+        match value.map(str::to_lowercase).as_deref() {
+            Some("off") => Self::Off,
+            Some("slow") => Self::Slow,
+            Some("fast") => Self::Fast,
+            Some("normal") | None => Self::Normal,
+            Some(other) => {
+                warn!("unknown chat.typewriterSpeed '{other}', using normal");
+                Self::Normal
+            },
+        }
+    }
+
+    fn base_delay(&self) -> Duration {
+        match self {
+            Self::Off => Duration::ZERO,
+            Self::Slow => Duration::from_millis(16),
+            Self::Normal => Duration::from_millis(8),
+            Self::Fast => Duration::from_millis(4),
+        }
+    }
 
-    for (byte_idx, _) in &mut char_indices {
-        if byte_count + (byte_idx - byte_count) > max_bytes {
-            break;
+    /// Returns how long to pause after printing a parsed chunk, given the number of bytes that
+    /// are still waiting to be printed. The pause shrinks as the backlog grows so that text which
+    /// arrives faster than it is printed catches up instead of trailing behind the stream.
+    pub fn delay(&self, pending_bytes: usize) -> Duration {
+        let base = self.base_delay();
+        if pending_bytes <= Self::STEADY_BACKLOG {
+            base
+        } else if pending_bytes >= Self::MAX_BACKLOG {
+            Duration::ZERO
+        } else {
+            base.mul_f64(Self::STEADY_BACKLOG as f64 / pending_bytes as f64)
         }
-        byte_count = byte_idx;
     }
+}
 
-    &s[..byte_count]
+/// Formats a size in bytes for display, e.g. `512 B` or `1.5 KB`.
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
 }
 
 pub fn animate_output(output: &mut impl Write, bytes: &[u8]) -> Result<(), ChatError> {
@@ -70,6 +180,39 @@ pub fn play_notification_bell(requires_confirmation: bool) {
     }
 }
 
+/// Notify the user that a response has completed by running `notify_cmd` with the user's `shell`.
+/// Without a command, or if the shell can't be started, a desktop notification showing `message`
+/// is requested with OSC 9 and the terminal bell is rung for terminals that don't support it. The
+/// command is not waited on.
+pub fn notify_response_complete(shell: &UserShell, notify_cmd: Option<&str>, message: &str) {
+    if let Some(cmd) = notify_cmd.map(str::trim).filter(|cmd| !cmd.is_empty()) {
+        match tokio::process::Command::from(shell.command(cmd))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(_) => return,
+            Err(err) => warn!(
+                ?err,
+                shell = %shell.program,
+                "Failed to run the response complete notification command"
+            ),
+        }
+    }
+
+    print!("{}", osc9_notification(message));
+    let _ = std::io::stdout().flush();
+    play_notification_bell(true);
+}
+
+/// The OSC 9 escape sequence that asks terminals like iTerm2, WezTerm, and kitty to show `message`
+/// as a desktop notification. Control characters are dropped since they'd end the sequence early.
+fn osc9_notification(message: &str) -> String {
+    let message = message.chars().filter(|c| !c.is_control()).collect::<String>();
+    format!("\x1b]9;{message}\x07")
+}
+
 /// Determine if we should play the bell based on terminal type
 fn should_play_bell() -> bool {
     // Get the TERM environment variable
@@ -187,12 +330,86 @@ pub fn document_to_serde_value(value: Document) -> serde_json::Value {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024 * 1024), "5120.0 GB");
+    }
+
+    #[test]
+    fn test_osc9_notification() {
+        assert_eq!(osc9_notification("Response ready"), "\x1b]9;Response ready\x07");
+        assert_eq!(osc9_notification("done\x07\n\x1b]9;x"), "\x1b]9;done]9;x\x07");
+    }
+
     #[test]
     fn test_truncate_safe() {
         assert_eq!(truncate_safe("Hello World", 5), "Hello");
         assert_eq!(truncate_safe("Hello ", 5), "Hello");
         assert_eq!(truncate_safe("Hello World", 11), "Hello World");
         assert_eq!(truncate_safe("Hello World", 15), "Hello World");
+
+        // Multi-byte characters are never split.
+        assert_eq!(truncate_safe("héllo", 2), "h");
+        assert_eq!(truncate_safe("héllo", 3), "hé");
+
+        // ZWJ sequences and emoji with skin tone modifiers are kept whole.
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(truncate_safe(&format!("a{family}b"), family.len()), "a");
+        assert_eq!(
+            truncate_safe(&format!("a{family}b"), family.len() + 1),
+            format!("a{family}")
+        );
+        assert_eq!(truncate_safe("👍🏽👍🏽", 6), "");
+        assert_eq!(truncate_safe("👍🏽👍🏽", 8), "👍🏽");
+
+        // So are letters with combining marks.
+        let e_acute = "e\u{301}";
+        assert_eq!(truncate_safe(&format!("{e_acute}{e_acute}"), 4), e_acute);
+        assert_eq!(truncate_safe(&format!("{e_acute}{e_acute}"), 2), "");
+    }
+
+    #[test]
+    fn test_region_check() {
+        for endpoint in Endpoint::available() {
+            assert!(region_check("chat", Some(endpoint.region().as_ref())).is_ok());
+        }
+
+        let err = region_check("chat", Some("mars-north-1")).unwrap_err().to_string();
+        assert!(err.starts_with("Region mars-north-1 is not supported for chat. Valid regions are: "));
+        assert!(err.contains(Endpoint::DEFAULT_ENDPOINT.region().as_ref()));
+    }
+
+    #[test]
+    fn test_typewriter_speed() {
+        assert_eq!(TypewriterSpeed::from_setting(None), TypewriterSpeed::Normal);
+        assert_eq!(TypewriterSpeed::from_setting(Some("OFF")), TypewriterSpeed::Off);
+        assert_eq!(TypewriterSpeed::from_setting(Some("bogus")), TypewriterSpeed::Normal);
+
+        let normal = TypewriterSpeed::Normal;
+        assert_eq!(normal.delay(10), Duration::from_millis(8));
+        assert_eq!(normal.delay(128), Duration::from_millis(4));
+        assert_eq!(normal.delay(4096), Duration::ZERO);
+        assert!(TypewriterSpeed::Slow.delay(10) > TypewriterSpeed::Fast.delay(10));
+        assert_eq!(TypewriterSpeed::Off.delay(0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_first_code_block() {
+        let text = "Try this:\n```rust\nfn main() {}\n```\nor\n```\nsecond\n```";
+        assert_eq!(first_code_block(text), Some("fn main() {}".to_string()));
+        assert_eq!(first_code_block("```sh\necho hi"), Some("echo hi".to_string()));
+        assert_eq!(first_code_block("no code here"), None);
+    }
+
+    #[test]
+    fn test_code_blocks() {
+        let text = "Try this:\n```rust\nfn main() {}\n```\nor\n  ```\nsecond\nblock\n  ```\nand\n```sh\necho hi";
+        assert_eq!(code_blocks(text), vec!["fn main() {}", "second\nblock", "echo hi"]);
+        assert!(code_blocks("no code here").is_empty());
     }
 
     #[test]
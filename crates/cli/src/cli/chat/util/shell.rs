@@ -0,0 +1,88 @@
+//! Resolving the user's shell for running `!command` input and the `chat.notifyCmd` hook.
+
+use crate::platform::{
+    Context,
+    Os,
+};
+
+/// A shell along with the flag it uses to run a single command string, e.g. `sh -c <command>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserShell {
+    pub program: String,
+    pub command_flag: &'static str,
+}
+
+impl UserShell {
+    /// Uses `$SHELL` (falling back to `/bin/sh`) on Unix and `%COMSPEC%` (falling back to `cmd`) on
+    /// Windows.
+    pub fn from_env(ctx: &Context) -> Self {
+        let (var, fallback) = match ctx.platform().os() {
+            Os::Windows => ("COMSPEC", "cmd"),
+            Os::Mac | Os::Linux => ("SHELL", "/bin/sh"),
+        };
+        let program = ctx
+            .env()
+            .get(var)
+            .ok()
+            .filter(|program| !program.trim().is_empty())
+            .unwrap_or_else(|| fallback.to_string());
+
+        // Split on both separators since Windows paths are handled on any host.
+        let name = program.rsplit(['/', '\\']).next().unwrap_or_default().to_lowercase();
+        let command_flag = match name.trim_end_matches(".exe") {
+            "cmd" => "/C",
+            "powershell" | "pwsh" => "-Command",
+            _ => "-c",
+        };
+
+        Self { program, command_flag }
+    }
+
+    pub fn command(&self, command: &str) -> std::process::Command {
+        let mut cmd = std::process::Command::new(&self.program);
+        cmd.args([self.command_flag, command]);
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{
+        Env,
+        Platform,
+    };
+
+    fn shell_for(os: Os, env: &[(&str, &str)]) -> UserShell {
+        let ctx = Context::builder()
+            .with_platform(Platform::new_fake(os))
+            .with_env(Env::from_slice(env))
+            .build_fake();
+        UserShell::from_env(&ctx)
+    }
+
+    #[test]
+    fn test_user_shell_from_env() {
+        assert_eq!(shell_for(Os::Linux, &[("SHELL", "/usr/bin/fish")]), UserShell {
+            program: "/usr/bin/fish".to_string(),
+            command_flag: "-c",
+        });
+        assert_eq!(shell_for(Os::Linux, &[]), UserShell {
+            program: "/bin/sh".to_string(),
+            command_flag: "-c",
+        });
+        assert_eq!(shell_for(Os::Mac, &[("SHELL", "")]).program, "/bin/sh");
+        assert_eq!(shell_for(Os::Windows, &[]), UserShell {
+            program: "cmd".to_string(),
+            command_flag: "/C",
+        });
+        assert_eq!(
+            shell_for(Os::Windows, &[(
+                "COMSPEC",
+                r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe"
+            )])
+            .command_flag,
+            "-Command"
+        );
+    }
+}
@@ -18,6 +18,11 @@ use anstream::{
 };
 pub use chat::ConversationState;
 use chat::cli::Chat;
+pub use chat::session::{
+    ChatSession,
+    ChatSessionBuilder,
+    ResponseEvent,
+};
 use clap::{
     ArgAction,
     CommandFactory,
@@ -315,12 +320,15 @@ impl Cli {
 
 #[cfg(test)]
 mod test {
+    use std::path::PathBuf;
+
     use super::*;
     use crate::cli::chat::cli::{
         McpAdd,
         McpImport,
         McpList,
         McpRemove,
+        OutputFormat,
         Scope,
     };
 
@@ -369,11 +377,25 @@ mod test {
             subcommand: Some(CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
+                interactive: false,
                 resume: false,
                 input: None,
+                file: None,
                 profile: None,
+                region: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })),
             verbose: 2,
             help_all: false,
@@ -408,11 +430,25 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
+                interactive: false,
                 resume: false,
                 input: None,
+                file: None,
                 profile: Some("my-profile".to_string()),
+                region: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })
         );
     }
@@ -424,11 +460,25 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
+                interactive: false,
                 resume: false,
                 input: Some("Hello".to_string()),
+                file: None,
                 profile: Some("my-profile".to_string()),
+                region: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })
         );
     }
@@ -440,11 +490,25 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: true,
                 no_interactive: false,
+                interactive: false,
                 resume: false,
                 input: None,
+                file: None,
                 profile: Some("my-profile".to_string()),
+                region: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })
         );
     }
@@ -456,11 +520,25 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: true,
+                interactive: false,
                 resume: true,
                 input: None,
+                file: None,
                 profile: None,
+                region: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })
         );
         assert_parse!(
@@ -468,13 +546,58 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: true,
+                interactive: false,
                 resume: true,
                 input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_interactive() {
+        assert_parse!(
+            ["chat", "--interactive"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: true,
+                resume: false,
+                input: None,
+                file: None,
                 profile: None,
+                region: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })
         );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--interactive", "--no-interactive"]).is_err());
     }
 
     #[test]
@@ -484,11 +607,25 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
+                interactive: false,
                 resume: false,
                 input: None,
+                file: None,
                 profile: None,
+                region: None,
                 trust_all_tools: true,
                 trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })
         );
     }
@@ -500,11 +637,25 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
+                interactive: false,
                 resume: false,
                 input: None,
+                file: None,
                 profile: None,
+                region: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["".to_string()]),
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
             })
         );
     }
@@ -516,14 +667,330 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
+                interactive: false,
                 resume: false,
                 input: None,
+                file: None,
                 profile: None,
+                region: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["fs_read".to_string(), "fs_write".to_string()]),
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_output_format_json() {
+        assert_parse!(
+            ["chat", "--no-interactive", "--output-format", "json"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: true,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Json,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_file() {
+        assert_parse!(
+            ["chat", "--file", "prompt.md"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: Some(PathBuf::from("prompt.md")),
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_max_turns() {
+        assert_parse!(
+            ["chat", "--no-interactive", "--max-turns", "5"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: true,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: Some(5),
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_dry_run() {
+        assert_parse!(
+            ["chat", "--dry-run", "question"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: Some("question".to_string()),
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: true,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_quiet() {
+        assert_parse!(
+            ["chat", "--quiet"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: true,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_no_stream() {
+        assert_parse!(
+            ["chat", "--no-stream"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: true,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_list_profiles() {
+        assert_parse!(
+            ["chat", "--list-profiles"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: true,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_record_and_replay() {
+        assert_parse!(
+            ["chat", "--record", "session.json"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: Some(PathBuf::from("session.json")),
+                replay: None,
+                system_prompt_file: None,
             })
         );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--record", "a.json", "--replay", "b.json"]).is_err());
     }
+
+    #[test]
+    fn test_chat_with_system_prompt_file() {
+        assert_parse!(
+            ["chat", "--system-prompt-file", "prompt.txt"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: Some(PathBuf::from("prompt.txt")),
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_region() {
+        assert_parse!(
+            ["chat", "--region", "eu-central-1"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                interactive: false,
+                resume: false,
+                input: None,
+                file: None,
+                profile: None,
+                region: Some("eu-central-1".to_string()),
+                trust_all_tools: false,
+                trust_tools: None,
+                approval_file: None,
+                keep_scratch: false,
+                output_format: OutputFormat::Text,
+                max_turns: None,
+                dry_run: false,
+                quiet: false,
+                no_stream: false,
+                list_profiles: false,
+                record: None,
+                replay: None,
+                system_prompt_file: None,
+            })
+        );
+    }
+
     #[test]
     fn test_mcp_subcomman_add() {
         assert_parse!(
@@ -1,9 +1,6 @@
 use std::fmt;
 use std::fmt::Display;
-use std::process::{
-    ExitCode,
-    exit,
-};
+use std::process::ExitCode;
 use std::time::Duration;
 
 use anstream::{
@@ -127,9 +124,12 @@ impl UserSubcommand {
                     );
                 }
 
-                login_interactive(database, telemetry, args).await?;
-
-                Ok(ExitCode::SUCCESS)
+                match login_interactive(database, telemetry, args).await {
+                    Ok(()) => Ok(ExitCode::SUCCESS),
+                    // Pressing Ctrl+C is enough of an explanation, so there's no error to print.
+                    Err(err) if err.is::<LoginCancelled>() => Ok(ExitCode::FAILURE),
+                    Err(err) => Err(err),
+                }
             },
             Self::Logout => {
                 let _ = crate::auth::logout(database).await;
@@ -207,6 +207,12 @@ impl UserSubcommand {
     }
 }
 
+/// Returned by [login_interactive] when the user presses Ctrl+C while waiting on the browser, so
+/// that signing in again from a chat whose session expired can be abandoned without exiting.
+#[derive(Debug, thiserror::Error)]
+#[error("Login cancelled")]
+pub struct LoginCancelled;
+
 pub async fn login_interactive(database: &mut Database, telemetry: &TelemetryThread, args: LoginArgs) -> Result<()> {
     let login_method = match args.license {
         Some(LicenseType::Free) => AuthMethod::BuilderId,
@@ -270,8 +276,8 @@ pub async fn login_interactive(database: &mut Database, telemetry: &TelemetryThr
                         tokio::select! {
                             res = registration.finish(&client, Some(database)) => res?,
                             Ok(_) = ctrl_c_stream => {
-                                #[allow(clippy::exit)]
-                                exit(1);
+                                spinner.stop();
+                                return Err(LoginCancelled.into());
                             },
                         }
                         telemetry.send_user_logged_in().ok();
@@ -329,8 +335,8 @@ async fn try_device_authorization(
         tokio::select! {
             _ = tokio::time::sleep(Duration::from_secs(device_auth.interval.try_into().unwrap_or(1))) => (),
             Ok(_) = ctrl_c_stream => {
-                #[allow(clippy::exit)]
-                exit(1);
+                spinner.stop();
+                return Err(LoginCancelled.into());
             }
         }
         match poll_create_token(
@@ -60,6 +60,7 @@ const IDC_REGION_KEY: &str = "auth.idc.region";
 // We include this key to remove for backwards compatibility
 const CUSTOMIZATION_STATE_KEY: &str = "api.selectedCustomization";
 const ROTATING_TIP_KEY: &str = "chat.greeting.rotating_tips_current_index";
+const LAST_CONVERSATION_PATH_KEY: &str = "chat.lastConversationPath";
 
 const MIGRATIONS: &[Migration] = migrations![
     "000_migration_table",
@@ -86,6 +87,13 @@ pub struct AuthProfile {
     pub profile_name: String,
 }
 
+impl AuthProfile {
+    /// The region in the profile's ARN, which is the only one its requests can be sent to.
+    pub fn region(&self) -> &str {
+        self.arn.split(':').nth(3).unwrap_or_default()
+    }
+}
+
 impl From<amzn_codewhisperer_client::types::Profile> for AuthProfile {
     fn from(profile: amzn_codewhisperer_client::types::Profile) -> Self {
         Self {
@@ -333,7 +341,20 @@ impl Database {
             None => return Ok(0),
         };
 
-        self.set_json_entry(Table::Conversations, path, state)
+        let count = self.set_json_entry(Table::Conversations, path, state)?;
+        self.set_entry(Table::State, LAST_CONVERSATION_PATH_KEY, path)?;
+        Ok(count)
+    }
+
+    /// Get the chat conversation that was saved most recently, along with its path.
+    pub fn get_last_conversation(&mut self) -> Result<Option<(String, ConversationState)>, DatabaseError> {
+        let Some(path) = self.get_entry::<String>(Table::State, LAST_CONVERSATION_PATH_KEY)? else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .get_json_entry(Table::Conversations, &path)?
+            .map(|conversation| (path, conversation)))
     }
 
     pub async fn get_secret(&self, key: &str) -> Result<Option<Secret>, DatabaseError> {
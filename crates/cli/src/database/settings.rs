@@ -26,6 +26,45 @@ pub enum Setting {
     ApiTimeout,
     ChatEditMode,
     ChatEnableNotifications,
+    ChatNotifyOnComplete,
+    ChatNotifyCmd,
+    ChatContextWarningTokens,
+    ChatCodeHighlighting,
+    ChatTypewriterSpeed,
+    ChatToolPolicy,
+    ChatDiffPreviewMaxLines,
+    ChatCompactKeepTurns,
+    ChatMaxContextFileBytes,
+    ChatMaxContextFiles,
+    ChatContextFilesOrder,
+    ChatSpinnerEnabled,
+    ChatSpinnerThinking,
+    ChatSpinnerSummarizing,
+    ChatSpinnerDividingWork,
+    ChatSpinnerRetryingWork,
+    ChatToolTimeoutSecs,
+    ChatHyperlinks,
+    ChatRespectGitignore,
+    ChatMaxRetries,
+    ChatPager,
+    ChatShowToolResults,
+    ChatPromptFormat,
+    ChatPromptColor,
+    ChatIssueTranscriptTurns,
+    ChatRedactSecrets,
+    ChatRedactPatterns,
+    ChatInterruptExitWindowMs,
+    ChatWebSearchEndpoint,
+    ChatWebSearchApiKey,
+    ChatHistorySize,
+    ChatShowUsage,
+    ChatMaxRepeatedToolFailures,
+    ChatHistoryStrategy,
+    ChatStreaming,
+    ChatNotifyMinDurationSecs,
+    ChatAuditLog,
+    ChatWorkspaceRoot,
+    ChatMaxResponseBytes,
     ApiCodeWhispererService,
     ApiQService,
     McpInitTimeout,
@@ -45,6 +84,45 @@ impl AsRef<str> for Setting {
             Self::ApiTimeout => "api.timeout",
             Self::ChatEditMode => "chat.editMode",
             Self::ChatEnableNotifications => "chat.enableNotifications",
+            Self::ChatNotifyOnComplete => "chat.notifyOnComplete",
+            Self::ChatNotifyCmd => "chat.notifyCmd",
+            Self::ChatContextWarningTokens => "chat.contextWarningTokens",
+            Self::ChatCodeHighlighting => "chat.codeHighlighting",
+            Self::ChatTypewriterSpeed => "chat.typewriterSpeed",
+            Self::ChatToolPolicy => "chat.toolPolicy",
+            Self::ChatDiffPreviewMaxLines => "chat.diffPreviewMaxLines",
+            Self::ChatCompactKeepTurns => "chat.compactKeepTurns",
+            Self::ChatMaxContextFileBytes => "chat.maxContextFileBytes",
+            Self::ChatMaxContextFiles => "chat.maxContextFiles",
+            Self::ChatContextFilesOrder => "chat.contextFilesOrder",
+            Self::ChatSpinnerEnabled => "chat.spinner.enabled",
+            Self::ChatSpinnerThinking => "chat.spinner.thinking",
+            Self::ChatSpinnerSummarizing => "chat.spinner.summarizing",
+            Self::ChatSpinnerDividingWork => "chat.spinner.dividingWork",
+            Self::ChatSpinnerRetryingWork => "chat.spinner.retryingWork",
+            Self::ChatToolTimeoutSecs => "chat.toolTimeoutSecs",
+            Self::ChatHyperlinks => "chat.hyperlinks",
+            Self::ChatRespectGitignore => "chat.respectGitignore",
+            Self::ChatMaxRetries => "chat.maxRetries",
+            Self::ChatPager => "chat.pager",
+            Self::ChatShowToolResults => "chat.showToolResults",
+            Self::ChatPromptFormat => "chat.promptFormat",
+            Self::ChatPromptColor => "chat.promptColor",
+            Self::ChatIssueTranscriptTurns => "chat.issueTranscriptTurns",
+            Self::ChatRedactSecrets => "chat.redactSecrets",
+            Self::ChatRedactPatterns => "chat.redactPatterns",
+            Self::ChatInterruptExitWindowMs => "chat.interruptExitWindowMs",
+            Self::ChatWebSearchEndpoint => "chat.webSearchEndpoint",
+            Self::ChatWebSearchApiKey => "chat.webSearchApiKey",
+            Self::ChatHistorySize => "chat.historySize",
+            Self::ChatShowUsage => "chat.showUsage",
+            Self::ChatMaxRepeatedToolFailures => "chat.maxRepeatedToolFailures",
+            Self::ChatHistoryStrategy => "chat.historyStrategy",
+            Self::ChatStreaming => "chat.streaming",
+            Self::ChatNotifyMinDurationSecs => "chat.notifyMinDurationSecs",
+            Self::ChatAuditLog => "chat.auditLog",
+            Self::ChatWorkspaceRoot => "chat.workspaceRoot",
+            Self::ChatMaxResponseBytes => "chat.maxResponseBytes",
             Self::ApiCodeWhispererService => "api.codewhisperer.service",
             Self::ApiQService => "api.q.service",
             Self::McpInitTimeout => "mcp.initTimeout",
@@ -74,6 +152,45 @@ impl TryFrom<&str> for Setting {
             "api.timeout" => Ok(Self::ApiTimeout),
             "chat.editMode" => Ok(Self::ChatEditMode),
             "chat.enableNotifications" => Ok(Self::ChatEnableNotifications),
+            "chat.notifyOnComplete" => Ok(Self::ChatNotifyOnComplete),
+            "chat.notifyCmd" => Ok(Self::ChatNotifyCmd),
+            "chat.contextWarningTokens" => Ok(Self::ChatContextWarningTokens),
+            "chat.codeHighlighting" => Ok(Self::ChatCodeHighlighting),
+            "chat.typewriterSpeed" => Ok(Self::ChatTypewriterSpeed),
+            "chat.toolPolicy" => Ok(Self::ChatToolPolicy),
+            "chat.diffPreviewMaxLines" => Ok(Self::ChatDiffPreviewMaxLines),
+            "chat.compactKeepTurns" => Ok(Self::ChatCompactKeepTurns),
+            "chat.maxContextFileBytes" => Ok(Self::ChatMaxContextFileBytes),
+            "chat.maxContextFiles" => Ok(Self::ChatMaxContextFiles),
+            "chat.contextFilesOrder" => Ok(Self::ChatContextFilesOrder),
+            "chat.spinner.enabled" => Ok(Self::ChatSpinnerEnabled),
+            "chat.spinner.thinking" => Ok(Self::ChatSpinnerThinking),
+            "chat.spinner.summarizing" => Ok(Self::ChatSpinnerSummarizing),
+            "chat.spinner.dividingWork" => Ok(Self::ChatSpinnerDividingWork),
+            "chat.spinner.retryingWork" => Ok(Self::ChatSpinnerRetryingWork),
+            "chat.toolTimeoutSecs" => Ok(Self::ChatToolTimeoutSecs),
+            "chat.hyperlinks" => Ok(Self::ChatHyperlinks),
+            "chat.respectGitignore" => Ok(Self::ChatRespectGitignore),
+            "chat.maxRetries" => Ok(Self::ChatMaxRetries),
+            "chat.pager" => Ok(Self::ChatPager),
+            "chat.showToolResults" => Ok(Self::ChatShowToolResults),
+            "chat.promptFormat" => Ok(Self::ChatPromptFormat),
+            "chat.promptColor" => Ok(Self::ChatPromptColor),
+            "chat.issueTranscriptTurns" => Ok(Self::ChatIssueTranscriptTurns),
+            "chat.redactSecrets" => Ok(Self::ChatRedactSecrets),
+            "chat.redactPatterns" => Ok(Self::ChatRedactPatterns),
+            "chat.interruptExitWindowMs" => Ok(Self::ChatInterruptExitWindowMs),
+            "chat.webSearchEndpoint" => Ok(Self::ChatWebSearchEndpoint),
+            "chat.webSearchApiKey" => Ok(Self::ChatWebSearchApiKey),
+            "chat.historySize" => Ok(Self::ChatHistorySize),
+            "chat.showUsage" => Ok(Self::ChatShowUsage),
+            "chat.maxRepeatedToolFailures" => Ok(Self::ChatMaxRepeatedToolFailures),
+            "chat.historyStrategy" => Ok(Self::ChatHistoryStrategy),
+            "chat.streaming" => Ok(Self::ChatStreaming),
+            "chat.notifyMinDurationSecs" => Ok(Self::ChatNotifyMinDurationSecs),
+            "chat.auditLog" => Ok(Self::ChatAuditLog),
+            "chat.workspaceRoot" => Ok(Self::ChatWorkspaceRoot),
+            "chat.maxResponseBytes" => Ok(Self::ChatMaxResponseBytes),
             "api.codewhisperer.service" => Ok(Self::ApiCodeWhispererService),
             "api.q.service" => Ok(Self::ApiQService),
             "mcp.initTimeout" => Ok(Self::McpInitTimeout),
@@ -1,10 +1,14 @@
 #![cfg(not(test))]
-//! This lib.rs is only here for testing purposes.
-//! `test_mcp_server/test_server.rs` is declared as a separate binary and would need a way to
-//! reference types defined inside of this crate, hence the export.
+//! The library side of the crate, for code that builds on `q` instead of running it.
+//!
+//! [cli::ChatSession] drives a chat from other code, see `examples/embed_chat.rs`.
+//! `test_mcp_server/test_server.rs` is declared as a separate binary and uses the MCP types
+//! exported here.
 pub mod api_client;
 pub mod auth;
 pub mod aws_common;
+// The binary's copy of `cli::chat::session` is dead code it expects, but the library uses it.
+#[allow(unfulfilled_lint_expectations)]
 pub mod cli;
 pub mod database;
 pub mod logging;
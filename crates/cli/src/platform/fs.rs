@@ -175,6 +175,33 @@ impl Fs {
         }
     }
 
+    /// Appends `contents` to the end of the file at `path`, creating it if it doesn't exist.
+    pub async fn append_file(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        use inner::Inner;
+        use tokio::io::AsyncWriteExt;
+
+        let path = match &self.0 {
+            Inner::Real => path.as_ref().to_path_buf(),
+            Inner::Chroot(root) => append(root.path(), path),
+            Inner::Fake(map) => {
+                let Ok(mut lock) = map.lock() else {
+                    return Err(io::Error::new(io::ErrorKind::Other, "poisoned lock"));
+                };
+                lock.entry(path.as_ref().to_owned())
+                    .or_default()
+                    .extend_from_slice(contents.as_ref());
+                return Ok(());
+            },
+        };
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?
+            .write_all(contents.as_ref())
+            .await
+    }
+
     /// Removes a file from the filesystem.
     ///
     /// Note that there is no guarantee that the file is immediately deleted (e.g.
@@ -452,6 +479,10 @@ mod tests {
         fs.write(dir.join("write"), b"write").await.unwrap();
         assert_eq!(fs.read(dir.join("write")).await.unwrap(), b"write");
         assert_eq!(fs.read_to_string(dir.join("write")).await.unwrap(), "write");
+        fs.append_file(dir.join("write"), b" more").await.unwrap();
+        fs.append_file(dir.join("append"), b"append").await.unwrap();
+        assert_eq!(fs.read_to_string(dir.join("write")).await.unwrap(), "write more");
+        assert_eq!(fs.read_to_string(dir.join("append")).await.unwrap(), "append");
     }
 
     #[tokio::test]
@@ -464,6 +495,10 @@ mod tests {
         fs.write(dir.path().join("write"), b"write").await.unwrap();
         assert_eq!(fs.read(dir.path().join("write")).await.unwrap(), b"write");
         assert_eq!(fs.read_to_string(dir.path().join("write")).await.unwrap(), "write");
+        fs.append_file(dir.path().join("write"), b" more").await.unwrap();
+        fs.append_file(dir.path().join("append"), b"append").await.unwrap();
+        assert_eq!(fs.read_to_string(dir.path().join("write")).await.unwrap(), "write more");
+        assert_eq!(fs.read_to_string(dir.path().join("append")).await.unwrap(), "append");
     }
 
     #[test]
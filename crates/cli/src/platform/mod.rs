@@ -8,6 +8,10 @@ mod os;
 mod providers;
 mod sysinfo;
 
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::sync::Arc;
 
 pub use env::Env;
@@ -34,6 +38,8 @@ pub struct Context {
     env: Env,
     sysinfo: SysInfo,
     platform: Platform,
+    /// Per-session directory where tools can place temporary artifacts.
+    scratch_dir: Option<PathBuf>,
 }
 
 impl Context {
@@ -45,12 +51,14 @@ impl Context {
                 env: Env::new(),
                 sysinfo: SysInfo::new(),
                 platform: Platform::new(),
+                scratch_dir: None,
             }),
             false => Arc::new_cyclic(|_| Self {
                 fs: Default::default(),
                 env: Default::default(),
                 sysinfo: SysInfo::default(),
                 platform: Platform::new(),
+                scratch_dir: None,
             }),
         }
     }
@@ -74,6 +82,10 @@ impl Context {
     pub fn platform(&self) -> &Platform {
         &self.platform
     }
+
+    pub fn scratch_dir(&self) -> Option<&Path> {
+        self.scratch_dir.as_deref()
+    }
 }
 
 #[derive(Default, Debug)]
@@ -82,6 +94,7 @@ pub struct ContextBuilder {
     env: Option<Env>,
     sysinfo: Option<SysInfo>,
     platform: Option<Platform>,
+    scratch_dir: Option<PathBuf>,
 }
 
 impl ContextBuilder {
@@ -95,11 +108,13 @@ impl ContextBuilder {
         let env = self.env.unwrap_or_default();
         let sysinfo = self.sysinfo.unwrap_or_default();
         let platform = self.platform.unwrap_or_default();
+        let scratch_dir = self.scratch_dir;
         Arc::new_cyclic(|_| Context {
             fs,
             env,
             sysinfo,
             platform,
+            scratch_dir,
         })
     }
 
@@ -109,11 +124,13 @@ impl ContextBuilder {
         let env = self.env.unwrap_or_default();
         let sysinfo = self.sysinfo.unwrap_or_default();
         let platform = self.platform.unwrap_or_default();
+        let scratch_dir = self.scratch_dir;
         Arc::new_cyclic(|_| Context {
             fs,
             env,
             sysinfo,
             platform,
+            scratch_dir,
         })
     }
 
@@ -127,6 +144,16 @@ impl ContextBuilder {
         self
     }
 
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    pub fn with_scratch_dir(mut self, scratch_dir: impl Into<PathBuf>) -> Self {
+        self.scratch_dir = Some(scratch_dir.into());
+        self
+    }
+
     /// Creates a chroot filesystem and fake environment so that `$HOME`
     /// points to `<tempdir>/home/testuser`. Note that this replaces the
     /// [Fs] and [Env] currently set with the builder.
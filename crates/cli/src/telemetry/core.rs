@@ -14,6 +14,7 @@ use crate::telemetry::definitions::metrics::{
     AmazonqProfileState,
     AmazonqStartChat,
     CodewhispererterminalAddChatMessage,
+    CodewhispererterminalChatFeedback,
     CodewhispererterminalCliSubcommandExecuted,
     CodewhispererterminalMcpServerInit,
     CodewhispererterminalRefreshCredentials,
@@ -24,9 +25,11 @@ use crate::telemetry::definitions::types::{
     CodewhispererterminalCustomToolInputTokenSize,
     CodewhispererterminalCustomToolLatency,
     CodewhispererterminalCustomToolOutputTokenSize,
+    CodewhispererterminalFeedbackNote,
     CodewhispererterminalInCloudshell,
     CodewhispererterminalIsToolValid,
     CodewhispererterminalMcpServerInitFailureReason,
+    CodewhispererterminalMessageId,
     CodewhispererterminalToolName,
     CodewhispererterminalToolUseId,
     CodewhispererterminalToolUseIsSuccess,
@@ -182,6 +185,24 @@ impl Event {
                 }
                 .into_metric_datum(),
             ),
+            EventType::ChatFeedback {
+                conversation_id,
+                message_id,
+                sentiment,
+                note,
+            } => Some(
+                CodewhispererterminalChatFeedback {
+                    create_time: self.created_time,
+                    value: None,
+                    amazonq_conversation_id: Some(conversation_id.into()),
+                    codewhispererterminal_message_id: message_id.map(CodewhispererterminalMessageId),
+                    codewhispererterminal_feedback_sentiment: Some(sentiment.to_string().into()),
+                    codewhispererterminal_feedback_note: note.map(CodewhispererterminalFeedbackNote),
+                    credential_start_url: self.credential_start_url.map(Into::into),
+                    codewhispererterminal_in_cloudshell: in_cloudshell(),
+                }
+                .into_metric_datum(),
+            ),
             EventType::DidSelectProfile {
                 source,
                 amazonq_profile_region,
@@ -266,6 +287,12 @@ pub enum EventType {
         init_failure_reason: Option<String>,
         number_of_tools: usize,
     },
+    ChatFeedback {
+        conversation_id: String,
+        message_id: Option<String>,
+        sentiment: FeedbackSentiment,
+        note: Option<String>,
+    },
     DidSelectProfile {
         source: QProfileSwitchIntent,
         amazonq_profile_region: String,
@@ -375,6 +402,14 @@ pub enum QProfileSwitchIntent {
     Reload,
 }
 
+/// How the user rated a response with `/feedback`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumString, Display, serde::Serialize, serde::Deserialize)]
+#[strum(serialize_all = "lowercase")]
+pub enum FeedbackSentiment {
+    Positive,
+    Negative,
+}
+
 fn in_cloudshell() -> Option<CodewhispererterminalInCloudshell> {
     Some(crate::util::system_info::in_cloudshell().into())
 }
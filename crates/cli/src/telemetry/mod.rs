@@ -59,6 +59,7 @@ use crate::platform::Env;
 use crate::telemetry::core::Event;
 pub use crate::telemetry::core::{
     EventType,
+    FeedbackSentiment,
     QProfileSwitchIntent,
     TelemetryResult,
 };
@@ -236,6 +237,21 @@ impl TelemetryThread {
         }))?)
     }
 
+    pub fn send_chat_feedback(
+        &self,
+        conversation_id: String,
+        message_id: Option<String>,
+        sentiment: FeedbackSentiment,
+        note: Option<String>,
+    ) -> Result<(), TelemetryError> {
+        Ok(self.tx.send(Event::new(EventType::ChatFeedback {
+            conversation_id,
+            message_id,
+            sentiment,
+            note,
+        }))?)
+    }
+
     pub fn send_did_select_profile(
         &self,
         source: QProfileSwitchIntent,
@@ -497,6 +513,14 @@ mod test {
         thread
             .send_chat_added_message("version".to_owned(), "version".to_owned(), Some(123))
             .ok();
+        thread
+            .send_chat_feedback(
+                "version".to_owned(),
+                Some("version".to_owned()),
+                FeedbackSentiment::Positive,
+                None,
+            )
+            .ok();
 
         drop(thread);
 
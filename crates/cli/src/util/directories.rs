@@ -113,6 +113,20 @@ pub fn logs_dir() -> Result<PathBuf> {
     }
 }
 
+/// The per-session scratch directory for `q chat` tool artifacts, keyed by conversation id
+/// - Linux: `$XDG_RUNTIME_DIR/qchat/<conversation_id>`
+/// - MacOS: `$TMPDIR/qchat/<conversation_id>`
+/// - Windows: `%TEMP%\amazon-q\chat\<conversation_id>`
+pub fn chat_scratch_dir(conversation_id: &str) -> Result<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            Ok(runtime_dir()?.join("qchat").join(conversation_id))
+        } else if #[cfg(windows)] {
+            Ok(std::env::temp_dir().join("amazon-q").join("chat").join(conversation_id))
+        }
+    }
+}
+
 /// The directory to the directory containing config for the `/context` feature in `q chat`.
 pub fn chat_global_context_path(ctx: &Context) -> Result<PathBuf> {
     Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("global_context.json"))
@@ -123,6 +137,17 @@ pub fn chat_profiles_dir(ctx: &Context) -> Result<PathBuf> {
     Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("profiles"))
 }
 
+/// The file that prompts entered in `q chat` are saved to, so they can be recalled in later
+/// sessions.
+pub fn chat_history_path(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join(".cli_history"))
+}
+
+/// The file of user-defined starter prompts listed by `/templates` in `q chat`.
+pub fn chat_templates_path(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("templates.json"))
+}
+
 /// The path to the fig settings file
 pub fn settings_path() -> Result<PathBuf> {
     Ok(fig_data_dir()?.join("settings.json"))